@@ -0,0 +1,446 @@
+//! Command line arguments of the `neural_network` binary.
+
+use neural_network::config::ExperimentConfig;
+use std::fmt;
+use std::str::FromStr;
+
+pub const USAGE: &str = "\
+usage: neural_network <command> [options]
+
+commands:
+  train     trains a network on a CSV file and saves it as model, next to
+            the resolved experiment config (<model>.config.toml)
+    --config <path>            reads the whole experiment from a TOML file
+    --train <path>             training samples (default train.csv)
+    --test <path>              evaluates the trained model on these samples
+    --validation-split <f>     share of the training samples used for validation (default 0.1)
+    --layers <i,h,o>           input, hidden and output nodes (default 784,200,10)
+    --activation <name>        activation function: sigmoid or relu (default sigmoid)
+    --input-dropout <f>        share of the inputs dropped while training (default 0)
+    --hidden-dropout <f>       share of the hidden outputs dropped while training (default 0)
+    --normalization <name>     normalises the hidden outputs: none, batch or layer (default none)
+    --epochs <n>               number of epochs (default 5)
+    --batch-size <n>           samples averaged in one training step (default 1)
+    --learning-rate <f>        learning rate (default 0.1)
+    --l1 <f>                   L1 penalty of the weights (default 0)
+    --l2 <f>                   L2 penalty of the weights (default 0)
+    --weight-decay <f>         decoupled weight decay (default 0)
+    --seed <n>                 seed for weightings, split and shuffling (default 0)
+    --patience <n>             stops after n epochs without improvement
+    --model <path>             where the model is saved (default model.txt)
+    --checkpoint <path>        writes checkpoints while training
+    --checkpoint-every <n>     batches between two checkpoints (default 1000)
+    --resume                   continues the training from the checkpoint
+    --log <path>               writes the metrics of every epoch as CSV
+  evaluate  measures a saved model on labeled samples
+    --model <path>             the model (default model.txt)
+    --test <path>              the samples (default test.csv)
+    --top-k <n>                also reports the top k accuracy (default 3)
+    --csv <path>               exports the report as CSV
+    --json <path>              exports the report as JSON
+  predict   prints the classes a saved model predicts for every row or image
+            usage: neural_network predict [options] [image.png|image.pgm ...]
+    --model <path>             the model (default model.txt)
+    --input <path>             rows in the format of the training samples
+    --top-k <n>                number of classes shown with their scores (default 3)
+  inspect   shows what a saved model consists of
+    --model <path>             the model (default model.txt)
+  help      shows this text";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Train(TrainOptions),
+    Evaluate(EvaluateOptions),
+    Predict(PredictOptions),
+    Inspect(InspectOptions),
+    Help,
+}
+
+/// Where the experiment of `train` is described.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Experiment {
+    /// Path of an experiment file.
+    File(String),
+    /// Built from the defaults and the flags.
+    Options(Box<ExperimentConfig>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainOptions {
+    pub experiment: Experiment,
+    pub resume: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvaluateOptions {
+    pub model: String,
+    pub test: String,
+    pub top_k: usize,
+    pub csv: Option<String>,
+    pub json: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PredictOptions {
+    pub model: String,
+    /// CSV file with one sample per row.
+    pub input: Option<String>,
+    /// PGM or PNG files with one digit each.
+    pub images: Vec<String>,
+    pub top_k: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InspectOptions {
+    pub model: String,
+}
+
+/// The arguments do not make sense - the usage should be shown.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// `args` without the name of the program.
+pub fn parse(args: &[String]) -> Result<Command, UsageError> {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => return Err(UsageError("no command given".to_owned())),
+    };
+
+    match command {
+        "train" => {
+            let mut flags = Flags::parse(rest, &["resume"])?;
+            let resume = flags.switch("resume");
+            let experiment = match flags.value("config")? {
+                Some(path) => {
+                    if let Some((flag, _)) = flags.values.first() {
+                        let message = format!("--{} can not be combined with --config", flag);
+                        return Err(UsageError(message));
+                    }
+                    Experiment::File(path)
+                }
+                None => Experiment::Options(Box::new(train_config(&mut flags, resume)?)),
+            };
+            flags.finish()?;
+            Ok(Command::Train(TrainOptions { experiment, resume }))
+        }
+        "evaluate" => {
+            let mut flags = Flags::parse(rest, &[])?;
+            let options = EvaluateOptions {
+                model: flags.value("model")?.unwrap_or_else(|| "model.txt".to_owned()),
+                test: flags.value("test")?.unwrap_or_else(|| "test.csv".to_owned()),
+                top_k: flags.value("top-k")?.unwrap_or(3),
+                csv: flags.value("csv")?,
+                json: flags.value("json")?,
+            };
+            flags.finish()?;
+            Ok(Command::Evaluate(options))
+        }
+        "predict" => {
+            let mut flags = Flags::parse(rest, &[])?;
+            let options = PredictOptions {
+                model: flags.value("model")?.unwrap_or_else(|| "model.txt".to_owned()),
+                input: flags.value("input")?,
+                images: flags.arguments(),
+                top_k: flags.value("top-k")?.unwrap_or(3),
+            };
+            flags.finish()?;
+            if options.input.is_none() && options.images.is_empty() {
+                return Err(UsageError("predict needs --input or image files".to_owned()));
+            }
+            Ok(Command::Predict(options))
+        }
+        "inspect" => {
+            let mut flags = Flags::parse(rest, &[])?;
+            let options = InspectOptions {
+                model: flags.value("model")?.unwrap_or_else(|| "model.txt".to_owned()),
+            };
+            flags.finish()?;
+            Ok(Command::Inspect(options))
+        }
+        "help" | "--help" | "-h" => Ok(Command::Help),
+        other => Err(UsageError(format!("unknown command '{}'", other))),
+    }
+}
+
+/// The default experiment changed by the flags of `train`.
+fn train_config(flags: &mut Flags, resume: bool) -> Result<ExperimentConfig, UsageError> {
+    let mut config = ExperimentConfig::default();
+    flags.set("train", &mut config.data.train)?;
+    config.data.test = flags.value("test")?;
+    flags.set("validation-split", &mut config.data.validation_split)?;
+    if let Some(layers) = flags.value::<String>("layers")? {
+        config.network.layers = parse_layers(&layers)?.to_vec();
+    }
+    flags.set("activation", &mut config.network.activation)?;
+    flags.set("input-dropout", &mut config.network.input_dropout)?;
+    flags.set("hidden-dropout", &mut config.network.hidden_dropout)?;
+    flags.set("normalization", &mut config.network.normalization)?;
+    flags.set("epochs", &mut config.training.epochs)?;
+    flags.set("batch-size", &mut config.optimizer.batch_size)?;
+    flags.set("learning-rate", &mut config.optimizer.learning_rate)?;
+    flags.set("l1", &mut config.optimizer.l1)?;
+    flags.set("l2", &mut config.optimizer.l2)?;
+    flags.set("weight-decay", &mut config.optimizer.weight_decay)?;
+    flags.set("seed", &mut config.training.seed)?;
+    config.training.patience = flags.value("patience")?;
+    flags.set("model", &mut config.model)?;
+    config.training.checkpoint = flags.value("checkpoint")?;
+    flags.set("checkpoint-every", &mut config.training.checkpoint_every)?;
+    config.training.log = flags.value("log")?;
+
+    if !(0.0..1.0).contains(&config.data.validation_split) {
+        return Err(UsageError("--validation-split has to be in [0, 1)".to_owned()));
+    }
+    if config.optimizer.batch_size == 0 || config.training.checkpoint_every == 0 {
+        return Err(UsageError(
+            "--batch-size and --checkpoint-every have to be at least 1".to_owned(),
+        ));
+    }
+    let network = &config.network;
+    if !(0.0..1.0).contains(&network.input_dropout)
+        || !(0.0..1.0).contains(&network.hidden_dropout)
+    {
+        return Err(UsageError(
+            "--input-dropout and --hidden-dropout have to be in [0, 1)".to_owned(),
+        ));
+    }
+    let optimizer = &config.optimizer;
+    if [optimizer.l1, optimizer.l2, optimizer.weight_decay]
+        .iter()
+        .any(|&value| value.is_nan() || value < 0.0)
+    {
+        return Err(UsageError("--l1, --l2 and --weight-decay cannot be negative".to_owned()));
+    }
+    if resume && config.training.checkpoint.is_none() {
+        return Err(UsageError("--resume needs --checkpoint".to_owned()));
+    }
+    Ok(config)
+}
+
+/// `--name value`, `--name=value`, switches without value and arguments
+/// that are no flags.
+struct Flags {
+    values: Vec<(String, Option<String>)>,
+    arguments: Vec<String>,
+}
+
+impl Flags {
+    fn parse(args: &[String], switches: &[&str]) -> Result<Flags, UsageError> {
+        let mut values = Vec::new();
+        let mut arguments = Vec::new();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let flag = match arg.strip_prefix("--") {
+                Some(flag) => flag,
+                None => {
+                    arguments.push(arg.clone());
+                    continue;
+                }
+            };
+            if let Some(position) = flag.find('=') {
+                values.push((flag[..position].to_owned(), Some(flag[position + 1..].to_owned())));
+            } else if switches.contains(&flag) {
+                values.push((flag.to_owned(), None));
+            } else {
+                match args.next() {
+                    Some(value) => values.push((flag.to_owned(), Some(value.clone()))),
+                    None => return Err(UsageError(format!("--{} needs a value", flag))),
+                }
+            }
+        }
+        Ok(Flags { values, arguments })
+    }
+
+    /// Takes the flag out. The last one wins if it was given several times.
+    fn value<V: FromStr>(&mut self, name: &str) -> Result<Option<V>, UsageError> {
+        let mut result = None;
+        while let Some(position) = self.values.iter().position(|(flag, _)| flag == name) {
+            let value = match self.values.remove(position).1 {
+                Some(value) => value,
+                None => return Err(UsageError(format!("--{} needs a value", name))),
+            };
+            result = match value.parse() {
+                Ok(parsed) => Some(parsed),
+                Err(_) => {
+                    return Err(UsageError(format!("invalid value '{}' for --{}", value, name)))
+                }
+            };
+        }
+        Ok(result)
+    }
+
+    /// Overwrites `target` if the flag was given.
+    fn set<V: FromStr>(&mut self, name: &str, target: &mut V) -> Result<(), UsageError> {
+        if let Some(value) = self.value(name)? {
+            *target = value;
+        }
+        Ok(())
+    }
+
+    fn switch(&mut self, name: &str) -> bool {
+        let before = self.values.len();
+        self.values.retain(|(flag, _)| flag != name);
+        self.values.len() != before
+    }
+
+    /// Takes all arguments that are no flags.
+    fn arguments(&mut self) -> Vec<String> {
+        self.arguments.drain(..).collect()
+    }
+
+    /// Fails for every flag and argument that was not taken.
+    fn finish(self) -> Result<(), UsageError> {
+        if let Some((flag, _)) = self.values.first() {
+            return Err(UsageError(format!("unknown option --{}", flag)));
+        }
+        match self.arguments.first() {
+            Some(argument) => Err(UsageError(format!("unexpected argument '{}'", argument))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse_layers(value: &str) -> Result<[usize; 3], UsageError> {
+    let error = || {
+        UsageError(format!(
+            "invalid value '{}' for --layers: expected input,hidden,output nodes",
+            value
+        ))
+    };
+    let nodes: Vec<usize> = value
+        .split(',')
+        .map(|nodes| nodes.trim().parse().map_err(|_| error()))
+        .collect::<Result<_, _>>()?;
+    match nodes[..] {
+        [input, hidden, output] if input > 0 && hidden > 0 && output > 0 => {
+            Ok([input, hidden, output])
+        }
+        _ => Err(error()),
+    }
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_owned).collect()
+    }
+
+    fn train_options(line: &str) -> TrainOptions {
+        match parse(&args(line)).unwrap() {
+            Command::Train(options) => options,
+            other => panic!("unexpected command {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_train_defaults() {
+        let options = train_options("train");
+        assert_eq!(options.experiment, Experiment::Options(Box::default()));
+        assert!(!options.resume);
+    }
+
+    #[test]
+    fn test_train_options() {
+        let line = "train --train a.csv --layers 4,8,3 --epochs=2 --learning-rate 0.3 \
+                    --seed 7 --checkpoint c.txt --resume --patience 1 --l2 0.01 \
+                    --weight-decay 0.001 --hidden-dropout 0.3 --normalization batch";
+        let options = train_options(line);
+        let config = match options.experiment {
+            Experiment::Options(config) => config,
+            other => panic!("unexpected experiment {:?}", other),
+        };
+        assert_eq!(config.data.train, "a.csv");
+        assert_eq!(config.network.layers, vec![4, 8, 3]);
+        assert_eq!(config.training.epochs, 2);
+        assert_eq!(config.optimizer.learning_rate, 0.3);
+        assert_eq!(config.training.seed, 7);
+        assert_eq!(config.training.checkpoint, Some("c.txt".to_owned()));
+        assert!(options.resume);
+        assert_eq!(config.training.patience, Some(1));
+        assert_eq!((config.optimizer.l1, config.optimizer.l2), (0.0, 0.01));
+        assert_eq!(config.optimizer.weight_decay, 0.001);
+        assert_eq!(config.network.hidden_dropout, 0.3);
+        assert_eq!(config.network.normalization, "batch");
+    }
+
+    #[test]
+    fn test_train_config_file() {
+        let options = train_options("train --config experiment.toml --resume");
+        assert_eq!(options.experiment, Experiment::File("experiment.toml".to_owned()));
+        assert!(options.resume);
+    }
+
+    #[test]
+    fn test_other_commands() {
+        assert_eq!(
+            parse(&args("evaluate --test t.csv --json r.json")),
+            Ok(Command::Evaluate(EvaluateOptions {
+                model: "model.txt".to_owned(),
+                test: "t.csv".to_owned(),
+                top_k: 3,
+                csv: None,
+                json: Some("r.json".to_owned()),
+            }))
+        );
+        assert_eq!(
+            parse(&args("predict --model m.txt --input i.csv")),
+            Ok(Command::Predict(PredictOptions {
+                model: "m.txt".to_owned(),
+                input: Some("i.csv".to_owned()),
+                images: Vec::new(),
+                top_k: 3,
+            }))
+        );
+        assert_eq!(
+            parse(&args("predict one.png --top-k 2 two.pgm")),
+            Ok(Command::Predict(PredictOptions {
+                model: "model.txt".to_owned(),
+                input: None,
+                images: vec!["one.png".to_owned(), "two.pgm".to_owned()],
+                top_k: 2,
+            }))
+        );
+        assert_eq!(
+            parse(&args("inspect")),
+            Ok(Command::Inspect(InspectOptions {
+                model: "model.txt".to_owned(),
+            }))
+        );
+        assert_eq!(parse(&args("--help")), Ok(Command::Help));
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        let error = |line| parse(&args(line)).unwrap_err().0;
+
+        assert_eq!(error(""), "no command given");
+        assert_eq!(error("fly"), "unknown command 'fly'");
+        assert_eq!(error("train --epochs"), "--epochs needs a value");
+        assert_eq!(error("train --epochs many"), "invalid value 'many' for --epochs");
+        assert_eq!(error("train --colour red"), "unknown option --colour");
+        assert_eq!(error("train extra"), "unexpected argument 'extra'");
+        assert_eq!(error("train --resume"), "--resume needs --checkpoint");
+        assert_eq!(error("predict"), "predict needs --input or image files");
+        assert_eq!(error("inspect extra"), "unexpected argument 'extra'");
+        assert!(error("train --layers 784,10").starts_with("invalid value '784,10' for --layers"));
+        assert_eq!(error("train --validation-split 1"), "--validation-split has to be in [0, 1)");
+        assert_eq!(
+            error("train --input-dropout 1.5"),
+            "--input-dropout and --hidden-dropout have to be in [0, 1)"
+        );
+        assert_eq!(error("train --l1 -1"), "--l1, --l2 and --weight-decay cannot be negative");
+        assert_eq!(
+            error("train --config e.toml --epochs 3"),
+            "--epochs can not be combined with --config"
+        );
+    }
+}
@@ -0,0 +1,724 @@
+//! Experiment files: everything needed to train a model again - data,
+//! preprocessing, architecture, optimizer, schedule and training loop.
+//!
+//! They are written in TOML:
+//!
+//! ```toml
+//! [data]
+//! train = "train.csv"
+//! validation_split = 0.1
+//!
+//! [network]
+//! layers = [784, 200, 10]
+//!
+//! [schedule]
+//! type = "step"
+//! every = 2
+//! factor = 0.5
+//! ```
+//!
+//! `[preprocessing]` takes `scaler` (`min_max`, `standard` or `none`), the
+//! ranges of the min-max scaler and the `target_on` / `target_off` outputs,
+//! `[optimizer]` and `[schedule]` a `type` and its parameters.
+//!
+//! Missing keys get their default. Unknown keys and tables are errors so
+//! that a typo does not silently fall back to a default.
+
+pub mod toml;
+
+use self::toml::{Table, Value};
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use training::callbacks::LearningRateSchedule;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    /// The file is not valid TOML or has unknown or mistyped keys.
+    Syntax { line: usize, message: String },
+    /// The values do not describe a valid experiment.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref e) => write!(f, "could not access config: {}", e),
+            ConfigError::Syntax { line, ref message } => {
+                write!(f, "invalid config in line {}: {}", line, message)
+            }
+            ConfigError::Invalid(ref message) => write!(f, "invalid config: {}", message),
+        }
+    }
+}
+
+impl error::Error for ConfigError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ConfigError::Io(ref e) => Some(e),
+            ConfigError::Syntax { .. } | ConfigError::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::Io(e)
+    }
+}
+
+/// Activation functions the network can be configured with.
+pub const ACTIVATIONS: &[&str] = &["sigmoid", "relu"];
+
+/// Normalisations of the hidden outputs.
+pub const NORMALIZATIONS: &[&str] = &["none", "batch", "layer"];
+
+/// Ways to initialise the weightings.
+pub const INITIALIZERS: &[&str] = &["uniform"];
+
+/// Optimizers the trainer supports.
+pub const OPTIMIZERS: &[&str] = &["sgd"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataConfig {
+    /// CSV file with the training samples.
+    pub train: String,
+    /// CSV file the trained model is evaluated on.
+    pub test: Option<String>,
+    /// Share of the training samples used for validation.
+    pub validation_split: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScalerConfig {
+    None,
+    /// Without input range (`fit = true`) it is learned from the training
+    /// samples.
+    MinMax {
+        input_range: Option<(f64, f64)>,
+        output_min: f64,
+        output_max: f64,
+    },
+    Standard,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreprocessingConfig {
+    pub scaler: ScalerConfig,
+    /// Awaited output of the node of the label.
+    pub target_on: f64,
+    /// Awaited output of all other nodes.
+    pub target_off: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkConfig {
+    /// Input, hidden and output nodes.
+    pub layers: Vec<usize>,
+    pub activation: String,
+    pub initializer: String,
+    /// Dropout rates while training.
+    pub input_dropout: f64,
+    pub hidden_dropout: f64,
+    pub normalization: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimizerConfig {
+    pub name: String,
+    pub learning_rate: f64,
+    pub batch_size: usize,
+    /// Penalties of both weightings.
+    pub l1: f64,
+    pub l2: f64,
+    pub weight_decay: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainingConfig {
+    pub epochs: usize,
+    /// Seed of the weightings, the validation split and the shuffling.
+    pub seed: u64,
+    /// Stops after that many epochs without improvement.
+    pub patience: Option<usize>,
+    pub restore_best_weights: bool,
+    pub checkpoint: Option<String>,
+    /// Batches between two checkpoints.
+    pub checkpoint_every: usize,
+    /// CSV file with the metrics of every epoch.
+    pub log: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExperimentConfig {
+    pub data: DataConfig,
+    pub preprocessing: PreprocessingConfig,
+    pub network: NetworkConfig,
+    pub optimizer: OptimizerConfig,
+    pub schedule: LearningRateSchedule,
+    pub training: TrainingConfig,
+    /// Where the model is saved.
+    pub model: String,
+}
+
+impl Default for ExperimentConfig {
+    /// The MNIST setup the binary always used.
+    fn default() -> ExperimentConfig {
+        ExperimentConfig {
+            data: DataConfig {
+                train: "train.csv".to_owned(),
+                test: None,
+                validation_split: 0.1,
+            },
+            preprocessing: PreprocessingConfig {
+                scaler: ScalerConfig::MinMax {
+                    input_range: Some((0.0, 255.0)),
+                    output_min: 0.01,
+                    output_max: 1.0,
+                },
+                target_on: 0.99,
+                target_off: 0.0,
+            },
+            network: NetworkConfig {
+                layers: vec![784, 200, 10],
+                activation: "sigmoid".to_owned(),
+                initializer: "uniform".to_owned(),
+                input_dropout: 0.0,
+                hidden_dropout: 0.0,
+                normalization: "none".to_owned(),
+            },
+            optimizer: OptimizerConfig {
+                name: "sgd".to_owned(),
+                learning_rate: 0.1,
+                batch_size: 1,
+                l1: 0.0,
+                l2: 0.0,
+                weight_decay: 0.0,
+            },
+            schedule: LearningRateSchedule::Constant,
+            training: TrainingConfig {
+                epochs: 5,
+                seed: 0,
+                patience: None,
+                restore_best_weights: true,
+                checkpoint: None,
+                checkpoint_every: 1000,
+                log: None,
+            },
+            model: "model.txt".to_owned(),
+        }
+    }
+}
+
+impl ExperimentConfig {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ExperimentConfig, ConfigError> {
+        ExperimentConfig::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(text: &str) -> Result<ExperimentConfig, ConfigError> {
+        let mut document = toml::parse(text)?;
+        let mut config = ExperimentConfig::default();
+
+        if let Some(mut table) = document.take("data") {
+            set(&mut config.data.train, table.string("train")?);
+            config.data.test = table.string("test")?;
+            set(&mut config.data.validation_split, table.float("validation_split")?);
+            table.finish()?;
+        }
+        if let Some(mut table) = document.take("preprocessing") {
+            config.preprocessing = read_preprocessing(&mut table)?;
+            table.finish()?;
+        }
+        if let Some(mut table) = document.take("network") {
+            if let Some(layers) = table.unsigned_array("layers")? {
+                config.network.layers = layers.into_iter().map(|nodes| nodes as usize).collect();
+            }
+            set(&mut config.network.activation, table.string("activation")?);
+            set(&mut config.network.initializer, table.string("initializer")?);
+            set(&mut config.network.input_dropout, table.float("input_dropout")?);
+            set(&mut config.network.hidden_dropout, table.float("hidden_dropout")?);
+            set(&mut config.network.normalization, table.string("normalization")?);
+            table.finish()?;
+        }
+        if let Some(mut table) = document.take("optimizer") {
+            set(&mut config.optimizer.name, table.string("type")?);
+            set(&mut config.optimizer.learning_rate, table.float("learning_rate")?);
+            set(&mut config.optimizer.batch_size, usize_value(&mut table, "batch_size")?);
+            set(&mut config.optimizer.l1, table.float("l1")?);
+            set(&mut config.optimizer.l2, table.float("l2")?);
+            set(&mut config.optimizer.weight_decay, table.float("weight_decay")?);
+            table.finish()?;
+        }
+        if let Some(mut table) = document.take("schedule") {
+            config.schedule = read_schedule(&mut table)?;
+            table.finish()?;
+        }
+        if let Some(mut table) = document.take("training") {
+            let training = &mut config.training;
+            set(&mut training.epochs, usize_value(&mut table, "epochs")?);
+            set(&mut training.seed, table.unsigned("seed")?);
+            training.patience = usize_value(&mut table, "patience")?;
+            set(&mut training.restore_best_weights, table.boolean("restore_best_weights")?);
+            training.checkpoint = table.string("checkpoint")?;
+            set(&mut training.checkpoint_every, usize_value(&mut table, "checkpoint_every")?);
+            training.log = table.string("log")?;
+            table.finish()?;
+        }
+        if let Some(mut table) = document.take("output") {
+            set(&mut config.model, table.string("model")?);
+            table.finish()?;
+        }
+        document.finish()?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks what the types alone do not ensure.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+        if !(0.0..1.0).contains(&self.data.validation_split) {
+            return invalid("validation_split has to be in [0, 1)".to_owned());
+        }
+        if self.network.layers.len() != 3 || self.network.layers.contains(&0) {
+            return invalid(format!(
+                "layers has to be the input, hidden and output nodes but is {:?}",
+                self.network.layers
+            ));
+        }
+        let network = &self.network;
+        if !(0.0..1.0).contains(&network.input_dropout)
+            || !(0.0..1.0).contains(&network.hidden_dropout)
+        {
+            return invalid("input_dropout and hidden_dropout have to be in [0, 1)".to_owned());
+        }
+        check_name("activation", &self.network.activation, ACTIVATIONS)?;
+        check_name("normalization", &self.network.normalization, NORMALIZATIONS)?;
+        check_name("initializer", &self.network.initializer, INITIALIZERS)?;
+        check_name("optimizer", &self.optimizer.name, OPTIMIZERS)?;
+        if self.optimizer.batch_size == 0 || self.training.checkpoint_every == 0 {
+            return invalid("batch_size and checkpoint_every have to be at least 1".to_owned());
+        }
+        // TOML integers have 64 bits with a sign - a larger seed could not be saved
+        if self.training.seed > i64::MAX as u64 {
+            return invalid(format!("seed can be at most {}", i64::MAX));
+        }
+        let optimizer = &self.optimizer;
+        if [optimizer.l1, optimizer.l2, optimizer.weight_decay]
+            .iter()
+            .any(|&value| value.is_nan() || value < 0.0)
+        {
+            return invalid("l1, l2 and weight_decay cannot be negative".to_owned());
+        }
+        match self.preprocessing.scaler {
+            ScalerConfig::MinMax {
+                input_range: Some((min, max)),
+                ..
+            } if min >= max => invalid(format!("input range [{}, {}] is empty", min, max)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The complete config with all defaults filled in.
+    pub fn to_toml(&self) -> String {
+        let mut tables: Vec<(&str, Vec<(&str, Value)>)> = Vec::new();
+
+        let mut data = vec![("train", string(&self.data.train))];
+        if let Some(ref test) = self.data.test {
+            data.push(("test", string(test)));
+        }
+        data.push(("validation_split", Value::Float(self.data.validation_split)));
+        tables.push(("data", data));
+
+        let mut preprocessing = Vec::new();
+        match self.preprocessing.scaler {
+            ScalerConfig::None => preprocessing.push(("scaler", string("none"))),
+            ScalerConfig::Standard => preprocessing.push(("scaler", string("standard"))),
+            ScalerConfig::MinMax {
+                input_range,
+                output_min,
+                output_max,
+            } => {
+                preprocessing.push(("scaler", string("min_max")));
+                match input_range {
+                    Some((input_min, input_max)) => {
+                        preprocessing.push(("input_min", Value::Float(input_min)));
+                        preprocessing.push(("input_max", Value::Float(input_max)));
+                    }
+                    None => preprocessing.push(("fit", Value::Boolean(true))),
+                }
+                preprocessing.push(("output_min", Value::Float(output_min)));
+                preprocessing.push(("output_max", Value::Float(output_max)));
+            }
+        }
+        preprocessing.push(("target_on", Value::Float(self.preprocessing.target_on)));
+        preprocessing.push(("target_off", Value::Float(self.preprocessing.target_off)));
+        tables.push(("preprocessing", preprocessing));
+
+        let layers = self.network.layers.iter().map(|&nodes| integer(nodes)).collect();
+        tables.push((
+            "network",
+            vec![
+                ("layers", Value::Array(layers)),
+                ("activation", string(&self.network.activation)),
+                ("initializer", string(&self.network.initializer)),
+                ("input_dropout", Value::Float(self.network.input_dropout)),
+                ("hidden_dropout", Value::Float(self.network.hidden_dropout)),
+                ("normalization", string(&self.network.normalization)),
+            ],
+        ));
+
+        tables.push((
+            "optimizer",
+            vec![
+                ("type", string(&self.optimizer.name)),
+                ("learning_rate", Value::Float(self.optimizer.learning_rate)),
+                ("batch_size", integer(self.optimizer.batch_size)),
+                ("l1", Value::Float(self.optimizer.l1)),
+                ("l2", Value::Float(self.optimizer.l2)),
+                ("weight_decay", Value::Float(self.optimizer.weight_decay)),
+            ],
+        ));
+
+        tables.push((
+            "schedule",
+            match self.schedule {
+                LearningRateSchedule::Constant => vec![("type", string("constant"))],
+                LearningRateSchedule::Step { every, factor } => vec![
+                    ("type", string("step")),
+                    ("every", integer(every)),
+                    ("factor", Value::Float(factor)),
+                ],
+                LearningRateSchedule::Exponential { gamma } => {
+                    vec![("type", string("exponential")), ("gamma", Value::Float(gamma))]
+                }
+                LearningRateSchedule::Cosine { epochs, minimum } => vec![
+                    ("type", string("cosine")),
+                    ("epochs", integer(epochs)),
+                    ("minimum", Value::Float(minimum)),
+                ],
+            },
+        ));
+
+        let training = &self.training;
+        let mut entries = vec![
+            ("epochs", integer(training.epochs)),
+            ("seed", Value::Integer(training.seed as i64)),
+        ];
+        if let Some(patience) = training.patience {
+            entries.push(("patience", integer(patience)));
+        }
+        entries.push(("restore_best_weights", Value::Boolean(training.restore_best_weights)));
+        if let Some(ref checkpoint) = training.checkpoint {
+            entries.push(("checkpoint", string(checkpoint)));
+        }
+        entries.push(("checkpoint_every", integer(training.checkpoint_every)));
+        if let Some(ref log) = training.log {
+            entries.push(("log", string(log)));
+        }
+        tables.push(("training", entries));
+
+        tables.push(("output", vec![("model", string(&self.model))]));
+
+        let mut text = String::new();
+        for (name, entries) in tables {
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(&format!("[{}]\n", name));
+            for (key, value) in entries {
+                text.push_str(&format!("{} = {}\n", key, value.to_toml()));
+            }
+        }
+        text
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ConfigError> {
+        fs::write(path, self.to_toml())?;
+        Ok(())
+    }
+}
+
+/// Where the resolved config of a model is written: next to it, e.g.
+/// `model.config.toml` for `model.txt`.
+pub fn config_path_for<P: AsRef<Path>>(model: P) -> PathBuf {
+    model.as_ref().with_extension("config.toml")
+}
+
+fn set<V>(target: &mut V, value: Option<V>) {
+    if let Some(value) = value {
+        *target = value;
+    }
+}
+
+fn string(value: &str) -> Value {
+    Value::String(value.to_owned())
+}
+
+fn integer(value: usize) -> Value {
+    Value::Integer(value as i64)
+}
+
+fn usize_value(table: &mut Table, key: &str) -> Result<Option<usize>, ConfigError> {
+    Ok(table.unsigned(key)?.map(|value| value as usize))
+}
+
+fn check_name(kind: &str, name: &str, known: &[&str]) -> Result<(), ConfigError> {
+    if known.contains(&name) {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(format!(
+            "unknown {} '{}' - expected one of {}",
+            kind,
+            name,
+            known.join(", ")
+        )))
+    }
+}
+
+fn read_preprocessing(table: &mut Table) -> Result<PreprocessingConfig, ConfigError> {
+    let mut preprocessing = ExperimentConfig::default().preprocessing;
+    let fit = table.boolean("fit")?;
+    let input_min = table.float("input_min")?;
+    let input_max = table.float("input_max")?;
+    let output_min = table.float("output_min")?;
+    let output_max = table.float("output_max")?;
+
+    let scaler = table.string("scaler")?.unwrap_or_else(|| "min_max".to_owned());
+    preprocessing.scaler = match scaler.as_str() {
+        "none" => ScalerConfig::None,
+        "standard" => ScalerConfig::Standard,
+        "min_max" => ScalerConfig::MinMax {
+            input_range: if fit == Some(true) {
+                if input_min.is_some() || input_max.is_some() {
+                    return Err(ConfigError::Invalid(
+                        "fit learns input_min and input_max - they can not be given".to_owned(),
+                    ));
+                }
+                None
+            } else {
+                Some((input_min.unwrap_or(0.0), input_max.unwrap_or(255.0)))
+            },
+            output_min: output_min.unwrap_or(0.01),
+            output_max: output_max.unwrap_or(1.0),
+        },
+        other => {
+            return Err(ConfigError::Invalid(format!(
+                "unknown scaler '{}' - expected one of none, min_max, standard",
+                other
+            )))
+        }
+    };
+    let range_given = [input_min, input_max, output_min, output_max]
+        .iter()
+        .any(Option::is_some);
+    if (range_given || fit.is_some()) && scaler != "min_max" {
+        return Err(ConfigError::Invalid(format!(
+            "ranges only apply to the min_max scaler, not to '{}'",
+            scaler
+        )));
+    }
+
+    set(&mut preprocessing.target_on, table.float("target_on")?);
+    set(&mut preprocessing.target_off, table.float("target_off")?);
+    Ok(preprocessing)
+}
+
+fn read_schedule(table: &mut Table) -> Result<LearningRateSchedule, ConfigError> {
+    let name = table.string("type")?.unwrap_or_else(|| "constant".to_owned());
+
+    let schedule = match name.as_str() {
+        "constant" => LearningRateSchedule::Constant,
+        "step" => LearningRateSchedule::Step {
+            every: required(usize_value(table, "every")?, &name, "every")?,
+            factor: required(table.float("factor")?, &name, "factor")?,
+        },
+        "exponential" => LearningRateSchedule::Exponential {
+            gamma: required(table.float("gamma")?, &name, "gamma")?,
+        },
+        "cosine" => LearningRateSchedule::Cosine {
+            epochs: required(usize_value(table, "epochs")?, &name, "epochs")?,
+            minimum: table.float("minimum")?.unwrap_or(0.0),
+        },
+        other => {
+            return Err(ConfigError::Invalid(format!(
+                "unknown schedule '{}' - expected one of constant, step, exponential, cosine",
+                other
+            )))
+        }
+    };
+    Ok(schedule)
+}
+
+fn required<V>(value: Option<V>, schedule: &str, key: &str) -> Result<V, ConfigError> {
+    value.ok_or_else(|| ConfigError::Invalid(format!("the {} schedule needs {}", schedule, key)))
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        let config = ExperimentConfig::from_toml("# nothing set\n").unwrap();
+        assert_eq!(config, ExperimentConfig::default());
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let text = "[data]\n\
+                    train = 'small.csv'\n\
+                    test = 'small_test.csv'\n\
+                    validation_split = 0.2\n\
+                    [preprocessing]\n\
+                    scaler = 'standard'\n\
+                    target_on = 1\n\
+                    [network]\n\
+                    layers = [4, 8, 3]\n\
+                    hidden_dropout = 0.5\n\
+                    normalization = 'batch'\n\
+                    [optimizer]\n\
+                    learning_rate = 0.3\n\
+                    batch_size = 16\n\
+                    l2 = 0.001\n\
+                    [schedule]\n\
+                    type = 'step'\n\
+                    every = 2\n\
+                    factor = 0.5\n\
+                    [training]\n\
+                    epochs = 20\n\
+                    seed = 7\n\
+                    patience = 3\n\
+                    [output]\n\
+                    model = 'small.txt'\n";
+        let config = ExperimentConfig::from_toml(text).unwrap();
+
+        assert_eq!(config.data.train, "small.csv");
+        assert_eq!(config.data.test, Some("small_test.csv".to_owned()));
+        assert_eq!(config.data.validation_split, 0.2);
+        assert_eq!(config.preprocessing.scaler, ScalerConfig::Standard);
+        assert_eq!(config.preprocessing.target_on, 1.0);
+        assert_eq!(config.network.layers, vec![4, 8, 3]);
+        assert_eq!(config.network.activation, "sigmoid");
+        assert_eq!((config.network.input_dropout, config.network.hidden_dropout), (0.0, 0.5));
+        assert_eq!(config.network.normalization, "batch");
+        assert_eq!(config.optimizer.learning_rate, 0.3);
+        assert_eq!(config.optimizer.batch_size, 16);
+        assert_eq!((config.optimizer.l1, config.optimizer.l2), (0.0, 0.001));
+        assert_eq!(
+            config.schedule,
+            LearningRateSchedule::Step {
+                every: 2,
+                factor: 0.5
+            }
+        );
+        assert_eq!(config.training.epochs, 20);
+        assert_eq!(config.training.seed, 7);
+        assert_eq!(config.training.patience, Some(3));
+        assert_eq!(config.model, "small.txt");
+    }
+
+    #[test]
+    fn test_preprocessing() {
+        let config = ExperimentConfig::from_toml("[preprocessing]\ntarget_off = 0.01").unwrap();
+        assert_eq!(config.preprocessing.scaler, ExperimentConfig::default().preprocessing.scaler);
+        assert_eq!(config.preprocessing.target_off, 0.01);
+
+        let config = ExperimentConfig::from_toml("[preprocessing]\nfit = true").unwrap();
+        assert_eq!(
+            config.preprocessing.scaler,
+            ScalerConfig::MinMax {
+                input_range: None,
+                output_min: 0.01,
+                output_max: 1.0
+            }
+        );
+        assert!(ExperimentConfig::from_toml("[preprocessing]\nfit = true\ninput_max = 9").is_err());
+    }
+
+    #[test]
+    fn test_resolved_round_trip() {
+        let mut config = ExperimentConfig::default();
+        config.data.test = Some("test.csv".to_owned());
+        config.preprocessing.scaler = ScalerConfig::MinMax {
+            input_range: None,
+            output_min: 0.0,
+            output_max: 1.0,
+        };
+        config.optimizer.learning_rate = 0.1 + 0.2;
+        config.optimizer.l1 = 1e-5;
+        config.optimizer.weight_decay = 0.01;
+        config.network.input_dropout = 0.2;
+        config.network.normalization = "layer".to_owned();
+        config.schedule = LearningRateSchedule::Cosine {
+            epochs: 10,
+            minimum: 0.001,
+        };
+        config.training.patience = Some(2);
+        config.training.checkpoint = Some("checkpoint.txt".to_owned());
+        config.training.log = Some("log.csv".to_owned());
+
+        assert_eq!(ExperimentConfig::from_toml(&config.to_toml()).unwrap(), config);
+        let defaults = ExperimentConfig::default();
+        assert_eq!(ExperimentConfig::from_toml(&defaults.to_toml()).unwrap(), defaults);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let message = |text: &str| ExperimentConfig::from_toml(text).unwrap_err().to_string();
+
+        assert_eq!(
+            message("[network]\nlayers = [4, 3]"),
+            "invalid config: layers has to be the input, hidden and output nodes but is [4, 3]"
+        );
+        assert_eq!(
+            message("[network]\nactivation = 'tanh'"),
+            "invalid config: unknown activation 'tanh' - expected one of sigmoid, relu"
+        );
+        assert_eq!(
+            message("[network]\nnormalization = 'group'"),
+            "invalid config: unknown normalization 'group' - expected one of none, batch, layer"
+        );
+        assert_eq!(
+            message("[schedule]\ntype = 'step'\nfactor = 0.5"),
+            "invalid config: the step schedule needs every"
+        );
+        assert_eq!(
+            message("[preprocessing]\nscaler = 'none'\noutput_min = 0"),
+            "invalid config: ranges only apply to the min_max scaler, not to 'none'"
+        );
+        assert_eq!(
+            message("[training]\nepoch = 3"),
+            "invalid config in line 2: unknown key 'epoch' in [training]"
+        );
+        assert_eq!(message("[model]"), "invalid config in line 1: unknown table [model]");
+        assert_eq!(
+            message("[network]\nhidden_dropout = 1"),
+            "invalid config: input_dropout and hidden_dropout have to be in [0, 1)"
+        );
+        assert_eq!(
+            message("[optimizer]\nweight_decay = -0.1"),
+            "invalid config: l1, l2 and weight_decay cannot be negative"
+        );
+        assert_eq!(
+            message("[data]\nvalidation_split = 1.5"),
+            "invalid config: validation_split has to be in [0, 1)"
+        );
+
+        let mut config = ExperimentConfig::default();
+        config.training.seed = i64::MAX as u64 + 1;
+        assert_eq!(
+            config.validate().unwrap_err().to_string(),
+            "invalid config: seed can be at most 9223372036854775807"
+        );
+    }
+
+    #[test]
+    fn test_config_path() {
+        assert_eq!(config_path_for("out/model.txt"), PathBuf::from("out/model.config.toml"));
+    }
+}
@@ -0,0 +1,494 @@
+//! The part of TOML experiment files need: `[table]` headers and
+//! `key = value` lines with strings, numbers, booleans and single line
+//! arrays. Everything after a `#` outside of a string is a comment.
+
+use super::ConfigError;
+use std::fmt::Write;
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// How the value is written in a TOML file. Floats use `{}` so that they
+    /// are read back exactly.
+    pub fn to_toml(&self) -> String {
+        match *self {
+            Value::String(ref value) => {
+                let mut quoted = String::from("\"");
+                for c in value.chars() {
+                    match c {
+                        '"' => quoted.push_str("\\\""),
+                        '\\' => quoted.push_str("\\\\"),
+                        '\n' => quoted.push_str("\\n"),
+                        '\t' => quoted.push_str("\\t"),
+                        c => quoted.push(c),
+                    }
+                }
+                quoted.push('"');
+                quoted
+            }
+            Value::Integer(value) => format!("{}", value),
+            Value::Float(value) if value.fract() == 0.0 && value.is_finite() => {
+                format!("{:.1}", value)
+            }
+            Value::Float(value) => format!("{}", value),
+            Value::Boolean(value) => format!("{}", value),
+            Value::Array(ref values) => {
+                let mut array = String::from("[");
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        array.push_str(", ");
+                    }
+                    write!(array, "{}", value.to_toml()).unwrap();
+                }
+                array.push(']');
+                array
+            }
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match *self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Float(_) => "a number",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+/// A key with its value and the line it was defined in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+/// A `[name]` table - the keys before the first header are in the table "".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub name: String,
+    pub line: usize,
+    entries: Vec<Entry>,
+}
+
+impl Table {
+    /// Takes the entry out so that `finish` knows it was used.
+    pub fn take(&mut self, key: &str) -> Option<Entry> {
+        let position = self.entries.iter().position(|entry| entry.key == key)?;
+        Some(self.entries.remove(position))
+    }
+
+    pub fn string(&mut self, key: &str) -> Result<Option<String>, ConfigError> {
+        self.typed(key, |value| match *value {
+            Value::String(ref value) => Some(value.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn float(&mut self, key: &str) -> Result<Option<f64>, ConfigError> {
+        self.typed(key, |value| match *value {
+            Value::Float(value) => Some(value),
+            Value::Integer(value) => Some(value as f64),
+            _ => None,
+        })
+    }
+
+    /// A negative integer has the right type but still fails.
+    pub fn unsigned(&mut self, key: &str) -> Result<Option<u64>, ConfigError> {
+        let line = self.line_of(key);
+        let value = self.typed(key, |value| match *value {
+            Value::Integer(value) => Some(value),
+            _ => None,
+        })?;
+        match value {
+            Some(value) if value < 0 => Err(self.negative(key, line)),
+            value => Ok(value.map(|value| value as u64)),
+        }
+    }
+
+    pub fn boolean(&mut self, key: &str) -> Result<Option<bool>, ConfigError> {
+        self.typed(key, |value| match *value {
+            Value::Boolean(value) => Some(value),
+            _ => None,
+        })
+    }
+
+    pub fn unsigned_array(&mut self, key: &str) -> Result<Option<Vec<u64>>, ConfigError> {
+        let line = self.line_of(key);
+        let values = self.typed(key, |value| match *value {
+            Value::Array(ref values) => values
+                .iter()
+                .map(|value| match *value {
+                    Value::Integer(value) => Some(value),
+                    _ => None,
+                })
+                .collect::<Option<Vec<i64>>>(),
+            _ => None,
+        })?;
+        match values {
+            Some(ref values) if values.iter().any(|&value| value < 0) => {
+                Err(self.negative(key, line))
+            }
+            values => Ok(values.map(|values| {
+                values.into_iter().map(|value| value as u64).collect()
+            })),
+        }
+    }
+
+    /// Fails for every entry that was not taken - most likely a typo.
+    pub fn finish(self) -> Result<(), ConfigError> {
+        match self.entries.first() {
+            Some(entry) => Err(ConfigError::Syntax {
+                line: entry.line,
+                message: format!("unknown key '{}'{}", entry.key, self.describe()),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn typed<V, F>(&mut self, key: &str, convert: F) -> Result<Option<V>, ConfigError>
+    where
+        F: FnOnce(&Value) -> Option<V>,
+    {
+        let entry = match self.take(key) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        match convert(&entry.value) {
+            Some(value) => Ok(Some(value)),
+            None => Err(ConfigError::Syntax {
+                line: entry.line,
+                message: format!(
+                    "unexpected value for '{}'{}: {} is {}",
+                    key,
+                    self.describe(),
+                    entry.value.to_toml(),
+                    entry.value.type_name()
+                ),
+            }),
+        }
+    }
+
+    fn line_of(&self, key: &str) -> usize {
+        self.entries
+            .iter()
+            .find(|entry| entry.key == key)
+            .map_or(self.line, |entry| entry.line)
+    }
+
+    fn negative(&self, key: &str, line: usize) -> ConfigError {
+        ConfigError::Syntax {
+            line,
+            message: format!("'{}'{} must not be negative", key, self.describe()),
+        }
+    }
+
+    fn describe(&self) -> String {
+        if self.name.is_empty() {
+            String::new()
+        } else {
+            format!(" in [{}]", self.name)
+        }
+    }
+}
+
+/// All tables of a file in the order they appear.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    tables: Vec<Table>,
+}
+
+impl Document {
+    /// Takes the table out so that `finish` knows it was used.
+    pub fn take(&mut self, name: &str) -> Option<Table> {
+        let position = self.tables.iter().position(|table| table.name == name)?;
+        Some(self.tables.remove(position))
+    }
+
+    /// Fails for every table that was not taken.
+    pub fn finish(self) -> Result<(), ConfigError> {
+        for table in self.tables {
+            if table.name.is_empty() {
+                table.finish()?;
+            } else {
+                return Err(ConfigError::Syntax {
+                    line: table.line,
+                    message: format!("unknown table [{}]", table.name),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse(text: &str) -> Result<Document, ConfigError> {
+    let mut tables = vec![Table {
+        name: String::new(),
+        line: 0,
+        entries: Vec::new(),
+    }];
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| ConfigError::Syntax {
+            line: line_number,
+            message,
+        };
+        let mut chars = line.chars().peekable();
+        skip_whitespace(&mut chars);
+
+        match chars.peek() {
+            None | Some('#') => continue,
+            Some('[') => {
+                chars.next();
+                let name: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                let name = name.trim();
+                if !is_bare_key(name) {
+                    return Err(error(format!("invalid table name '{}'", name)));
+                }
+                if tables.iter().any(|table| table.name == name) {
+                    return Err(error(format!("table [{}] is defined twice", name)));
+                }
+                expect_end(&mut chars).map_err(error)?;
+                tables.push(Table {
+                    name: name.to_owned(),
+                    line: line_number,
+                    entries: Vec::new(),
+                });
+            }
+            Some(_) => {
+                let key: String = chars.by_ref().take_while(|c| *c != '=').collect();
+                let key = key.trim();
+                if !is_bare_key(key) {
+                    return Err(error(format!("expected 'key = value' but found '{}'", line)));
+                }
+                let value = parse_value(&mut chars).map_err(&error)?;
+                expect_end(&mut chars).map_err(&error)?;
+
+                let table = tables.last_mut().unwrap();
+                if table.entries.iter().any(|entry| entry.key == key) {
+                    return Err(error(format!("key '{}' is defined twice", key)));
+                }
+                table.entries.push(Entry {
+                    key: key.to_owned(),
+                    value,
+                    line: line_number,
+                });
+            }
+        }
+    }
+    Ok(Document { tables })
+}
+
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_whitespace()) {
+        chars.next();
+    }
+}
+
+/// Only whitespace and a comment may follow.
+fn expect_end(chars: &mut Peekable<Chars>) -> Result<(), String> {
+    skip_whitespace(chars);
+    match chars.next() {
+        None | Some('#') => Ok(()),
+        Some(c) => Err(format!("unexpected '{}'", c)),
+    }
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Value, String> {
+    skip_whitespace(chars);
+    match chars.peek() {
+        None => Err("missing value".to_owned()),
+        Some('"') => {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    None => return Err("unterminated string".to_owned()),
+                    Some('"') => return Ok(Value::String(value)),
+                    Some('\\') => value.push(match chars.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        other => return Err(format!("unsupported escape {:?}", other)),
+                    }),
+                    Some(c) => value.push(c),
+                }
+            }
+        }
+        Some('\'') => {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    None => return Err("unterminated string".to_owned()),
+                    Some('\'') => return Ok(Value::String(value)),
+                    Some(c) => value.push(c),
+                }
+            }
+        }
+        Some('[') => {
+            chars.next();
+            let mut values = Vec::new();
+            loop {
+                skip_whitespace(chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                    return Ok(Value::Array(values));
+                }
+                values.push(parse_value(chars)?);
+                skip_whitespace(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => return Ok(Value::Array(values)),
+                    _ => return Err("expected ',' or ']' in array".to_owned()),
+                }
+            }
+        }
+        Some(_) => {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == ',' || c == ']' || c == '#' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            let number = token.replace('_', "");
+            match token.as_str() {
+                "true" => Ok(Value::Boolean(true)),
+                "false" => Ok(Value::Boolean(false)),
+                "inf" | "+inf" => Ok(Value::Float(f64::INFINITY)),
+                "-inf" => Ok(Value::Float(f64::NEG_INFINITY)),
+                _ => match number.parse::<i64>() {
+                    Ok(value) => Ok(Value::Integer(value)),
+                    Err(_) => match number.parse::<f64>() {
+                        Ok(value) if value.is_finite() => Ok(Value::Float(value)),
+                        _ => Err(format!("invalid value '{}'", token)),
+                    },
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod toml_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let text = "# experiment\n\
+                    name = 'first' # comment\n\
+                    \n\
+                    [network]\n\
+                    layers = [784, 200, 10]\n\
+                    rate = 0.1\n\
+                    big = 1_000\n\
+                    quoted = \"a \\\"b\\\" # c\"\n\
+                    enabled = true\n";
+        let mut document = parse(text).unwrap();
+
+        let mut root = document.take("").unwrap();
+        assert_eq!(root.string("name").unwrap(), Some("first".to_owned()));
+        root.finish().unwrap();
+
+        let mut network = document.take("network").unwrap();
+        assert_eq!(network.unsigned_array("layers").unwrap(), Some(vec![784, 200, 10]));
+        assert_eq!(network.float("rate").unwrap(), Some(0.1));
+        assert_eq!(network.unsigned("big").unwrap(), Some(1000));
+        assert_eq!(network.string("quoted").unwrap(), Some("a \"b\" # c".to_owned()));
+        assert_eq!(network.boolean("enabled").unwrap(), Some(true));
+        assert_eq!(network.boolean("missing").unwrap(), None);
+        network.finish().unwrap();
+        document.finish().unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let values = [
+            Value::String("tab\tquote\"".to_owned()),
+            Value::Integer(-3),
+            Value::Float(0.1 + 0.2),
+            Value::Float(2.0),
+            Value::Boolean(false),
+            Value::Array(vec![Value::Integer(1), Value::String("x".to_owned())]),
+        ];
+        for value in &values {
+            let mut document = parse(&format!("key = {}", value.to_toml())).unwrap();
+            assert_eq!(document.take("").unwrap().take("key").unwrap().value, *value);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let message = |text: &str| match parse(text) {
+            Err(ConfigError::Syntax { line, message }) => format!("{}: {}", line, message),
+            other => panic!("unexpected result {:?}", other),
+        };
+
+        assert_eq!(message("a = 1\na = 2"), "2: key 'a' is defined twice");
+        assert_eq!(message("[a]\n[a]"), "2: table [a] is defined twice");
+        assert_eq!(message("a = \"open"), "1: unterminated string");
+        assert_eq!(message("a = 1 2"), "1: unexpected '2'");
+        assert_eq!(message("a = [1 2]"), "1: expected ',' or ']' in array");
+        assert_eq!(message("a = yes"), "1: invalid value 'yes'");
+        assert_eq!(message("just text"), "1: expected 'key = value' but found 'just text'");
+    }
+
+    #[test]
+    fn test_unused_and_mistyped() {
+        let mut document = parse("[a]\nx = 'text'\ny = 1\n[b]").unwrap();
+        let mut a = document.take("a").unwrap();
+        match a.float("x") {
+            Err(ConfigError::Syntax { line: 2, message }) => {
+                assert_eq!(message, "unexpected value for 'x' in [a]: \"text\" is a string")
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        let mut b = parse("[b]\nseed = -1\nlayers = [4, -3]").unwrap().take("b").unwrap();
+        match b.unsigned("seed") {
+            Err(ConfigError::Syntax { line: 2, message }) => {
+                assert_eq!(message, "'seed' in [b] must not be negative")
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        match b.unsigned_array("layers") {
+            Err(ConfigError::Syntax { line: 3, message }) => {
+                assert_eq!(message, "'layers' in [b] must not be negative")
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        match a.finish() {
+            Err(ConfigError::Syntax { line: 3, message }) => {
+                assert_eq!(message, "unknown key 'y' in [a]")
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        match document.finish() {
+            Err(ConfigError::Syntax { line: 4, message }) => {
+                assert_eq!(message, "unknown table [b]")
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use super::Sample;
+use mnist_data;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+use std::str;
+
+/// What happens with rows that cannot be parsed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MalformedRowPolicy {
+    /// Stop with the first bad row.
+    Abort,
+    /// Remember the error and continue with the next row.
+    Skip,
+}
+
+#[derive(Debug)]
+pub enum CsvErrorKind {
+    Io(io::Error),
+    /// The value is not a number.
+    InvalidNumber(String),
+    /// The first value is not a non negative whole number.
+    InvalidLabel(String),
+    /// The row has not the expected amount of values (label excluded).
+    WrongValueCount { expected: usize, found: usize },
+    /// The row is no valid UTF-8 text.
+    InvalidUtf8,
+}
+
+/// A bad record. Line and column are counted from 1.
+#[derive(Debug)]
+pub struct CsvError {
+    pub line: usize,
+    /// None if the whole line is affected.
+    pub column: Option<usize>,
+    pub kind: CsvErrorKind,
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.column {
+            Some(column) => write!(f, "line {}, column {}: ", self.line, column)?,
+            None => write!(f, "line {}: ", self.line)?,
+        }
+        match self.kind {
+            CsvErrorKind::Io(ref e) => write!(f, "could not be read: {}", e),
+            CsvErrorKind::InvalidNumber(ref value) => write!(f, "'{}' is not a number", value),
+            CsvErrorKind::InvalidLabel(ref value) => write!(f, "'{}' is not a valid label", value),
+            CsvErrorKind::WrongValueCount { expected, found } => {
+                write!(f, "{} values expected but found {}", expected, found)
+            }
+            CsvErrorKind::InvalidUtf8 => write!(f, "is no valid UTF-8 text"),
+        }
+    }
+}
+
+impl error::Error for CsvError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match self.kind {
+            CsvErrorKind::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Streams MNIST like records (label followed by grey values) from a CSV source.
+///
+/// Every record is returned as soon as its line was read, so the file never
+/// needs to fit into memory. Empty lines are ignored.
+pub struct CsvLoader<R: BufRead> {
+    reader: R,
+    policy: MalformedRowPolicy,
+    expected_values: Option<usize>,
+    is_raw: bool,
+    line_number: usize,
+    line: Vec<u8>,
+    skipped: Vec<CsvError>,
+    is_aborted: bool,
+}
+
+impl CsvLoader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<CsvLoader<BufReader<File>>> {
+        Ok(CsvLoader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> CsvLoader<R> {
+    /// Creates a loader that aborts on the first malformed row.
+    pub fn new(reader: R) -> CsvLoader<R> {
+        CsvLoader {
+            reader,
+            policy: MalformedRowPolicy::Abort,
+            expected_values: None,
+            is_raw: false,
+            line_number: 0,
+            line: Vec::new(),
+            skipped: Vec::new(),
+            is_aborted: false,
+        }
+    }
+
+    pub fn on_malformed(mut self, policy: MalformedRowPolicy) -> CsvLoader<R> {
+        self.policy = policy;
+        self
+    }
+
+    /// Rows with another amount of values (label excluded) are malformed.
+    pub fn expect_values(mut self, count: usize) -> CsvLoader<R> {
+        self.expected_values = Some(count);
+        self
+    }
+
+    /// Returns the values as they are written in the file instead of
+    /// normalising them - e.g. to fit a scaler of the `preprocessing` module.
+    pub fn raw(mut self) -> CsvLoader<R> {
+        self.is_raw = true;
+        self
+    }
+
+    /// Errors of all rows skipped so far.
+    pub fn skipped(&self) -> &[CsvError] {
+        &self.skipped
+    }
+
+    fn read_record(&mut self) -> Option<Result<Sample, CsvError>> {
+        loop {
+            self.line.clear();
+            self.line_number += 1;
+
+            // read as bytes, so that a row with invalid UTF-8 is just malformed
+            match self.reader.read_until(b'\n', &mut self.line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => {
+                    return Some(Err(CsvError {
+                        line: self.line_number,
+                        column: None,
+                        kind: CsvErrorKind::Io(e),
+                    }))
+                }
+            }
+            let line = match str::from_utf8(&self.line) {
+                Ok(line) => line,
+                Err(_) => {
+                    return Some(Err(CsvError {
+                        line: self.line_number,
+                        column: None,
+                        kind: CsvErrorKind::InvalidUtf8,
+                    }))
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let record = if self.is_raw {
+                parse_record(line, self.line_number)
+            } else {
+                parse_mnist_record(line, self.line_number)
+            };
+            return Some(match (record, self.expected_values) {
+                (Ok((_, ref values)), Some(expected)) if values.len() != expected => {
+                    Err(CsvError {
+                        line: self.line_number,
+                        column: None,
+                        kind: CsvErrorKind::WrongValueCount {
+                            expected,
+                            found: values.len(),
+                        },
+                    })
+                }
+                (record, _) => record,
+            });
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for CsvLoader<R> {
+    type Item = Result<Sample, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.is_aborted {
+            return None;
+        }
+
+        loop {
+            match self.read_record()? {
+                Ok(record) => return Some(Ok(record)),
+                Err(e) => {
+                    let is_io_error = matches!(e.kind, CsvErrorKind::Io(_));
+                    if self.policy == MalformedRowPolicy::Abort || is_io_error {
+                        self.is_aborted = true;
+                        return Some(Err(e));
+                    }
+                    self.skipped.push(e);
+                }
+            }
+        }
+    }
+}
+
+/// Parses one line of the form `label,value,value,...`. The values get
+/// normalised with `mnist_data::normalise_pixel`.
+pub fn parse_mnist_record(line: &str, line_number: usize) -> Result<Sample, CsvError> {
+    let (label, values) = parse_record(line, line_number)?;
+    Ok((label, values.into_iter().map(mnist_data::normalise_pixel).collect()))
+}
+
+/// Parses one line of the form `label,value,value,...` without touching the values.
+pub fn parse_record(line: &str, line_number: usize) -> Result<Sample, CsvError> {
+    let mut label = None;
+    let mut values = Vec::new();
+
+    for (index, field) in line.trim().split(',').enumerate() {
+        let field = field.trim();
+        let error = |kind| {
+            Err(CsvError {
+                line: line_number,
+                column: Some(index + 1),
+                kind,
+            })
+        };
+
+        if index == 0 {
+            match field.parse::<usize>() {
+                Ok(v) => label = Some(v),
+                Err(_) => return error(CsvErrorKind::InvalidLabel(field.to_owned())),
+            }
+        } else {
+            match field.parse::<f64>() {
+                Ok(v) if v.is_finite() => values.push(v),
+                _ => return error(CsvErrorKind::InvalidNumber(field.to_owned())),
+            }
+        }
+    }
+
+    match label {
+        Some(label) => Ok((label, values)),
+        None => Err(CsvError {
+            line: line_number,
+            column: Some(1),
+            kind: CsvErrorKind::InvalidLabel(String::new()),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod csv_tests {
+    use super::*;
+
+    #[test]
+    fn test_load_bundled_samples() {
+        let loader = CsvLoader::open("mnist/mnist_test_10.csv").unwrap().expect_values(784);
+        let records: Vec<Sample> = loader.map(|r| r.unwrap()).collect();
+
+        assert_eq!(records.len(), 10);
+        assert_eq!(records[0].0, 7);
+        assert_eq!(records[0].1.len(), 784);
+    }
+
+    #[test]
+    fn test_raw() {
+        let mut loader = CsvLoader::new("3,255,0\n".as_bytes()).raw();
+        assert_eq!(loader.next().unwrap().unwrap(), (3, vec![255.0, 0.0]));
+    }
+
+    #[test]
+    fn test_abort() {
+        let source = "1,255,16\n\n2,1,x\n3,0,0\n";
+        let mut loader = CsvLoader::new(source.as_bytes());
+
+        assert_eq!(loader.next().unwrap().unwrap().0, 1);
+        let error = loader.next().unwrap().unwrap_err();
+        assert_eq!(error.line, 3);
+        assert_eq!(error.column, Some(3));
+        assert_eq!(error.to_string(), "line 3, column 3: 'x' is not a number");
+        assert!(loader.next().is_none());
+    }
+
+    #[test]
+    fn test_skip() {
+        let source = "1,255,16\n-2,1,1\n2,1\n3,0,0";
+        let mut loader = CsvLoader::new(source.as_bytes())
+            .on_malformed(MalformedRowPolicy::Skip)
+            .expect_values(2);
+
+        let labels: Vec<usize> = loader.by_ref().map(|r| r.unwrap().0).collect();
+        assert_eq!(labels, vec![1, 3]);
+
+        let skipped = loader.skipped();
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped[0].line, 2);
+        assert_eq!(skipped[0].column, Some(1));
+        assert_eq!(skipped[1].to_string(), "line 3: 2 values expected but found 1");
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let source = b"1,255,16\n2,\xff,1\n3,0,0\n";
+        let mut loader = CsvLoader::new(&source[..]).on_malformed(MalformedRowPolicy::Skip);
+
+        let labels: Vec<usize> = loader.by_ref().map(|r| r.unwrap().0).collect();
+        assert_eq!(labels, vec![1, 3]);
+        assert_eq!(loader.skipped()[0].to_string(), "line 2: is no valid UTF-8 text");
+
+        let mut loader = CsvLoader::new(&source[..]);
+        loader.next().unwrap().unwrap();
+        assert!(loader.next().unwrap().is_err());
+        assert!(loader.next().is_none());
+    }
+
+    #[test]
+    fn test_parse_mnist_record() {
+        let (label, values) = parse_mnist_record("1, 255 ,0\r\n", 1).unwrap();
+        assert_eq!(label, 1);
+        assert_eq!(values, vec![1.0, 0.01]);
+
+        assert_eq!(parse_record("1, 255 ,0", 1).unwrap().1, vec![255.0, 0.0]);
+        assert!(parse_mnist_record("1,inf", 1).is_err());
+        assert!(parse_mnist_record("1.5,0", 1).is_err());
+    }
+}
@@ -0,0 +1,484 @@
+pub mod csv;
+
+use matrix::Matrix;
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use util::SeededRng;
+
+/// A labeled sample: the awaited class and the input values.
+pub type Sample = (usize, Vec<f64>);
+
+/// A sample with continuous targets: the input values and the awaited outputs.
+pub type RegressionSample = (Vec<f64>, Vec<f64>);
+
+/// Everything the combinators of a `Dataset` refuse to do.
+#[derive(Debug, PartialEq)]
+pub enum DatasetError {
+    /// The shares of validation and test part are negative or exceed 1.
+    InvalidSplit { validation: f64, test: f64 },
+    /// A batch needs at least one sample.
+    InvalidBatchSize,
+    /// The sample has another amount of values than the ones before.
+    RaggedSample {
+        index: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DatasetError::InvalidSplit { validation, test } => write!(
+                f,
+                "invalid split: validation {} and test {} have to be positive and sum up to at \
+                 most 1",
+                validation, test
+            ),
+            DatasetError::InvalidBatchSize => write!(f, "batch size has to be at least 1"),
+            DatasetError::RaggedSample {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "sample {} has {} values instead of {}",
+                index, found, expected
+            ),
+        }
+    }
+}
+
+impl error::Error for DatasetError {}
+
+/// Random access to labeled samples.
+///
+/// The combinators do not copy any samples - they only remember which
+/// indices of the underlying dataset they show.
+pub trait Dataset {
+    fn len(&self) -> usize;
+
+    /// Label and input values of the sample at `index`.
+    fn get(&self, index: usize) -> Option<(usize, &[f64])>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn iter(&self) -> DatasetIter<'_, Self> {
+        DatasetIter {
+            dataset: self,
+            index: 0,
+        }
+    }
+
+    /// All samples in a random but reproducible order.
+    fn shuffle(&self, seed: u64) -> Subset<&Self>
+    where
+        Self: Sized,
+    {
+        let mut indices: Vec<usize> = (0..self.len()).collect();
+        SeededRng::new(seed).shuffle(&mut indices);
+        Subset::new(self, indices)
+    }
+
+    /// Groups the samples into matrices with one sample per row. The last
+    /// batch is smaller if the samples do not divide evenly.
+    fn batches(&self, batch_size: usize) -> Result<Batches<'_, Self>, DatasetError>
+    where
+        Self: Sized,
+    {
+        if batch_size == 0 {
+            return Err(DatasetError::InvalidBatchSize);
+        }
+        Ok(Batches {
+            dataset: self,
+            batch_size,
+            index: 0,
+            values: None,
+        })
+    }
+
+    /// Shuffles the samples and splits them into train, validation and test
+    /// part. The fractions are the shares of validation and test part.
+    fn split(
+        &self,
+        validation: f64,
+        test: f64,
+        seed: u64,
+    ) -> Result<DatasetSplit<Subset<&Self>>, DatasetError>
+    where
+        Self: Sized,
+    {
+        check_fractions(validation, test)?;
+        let mut indices: Vec<usize> = (0..self.len()).collect();
+        SeededRng::new(seed).shuffle(&mut indices);
+
+        let (train, validation, test) = split_indices(&indices, validation, test);
+        Ok(DatasetSplit {
+            train: Subset::new(self, train),
+            validation: Subset::new(self, validation),
+            test: Subset::new(self, test),
+        })
+    }
+
+    /// Like `split` but every label keeps its share in all three parts.
+    fn stratified_split(
+        &self,
+        validation: f64,
+        test: f64,
+        seed: u64,
+    ) -> Result<DatasetSplit<Subset<&Self>>, DatasetError>
+    where
+        Self: Sized,
+    {
+        check_fractions(validation, test)?;
+        let mut rng = SeededRng::new(seed);
+
+        let mut by_label: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for index in 0..self.len() {
+            if let Some((label, _)) = self.get(index) {
+                by_label.entry(label).or_default().push(index);
+            }
+        }
+
+        let (mut train_part, mut validation_part, mut test_part) = (vec![], vec![], vec![]);
+        for indices in by_label.values_mut() {
+            rng.shuffle(indices);
+            let (train, validation, test) = split_indices(indices, validation, test);
+            train_part.extend(train);
+            validation_part.extend(validation);
+            test_part.extend(test);
+        }
+        rng.shuffle(&mut train_part);
+        rng.shuffle(&mut validation_part);
+        rng.shuffle(&mut test_part);
+
+        Ok(DatasetSplit {
+            train: Subset::new(self, train_part),
+            validation: Subset::new(self, validation_part),
+            test: Subset::new(self, test_part),
+        })
+    }
+
+    /// The samples of this dataset followed by the samples of `other`.
+    fn concat<D: Dataset>(self, other: D) -> Concat<Self, D>
+    where
+        Self: Sized,
+    {
+        Concat {
+            first: self,
+            second: other,
+        }
+    }
+}
+
+impl<D: Dataset + ?Sized> Dataset for &D {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn get(&self, index: usize) -> Option<(usize, &[f64])> {
+        (**self).get(index)
+    }
+}
+
+impl Dataset for [Sample] {
+    fn len(&self) -> usize {
+        <[Sample]>::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<(usize, &[f64])> {
+        <[Sample]>::get(self, index).map(|&(label, ref values)| (label, &values[..]))
+    }
+}
+
+impl Dataset for Vec<Sample> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn get(&self, index: usize) -> Option<(usize, &[f64])> {
+        Dataset::get(&self[..], index)
+    }
+}
+
+/// Iterates over all samples of a dataset.
+pub struct DatasetIter<'a, D: 'a + ?Sized> {
+    dataset: &'a D,
+    index: usize,
+}
+
+impl<'a, D: Dataset + ?Sized> Iterator for DatasetIter<'a, D> {
+    type Item = (usize, &'a [f64]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.dataset.get(self.index)?;
+        self.index += 1;
+        Some(sample)
+    }
+}
+
+/// Some samples of another dataset in a given order.
+pub struct Subset<D> {
+    dataset: D,
+    indices: Vec<usize>,
+}
+
+impl<D: Dataset> Subset<D> {
+    pub fn new(dataset: D, indices: Vec<usize>) -> Subset<D> {
+        Subset { dataset, indices }
+    }
+
+    /// Positions of the shown samples in the underlying dataset.
+    pub fn indices(&self) -> &[usize] {
+        &self.indices
+    }
+}
+
+impl<D: Dataset> Dataset for Subset<D> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(usize, &[f64])> {
+        self.dataset.get(*self.indices.get(index)?)
+    }
+}
+
+/// Two datasets after each other.
+pub struct Concat<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: Dataset, B: Dataset> Dataset for Concat<A, B> {
+    fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(usize, &[f64])> {
+        if index < self.first.len() {
+            self.first.get(index)
+        } else {
+            self.second.get(index - self.first.len())
+        }
+    }
+}
+
+pub struct DatasetSplit<D> {
+    pub train: D,
+    pub validation: D,
+    pub test: D,
+}
+
+/// Some samples as one matrix - one sample per row.
+pub struct Batch {
+    pub labels: Vec<usize>,
+    pub inputs: Matrix,
+}
+
+/// Iterates over the batches of a dataset. A sample with another amount of
+/// values than the first one ends the iteration with an error.
+pub struct Batches<'a, D: 'a> {
+    dataset: &'a D,
+    batch_size: usize,
+    index: usize,
+    values: Option<usize>,
+}
+
+impl<'a, D: Dataset> Iterator for Batches<'a, D> {
+    type Item = Result<Batch, DatasetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let end = self.dataset.len().min(self.index + self.batch_size);
+        if self.index >= end {
+            return None;
+        }
+
+        let mut labels = Vec::with_capacity(end - self.index);
+        let mut rows = Vec::with_capacity(end - self.index);
+        for index in self.index..end {
+            if let Some((label, values)) = self.dataset.get(index) {
+                let expected = *self.values.get_or_insert(values.len());
+                if values.len() != expected {
+                    self.index = self.dataset.len();
+                    return Some(Err(DatasetError::RaggedSample {
+                        index,
+                        expected,
+                        found: values.len(),
+                    }));
+                }
+                labels.push(label);
+                rows.push(values.to_vec());
+            }
+        }
+        self.index = end;
+
+        Some(Ok(Batch {
+            labels,
+            inputs: Matrix::from_2d_vec(&rows),
+        }))
+    }
+}
+
+fn check_fractions(validation: f64, test: f64) -> Result<(), DatasetError> {
+    // written so that NaN fails as well
+    if !(validation >= 0.0 && test >= 0.0 && validation + test <= 1.0) {
+        return Err(DatasetError::InvalidSplit { validation, test });
+    }
+    Ok(())
+}
+
+fn split_indices(
+    indices: &[usize],
+    validation: f64,
+    test: f64,
+) -> (Vec<usize>, Vec<usize>, Vec<usize>) {
+    let len = indices.len() as f64;
+    let test_count = (len * test).round() as usize;
+    let validation_count = ((len * validation).round() as usize).min(indices.len() - test_count);
+    let train_count = indices.len() - test_count - validation_count;
+
+    (
+        indices[..train_count].to_vec(),
+        indices[train_count..train_count + validation_count].to_vec(),
+        indices[train_count + validation_count..].to_vec(),
+    )
+}
+
+#[cfg(test)]
+mod dataset_tests {
+    use super::*;
+    use mnist_data::MnistDataset;
+
+    fn samples(count: usize) -> Vec<Sample> {
+        (0..count).map(|x| (x % 2, vec![x as f64, 1.0])).collect()
+    }
+
+    #[test]
+    fn test_vec_dataset() {
+        let data = samples(3);
+        assert_eq!(Dataset::len(&data), 3);
+        assert_eq!(Dataset::get(&data, 1), Some((1, &[1.0, 1.0][..])));
+        assert_eq!(Dataset::get(&data, 3), None);
+        assert_eq!(Dataset::iter(&data).count(), 3);
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let data = samples(20);
+        let shuffled = data.shuffle(7);
+        let again = data.shuffle(7);
+
+        assert_eq!(shuffled.len(), 20);
+        assert_eq!(shuffled.indices(), again.indices());
+        assert_ne!(shuffled.indices(), data.shuffle(8).indices());
+
+        let mut indices = shuffled.indices().to_vec();
+        indices.sort();
+        assert_eq!(indices, (0..20).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_batches() {
+        let data = samples(5);
+        let batches: Vec<Batch> = data.batches(2).unwrap().map(Result::unwrap).collect();
+
+        assert_eq!(batches.len(), 3);
+        assert_eq!(batches[0].labels, vec![0, 1]);
+        assert_eq!(batches[0].inputs.rows(), 2);
+        assert_eq!(batches[0].inputs.columns(), 2);
+        assert_eq!(batches[1].inputs.data_container()[1], vec![3.0, 1.0]);
+        assert_eq!(batches[2].labels, vec![0]);
+        assert_eq!(
+            data.batches(0).err(),
+            Some(DatasetError::InvalidBatchSize)
+        );
+    }
+
+    #[test]
+    fn test_ragged_batches() {
+        let mut data = samples(5);
+        data[3].1.push(2.0);
+        let mut batches = data.batches(2).unwrap();
+
+        assert!(batches.next().unwrap().is_ok());
+        assert_eq!(
+            batches.next().unwrap().err().unwrap().to_string(),
+            "sample 3 has 3 values instead of 2"
+        );
+        assert!(batches.next().is_none());
+    }
+
+    #[test]
+    fn test_split() {
+        let data = samples(10);
+        let split = data.split(0.2, 0.3, 1).unwrap();
+
+        assert_eq!(split.train.len(), 5);
+        assert_eq!(split.validation.len(), 2);
+        assert_eq!(split.test.len(), 3);
+
+        let mut all: Vec<usize> = split.train.indices().to_vec();
+        all.extend(split.validation.indices());
+        all.extend(split.test.indices());
+        all.sort();
+        assert_eq!(all, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_stratified_split() {
+        // 8 samples of label 0 and 4 of label 1
+        let data: Vec<Sample> = (0..12)
+            .map(|x| ((x % 3 == 0) as usize, vec![x as f64]))
+            .collect();
+        let split = data.stratified_split(0.25, 0.25, 3).unwrap();
+
+        let count = |part: &Subset<&Vec<Sample>>, label| {
+            part.iter().filter(|s| s.0 == label).count()
+        };
+        assert_eq!(count(&split.train, 0), 4);
+        assert_eq!(count(&split.train, 1), 2);
+        assert_eq!(count(&split.validation, 0), 2);
+        assert_eq!(count(&split.validation, 1), 1);
+        assert_eq!(count(&split.test, 0), 2);
+        assert_eq!(count(&split.test, 1), 1);
+    }
+
+    #[test]
+    fn test_invalid_split() {
+        let data = samples(4);
+        assert_eq!(
+            data.split(0.6, 0.6, 0).err().unwrap().to_string(),
+            "invalid split: validation 0.6 and test 0.6 have to be positive and sum up to at most 1"
+        );
+        assert!(data.split(-0.1, 0.0, 0).is_err());
+        assert!(data.stratified_split(f64::NAN, 0.0, 0).is_err());
+    }
+
+    #[test]
+    fn test_concat() {
+        let first = samples(2);
+        let second = samples(3);
+        let both = (&first).concat(second.shuffle(0)).concat(&first);
+
+        assert_eq!(both.len(), 7);
+        assert_eq!(both.get(1), Some((1, &[1.0, 1.0][..])));
+        assert_eq!(both.get(6), Some((1, &[1.0, 1.0][..])));
+        assert_eq!(both.get(7), None);
+    }
+
+    #[test]
+    fn test_mnist_samples() {
+        let loader = csv::CsvLoader::open("mnist/mnist_train_100.csv").unwrap();
+        let data = MnistDataset::from_csv(loader).unwrap();
+        let split = data.stratified_split(0.1, 0.1, 0).unwrap();
+
+        assert_eq!(split.train.len() + split.validation.len() + split.test.len(), 100);
+        for batch in split.train.batches(32).unwrap() {
+            assert_eq!(batch.unwrap().inputs.columns(), 784);
+        }
+    }
+}
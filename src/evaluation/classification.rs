@@ -0,0 +1,533 @@
+//! Confusion matrix and the metrics derived from it.
+
+use dataset::Dataset;
+use matrix::math;
+use std::cmp::Ordering;
+use std::fmt;
+use std::io;
+use std::io::prelude::*;
+use super::{json_number, query_dataset};
+use NeuralNetwork;
+
+/// Counts of all pairs of actual and predicted class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfusionMatrix {
+    /// counts[actual][predicted]
+    counts: Vec<Vec<usize>>,
+}
+
+impl ConfusionMatrix {
+    pub fn new(classes: usize) -> ConfusionMatrix {
+        ConfusionMatrix {
+            counts: vec![vec![0; classes]; classes],
+        }
+    }
+
+    /// Panics if a label or prediction is not one of the classes.
+    pub fn from_predictions(
+        labels: &[usize],
+        predictions: &[usize],
+        classes: usize,
+    ) -> ConfusionMatrix {
+        if labels.len() != predictions.len() {
+            panic!(
+                "{} labels but {} predictions",
+                labels.len(),
+                predictions.len()
+            );
+        }
+        let mut confusion = ConfusionMatrix::new(classes);
+        for (actual, predicted) in labels.iter().zip(predictions) {
+            confusion.add(*actual, *predicted);
+        }
+        confusion
+    }
+
+    pub fn add(&mut self, actual: usize, predicted: usize) {
+        let classes = self.classes();
+        match self.counts.get_mut(actual).and_then(|row| row.get_mut(predicted)) {
+            Some(count) => *count += 1,
+            None => panic!(
+                "class {} or {} is not one of the {} classes",
+                actual,
+                predicted,
+                classes
+            ),
+        }
+    }
+
+    pub fn classes(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn count(&self, actual: usize, predicted: usize) -> usize {
+        self.counts[actual][predicted]
+    }
+
+    /// One row per actual class, one column per predicted class.
+    pub fn counts(&self) -> &[Vec<usize>] {
+        &self.counts
+    }
+
+    pub fn total(&self) -> usize {
+        self.counts.iter().map(|row| row.iter().sum::<usize>()).sum()
+    }
+
+    pub fn correct(&self) -> usize {
+        (0..self.classes()).map(|class| self.counts[class][class]).sum()
+    }
+
+    /// Number of samples that actually belong to the class.
+    pub fn support(&self, class: usize) -> usize {
+        self.counts[class].iter().sum()
+    }
+
+    /// Number of samples predicted as the class.
+    pub fn predicted(&self, class: usize) -> usize {
+        self.counts.iter().map(|row| row[class]).sum()
+    }
+
+    /// 0 without any samples.
+    pub fn accuracy(&self) -> f64 {
+        ratio(self.correct(), self.total())
+    }
+
+    /// Share of the predictions of the class that are right. 0 if the
+    /// class was never predicted.
+    pub fn precision(&self, class: usize) -> f64 {
+        ratio(self.counts[class][class], self.predicted(class))
+    }
+
+    /// Share of the samples of the class that were found. 0 if there are no
+    /// samples of the class.
+    pub fn recall(&self, class: usize) -> f64 {
+        ratio(self.counts[class][class], self.support(class))
+    }
+
+    /// Harmonic mean of precision and recall.
+    pub fn f1(&self, class: usize) -> f64 {
+        let (precision, recall) = (self.precision(class), self.recall(class));
+        if precision + recall > 0.0 {
+            2.0 * precision * recall / (precision + recall)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Share of samples whose label is among the `k` highest outputs.
+pub fn top_k_accuracy(outputs: &[Vec<f64>], labels: &[usize], k: usize) -> f64 {
+    let hits = outputs
+        .iter()
+        .zip(labels)
+        .filter(|&(output, &label)| match output.get(label) {
+            Some(score) => output.iter().filter(|other| *other > score).count() < k,
+            None => false,
+        })
+        .count();
+    ratio(hits, labels.len())
+}
+
+/// The `k` classes with the highest outputs together with their output,
+/// the highest first. Equal outputs keep the order of the classes.
+pub fn top_classes(outputs: &[f64], k: usize) -> Vec<(usize, f64)> {
+    let mut classes: Vec<(usize, f64)> = outputs.iter().cloned().enumerate().collect();
+    classes.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    classes.truncate(k);
+    classes
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClassMetrics {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub support: usize,
+}
+
+/// Everything about a classifier in one place - printable with `{}` and
+/// exportable as CSV and JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassificationReport {
+    pub confusion: ConfusionMatrix,
+    pub accuracy: f64,
+    pub per_class: Vec<ClassMetrics>,
+    /// Unweighted mean over the classes.
+    pub macro_average: ClassMetrics,
+    /// Mean over the classes weighted by their support.
+    pub weighted_average: ClassMetrics,
+    /// `(k, accuracy)` - only known if the outputs were given.
+    pub top_k: Vec<(usize, f64)>,
+    /// Samples whose label is none of the classes. They are left out of all
+    /// other metrics.
+    pub unknown_labels: usize,
+}
+
+impl ClassificationReport {
+    pub fn new(confusion: ConfusionMatrix) -> ClassificationReport {
+        let per_class: Vec<ClassMetrics> = (0..confusion.classes())
+            .map(|class| ClassMetrics {
+                precision: confusion.precision(class),
+                recall: confusion.recall(class),
+                f1: confusion.f1(class),
+                support: confusion.support(class),
+            })
+            .collect();
+        let total = confusion.total();
+
+        let average = |weight: &dyn Fn(&ClassMetrics) -> f64| {
+            let weights: f64 = per_class.iter().map(weight).sum();
+            let mean = |value: &dyn Fn(&ClassMetrics) -> f64| {
+                if weights > 0.0 {
+                    per_class.iter().map(|m| weight(m) * value(m)).sum::<f64>() / weights
+                } else {
+                    0.0
+                }
+            };
+            ClassMetrics {
+                precision: mean(&|m| m.precision),
+                recall: mean(&|m| m.recall),
+                f1: mean(&|m| m.f1),
+                support: total,
+            }
+        };
+
+        ClassificationReport {
+            accuracy: confusion.accuracy(),
+            macro_average: average(&|_| 1.0),
+            weighted_average: average(&|m| m.support as f64),
+            per_class,
+            confusion,
+            top_k: vec![],
+            unknown_labels: 0,
+        }
+    }
+
+    /// Predicts the class with the highest output - there is one class per
+    /// output. Labels that are no class are only counted as unknown. Panics
+    /// if the outputs have different lengths.
+    pub fn from_outputs(
+        outputs: &[Vec<f64>],
+        labels: &[usize],
+        top_k: &[usize],
+    ) -> ClassificationReport {
+        if labels.len() != outputs.len() {
+            panic!("{} labels but {} outputs", labels.len(), outputs.len());
+        }
+        let classes = outputs.first().map_or(0, Vec::len);
+        if outputs.iter().any(|output| output.len() != classes) {
+            panic!("all outputs need {} values", classes);
+        }
+
+        let (known_outputs, known_labels): (Vec<Vec<f64>>, Vec<usize>) = outputs
+            .iter()
+            .zip(labels)
+            .filter(|&(_, &label)| label < classes)
+            .map(|(output, &label)| (output.clone(), label))
+            .unzip();
+        let predictions: Vec<usize> = known_outputs
+            .iter()
+            .map(|output| math::argmax(output).unwrap_or(0))
+            .collect();
+
+        let confusion = ConfusionMatrix::from_predictions(&known_labels, &predictions, classes);
+        let mut report = ClassificationReport::new(confusion);
+        report.top_k = top_k
+            .iter()
+            .map(|&k| (k, top_k_accuracy(&known_outputs, &known_labels, k)))
+            .collect();
+        report.unknown_labels = labels.len() - known_labels.len();
+        report
+    }
+
+    /// Queries the network for every sample of the dataset.
+    pub fn evaluate<T, D>(
+        network: &NeuralNetwork<T>,
+        dataset: &D,
+        top_k: &[usize],
+    ) -> ClassificationReport
+    where
+        T: Fn(f64) -> f64,
+        D: Dataset,
+    {
+        let (labels, outputs) = query_dataset(network, dataset);
+        ClassificationReport::from_outputs(&outputs, &labels, top_k)
+    }
+
+    /// One line per class and average: `class,precision,recall,f1,support`
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "class,precision,recall,f1,support")?;
+        let rows = self
+            .per_class
+            .iter()
+            .enumerate()
+            .map(|(class, metrics)| (class.to_string(), metrics))
+            .chain(vec![
+                ("macro_average".to_owned(), &self.macro_average),
+                ("weighted_average".to_owned(), &self.weighted_average),
+            ]);
+        for (name, metrics) in rows {
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                name,
+                metrics.precision,
+                metrics.recall,
+                metrics.f1,
+                metrics.support
+            )?;
+        }
+        writeln!(writer, "accuracy,,,{},{}", self.accuracy, self.confusion.total())?;
+        for &(k, accuracy) in &self.top_k {
+            writeln!(writer, "top_{}_accuracy,,,{},{}", k, accuracy, self.confusion.total())?;
+        }
+        Ok(())
+    }
+
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writeln!(writer, "{{")?;
+        writeln!(writer, "  \"accuracy\": {},", json_number(self.accuracy))?;
+        let top_k: Vec<String> = self
+            .top_k
+            .iter()
+            .map(|&(k, accuracy)| format!("\"{}\": {}", k, json_number(accuracy)))
+            .collect();
+        writeln!(writer, "  \"top_k_accuracy\": {{{}}},", top_k.join(", "))?;
+        writeln!(writer, "  \"unknown_labels\": {},", self.unknown_labels)?;
+        writeln!(writer, "  \"classes\": [")?;
+        for (class, metrics) in self.per_class.iter().enumerate() {
+            let separator = if class + 1 < self.per_class.len() { "," } else { "" };
+            writeln!(
+                writer,
+                "    {{\"class\": {}, {}}}{}",
+                class,
+                json_metrics(metrics),
+                separator
+            )?;
+        }
+        writeln!(writer, "  ],")?;
+        writeln!(writer, "  \"macro_average\": {{{}}},", json_metrics(&self.macro_average))?;
+        writeln!(
+            writer,
+            "  \"weighted_average\": {{{}}},",
+            json_metrics(&self.weighted_average)
+        )?;
+        let rows: Vec<String> = self
+            .confusion
+            .counts()
+            .iter()
+            .map(|row| {
+                let counts: Vec<String> = row.iter().map(usize::to_string).collect();
+                format!("[{}]", counts.join(", "))
+            })
+            .collect();
+        writeln!(writer, "  \"confusion_matrix\": [{}]", rows.join(", "))?;
+        writeln!(writer, "}}")
+    }
+}
+
+impl fmt::Display for ClassificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>16} {:>9} {:>9} {:>9} {:>9}",
+            "", "precision", "recall", "f1", "support"
+        )?;
+        for (class, metrics) in self.per_class.iter().enumerate() {
+            write_metrics_line(f, &class.to_string(), metrics)?;
+        }
+        writeln!(f)?;
+        write_metrics_line(f, "macro average", &self.macro_average)?;
+        write_metrics_line(f, "weighted average", &self.weighted_average)?;
+        writeln!(
+            f,
+            "{:>16} {:>9} {:>9} {:>9.4} {:>9}",
+            "accuracy",
+            "",
+            "",
+            self.accuracy,
+            self.confusion.total()
+        )?;
+        for &(k, accuracy) in &self.top_k {
+            writeln!(
+                f,
+                "{:>16} {:>9} {:>9} {:>9.4} {:>9}",
+                format!("top {} accuracy", k),
+                "",
+                "",
+                accuracy,
+                self.confusion.total()
+            )?;
+        }
+        if self.unknown_labels > 0 {
+            writeln!(
+                f,
+                "{:>16} {:>9} {:>9} {:>9} {:>9}",
+                "unknown labels", "", "", "", self.unknown_labels
+            )?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "confusion matrix (rows: actual, columns: predicted)")?;
+        let width = self.confusion.total().to_string().len().max(3);
+        write!(f, "{:>5}", "")?;
+        for class in 0..self.confusion.classes() {
+            write!(f, " {:>width$}", class, width = width)?;
+        }
+        writeln!(f)?;
+        for (class, row) in self.confusion.counts().iter().enumerate() {
+            write!(f, "{:>5}", class)?;
+            for count in row {
+                write!(f, " {:>width$}", count, width = width)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_metrics_line(f: &mut fmt::Formatter, name: &str, metrics: &ClassMetrics) -> fmt::Result {
+    writeln!(
+        f,
+        "{:>16} {:>9.4} {:>9.4} {:>9.4} {:>9}",
+        name,
+        metrics.precision,
+        metrics.recall,
+        metrics.f1,
+        metrics.support
+    )
+}
+
+fn json_metrics(metrics: &ClassMetrics) -> String {
+    format!(
+        "\"precision\": {}, \"recall\": {}, \"f1\": {}, \"support\": {}",
+        json_number(metrics.precision),
+        json_number(metrics.recall),
+        json_number(metrics.f1),
+        metrics.support
+    )
+}
+
+fn ratio(count: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod classification_tests {
+    use super::*;
+    use dataset::Sample;
+    use util;
+
+    // actual:    0 0 0 1 1 2
+    // predicted: 0 0 1 1 2 2
+    fn report() -> ClassificationReport {
+        ClassificationReport::new(ConfusionMatrix::from_predictions(
+            &[0, 0, 0, 1, 1, 2],
+            &[0, 0, 1, 1, 2, 2],
+            3,
+        ))
+    }
+
+    #[test]
+    fn test_confusion_matrix() {
+        let report = report();
+        let confusion = &report.confusion;
+
+        assert_eq!(confusion.counts(), &[vec![2, 1, 0], vec![0, 1, 1], vec![0, 0, 1]]);
+        assert_eq!(confusion.total(), 6);
+        assert_eq!(confusion.correct(), 4);
+        assert_eq!(confusion.precision(1), 0.5);
+        assert_eq!(confusion.recall(0), 2.0 / 3.0);
+        assert_eq!(confusion.f1(2), 2.0 / 3.0);
+        assert_eq!(ConfusionMatrix::new(2).precision(0), 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "not one of the 2 classes")]
+    fn test_unknown_class() {
+        ConfusionMatrix::new(2).add(0, 2);
+    }
+
+    #[test]
+    fn test_averages() {
+        let report = report();
+
+        assert_eq!(report.accuracy, 4.0 / 6.0);
+        assert!((report.macro_average.precision - (1.0 + 0.5 + 0.5) / 3.0).abs() < 1e-12);
+        assert!((report.macro_average.recall - (2.0 / 3.0 + 0.5 + 1.0) / 3.0).abs() < 1e-12);
+        let weighted_recall = (3.0 * 2.0 / 3.0 + 2.0 * 0.5 + 1.0) / 6.0;
+        assert!((report.weighted_average.recall - weighted_recall).abs() < 1e-12);
+        assert_eq!(report.weighted_average.support, 6);
+    }
+
+    #[test]
+    fn test_top_k() {
+        let outputs = vec![vec![0.1, 0.5, 0.4], vec![0.7, 0.2, 0.1], vec![0.2, 0.3, 0.5]];
+        let labels = [2, 0, 0];
+
+        assert_eq!(top_k_accuracy(&outputs, &labels, 1), 1.0 / 3.0);
+        assert_eq!(top_k_accuracy(&outputs, &labels, 2), 2.0 / 3.0);
+        assert_eq!(top_k_accuracy(&outputs, &labels, 3), 1.0);
+
+        let report = ClassificationReport::from_outputs(&outputs, &labels, &[2]);
+        assert_eq!(report.top_k, vec![(2, 2.0 / 3.0)]);
+        assert_eq!(top_classes(&outputs[0], 2), vec![(1, 0.5), (2, 0.4)]);
+        assert_eq!(top_classes(&[0.3, 0.3], 5), vec![(0, 0.3), (1, 0.3)]);
+        assert_eq!(report.confusion.count(2, 1), 1);
+    }
+
+    #[test]
+    fn test_export() {
+        let report = report();
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "class,precision,recall,f1,support");
+        assert_eq!(lines[2], "1,0.5,0.5,0.5,2");
+        assert_eq!(lines[6], format!("accuracy,,,{},6", 4.0 / 6.0));
+
+        let mut json = Vec::new();
+        report.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"confusion_matrix\": [[2, 1, 0], [0, 1, 1], [0, 0, 1]]"));
+        assert!(json.contains("{\"class\": 1, \"precision\": 0.5, \"recall\": 0.5"));
+
+        let text = report.to_string();
+        assert!(text.contains("weighted average"));
+        assert!(text.contains("    1   0   1   1"));
+    }
+
+    #[test]
+    fn test_evaluate_network() {
+        let nn = NeuralNetwork::new(2, 3, 4, 0.3, util::sigmoid);
+        let data: Vec<Sample> = vec![(0, vec![0.1, 0.9]), (3, vec![0.8, 0.2])];
+
+        let report = ClassificationReport::evaluate(&nn, &data, &[4]);
+        assert_eq!(report.confusion.classes(), 4);
+        assert_eq!(report.confusion.total(), 2);
+        assert_eq!(report.top_k, vec![(4, 1.0)]);
+    }
+
+    #[test]
+    fn test_unknown_labels() {
+        let outputs = vec![vec![0.9, 0.1], vec![0.2, 0.8], vec![0.6, 0.4]];
+        let report = ClassificationReport::from_outputs(&outputs, &[0, 1_000_000_000, 1], &[1]);
+
+        assert_eq!(report.confusion.classes(), 2);
+        assert_eq!(report.confusion.total(), 2);
+        assert_eq!(report.unknown_labels, 1);
+        assert_eq!(report.accuracy, 0.5);
+        assert_eq!(report.top_k, vec![(1, 0.5)]);
+        assert!(report.to_string().contains("unknown labels"));
+
+        let mut json = Vec::new();
+        report.write_json(&mut json).unwrap();
+        assert!(String::from_utf8(json).unwrap().contains("\"unknown_labels\": 1,"));
+    }
+}
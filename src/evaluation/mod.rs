@@ -0,0 +1,29 @@
+//! Measures how good a trained network is.
+
+pub mod classification;
+pub mod regression;
+pub mod probabilistic;
+
+use dataset::Dataset;
+use NeuralNetwork;
+
+/// Labels and network outputs of all samples.
+pub fn query_dataset<T, D>(network: &NeuralNetwork<T>, dataset: &D) -> (Vec<usize>, Vec<Vec<f64>>)
+where
+    T: Fn(f64) -> f64,
+    D: Dataset,
+{
+    dataset
+        .iter()
+        .map(|(label, inputs)| (label, network.query(inputs)))
+        .unzip()
+}
+
+/// JSON has no representation for NaN and infinity.
+fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{}", value)
+    } else {
+        "null".to_owned()
+    }
+}
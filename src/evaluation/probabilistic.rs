@@ -0,0 +1,465 @@
+//! Metrics that look at the outputs themselves instead of only at the class
+//! with the highest output - mostly for binary classifiers.
+//!
+//! A score is the output for the positive class, a label is `true` for the
+//! positive class.
+
+use dataset::Dataset;
+use std::f64;
+use super::query_dataset;
+use NeuralNetwork;
+
+/// Probabilities are clipped to `[EPSILON, 1 - EPSILON]` for the log loss.
+const EPSILON: f64 = 1e-15;
+
+/// The scores of the positive class. With one output that output is the
+/// score, otherwise the output of the positive class divided by the sum of
+/// all outputs - sigmoid outputs do not add up to 1 on their own. Panics if
+/// an output with several values has no value for `positive_class`.
+pub fn binary_scores(outputs: &[Vec<f64>], positive_class: usize) -> Vec<f64> {
+    outputs
+        .iter()
+        .map(|output| {
+            if output.len() > 1 && positive_class >= output.len() {
+                panic!(
+                    "class {} is not one of the {} outputs",
+                    positive_class,
+                    output.len()
+                );
+            }
+            if output.len() == 1 {
+                output[0]
+            } else {
+                let sum: f64 = output.iter().sum();
+                if sum > 0.0 {
+                    output[positive_class] / sum
+                } else {
+                    0.0
+                }
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RocPoint {
+    /// Samples with a score of at least the threshold are positive.
+    pub threshold: f64,
+    pub false_positive_rate: f64,
+    pub true_positive_rate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RocCurve {
+    /// From the highest threshold to the lowest - starts at (0, 0) and ends
+    /// at (1, 1).
+    pub points: Vec<RocPoint>,
+}
+
+impl RocCurve {
+    /// Area under the curve by the trapezoidal rule.
+    pub fn auc(&self) -> f64 {
+        self.points
+            .windows(2)
+            .map(|pair| {
+                let width = pair[1].false_positive_rate - pair[0].false_positive_rate;
+                width * (pair[0].true_positive_rate + pair[1].true_positive_rate) / 2.0
+            })
+            .sum()
+    }
+}
+
+/// None if there are not both positive and negative samples or a score is
+/// NaN.
+pub fn roc_curve(scores: &[f64], labels: &[bool]) -> Option<RocCurve> {
+    let thresholds = cumulative_counts(scores, labels)?;
+    let (positives, negatives) = match thresholds.last() {
+        Some(&(_, tp, fp)) if tp > 0 && fp > 0 => (tp as f64, fp as f64),
+        _ => return None,
+    };
+
+    let mut points = vec![RocPoint {
+        threshold: f64::INFINITY,
+        false_positive_rate: 0.0,
+        true_positive_rate: 0.0,
+    }];
+    points.extend(thresholds.iter().map(|&(threshold, tp, fp)| RocPoint {
+        threshold,
+        false_positive_rate: fp as f64 / negatives,
+        true_positive_rate: tp as f64 / positives,
+    }));
+    Some(RocCurve { points })
+}
+
+/// Area under the ROC curve - the probability that a random positive sample
+/// gets a higher score than a random negative one.
+pub fn roc_auc(scores: &[f64], labels: &[bool]) -> Option<f64> {
+    roc_curve(scores, labels).map(|curve| curve.auc())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrecisionRecallPoint {
+    pub threshold: f64,
+    pub precision: f64,
+    pub recall: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrecisionRecallCurve {
+    /// From the highest threshold to the lowest.
+    pub points: Vec<PrecisionRecallPoint>,
+}
+
+impl PrecisionRecallCurve {
+    /// Precision at every threshold weighted by the increase in recall.
+    pub fn average_precision(&self) -> f64 {
+        let mut previous_recall = 0.0;
+        let mut result = 0.0;
+        for point in &self.points {
+            result += (point.recall - previous_recall) * point.precision;
+            previous_recall = point.recall;
+        }
+        result
+    }
+}
+
+/// None without positive samples or if a score is NaN.
+pub fn precision_recall_curve(scores: &[f64], labels: &[bool]) -> Option<PrecisionRecallCurve> {
+    let thresholds = cumulative_counts(scores, labels)?;
+    let positives = match thresholds.last() {
+        Some(&(_, tp, _)) if tp > 0 => tp as f64,
+        _ => return None,
+    };
+
+    Some(PrecisionRecallCurve {
+        points: thresholds
+            .iter()
+            .map(|&(threshold, tp, fp)| PrecisionRecallPoint {
+                threshold,
+                precision: tp as f64 / (tp + fp) as f64,
+                recall: tp as f64 / positives,
+            })
+            .collect(),
+    })
+}
+
+pub fn average_precision(scores: &[f64], labels: &[bool]) -> Option<f64> {
+    precision_recall_curve(scores, labels).map(|curve| curve.average_precision())
+}
+
+/// Cross entropy of the scores as probabilities. 0 without samples.
+pub fn log_loss(scores: &[f64], labels: &[bool]) -> f64 {
+    check_lengths(scores.len(), labels.len());
+    mean(scores.iter().zip(labels).map(|(score, &label)| {
+        let probability = score.clamp(EPSILON, 1.0 - EPSILON);
+        if label {
+            -probability.ln()
+        } else {
+            -(1.0 - probability).ln()
+        }
+    }))
+}
+
+/// Cross entropy over several classes. Every output is divided by its sum
+/// to become a probability distribution first.
+pub fn categorical_log_loss(outputs: &[Vec<f64>], labels: &[usize]) -> f64 {
+    check_lengths(outputs.len(), labels.len());
+    mean(outputs.iter().zip(labels).map(|(output, &label)| {
+        let sum: f64 = output.iter().sum();
+        let probability = match output.get(label) {
+            Some(value) if sum > 0.0 => value / sum,
+            _ => 0.0,
+        };
+        -probability.clamp(EPSILON, 1.0 - EPSILON).ln()
+    }))
+}
+
+/// Mean squared difference between score and label. 0 without samples.
+pub fn brier_score(scores: &[f64], labels: &[bool]) -> f64 {
+    check_lengths(scores.len(), labels.len());
+    mean(scores.iter().zip(labels).map(|(score, &label)| {
+        let error = score - if label { 1.0 } else { 0.0 };
+        error * error
+    }))
+}
+
+/// One bar of a reliability diagram.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CalibrationBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub mean_score: f64,
+    /// Share of positive samples - equals `mean_score` if calibrated.
+    pub fraction_positive: f64,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationCurve {
+    /// Only bins with samples.
+    pub bins: Vec<CalibrationBin>,
+}
+
+impl CalibrationCurve {
+    /// Distance between score and share of positive samples - weighted by
+    /// the number of samples per bin.
+    pub fn expected_calibration_error(&self) -> f64 {
+        let total: usize = self.bins.iter().map(|bin| bin.count).sum();
+        if total == 0 {
+            return 0.0;
+        }
+        self.bins
+            .iter()
+            .map(|bin| bin.count as f64 * (bin.fraction_positive - bin.mean_score).abs())
+            .sum::<f64>() / total as f64
+    }
+
+    /// The largest distance of any bin.
+    pub fn maximum_calibration_error(&self) -> f64 {
+        self.bins
+            .iter()
+            .map(|bin| (bin.fraction_positive - bin.mean_score).abs())
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Splits `[0, 1]` into `bins` bins of the same width. Scores outside are
+/// put into the first or last bin.
+pub fn calibration_curve(scores: &[f64], labels: &[bool], bins: usize) -> CalibrationCurve {
+    check_lengths(scores.len(), labels.len());
+    if bins == 0 {
+        panic!("calibration curve needs at least 1 bin");
+    }
+
+    // (sum of scores, positives, count)
+    let mut sums = vec![(0.0, 0, 0); bins];
+    for (score, &label) in scores.iter().zip(labels) {
+        let index = ((score * bins as f64) as isize).max(0).min(bins as isize - 1) as usize;
+        sums[index].0 += score;
+        sums[index].1 += label as usize;
+        sums[index].2 += 1;
+    }
+
+    CalibrationCurve {
+        bins: sums
+            .iter()
+            .enumerate()
+            .filter(|&(_, &(_, _, count))| count > 0)
+            .map(|(index, &(score_sum, positives, count))| CalibrationBin {
+                lower: index as f64 / bins as f64,
+                upper: (index + 1) as f64 / bins as f64,
+                mean_score: score_sum / count as f64,
+                fraction_positive: positives as f64 / count as f64,
+                count,
+            })
+            .collect(),
+    }
+}
+
+/// All probabilistic metrics of a binary classifier.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryReport {
+    pub roc: Option<RocCurve>,
+    pub precision_recall: Option<PrecisionRecallCurve>,
+    pub log_loss: f64,
+    pub brier_score: f64,
+    pub calibration: CalibrationCurve,
+}
+
+impl BinaryReport {
+    pub fn new(scores: &[f64], labels: &[bool], bins: usize) -> BinaryReport {
+        BinaryReport {
+            roc: roc_curve(scores, labels),
+            precision_recall: precision_recall_curve(scores, labels),
+            log_loss: log_loss(scores, labels),
+            brier_score: brier_score(scores, labels),
+            calibration: calibration_curve(scores, labels, bins),
+        }
+    }
+
+    /// Queries the network for every sample. Samples with the label
+    /// `positive_class` are positive, all others negative. Panics like
+    /// `binary_scores` if the network has no output for `positive_class`.
+    pub fn evaluate<T, D>(
+        network: &NeuralNetwork<T>,
+        dataset: &D,
+        positive_class: usize,
+        bins: usize,
+    ) -> BinaryReport
+    where
+        T: Fn(f64) -> f64,
+        D: Dataset,
+    {
+        let (labels, outputs) = query_dataset(network, dataset);
+        let labels: Vec<bool> = labels.iter().map(|&label| label == positive_class).collect();
+        BinaryReport::new(&binary_scores(&outputs, positive_class), &labels, bins)
+    }
+
+    pub fn auc(&self) -> Option<f64> {
+        self.roc.as_ref().map(RocCurve::auc)
+    }
+
+    pub fn average_precision(&self) -> Option<f64> {
+        self.precision_recall
+            .as_ref()
+            .map(PrecisionRecallCurve::average_precision)
+    }
+
+    pub fn expected_calibration_error(&self) -> f64 {
+        self.calibration.expected_calibration_error()
+    }
+}
+
+/// `(threshold, true positives, false positives)` for every distinct score
+/// from the highest to the lowest. None if a score is NaN - it has no place
+/// in that order.
+fn cumulative_counts(scores: &[f64], labels: &[bool]) -> Option<Vec<(f64, usize, usize)>> {
+    check_lengths(scores.len(), labels.len());
+    if scores.iter().any(|score| score.is_nan()) {
+        return None;
+    }
+    let mut order: Vec<usize> = (0..scores.len()).collect();
+    order.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+
+    let mut result: Vec<(f64, usize, usize)> = Vec::new();
+    let (mut tp, mut fp) = (0, 0);
+    for (position, &index) in order.iter().enumerate() {
+        if labels[index] {
+            tp += 1;
+        } else {
+            fp += 1;
+        }
+        // equal scores can only be told apart together
+        let last_of_score = order
+            .get(position + 1)
+            .is_none_or(|&next| scores[next] != scores[index]);
+        if last_of_score {
+            result.push((scores[index], tp, fp));
+        }
+    }
+    Some(result)
+}
+
+fn check_lengths(scores: usize, labels: usize) {
+    if scores != labels {
+        panic!("{} scores but {} labels", scores, labels);
+    }
+}
+
+fn mean<I: Iterator<Item = f64>>(values: I) -> f64 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f64
+    }
+}
+
+#[cfg(test)]
+mod probabilistic_tests {
+    use super::*;
+    use dataset::Sample;
+    use util;
+
+    fn close(left: f64, right: f64) -> bool {
+        (left - right).abs() < 1e-12
+    }
+
+    #[test]
+    fn test_roc() {
+        let scores = [0.1, 0.4, 0.35, 0.8];
+        let labels = [false, false, true, true];
+
+        let curve = roc_curve(&scores, &labels).unwrap();
+        let rates: Vec<(f64, f64)> = curve
+            .points
+            .iter()
+            .map(|p| (p.false_positive_rate, p.true_positive_rate))
+            .collect();
+        assert_eq!(rates, vec![(0.0, 0.0), (0.0, 0.5), (0.5, 0.5), (0.5, 1.0), (1.0, 1.0)]);
+        assert_eq!(curve.points[1].threshold, 0.8);
+        assert_eq!(curve.auc(), 0.75);
+
+        assert_eq!(roc_auc(&[0.3, 0.7], &[true, true]), None);
+        // ties count half
+        assert_eq!(roc_auc(&[0.5, 0.5], &[false, true]), Some(0.5));
+        assert_eq!(roc_curve(&[0.2, f64::NAN], &[false, true]), None);
+    }
+
+    #[test]
+    fn test_average_precision() {
+        let scores = [0.1, 0.4, 0.35, 0.8];
+        let labels = [false, false, true, true];
+
+        let curve = precision_recall_curve(&scores, &labels).unwrap();
+        assert_eq!(curve.points[1].precision, 0.5);
+        assert_eq!(curve.points[1].recall, 0.5);
+        assert!(close(curve.average_precision(), 0.833_333_333_333_333_3));
+        assert_eq!(average_precision(&[0.2], &[false]), None);
+        assert_eq!(precision_recall_curve(&[f64::NAN, 0.3], &[true, false]), None);
+    }
+
+    #[test]
+    fn test_log_loss() {
+        let labels = [false, true, true, false];
+        let loss = log_loss(&[0.1, 0.9, 0.8, 0.35], &labels);
+        assert!(close(loss, 0.216_161_874_680_579_12));
+        assert!(log_loss(&[1.0], &[false]) > 34.0);
+
+        let outputs = vec![vec![0.9, 0.1], vec![0.2, 0.2]];
+        let expected = -(0.9f64.ln() + 0.5f64.ln()) / 2.0;
+        assert!(close(categorical_log_loss(&outputs, &[0, 1]), expected));
+    }
+
+    #[test]
+    fn test_brier_score() {
+        let labels = [false, true, true, false];
+        assert!(close(brier_score(&[0.1, 0.9, 0.8, 0.3], &labels), 0.0375));
+    }
+
+    #[test]
+    fn test_calibration() {
+        let scores = [0.05, 0.15, 0.12, 0.95, 1.0, 0.9];
+        let labels = [false, false, true, true, true, false];
+
+        let curve = calibration_curve(&scores, &labels, 5);
+        assert_eq!(curve.bins.len(), 2);
+        let first = curve.bins[0];
+        assert_eq!((first.lower, first.upper, first.count), (0.0, 0.2, 3));
+        assert!(close(first.mean_score, 0.32 / 3.0));
+        assert!(close(first.fraction_positive, 1.0 / 3.0));
+        let last = curve.bins[1];
+        assert_eq!((last.lower, last.count), (0.8, 3));
+
+        let expected = (3.0 * (1.0 / 3.0 - 0.32 / 3.0) + 3.0 * (2.85 / 3.0 - 2.0 / 3.0)) / 6.0;
+        assert!(close(curve.expected_calibration_error(), expected));
+        assert!(close(curve.maximum_calibration_error(), 2.85 / 3.0 - 2.0 / 3.0));
+    }
+
+    #[test]
+    fn test_binary_scores() {
+        let outputs = [vec![0.2, 0.6], vec![0.7]];
+        assert!(close(binary_scores(&outputs[..1], 1)[0], 0.75));
+        assert_eq!(binary_scores(&outputs[1..], 0), vec![0.7]);
+        assert_eq!(binary_scores(&outputs[1..], 3), vec![0.7]);
+    }
+
+    #[test]
+    #[should_panic(expected = "class 2 is not one of the 2 outputs")]
+    fn test_binary_scores_unknown_class() {
+        binary_scores(&[vec![0.0, 0.0]], 2);
+    }
+
+    #[test]
+    fn test_evaluate_network() {
+        let nn = NeuralNetwork::new(2, 3, 2, 0.3, util::sigmoid);
+        let data: Vec<Sample> = vec![(0, vec![0.1, 0.9]), (1, vec![0.8, 0.2]), (2, vec![0.5, 0.5])];
+
+        let report = BinaryReport::evaluate(&nn, &data, 1, 10);
+        assert!(report.auc().is_some());
+        assert!(report.average_precision().is_some());
+        assert!(report.log_loss > 0.0);
+        let counted: usize = report.calibration.bins.iter().map(|bin| bin.count).sum();
+        assert_eq!(counted, 3);
+    }
+}
@@ -0,0 +1,178 @@
+//! Metrics for networks that approximate continuous values.
+
+use dataset::RegressionSample;
+use matrix::math;
+use std::fmt;
+use NeuralNetwork;
+
+/// Errors between awaited and predicted outputs. With several outputs per
+/// sample R² and explained variance are the mean over the outputs, all
+/// other metrics treat every single value the same.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegressionMetrics {
+    /// Mean squared error
+    pub mse: f64,
+    /// Root mean squared error
+    pub rmse: f64,
+    /// Mean absolute error
+    pub mae: f64,
+    /// Coefficient of determination - 1 is perfect, 0 is not better than the mean.
+    pub r2: f64,
+    pub explained_variance: f64,
+    pub max_error: f64,
+    pub samples: usize,
+}
+
+impl RegressionMetrics {
+    /// Panics if the number of samples or outputs differs.
+    pub fn from_predictions(targets: &[Vec<f64>], predictions: &[Vec<f64>]) -> RegressionMetrics {
+        if targets.len() != predictions.len() {
+            panic!(
+                "{} targets but {} predictions",
+                targets.len(),
+                predictions.len()
+            );
+        }
+        let outputs = targets.first().map_or(0, Vec::len);
+        if targets.iter().chain(predictions).any(|values| values.len() != outputs) {
+            panic!("all targets and predictions need {} values", outputs);
+        }
+
+        let errors: Vec<f64> = targets
+            .iter()
+            .zip(predictions)
+            .flat_map(|(target, prediction)| math::subtract_vectors(target, prediction))
+            .collect();
+        let mse = math::mean(&errors.iter().map(|e| e * e).collect::<Vec<f64>>()).unwrap_or(0.0);
+        let mae = math::mean(&errors.iter().map(|e| e.abs()).collect::<Vec<f64>>()).unwrap_or(0.0);
+
+        // per output: variance of the targets and of the errors
+        let target_columns = math::transpose_2d_vector(targets);
+        let error_columns = math::transpose_2d_vector(
+            &targets
+                .iter()
+                .zip(predictions)
+                .map(|(target, prediction)| math::subtract_vectors(target, prediction))
+                .collect::<Vec<Vec<f64>>>(),
+        );
+        let (mut r2, mut explained_variance) = (0.0, 0.0);
+        for (target, error) in target_columns.iter().zip(&error_columns) {
+            let target_variance = math::var(target).unwrap_or(0.0);
+            let squared_error = math::mean(&math::multiply_vectors(error, error)).unwrap_or(0.0);
+            r2 += score(squared_error, target_variance);
+            explained_variance += score(math::var(error).unwrap_or(0.0), target_variance);
+        }
+        if outputs > 0 {
+            r2 /= outputs as f64;
+            explained_variance /= outputs as f64;
+        }
+
+        RegressionMetrics {
+            mse,
+            rmse: mse.sqrt(),
+            mae,
+            r2,
+            explained_variance,
+            max_error: math::linf_norm(&errors),
+            samples: targets.len(),
+        }
+    }
+
+    /// Queries the network for the inputs of every sample.
+    pub fn evaluate<T>(
+        network: &NeuralNetwork<T>,
+        samples: &[RegressionSample],
+    ) -> RegressionMetrics
+    where
+        T: Fn(f64) -> f64,
+    {
+        let targets: Vec<Vec<f64>> = samples.iter().map(|sample| sample.1.clone()).collect();
+        let predictions: Vec<Vec<f64>> = samples
+            .iter()
+            .map(|sample| network.query(&sample.0))
+            .collect();
+        RegressionMetrics::from_predictions(&targets, &predictions)
+    }
+}
+
+impl fmt::Display for RegressionMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>18} {:.6}", "mse", self.mse)?;
+        writeln!(f, "{:>18} {:.6}", "rmse", self.rmse)?;
+        writeln!(f, "{:>18} {:.6}", "mae", self.mae)?;
+        writeln!(f, "{:>18} {:.6}", "r2", self.r2)?;
+        writeln!(f, "{:>18} {:.6}", "explained variance", self.explained_variance)?;
+        writeln!(f, "{:>18} {:.6}", "max error", self.max_error)?;
+        writeln!(f, "{:>18} {}", "samples", self.samples)
+    }
+}
+
+/// `1 - error / variance`. Constant targets score 1 if they are met exactly
+/// and 0 otherwise.
+fn score(error: f64, variance: f64) -> f64 {
+    if variance > 0.0 {
+        1.0 - error / variance
+    } else if error == 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod regression_tests {
+    use super::*;
+    use util;
+
+    #[test]
+    fn test_metrics() {
+        let targets = vec![vec![3.0], vec![-0.5], vec![2.0], vec![7.0]];
+        let predictions = vec![vec![2.5], vec![0.0], vec![2.0], vec![8.0]];
+
+        let metrics = RegressionMetrics::from_predictions(&targets, &predictions);
+        assert_eq!(metrics.mse, 0.375);
+        assert_eq!(metrics.rmse, 0.375f64.sqrt());
+        assert_eq!(metrics.mae, 0.5);
+        assert_eq!(metrics.max_error, 1.0);
+        assert_eq!(metrics.samples, 4);
+        // the same values as scikit-learn gives
+        assert!((metrics.r2 - 0.948_608_137_044_967_9).abs() < 1e-12);
+        assert!((metrics.explained_variance - 0.957_173_447_537_473_2).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_several_outputs() {
+        let targets = vec![vec![0.5, 1.0], vec![-1.0, 1.0], vec![7.0, -6.0]];
+        let predictions = vec![vec![0.0, 2.0], vec![-1.0, 2.0], vec![8.0, -5.0]];
+
+        let metrics = RegressionMetrics::from_predictions(&targets, &predictions);
+        assert!((metrics.mse - 0.708_333_333_333_333_3).abs() < 1e-12);
+        assert!((metrics.r2 - 0.936_800_526_662_277_9).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_perfect_constant() {
+        let targets = vec![vec![1.0], vec![1.0]];
+
+        let metrics = RegressionMetrics::from_predictions(&targets, &targets);
+        assert_eq!(metrics.r2, 1.0);
+        assert_eq!(metrics.max_error, 0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "2 targets but 1 predictions")]
+    fn test_length_mismatch() {
+        RegressionMetrics::from_predictions(&[vec![1.0], vec![2.0]], &[vec![1.0]]);
+    }
+
+    #[test]
+    fn test_evaluate_network() {
+        let nn = NeuralNetwork::new(2, 3, 1, 0.3, util::sigmoid);
+        let samples = vec![(vec![0.1, 0.9], vec![0.5]), (vec![0.8, 0.2], vec![0.5])];
+
+        let metrics = RegressionMetrics::evaluate(&nn, &samples);
+        let output = nn.query(&[0.1, 0.9])[0];
+        assert_eq!(metrics.samples, 2);
+        assert!(metrics.max_error >= (output - 0.5).abs());
+    }
+}
@@ -0,0 +1,478 @@
+//! Numerical check of the gradients backpropagation calculates.
+//!
+//! `train` minimises the loss `1/2 * sum((target - output)²)` plus the
+//! regularisation penalty of the network. Its analytic
+//! gradient is read from the change of the parameters in one training step
+//! or taken from `backward`; the numerical one comes from central
+//! differences of the loss while every parameter is perturbed by `epsilon`.
+//! Both should agree to about 1e-7. Dropout is switched off while checking.
+
+use gradients::Gradients;
+use layers::{self, Layer as _};
+use matrix::error::MathError;
+use matrix::sparse::SparseMatrix;
+use matrix::Matrix;
+use std::fmt;
+use {Mode, NeuralNetwork};
+
+/// Gradients smaller than this count as this size when the relative error
+/// is calculated - otherwise rounding errors of tiny gradients dominate.
+const MIN_GRADIENT: f64 = 1e-8;
+
+/// The loss `train` minimises.
+pub fn squared_error_loss(outputs: &[f64], targets: &[f64]) -> f64 {
+    outputs
+        .iter()
+        .zip(targets)
+        .map(|(output, target)| (target - output) * (target - output))
+        .sum::<f64>()
+        / 2.0
+}
+
+/// Deviation of the analytic from the numerical gradient in one parameter
+/// of a layer.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerCheck {
+    /// E.g. `layer 1 weights` - the position of the layer in the network
+    /// starts with 1.
+    pub name: String,
+    /// `|analytic - numerical| / (|analytic| + |numerical|)` of the worst weight.
+    pub max_relative_error: f64,
+    pub max_absolute_error: f64,
+    pub weights: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientCheck {
+    pub layers: Vec<LayerCheck>,
+}
+
+impl GradientCheck {
+    pub fn max_relative_error(&self) -> f64 {
+        self.layers
+            .iter()
+            .fold(0.0, |max, layer| max.max(layer.max_relative_error))
+    }
+
+    /// True if no layer exceeds the relative error `tolerance`.
+    pub fn passed(&self, tolerance: f64) -> bool {
+        self.max_relative_error() <= tolerance
+    }
+}
+
+impl fmt::Display for GradientCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<16} {:>8} {:>14} {:>14}",
+            "layer", "weights", "max relative", "max absolute"
+        )?;
+        for layer in &self.layers {
+            writeln!(
+                f,
+                "{:<16} {:>8} {:>14.3e} {:>14.3e}",
+                layer.name, layer.weights, layer.max_relative_error, layer.max_absolute_error
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the gradients of one sample. The network is the same afterwards.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientChecker {
+    epsilon: f64,
+}
+
+impl Default for GradientChecker {
+    fn default() -> GradientChecker {
+        GradientChecker { epsilon: 1e-5 }
+    }
+}
+
+impl GradientChecker {
+    pub fn new() -> GradientChecker {
+        GradientChecker::default()
+    }
+
+    /// How far every weight is moved in both directions.
+    pub fn epsilon(mut self, epsilon: f64) -> GradientChecker {
+        self.epsilon = epsilon;
+        self
+    }
+
+    /// Checks the gradients of `train`.
+    pub fn check<T>(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        inputs: &[f64],
+        targets: &[f64],
+    ) -> Result<GradientCheck, MathError>
+    where
+        T: Fn(f64) -> f64,
+    {
+        self.check_with(network, inputs, targets, |network| network.train(inputs, targets))
+    }
+
+    /// Checks the gradients of `train_sparse`.
+    pub fn check_sparse<T>(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        inputs: &SparseMatrix,
+        targets: &[f64],
+    ) -> Result<GradientCheck, MathError>
+    where
+        T: Fn(f64) -> f64,
+    {
+        let dense = inputs.to_matrix().flatten();
+        self.check_with(network, &dense, targets, |network| network.train_sparse(inputs, targets))
+    }
+
+    /// Checks the gradients of `backward`.
+    pub fn check_backward<T>(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        inputs: &[f64],
+        targets: &[f64],
+    ) -> Result<GradientCheck, MathError>
+    where
+        T: Fn(f64) -> f64,
+    {
+        let mode = network.mode;
+        network.mode = Mode::Eval;
+        let gradients = network.backward(inputs, targets);
+        network.mode = mode;
+
+        Ok(self.compare_layers(network, &gradients?, inputs, targets))
+    }
+
+    /// Checks `Layer::backward` by the inputs and by every parameter for
+    /// the loss `1/2 * sum(factor * output²)`. The fixed factors between 1
+    /// and 2 keep symmetries of the layer from hiding wrong gradients. Layers
+    /// that draw random numbers while training, like dropout, need
+    /// `Mode::Eval`.
+    pub fn check_layer<L>(
+        &self,
+        layer: &mut L,
+        batch: &Matrix,
+        mode: Mode,
+    ) -> Result<GradientCheck, MathError>
+    where
+        L: layers::Layer + ?Sized,
+    {
+        let factor = |row: usize, column: usize| 1.0 + ((row + column) % 3) as f64 / 2.0;
+        let outputs = layer.forward(batch, mode)?;
+        let output_gradients: Vec<Vec<f64>> = outputs
+            .data_container()
+            .iter()
+            .enumerate()
+            .map(|(row, outputs)| {
+                let cells = outputs.iter().enumerate();
+                cells.map(|(column, output)| factor(row, column) * output).collect()
+            })
+            .collect();
+        let input_gradients = layer.backward(&Matrix::from_2d_vec(&output_gradients))?.flatten();
+        let parameter_gradients: Vec<(&'static str, Vec<f64>)> = layer
+            .parameters()
+            .iter()
+            .map(|parameter| (parameter.name, parameter.gradients.to_vec()))
+            .collect();
+
+        let loss = |layer: &mut L, batch: &Matrix| -> Result<f64, MathError> {
+            let outputs = layer.forward(batch, mode)?;
+            let mut loss = 0.0;
+            for (row, outputs) in outputs.data_container().iter().enumerate() {
+                for (column, output) in outputs.iter().enumerate() {
+                    loss += factor(row, column) * output * output / 2.0;
+                }
+            }
+            Ok(loss)
+        };
+
+        let mut batch = batch.clone();
+        let mut error = None;
+        let columns = batch.columns();
+        let mut layers = vec![compare("inputs".to_owned(), &input_gradients, |index| {
+            let (row, column) = (index / columns, index % columns);
+            let original = batch.data_container()[row][column];
+            let mut loss_with = |delta: f64| {
+                batch.row_mut(row)[column] = original + delta;
+                loss(layer, &batch).unwrap_or_else(|e| {
+                    error = Some(e);
+                    0.0
+                })
+            };
+            let difference = loss_with(self.epsilon) - loss_with(-self.epsilon);
+            batch.row_mut(row)[column] = original;
+            difference / (2.0 * self.epsilon)
+        })];
+        for (index, (name, gradients)) in parameter_gradients.into_iter().enumerate() {
+            layers.push(compare(name.to_owned(), &gradients, |column| {
+                let mut loss_with = |delta: f64| {
+                    layer.parameters()[index].values[column] += delta;
+                    let result = loss(layer, &batch);
+                    layer.parameters()[index].values[column] -= delta;
+                    result.unwrap_or_else(|e| {
+                        error = Some(e);
+                        0.0
+                    })
+                };
+                let difference = loss_with(self.epsilon) - loss_with(-self.epsilon);
+                difference / (2.0 * self.epsilon)
+            }));
+        }
+
+        match error {
+            Some(e) => Err(e),
+            None => Ok(GradientCheck { layers }),
+        }
+    }
+
+    fn check_with<T, F>(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        inputs: &[f64],
+        targets: &[f64],
+        train: F,
+    ) -> Result<GradientCheck, MathError>
+    where
+        T: Fn(f64) -> f64,
+        F: FnOnce(&mut NeuralNetwork<T>) -> Result<(), MathError>,
+    {
+        let before = parameter_values(network);
+        let (learning_rate, weight_decay) = (network.learning_rate, network.weight_decay);
+        let mode = network.mode;
+
+        // with a learning rate of 1 the change is the negative gradient - the
+        // weight decay is no gradient of the loss
+        network.learning_rate = 1.0;
+        network.weight_decay = 0.0;
+        network.mode = Mode::Eval;
+        let trained = train(network);
+        let gradients = Gradients {
+            parameters: before
+                .iter()
+                .zip(parameter_values(network))
+                .map(|(before, after)| before.iter().zip(after).map(|(b, a)| b - a).collect())
+                .collect(),
+        };
+        network.learning_rate = learning_rate;
+        network.weight_decay = weight_decay;
+        network.mode = mode;
+        for (parameter, values) in network.layers.parameters().into_iter().zip(before) {
+            parameter.values.copy_from_slice(&values);
+        }
+        trained?;
+
+        Ok(self.compare_layers(network, &gradients, inputs, targets))
+    }
+
+    fn compare_layers<T>(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        gradients: &Gradients,
+        inputs: &[f64],
+        targets: &[f64],
+    ) -> GradientCheck
+    where
+        T: Fn(f64) -> f64,
+    {
+        let names = parameter_names(network);
+        let layers = names
+            .into_iter()
+            .zip(&gradients.parameters)
+            .enumerate()
+            .map(|(parameter, (name, gradients))| {
+                compare(name, gradients, |index| {
+                    self.numerical_gradient(network, parameter, index, inputs, targets)
+                })
+            })
+            .collect();
+        GradientCheck { layers }
+    }
+
+    /// Of the value `index` of the parameter number `parameter` in the order
+    /// of `Layer::parameters`.
+    fn numerical_gradient<T>(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        parameter: usize,
+        index: usize,
+        inputs: &[f64],
+        targets: &[f64],
+    ) -> f64
+    where
+        T: Fn(f64) -> f64,
+    {
+        let loss_with = |network: &mut NeuralNetwork<T>, delta: f64| {
+            let original = network.layers.parameters()[parameter].values[index];
+            network.layers.parameters()[parameter].values[index] = original + delta;
+            let loss = squared_error_loss(&network.query(inputs), targets)
+                + network.regularization_loss();
+            network.layers.parameters()[parameter].values[index] = original;
+            loss
+        };
+        let difference = loss_with(network, self.epsilon) - loss_with(network, -self.epsilon);
+        difference / (2.0 * self.epsilon)
+    }
+}
+
+fn parameter_values<T>(network: &mut NeuralNetwork<T>) -> Vec<Vec<f64>>
+where
+    T: Fn(f64) -> f64,
+{
+    network
+        .layers
+        .parameters()
+        .iter()
+        .map(|parameter| parameter.values.to_vec())
+        .collect()
+}
+
+/// `layer <position> <parameter>` of every parameter of the network.
+fn parameter_names<T>(network: &mut NeuralNetwork<T>) -> Vec<String>
+where
+    T: Fn(f64) -> f64,
+{
+    let mut names = Vec::new();
+    for (position, layer) in network.layers.layers_mut().iter_mut().enumerate() {
+        for parameter in layer.parameters() {
+            names.push(format!("layer {} {}", position + 1, parameter.name));
+        }
+    }
+    names
+}
+
+/// Compares the analytic with the numerical gradient of every value.
+fn compare<F>(name: String, analytic: &[f64], mut numerical: F) -> LayerCheck
+where
+    F: FnMut(usize) -> f64,
+{
+    let mut check = LayerCheck {
+        name,
+        max_relative_error: 0.0,
+        max_absolute_error: 0.0,
+        weights: analytic.len(),
+    };
+    for (index, &analytic) in analytic.iter().enumerate() {
+        let numerical = numerical(index);
+        let error = (analytic - numerical).abs();
+        let scale = (analytic.abs() + numerical.abs()).max(MIN_GRADIENT);
+
+        check.max_absolute_error = check.max_absolute_error.max(error);
+        check.max_relative_error = check.max_relative_error.max(error / scale);
+    }
+    check
+}
+
+#[cfg(test)]
+mod gradient_check_tests {
+    use super::*;
+    use regularization::Regularization;
+    use util;
+
+    const TOLERANCE: f64 = 1e-6;
+
+    fn network<T: Fn(f64) -> f64 + 'static>(activation: T) -> NeuralNetwork<T> {
+        let mut rng = util::SeededRng::new(3);
+        let mut weighting = |columns, rows| {
+            let data: Vec<Vec<f64>> = (0..rows)
+                .map(|_| (0..columns).map(|_| rng.range(-1.0, 1.0)).collect())
+                .collect();
+            Matrix::from_2d_vec(&data)
+        };
+        let (wih, who) = (weighting(4, 5), weighting(5, 3));
+        NeuralNetwork::from_weightings(wih, who, 0.3, activation).unwrap()
+    }
+
+    #[test]
+    fn test_loss() {
+        assert_eq!(squared_error_loss(&[0.5, 1.0], &[1.0, 0.0]), 0.625);
+    }
+
+    #[test]
+    fn test_sigmoid_network() {
+        let mut nn = network(util::sigmoid);
+        let inputs = [0.9, 0.1, 0.5, 0.3];
+        let targets = [0.99, 0.01, 0.01];
+        let before = parameter_values(&mut nn);
+
+        let check = GradientChecker::new().check(&mut nn, &inputs, &targets).unwrap();
+        assert!(check.passed(TOLERANCE), "\n{}", check);
+        let sizes: Vec<usize> = check.layers.iter().map(|layer| layer.weights).collect();
+        assert_eq!(sizes, vec![20, 5, 15, 3]);
+        assert_eq!(check.layers[2].name, "layer 3 weights");
+
+        // the network is not changed
+        assert_eq!(parameter_values(&mut nn), before);
+        assert_eq!(nn.learning_rate(), 0.3);
+    }
+
+    #[test]
+    fn test_sparse_inputs() {
+        let mut nn = network(util::sigmoid);
+        let inputs = SparseMatrix::from_1d_vec(&[0.0, 0.7, 0.0, 0.2], true);
+        let targets = [0.01, 0.99, 0.01];
+
+        let check = GradientChecker::new().check_sparse(&mut nn, &inputs, &targets).unwrap();
+        assert!(check.passed(TOLERANCE), "\n{}", check);
+    }
+
+    #[test]
+    fn test_backward() {
+        let mut nn = network(util::sigmoid);
+        let check = GradientChecker::new()
+            .check_backward(&mut nn, &[0.9, 0.1, 0.5, 0.3], &[0.99, 0.01, 0.01])
+            .unwrap();
+        assert!(check.passed(TOLERANCE), "\n{}", check);
+    }
+
+    #[test]
+    fn test_regularization() {
+        let mut nn = network(util::sigmoid);
+        nn.set_regularization(Regularization::l2(0.1), Regularization::new(0.01, 0.3));
+        nn.set_weight_decay(0.2);
+        let (inputs, targets) = ([0.9, 0.1, 0.5, 0.3], [0.99, 0.01, 0.01]);
+
+        let check = GradientChecker::new().check(&mut nn, &inputs, &targets).unwrap();
+        assert!(check.passed(TOLERANCE), "\n{}", check);
+        let check = GradientChecker::new().check_backward(&mut nn, &inputs, &targets).unwrap();
+        assert!(check.passed(TOLERANCE), "\n{}", check);
+        assert_eq!(nn.weight_decay(), 0.2);
+    }
+
+    #[test]
+    fn test_dropout_is_switched_off() {
+        let mut nn = network(util::sigmoid);
+        nn.set_dropout(0.5, 0.5, 1);
+        let (inputs, targets) = ([0.9, 0.1, 0.5, 0.3], [0.99, 0.01, 0.01]);
+
+        let check = GradientChecker::new().check(&mut nn, &inputs, &targets).unwrap();
+        assert!(check.passed(TOLERANCE), "\n{}", check);
+        let check = GradientChecker::new().check_backward(&mut nn, &inputs, &targets).unwrap();
+        assert!(check.passed(TOLERANCE), "\n{}", check);
+        assert_eq!(nn.mode(), Mode::Train);
+    }
+
+    #[test]
+    fn test_relu_network() {
+        let mut nn = network(util::relu);
+        nn.set_activation_derivative(util::relu_derivative);
+        let inputs = [0.9, 0.1, 0.5, 0.3];
+        let targets = [0.99, 0.01, 0.01];
+
+        let check = GradientChecker::new().check(&mut nn, &inputs, &targets).unwrap();
+        assert!(check.passed(TOLERANCE), "\n{}", check);
+        let check = GradientChecker::new().check_backward(&mut nn, &inputs, &targets).unwrap();
+        assert!(check.passed(TOLERANCE), "\n{}", check);
+        let sparse = SparseMatrix::from_1d_vec(&[0.0, 0.7, 0.0, 0.2], true);
+        let check = GradientChecker::new().check_sparse(&mut nn, &sparse, &targets).unwrap();
+        assert!(check.passed(TOLERANCE), "\n{}", check);
+    }
+
+    #[test]
+    fn test_shape_mismatch() {
+        let mut nn = network(util::sigmoid);
+        assert!(GradientChecker::new().check(&mut nn, &[0.5], &[0.0, 0.0, 0.0]).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Gradients of the loss by the parameters, kept apart from the network so
+//! that they can be inspected, accumulated or changed before they are applied.
+
+use matrix::error::MathError;
+use matrix::simd;
+
+/// `d loss / d parameter` of every parameter for the loss `train`
+/// minimises - the network moves its parameters against them. They are in
+/// the order of `Layer::parameters` of the network's layers: weights and
+/// biases of the dense layer input -> hidden, scale and shift of the
+/// normalisation if it is set, then weights and biases of the dense layer
+/// hidden -> output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradients {
+    pub parameters: Vec<Vec<f64>>,
+}
+
+impl Gradients {
+    /// Adds the gradients of another sample.
+    pub fn add(&mut self, other: &Gradients) -> Result<(), MathError> {
+        if !self.has_shape_of(other) {
+            return Err(MathError);
+        }
+        for (sum, addend) in self.parameters.iter_mut().zip(&other.parameters) {
+            simd::axpy(1.0, addend, sum);
+        }
+        Ok(())
+    }
+
+    /// Multiplies every gradient, e.g. by `1 / samples` to get the mean.
+    pub fn scale(&mut self, factor: f64) {
+        for gradient in self.values_mut() {
+            *gradient *= factor;
+        }
+    }
+
+    /// L2 norm of all gradients together.
+    pub fn norm(&self) -> f64 {
+        self.values().map(|gradient| gradient * gradient).sum::<f64>().sqrt()
+    }
+
+    /// Scales all gradients down so that their global norm is at most
+    /// `max_norm` - the direction stays the same. True if they were scaled.
+    pub fn clip_by_norm(&mut self, max_norm: f64) -> bool {
+        let norm = self.norm();
+        if norm > max_norm {
+            self.scale(max_norm / norm);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Limits every gradient to `[-limit, limit]`.
+    pub fn clip_by_value(&mut self, limit: f64) {
+        for gradient in self.values_mut() {
+            *gradient = gradient.clamp(-limit, limit);
+        }
+    }
+
+    /// False if any gradient is NaN or infinite.
+    pub fn is_finite(&self) -> bool {
+        self.values().all(|gradient| gradient.is_finite())
+    }
+
+    pub(crate) fn has_shape_of(&self, other: &Gradients) -> bool {
+        self.parameters.len() == other.parameters.len()
+            && self
+                .parameters
+                .iter()
+                .zip(&other.parameters)
+                .all(|(left, right)| left.len() == right.len())
+    }
+
+    fn values(&self) -> impl Iterator<Item = &f64> {
+        self.parameters.iter().flat_map(|gradients| gradients.iter())
+    }
+
+    fn values_mut(&mut self) -> impl Iterator<Item = &mut f64> {
+        self.parameters
+            .iter_mut()
+            .flat_map(|gradients| gradients.iter_mut())
+    }
+}
+
+#[cfg(test)]
+mod gradients_tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulate() {
+        let mut sum = Gradients {
+            parameters: vec![vec![0.0, 0.0], vec![0.0]],
+        };
+        let gradients = Gradients {
+            parameters: vec![vec![1.0, -2.0], vec![4.0]],
+        };
+        sum.add(&gradients).unwrap();
+        sum.add(&gradients).unwrap();
+        sum.scale(0.5);
+
+        assert_eq!(sum, gradients);
+        let wrong = Gradients {
+            parameters: vec![vec![0.0; 3], vec![0.0]],
+        };
+        assert!(sum.add(&wrong).is_err());
+    }
+
+    #[test]
+    fn test_clip() {
+        let gradients = Gradients {
+            parameters: vec![vec![3.0, 0.0], vec![-4.0]],
+        };
+        assert_eq!(gradients.norm(), 5.0);
+
+        let mut by_norm = gradients.clone();
+        assert!(!by_norm.clip_by_norm(5.0));
+        assert!(by_norm.clip_by_norm(2.5));
+        assert_eq!(by_norm.parameters, vec![vec![1.5, 0.0], vec![-2.0]]);
+
+        let mut by_value = gradients.clone();
+        by_value.clip_by_value(1.0);
+        assert_eq!(by_value.parameters, vec![vec![1.0, 0.0], vec![-1.0]]);
+    }
+
+    #[test]
+    fn test_is_finite() {
+        let mut gradients = Gradients {
+            parameters: vec![vec![0.0; 4], vec![0.0; 2]],
+        };
+        assert!(gradients.is_finite());
+        gradients.parameters[1][1] = f64::NAN;
+        assert!(!gradients.is_finite());
+        gradients.parameters[1][1] = f64::NEG_INFINITY;
+        assert!(!gradients.is_finite());
+    }
+}
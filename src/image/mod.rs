@@ -0,0 +1,352 @@
+//! Own images as network input: reads grayscale PGM and PNG files and
+//! prepares them like the MNIST digits were prepared.
+
+pub mod pgm;
+pub mod png;
+
+use mnist_data::normalise_pixel;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Width and height of an MNIST image.
+pub const MNIST_SIZE: usize = 28;
+/// MNIST digits are scaled into a box of this size before they are centred.
+pub const MNIST_DIGIT_SIZE: usize = 20;
+/// Grey values up to this one count as background when the digit is cropped.
+const BACKGROUND_THRESHOLD: u8 = 32;
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    /// The content does not follow the format.
+    Format(String),
+    /// Valid but uses a feature that is not supported, e.g. interlacing.
+    Unsupported(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ImageError::Io(ref e) => write!(f, "could not read image: {}", e),
+            ImageError::Format(ref message) => write!(f, "invalid image: {}", message),
+            ImageError::Unsupported(ref message) => {
+                write!(f, "unsupported image: {}", message)
+            }
+        }
+    }
+}
+
+impl error::Error for ImageError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ImageError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(e: io::Error) -> ImageError {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            ImageError::Format("data ends too early".to_owned())
+        } else {
+            ImageError::Io(e)
+        }
+    }
+}
+
+/// 8 bit grey values row by row - 0 is black, 255 is white.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrayImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl GrayImage {
+    /// Panics if there are not `width * height` pixels.
+    pub fn new(width: usize, height: usize, pixels: Vec<u8>) -> GrayImage {
+        if pixels.len() != width * height {
+            panic!(
+                "a {}x{} image needs {} pixels but got {}",
+                width,
+                height,
+                width * height,
+                pixels.len()
+            );
+        }
+        GrayImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Reads a PGM or PNG file - the format is recognised by its content.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<GrayImage, ImageError> {
+        GrayImage::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read<R: BufRead>(mut reader: R) -> Result<GrayImage, ImageError> {
+        let start = reader.fill_buf()?;
+        if start.starts_with(&png::SIGNATURE) {
+            png::read(reader)
+        } else if start.starts_with(b"P2") || start.starts_with(b"P5") {
+            pgm::read(reader)
+        } else {
+            Err(ImageError::Unsupported(
+                "only PGM and PNG files can be read".to_owned(),
+            ))
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn invert(&self) -> GrayImage {
+        GrayImage::new(
+            self.width,
+            self.height,
+            self.pixels.iter().map(|pixel| 255 - pixel).collect(),
+        )
+    }
+
+    /// A dark digit on a light background - judged by the border pixels.
+    pub fn has_light_background(&self) -> bool {
+        let border: Vec<u8> = (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|&(x, y)| x == 0 || y == 0 || x + 1 == self.width || y + 1 == self.height)
+            .map(|(x, y)| self.pixel(x, y))
+            .collect();
+        let sum: usize = border.iter().map(|&pixel| usize::from(pixel)).sum();
+        !border.is_empty() && sum / border.len() > 127
+    }
+
+    /// The smallest rectangle `(x, y, width, height)` with all pixels
+    /// brighter than `threshold`. None if there are none.
+    pub fn bounding_box(&self, threshold: u8) -> Option<(usize, usize, usize, usize)> {
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.pixel(x, y) > threshold {
+                    bounds = Some(match bounds {
+                        Some((left, top, right, bottom)) => {
+                            (left.min(x), top.min(y), right.max(x), bottom.max(y))
+                        }
+                        None => (x, y, x, y),
+                    });
+                }
+            }
+        }
+        bounds.map(|(left, top, right, bottom)| (left, top, right - left + 1, bottom - top + 1))
+    }
+
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> GrayImage {
+        if x + width > self.width || y + height > self.height {
+            panic!("crop exceeds the {}x{} image", self.width, self.height);
+        }
+        let pixels = (y..y + height)
+            .flat_map(|row| (x..x + width).map(move |column| (column, row)))
+            .map(|(column, row)| self.pixel(column, row))
+            .collect();
+        GrayImage::new(width, height, pixels)
+    }
+
+    /// Every new pixel is the mean of the area of the old image it covers.
+    pub fn resize(&self, width: usize, height: usize) -> GrayImage {
+        let scale_x = self.width as f64 / width as f64;
+        let scale_y = self.height as f64 / height as f64;
+        let mut pixels = Vec::with_capacity(width * height);
+
+        for y in 0..height {
+            let (top, bottom) = (y as f64 * scale_y, (y + 1) as f64 * scale_y);
+            for x in 0..width {
+                let (left, right) = (x as f64 * scale_x, (x + 1) as f64 * scale_x);
+                let (mut sum, mut area) = (0.0, 0.0);
+
+                for row in top.floor() as usize..(bottom.ceil() as usize).min(self.height) {
+                    let cover_y = (bottom.min(row as f64 + 1.0) - top.max(row as f64)).max(0.0);
+                    for column in left.floor() as usize..(right.ceil() as usize).min(self.width) {
+                        let cover_x =
+                            (right.min(column as f64 + 1.0) - left.max(column as f64)).max(0.0);
+                        sum += f64::from(self.pixel(column, row)) * cover_x * cover_y;
+                        area += cover_x * cover_y;
+                    }
+                }
+                pixels.push(if area > 0.0 { (sum / area).round() as u8 } else { 0 });
+            }
+        }
+        GrayImage::new(width, height, pixels)
+    }
+
+    /// Position of the centre of mass of the grey values, None for a black image.
+    pub fn center_of_mass(&self) -> Option<(f64, f64)> {
+        let (mut total, mut sum_x, mut sum_y) = (0.0, 0.0, 0.0);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mass = f64::from(self.pixel(x, y));
+                total += mass;
+                sum_x += mass * (x as f64 + 0.5);
+                sum_y += mass * (y as f64 + 0.5);
+            }
+        }
+        if total > 0.0 {
+            Some((sum_x / total, sum_y / total))
+        } else {
+            None
+        }
+    }
+
+    /// Prepares the image like the MNIST digits: a light digit on black,
+    /// scaled into 20x20 pixels keeping the aspect ratio and placed in a
+    /// 28x28 image with its centre of mass in the middle.
+    pub fn to_mnist(&self) -> GrayImage {
+        let image = if self.has_light_background() {
+            self.invert()
+        } else {
+            self.clone()
+        };
+        let (x, y, width, height) = match image.bounding_box(BACKGROUND_THRESHOLD) {
+            Some(bounds) => bounds,
+            None => return GrayImage::new(MNIST_SIZE, MNIST_SIZE, vec![0; MNIST_SIZE * MNIST_SIZE]),
+        };
+
+        let scale = MNIST_DIGIT_SIZE as f64 / width.max(height) as f64;
+        let digit = image.crop(x, y, width, height).resize(
+            ((width as f64 * scale).round() as usize).max(1),
+            ((height as f64 * scale).round() as usize).max(1),
+        );
+
+        let center = MNIST_SIZE as f64 / 2.0;
+        let (mass_x, mass_y) = digit
+            .center_of_mass()
+            .unwrap_or((digit.width as f64 / 2.0, digit.height as f64 / 2.0));
+        let offset_x = (center - mass_x).round() as isize;
+        let offset_y = (center - mass_y).round() as isize;
+
+        let mut pixels = vec![0; MNIST_SIZE * MNIST_SIZE];
+        for y in 0..digit.height {
+            for x in 0..digit.width {
+                let (target_x, target_y) = (x as isize + offset_x, y as isize + offset_y);
+                let size = MNIST_SIZE as isize;
+                if (0..size).contains(&target_x) && (0..size).contains(&target_y) {
+                    pixels[target_y as usize * MNIST_SIZE + target_x as usize] = digit.pixel(x, y);
+                }
+            }
+        }
+        GrayImage::new(MNIST_SIZE, MNIST_SIZE, pixels)
+    }
+
+    /// The grey values of `to_mnist` as they are written in the MNIST CSV files.
+    pub fn mnist_pixels(&self) -> Vec<f64> {
+        self.to_mnist()
+            .pixels
+            .iter()
+            .map(|&pixel| f64::from(pixel))
+            .collect()
+    }
+
+    /// The same input vector `convert_mnist_line` gives for the MNIST CSV
+    /// line of the image.
+    pub fn mnist_inputs(&self) -> Vec<f64> {
+        self.mnist_pixels().into_iter().map(normalise_pixel).collect()
+    }
+}
+
+#[cfg(test)]
+mod image_tests {
+    use super::*;
+    use mnist_data::convert_mnist_line;
+
+    /// A dark bar on white paper like a scanned "1".
+    fn scanned_one() -> GrayImage {
+        let (width, height) = (40, 60);
+        let pixels = (0..width * height)
+            .map(|index| {
+                let (x, y) = (index % width, index / width);
+                if (8..52).contains(&y) && (10..16).contains(&x) {
+                    0
+                } else {
+                    250
+                }
+            })
+            .collect();
+        GrayImage::new(width, height, pixels)
+    }
+
+    #[test]
+    fn test_to_mnist() {
+        let mnist = scanned_one().to_mnist();
+        assert_eq!((mnist.width(), mnist.height()), (28, 28));
+
+        // inverted, 20 pixels high and centred
+        let (x, y, width, height) = mnist.bounding_box(BACKGROUND_THRESHOLD).unwrap();
+        assert_eq!(height, 20);
+        assert_eq!(y, 4);
+        assert!(width <= 3);
+        assert!((13..=14).contains(&(x + width / 2)));
+        let (mass_x, mass_y) = mnist.center_of_mass().unwrap();
+        assert!((mass_x - 14.0).abs() <= 0.5 && (mass_y - 14.0).abs() <= 0.5);
+        assert_eq!(mnist.pixel(0, 0), 0);
+    }
+
+    #[test]
+    fn test_mnist_inputs() {
+        let image = scanned_one();
+        let line: Vec<String> = image
+            .mnist_pixels()
+            .iter()
+            .map(|pixel| pixel.to_string())
+            .collect();
+
+        let (_, expected) = convert_mnist_line(&format!("1,{}", line.join(",")), 1).unwrap();
+        assert_eq!(image.mnist_inputs(), expected);
+    }
+
+    #[test]
+    fn test_empty_image() {
+        let black = GrayImage::new(5, 5, vec![0; 25]);
+        assert_eq!(black.to_mnist().pixels(), &[0; 28 * 28][..]);
+    }
+
+    #[test]
+    fn test_resize() {
+        let image = GrayImage::new(4, 2, vec![0, 100, 200, 200, 0, 100, 200, 200]);
+        assert_eq!(image.resize(2, 1).pixels(), &[50, 200]);
+        assert_eq!(image.resize(8, 2).pixel(3, 1), 100);
+    }
+
+    #[test]
+    fn test_read_recognises_format() {
+        let pgm = GrayImage::read(&b"P2 1 1 255 7"[..]).unwrap();
+        assert_eq!(pgm.pixels(), &[7]);
+        match GrayImage::read(&b"GIF89a"[..]) {
+            Err(ImageError::Unsupported(_)) => {}
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "a 2x2 image needs 4 pixels but got 3")]
+    fn test_pixel_count() {
+        GrayImage::new(2, 2, vec![0, 0, 0]);
+    }
+}
@@ -0,0 +1,176 @@
+//! Netpbm grayscale images: `P2` (plain text) and `P5` (binary).
+
+use super::{GrayImage, ImageError};
+use std::io;
+use std::io::prelude::*;
+
+/// Larger images are refused before anything is allocated for them - a
+/// digit photo is far smaller.
+const MAX_PIXELS: usize = 1 << 26;
+
+pub fn read<R: BufRead>(mut reader: R) -> Result<GrayImage, ImageError> {
+    let magic = header_token(&mut reader)?;
+    let is_binary = match magic.as_str() {
+        "P2" => false,
+        "P5" => true,
+        other => return Err(format_error(format!("unknown magic number '{}'", other))),
+    };
+    let width = header_number(&mut reader, "width")?;
+    let height = header_number(&mut reader, "height")?;
+    let max_value = header_number(&mut reader, "maximum grey value")?;
+    if max_value == 0 || max_value > 65535 {
+        return Err(format_error(format!("invalid maximum grey value {}", max_value)));
+    }
+
+    if width == 0 || height == 0 {
+        return Err(format_error("image is empty".to_owned()));
+    }
+    let count = match width.checked_mul(height) {
+        Some(count) if count <= MAX_PIXELS => count,
+        _ => {
+            return Err(ImageError::Unsupported(format!(
+                "PGM: images of {} x {} pixels are too large",
+                width, height
+            )))
+        }
+    };
+    let values: Vec<usize> = if is_binary {
+        // exactly one whitespace character ends the header
+        let bytes_per_value = if max_value < 256 { 1 } else { 2 };
+        let length = count * bytes_per_value;
+        let mut bytes = Vec::new();
+        reader.take(length as u64).read_to_end(&mut bytes)?;
+        if bytes.len() < length {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        bytes
+            .chunks(bytes_per_value)
+            .map(|value| value.iter().fold(0, |sum, &byte| sum * 256 + usize::from(byte)))
+            .collect()
+    } else {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let values = strip_comments(&text)
+            .split_whitespace()
+            .map(|token| {
+                token
+                    .parse()
+                    .map_err(|_| format_error(format!("invalid grey value '{}'", token)))
+            })
+            .collect::<Result<Vec<usize>, ImageError>>()?;
+        if values.len() < count {
+            return Err(format_error(format!(
+                "{} grey values expected but found {}",
+                count,
+                values.len()
+            )));
+        }
+        values
+    };
+
+    let mut pixels = Vec::with_capacity(count);
+    for &value in values.iter().take(count) {
+        if value > max_value {
+            return Err(format_error(format!(
+                "grey value {} is above the maximum {}",
+                value, max_value
+            )));
+        }
+        pixels.push((value * 255 / max_value) as u8);
+    }
+    Ok(GrayImage::new(width, height, pixels))
+}
+
+fn format_error(message: String) -> ImageError {
+    ImageError::Format(format!("PGM: {}", message))
+}
+
+fn strip_comments(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Next whitespace separated token of the header - comments are skipped.
+/// Consumes the single whitespace character after the token.
+fn header_token<R: BufRead>(reader: &mut R) -> Result<String, ImageError> {
+    let mut token = String::new();
+    let mut byte = [0u8];
+    loop {
+        reader.read_exact(&mut byte)?;
+        match byte[0] {
+            b'#' => {
+                let mut comment = Vec::new();
+                reader.read_until(b'\n', &mut comment)?;
+                if !token.is_empty() {
+                    return Ok(token);
+                }
+            }
+            byte if byte.is_ascii_whitespace() => {
+                if !token.is_empty() {
+                    return Ok(token);
+                }
+            }
+            byte => token.push(char::from(byte)),
+        }
+    }
+}
+
+fn header_number<R: BufRead>(reader: &mut R, name: &str) -> Result<usize, ImageError> {
+    let token = header_token(reader)?;
+    token
+        .parse()
+        .map_err(|_| format_error(format!("invalid {} '{}'", name, token)))
+}
+
+#[cfg(test)]
+mod pgm_tests {
+    use super::*;
+
+    #[test]
+    fn test_plain() {
+        let text = "P2\n# a comment\n3 2\n15\n0 15 3 # values\n 6 9 12\n";
+        let image = read(text.as_bytes()).unwrap();
+
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(image.pixels(), &[0, 255, 51, 102, 153, 204]);
+    }
+
+    #[test]
+    fn test_binary() {
+        let mut bytes = b"P5 2 2 255\n".to_vec();
+        bytes.extend_from_slice(&[0, 32, 10, 255]);
+        assert_eq!(read(&bytes[..]).unwrap().pixels(), &[0, 32, 10, 255]);
+
+        let mut wide = b"P5 2 1 65535\n".to_vec();
+        wide.extend_from_slice(&[0xff, 0xff, 0x80, 0x00]);
+        assert_eq!(read(&wide[..]).unwrap().pixels(), &[255, 127]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let message = |bytes: &[u8]| read(bytes).unwrap_err().to_string();
+
+        assert_eq!(
+            message(b"P2 2 1 255 0"),
+            "invalid image: PGM: 2 grey values expected but found 1"
+        );
+        assert_eq!(
+            message(b"P2 1 1 9 10"),
+            "invalid image: PGM: grey value 10 is above the maximum 9"
+        );
+        assert_eq!(message(b"P2 x 1 255 0"), "invalid image: PGM: invalid width 'x'");
+        assert_eq!(message(b"P5 2 2 255\n\x01"), "invalid image: data ends too early");
+        assert_eq!(message(b"P2 0 0 255\n"), "invalid image: PGM: image is empty");
+        assert_eq!(message(b"P5 3 0 255\n"), "invalid image: PGM: image is empty");
+        assert_eq!(
+            message(b"P5 18446744073709551615 2 255\n"),
+            "unsupported image: PGM: images of 18446744073709551615 x 2 pixels are too large"
+        );
+        assert_eq!(
+            message(b"P5 100000 100000 255\n"),
+            "unsupported image: PGM: images of 100000 x 100000 pixels are too large"
+        );
+    }
+}
@@ -0,0 +1,376 @@
+//! PNG images of all colour types without interlacing. Colours are turned
+//! into grey values by their luminance, transparent pixels are put on white
+//! paper.
+
+use super::{GrayImage, ImageError};
+use flate2::read::ZlibDecoder;
+use std::io::prelude::*;
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+const GRAY: u8 = 0;
+const RGB: u8 = 2;
+const PALETTE: u8 = 3;
+const GRAY_ALPHA: u8 = 4;
+const RGBA: u8 = 6;
+
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            GRAY | PALETTE => 1,
+            GRAY_ALPHA => 2,
+            RGB => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * usize::from(self.bit_depth)
+    }
+
+    fn row_bytes(&self) -> usize {
+        (self.width * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+pub fn read<R: BufRead>(mut reader: R) -> Result<GrayImage, ImageError> {
+    let mut signature = [0u8; 8];
+    reader.read_exact(&mut signature)?;
+    if signature != SIGNATURE {
+        return Err(format_error("wrong signature".to_owned()));
+    }
+
+    let mut header: Option<Header> = None;
+    let mut palette: Vec<[u8; 3]> = Vec::new();
+    let mut compressed = Vec::new();
+    loop {
+        let (kind, data) = read_chunk(&mut reader)?;
+        match &kind {
+            b"IHDR" => header = Some(read_header(&data)?),
+            b"PLTE" => {
+                palette = data
+                    .chunks(3)
+                    .filter(|colour| colour.len() == 3)
+                    .map(|colour| [colour[0], colour[1], colour[2]])
+                    .collect()
+            }
+            b"IDAT" => compressed.extend_from_slice(&data),
+            b"IEND" => break,
+            // ancillary chunks like gamma or text do not change the grey values
+            _ if kind[0].is_ascii_lowercase() => {}
+            _ => {
+                return Err(ImageError::Unsupported(format!(
+                    "PNG: critical chunk {}",
+                    String::from_utf8_lossy(&kind)
+                )))
+            }
+        }
+    }
+    let header = header.ok_or_else(|| format_error("IHDR chunk is missing".to_owned()))?;
+    if header.color_type == PALETTE && palette.is_empty() {
+        return Err(format_error("PLTE chunk is missing".to_owned()));
+    }
+
+    let mut data = Vec::new();
+    ZlibDecoder::new(&compressed[..])
+        .read_to_end(&mut data)
+        .map_err(|e| format_error(format!("could not inflate image data: {}", e)))?;
+    let rows = unfilter(&header, &data)?;
+
+    let mut pixels = Vec::with_capacity(header.width * header.height);
+    for row in &rows {
+        for x in 0..header.width {
+            pixels.push(gray_value(&header, &palette, row, x)?);
+        }
+    }
+    Ok(GrayImage::new(header.width, header.height, pixels))
+}
+
+fn format_error(message: String) -> ImageError {
+    ImageError::Format(format!("PNG: {}", message))
+}
+
+fn read_chunk<R: Read>(reader: &mut R) -> Result<([u8; 4], Vec<u8>), ImageError> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+
+    let mut kind = [0u8; 4];
+    reader.read_exact(&mut kind)?;
+    let mut data = Vec::new();
+    reader.take(length as u64).read_to_end(&mut data)?;
+    if data.len() != length {
+        return Err(format_error("data ends too early".to_owned()));
+    }
+
+    let mut crc = [0u8; 4];
+    reader.read_exact(&mut crc)?;
+    let expected = crc32(kind.iter().chain(&data));
+    if u32::from_be_bytes(crc) != expected {
+        return Err(format_error(format!(
+            "checksum of chunk {} is wrong",
+            String::from_utf8_lossy(&kind)
+        )));
+    }
+    Ok((kind, data))
+}
+
+fn read_header(data: &[u8]) -> Result<Header, ImageError> {
+    if data.len() != 13 {
+        return Err(format_error("IHDR chunk has a wrong length".to_owned()));
+    }
+    let header = Header {
+        width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize,
+        height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]) as usize,
+        bit_depth: data[8],
+        color_type: data[9],
+    };
+    let valid_depths: &[u8] = match header.color_type {
+        GRAY => &[1, 2, 4, 8, 16],
+        PALETTE => &[1, 2, 4, 8],
+        RGB | GRAY_ALPHA | RGBA => &[8, 16],
+        other => return Err(format_error(format!("unknown colour type {}", other))),
+    };
+    if !valid_depths.contains(&header.bit_depth) {
+        return Err(format_error(format!(
+            "bit depth {} is not allowed for colour type {}",
+            header.bit_depth, header.color_type
+        )));
+    }
+    if header.width == 0 || header.height == 0 {
+        return Err(format_error("image is empty".to_owned()));
+    }
+    if data[10] != 0 || data[11] != 0 {
+        return Err(format_error("unknown compression or filter method".to_owned()));
+    }
+    if data[12] != 0 {
+        return Err(ImageError::Unsupported("PNG: interlaced images".to_owned()));
+    }
+    Ok(header)
+}
+
+/// Reverts the filter every row starts with.
+fn unfilter(header: &Header, data: &[u8]) -> Result<Vec<Vec<u8>>, ImageError> {
+    let row_bytes = header.row_bytes();
+    if data.len() < (row_bytes + 1) * header.height {
+        return Err(format_error("image data ends too early".to_owned()));
+    }
+    // distance to the corresponding byte of the pixel on the left
+    let distance = header.bits_per_pixel().div_ceil(8);
+
+    let mut rows: Vec<Vec<u8>> = Vec::with_capacity(header.height);
+    let empty = vec![0u8; row_bytes];
+    for line in data.chunks(row_bytes + 1).take(header.height) {
+        let (filter, filtered) = (line[0], &line[1..]);
+        let previous = rows.last().unwrap_or(&empty);
+        let mut row = vec![0u8; row_bytes];
+
+        for index in 0..row_bytes {
+            let left = if index >= distance { row[index - distance] } else { 0 };
+            let up = previous[index];
+            let up_left = if index >= distance { previous[index - distance] } else { 0 };
+            let prediction = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                other => return Err(format_error(format!("unknown filter type {}", other))),
+            };
+            row[index] = filtered[index].wrapping_add(prediction);
+        }
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let distance_left = (estimate - i16::from(left)).abs();
+    let distance_up = (estimate - i16::from(up)).abs();
+    let distance_up_left = (estimate - i16::from(up_left)).abs();
+
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Channel `channel` of pixel `x` scaled to 0..255 (palette images: the index).
+fn sample(header: &Header, row: &[u8], x: usize, channel: usize) -> u8 {
+    let depth = usize::from(header.bit_depth);
+    let index = x * header.channels() + channel;
+    match depth {
+        16 => row[index * 2],
+        8 => row[index],
+        _ => {
+            let bit = index * depth;
+            let value = (row[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1) as u8;
+            if header.color_type == PALETTE {
+                value
+            } else {
+                (usize::from(value) * 255 / ((1 << depth) - 1)) as u8
+            }
+        }
+    }
+}
+
+fn gray_value(
+    header: &Header,
+    palette: &[[u8; 3]],
+    row: &[u8],
+    x: usize,
+) -> Result<u8, ImageError> {
+    let luminance = |r: u8, g: u8, b: u8| {
+        0.299 * f64::from(r) + 0.587 * f64::from(g) + 0.114 * f64::from(b)
+    };
+    // on white paper
+    let blend = |value: f64, alpha: u8| {
+        let alpha = f64::from(alpha) / 255.0;
+        (value * alpha + 255.0 * (1.0 - alpha)).round() as u8
+    };
+    let channel = |channel| sample(header, row, x, channel);
+
+    Ok(match header.color_type {
+        GRAY => channel(0),
+        GRAY_ALPHA => blend(f64::from(channel(0)), channel(1)),
+        RGB => luminance(channel(0), channel(1), channel(2)).round() as u8,
+        RGBA => blend(luminance(channel(0), channel(1), channel(2)), channel(3)),
+        _ => {
+            let index = usize::from(channel(0));
+            let [r, g, b] = *palette
+                .get(index)
+                .ok_or_else(|| format_error(format!("palette has no colour {}", index)))?;
+            luminance(r, g, b).round() as u8
+        }
+    })
+}
+
+/// CRC-32 as used by PNG (and zlib, gzip).
+pub fn crc32<'a, I: IntoIterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod png_tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+        png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        png.extend_from_slice(kind);
+        png.extend_from_slice(data);
+        png.extend_from_slice(&crc32(kind.iter().chain(data)).to_be_bytes());
+    }
+
+    /// A PNG of the already filtered rows.
+    fn encode(width: u32, height: u32, bit_depth: u8, color_type: u8, rows: &[Vec<u8>]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for row in rows {
+            encoder.write_all(row).unwrap();
+        }
+
+        let mut png = SIGNATURE.to_vec();
+        chunk(&mut png, b"IHDR", &header);
+        chunk(&mut png, b"tEXt", b"Comment\0ignored");
+        if color_type == PALETTE {
+            chunk(&mut png, b"PLTE", &[0, 0, 0, 255, 255, 255]);
+        }
+        chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        chunk(&mut png, b"IEND", &[]);
+        png
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_gray_with_filters() {
+        // sub, up and paeth filter
+        let rows = vec![
+            vec![1, 10, 10, 10],
+            vec![2, 30, 30, 30],
+            vec![4, 30, 30, 30],
+        ];
+        let image = read(&encode(3, 3, 8, GRAY, &rows)[..]).unwrap();
+        assert_eq!(image.pixels(), &[10, 20, 30, 40, 50, 60, 70, 100, 130]);
+
+        let average = vec![vec![0, 10, 20], vec![3, 15, 5]];
+        let image = read(&encode(2, 2, 8, GRAY, &average)[..]).unwrap();
+        assert_eq!(image.pixels(), &[10, 20, 20, 25]);
+    }
+
+    #[test]
+    fn test_colour_types() {
+        let rgb = vec![vec![0, 255, 0, 0, 255, 255, 255]];
+        assert_eq!(read(&encode(2, 1, 8, RGB, &rgb)[..]).unwrap().pixels(), &[76, 255]);
+
+        // transparent black is white paper
+        let rgba = vec![vec![0, 0, 0, 0, 0, 0, 0, 0, 255]];
+        assert_eq!(read(&encode(2, 1, 8, RGBA, &rgba)[..]).unwrap().pixels(), &[255, 0]);
+
+        let gray_alpha = vec![vec![0, 0x12, 0x34, 0x00, 0x00, 0x80, 0x00, 0xff, 0xff]];
+        let image = read(&encode(2, 1, 16, GRAY_ALPHA, &gray_alpha)[..]).unwrap();
+        assert_eq!(image.pixels(), &[255, 128]);
+
+        let bits = vec![vec![0, 0b1001_0000]];
+        assert_eq!(read(&encode(4, 1, 1, GRAY, &bits)[..]).unwrap().pixels(), &[255, 0, 0, 255]);
+        assert_eq!(read(&encode(4, 1, 1, PALETTE, &bits)[..]).unwrap().pixels(), &[255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut png = encode(1, 1, 8, GRAY, &[vec![0, 0]]);
+        let last = png.len() - 1;
+        png[last] ^= 1;
+        assert_eq!(
+            read(&png[..]).unwrap_err().to_string(),
+            "invalid image: PNG: checksum of chunk IEND is wrong"
+        );
+
+        let short = encode(2, 2, 8, GRAY, &[vec![0, 0, 0]]);
+        assert_eq!(
+            read(&short[..]).unwrap_err().to_string(),
+            "invalid image: PNG: image data ends too early"
+        );
+
+        // the interlace method is the last byte of IHDR
+        let mut interlaced = encode(1, 1, 8, GRAY, &[vec![0, 0]]);
+        interlaced[28] = 1;
+        let crc = crc32(&interlaced[12..29]);
+        interlaced[29..33].copy_from_slice(&crc.to_be_bytes());
+        assert_eq!(
+            read(&interlaced[..]).unwrap_err().to_string(),
+            "unsupported image: PNG: interlaced images"
+        );
+    }
+}
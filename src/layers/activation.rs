@@ -0,0 +1,333 @@
+//! Element-wise non-linearity between two layers.
+
+use super::{copy_into, map_into, reset, Layer, Parameter};
+use matrix::error::MathError;
+use matrix::Matrix;
+use model::{ModelError, TextReader};
+use std::io::prelude::*;
+use std::rc::Rc;
+use util;
+use Mode;
+
+/// The functions an `Activation` layer can apply. They are named so that a
+/// saved layer can be loaded again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActivationFunction {
+    Sigmoid,
+    Relu,
+    Tanh,
+}
+
+impl ActivationFunction {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ActivationFunction::Sigmoid => "sigmoid",
+            ActivationFunction::Relu => "relu",
+            ActivationFunction::Tanh => "tanh",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ActivationFunction> {
+        match name {
+            "sigmoid" => Some(ActivationFunction::Sigmoid),
+            "relu" => Some(ActivationFunction::Relu),
+            "tanh" => Some(ActivationFunction::Tanh),
+            _ => None,
+        }
+    }
+
+    pub fn apply(&self, x: f64) -> f64 {
+        match *self {
+            ActivationFunction::Sigmoid => util::sigmoid(x),
+            ActivationFunction::Relu => util::relu(x),
+            ActivationFunction::Tanh => x.tanh(),
+        }
+    }
+
+    /// Derivative at the input `x` which gave the output `y`.
+    fn derivative(&self, x: f64, y: f64) -> f64 {
+        match *self {
+            ActivationFunction::Sigmoid => y * (1.0 - y),
+            ActivationFunction::Relu => {
+                if x > 0.0 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            ActivationFunction::Tanh => 1.0 - y * y,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activation {
+    function: ActivationFunction,
+    /// Inputs and outputs of the last `forward`.
+    last: Option<(Matrix, Matrix)>,
+    input_gradients: Matrix,
+}
+
+impl Activation {
+    pub fn new(function: ActivationFunction) -> Activation {
+        Activation {
+            function,
+            last: None,
+            input_gradients: Matrix::zeros(0, 0),
+        }
+    }
+
+    pub fn sigmoid() -> Activation {
+        Activation::new(ActivationFunction::Sigmoid)
+    }
+
+    pub fn relu() -> Activation {
+        Activation::new(ActivationFunction::Relu)
+    }
+
+    pub fn tanh() -> Activation {
+        Activation::new(ActivationFunction::Tanh)
+    }
+
+    pub fn function(&self) -> ActivationFunction {
+        self.function
+    }
+
+    /// Reads a layer written by `write_to`.
+    pub fn read_from<R: BufRead>(reader: &mut TextReader<R>) -> Result<Activation, ModelError> {
+        let line = reader.expect("activation", 1)?;
+        match ActivationFunction::from_name(line.get(0)) {
+            Some(function) => Ok(Activation::new(function)),
+            None => Err(reader.error(format!("unknown activation function '{}'", line.get(0)))),
+        }
+    }
+}
+
+impl Layer for Activation {
+    fn forward(&mut self, batch: &Matrix, _mode: Mode) -> Result<&Matrix, MathError> {
+        if batch.rows() == 0 {
+            return Err(MathError);
+        }
+        let function = self.function;
+        let last = self
+            .last
+            .get_or_insert_with(|| (Matrix::zeros(0, 0), Matrix::zeros(0, 0)));
+        copy_into(&mut last.0, batch);
+        map_into(&mut last.1, batch, |x| function.apply(x));
+        Ok(&last.1)
+    }
+
+    fn predict(&self, batch: &Matrix) -> Result<Matrix, MathError> {
+        if batch.rows() == 0 {
+            return Err(MathError);
+        }
+        let mut outputs = Matrix::zeros(0, 0);
+        map_into(&mut outputs, batch, |x| self.function.apply(x));
+        Ok(outputs)
+    }
+
+    fn backward(&mut self, output_gradients: &Matrix) -> Result<&Matrix, MathError> {
+        let (inputs, outputs) = match self.last {
+            Some((ref inputs, ref outputs)) => (inputs, outputs),
+            None => return Err(MathError),
+        };
+        if output_gradients.rows() != inputs.rows()
+            || output_gradients.columns() != inputs.columns()
+        {
+            return Err(MathError);
+        }
+        reset(&mut self.input_gradients, inputs.rows(), inputs.columns());
+        let rows = inputs
+            .data_container()
+            .iter()
+            .zip(outputs.data_container())
+            .zip(output_gradients.data_container());
+        let function = self.function;
+        for (row, ((inputs, outputs), gradients)) in rows.enumerate() {
+            let cells = inputs.iter().zip(outputs).zip(gradients);
+            let row = self.input_gradients.row_mut(row);
+            for (cell, ((&x, &y), &gradient)) in row.iter_mut().zip(cells) {
+                *cell = function.derivative(x, y) * gradient;
+            }
+        }
+        Ok(&self.input_gradients)
+    }
+
+    fn parameters(&mut self) -> Vec<Parameter<'_>> {
+        Vec::new()
+    }
+
+    fn output_shape(&self, input_features: usize) -> Result<usize, MathError> {
+        Ok(input_features)
+    }
+
+    /// `activation <name>`
+    fn write_to(&self, writer: &mut dyn Write) -> Result<(), ModelError> {
+        writeln!(writer, "activation {}", self.function.name())?;
+        Ok(())
+    }
+}
+
+/// Any function as an activation, with its derivative by the output - like
+/// `util::sigmoid_derivative`. This is how `NeuralNetwork` runs the function
+/// it was built with. A closure cannot be saved, so only the keyword is
+/// written and whoever reads the layer passes the function again.
+pub struct Function<F> {
+    function: Rc<F>,
+    derivative: fn(f64) -> f64,
+    /// Outputs of the last `forward`.
+    outputs: Option<Matrix>,
+    input_gradients: Matrix,
+}
+
+impl<F> Function<F> {
+    pub fn new(function: Rc<F>, derivative: fn(f64) -> f64) -> Function<F> {
+        Function {
+            function,
+            derivative,
+            outputs: None,
+            input_gradients: Matrix::zeros(0, 0),
+        }
+    }
+
+    pub fn set_derivative(&mut self, derivative: fn(f64) -> f64) {
+        self.derivative = derivative;
+    }
+
+    /// Reads a layer written by `write_to`, which runs `function`.
+    pub fn read_from<R: BufRead>(
+        reader: &mut TextReader<R>,
+        function: Rc<F>,
+        derivative: fn(f64) -> f64,
+    ) -> Result<Function<F>, ModelError> {
+        reader.expect("function", 0)?;
+        Ok(Function::new(function, derivative))
+    }
+}
+
+impl<F> Clone for Function<F> {
+    fn clone(&self) -> Function<F> {
+        Function {
+            function: Rc::clone(&self.function),
+            derivative: self.derivative,
+            outputs: self.outputs.clone(),
+            input_gradients: self.input_gradients.clone(),
+        }
+    }
+}
+
+impl<F: Fn(f64) -> f64 + 'static> Layer for Function<F> {
+    fn forward(&mut self, batch: &Matrix, _mode: Mode) -> Result<&Matrix, MathError> {
+        if batch.rows() == 0 {
+            return Err(MathError);
+        }
+        let function = &*self.function;
+        let outputs = self.outputs.get_or_insert_with(|| Matrix::zeros(0, 0));
+        map_into(outputs, batch, function);
+        Ok(outputs)
+    }
+
+    fn predict(&self, batch: &Matrix) -> Result<Matrix, MathError> {
+        if batch.rows() == 0 {
+            return Err(MathError);
+        }
+        let mut outputs = Matrix::zeros(0, 0);
+        map_into(&mut outputs, batch, &*self.function);
+        Ok(outputs)
+    }
+
+    fn backward(&mut self, output_gradients: &Matrix) -> Result<&Matrix, MathError> {
+        let outputs = match self.outputs {
+            Some(ref outputs) => outputs,
+            None => return Err(MathError),
+        };
+        if output_gradients.rows() != outputs.rows()
+            || output_gradients.columns() != outputs.columns()
+        {
+            return Err(MathError);
+        }
+        reset(&mut self.input_gradients, outputs.rows(), outputs.columns());
+        let rows = outputs.data_container().iter().zip(output_gradients.data_container());
+        let derivative = self.derivative;
+        for (row, (outputs, gradients)) in rows.enumerate() {
+            let row = self.input_gradients.row_mut(row);
+            for (cell, (&y, &gradient)) in row.iter_mut().zip(outputs.iter().zip(gradients)) {
+                *cell = derivative(y) * gradient;
+            }
+        }
+        Ok(&self.input_gradients)
+    }
+
+    fn parameters(&mut self) -> Vec<Parameter<'_>> {
+        Vec::new()
+    }
+
+    fn output_shape(&self, input_features: usize) -> Result<usize, MathError> {
+        Ok(input_features)
+    }
+
+    /// `function`
+    fn write_to(&self, writer: &mut dyn Write) -> Result<(), ModelError> {
+        writeln!(writer, "function")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod activation_tests {
+    use super::*;
+    use gradient_check::GradientChecker;
+    use layers::read_layer;
+
+    #[test]
+    fn test_forward() {
+        let batch = Matrix::from_2d_vec(&[vec![-1.0, 0.0, 2.0]]);
+        assert_eq!(
+            *Activation::relu().forward(&batch, Mode::Train).unwrap(),
+            Matrix::from_2d_vec(&[vec![0.0, 0.0, 2.0]])
+        );
+        assert_eq!(
+            Activation::sigmoid()
+                .forward(&batch, Mode::Train)
+                .unwrap()
+                .data_container()[0][1],
+            0.5
+        );
+        assert_eq!(Activation::tanh().output_shape(7).unwrap(), 7);
+    }
+
+    #[test]
+    fn test_gradients() {
+        let batch = Matrix::from_2d_vec(&[vec![-1.2, 0.3, 2.0], vec![0.7, -0.4, 1.1]]);
+        for &function in &[
+            ActivationFunction::Sigmoid,
+            ActivationFunction::Relu,
+            ActivationFunction::Tanh,
+        ] {
+            let mut layer = Activation::new(function);
+            let check = GradientChecker::new()
+                .check_layer(&mut layer, &batch, Mode::Train)
+                .unwrap();
+            assert!(check.passed(1e-6), "{}\n{}", function.name(), check);
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut text = Vec::new();
+        Activation::tanh().write_to(&mut text).unwrap();
+        assert_eq!(text, b"activation tanh\n");
+        let mut reader = TextReader::new(&b"activation tanh\nactivation step\n"[..]);
+        let mut layer = read_layer(&mut reader).unwrap();
+        let batch = Matrix::from_2d_vec(&[vec![0.5]]);
+        assert_eq!(
+            layer.forward(&batch, Mode::Eval).unwrap().flatten(),
+            vec![0.5f64.tanh()]
+        );
+
+        assert_eq!(
+            read_layer(&mut reader).err().unwrap().to_string(),
+            "invalid model in line 2: unknown activation function 'step'"
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Fully connected layer: every output is a weighted sum of all inputs plus
+//! a bias.
+
+use super::{copy_into, reset, Layer, Parameter};
+use matrix::error::MathError;
+use matrix::simd;
+use matrix::sparse::SparseMatrix;
+use matrix::Matrix;
+use model::{write_values, ModelError, TextReader};
+use std::io::prelude::*;
+use util::SeededRng;
+use Mode;
+
+/// The inputs of the last `forward`, for the gradients of the weights.
+#[derive(Debug, Clone, PartialEq)]
+enum Batch {
+    None,
+    Dense(Matrix),
+    /// Only the non zero inputs count.
+    Sparse(SparseMatrix),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dense {
+    inputs: usize,
+    outputs: usize,
+    /// One row of `inputs` weights per output, row after row.
+    weights: Vec<f64>,
+    bias: Vec<f64>,
+    weight_gradients: Vec<f64>,
+    bias_gradients: Vec<f64>,
+    last_batch: Batch,
+    forward_outputs: Matrix,
+    input_gradients: Matrix,
+}
+
+impl Dense {
+    /// Small random weights and a bias of 0.
+    pub fn new(inputs: usize, outputs: usize) -> Dense {
+        Dense::with_weights(Matrix::create_weighting_matrix(outputs, inputs))
+    }
+
+    /// Same as `new` but the weights are always the same for a seed.
+    pub fn with_seed(inputs: usize, outputs: usize, seed: u64) -> Dense {
+        let mut rng = SeededRng::new(seed);
+        Dense::with_weights(Matrix::create_seeded_weighting_matrix(
+            outputs, inputs, &mut rng,
+        ))
+    }
+
+    /// `weights` has one row per output and one column per input, like the
+    /// weightings of `NeuralNetwork`.
+    pub fn from_weights(weights: &Matrix, bias: Vec<f64>) -> Result<Dense, MathError> {
+        if bias.len() != weights.rows() {
+            return Err(MathError);
+        }
+        let mut layer = Dense::with_weights(weights.clone());
+        layer.bias = bias;
+        Ok(layer)
+    }
+
+    fn with_weights(weights: Matrix) -> Dense {
+        let (inputs, outputs) = (weights.columns(), weights.rows());
+        Dense {
+            inputs,
+            outputs,
+            weights: weights.flatten(),
+            bias: vec![0.0; outputs],
+            weight_gradients: vec![0.0; inputs * outputs],
+            bias_gradients: vec![0.0; outputs],
+            last_batch: Batch::None,
+            forward_outputs: Matrix::zeros(0, 0),
+            input_gradients: Matrix::zeros(0, 0),
+        }
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    pub fn weights(&self) -> Matrix {
+        let data: Vec<Vec<f64>> = (0..self.outputs)
+            .map(|output| self.weight_row(output).to_vec())
+            .collect();
+        Matrix::from_2d_vec(&data)
+    }
+
+    /// The weights row after row, as `parameters` gives them.
+    pub(crate) fn weight_values(&self) -> &[f64] {
+        &self.weights
+    }
+
+    pub fn bias(&self) -> &[f64] {
+        &self.bias
+    }
+
+    fn weight_row(&self, output: usize) -> &[f64] {
+        &self.weights[output * self.inputs..(output + 1) * self.inputs]
+    }
+
+    /// Reads a layer written by `write_to`.
+    pub fn read_from<R: BufRead>(reader: &mut TextReader<R>) -> Result<Dense, ModelError> {
+        let line = reader.expect("dense", 2)?;
+        let (inputs, outputs): (usize, usize) = (line.parse(0)?, line.parse(1)?);
+        let weights = reader.values("weights")?;
+        let bias = reader.values("bias")?;
+        if weights.len() != inputs * outputs || bias.len() != outputs {
+            return Err(reader.error(format!(
+                "dense {} {} needs {} weights and {} biases",
+                inputs,
+                outputs,
+                inputs * outputs,
+                outputs
+            )));
+        }
+
+        let mut layer = Dense::with_weights(Matrix::zeros(outputs, inputs));
+        layer.weights = weights;
+        layer.bias = bias;
+        Ok(layer)
+    }
+
+    /// Sums the gradients of weights and biases over the samples of the last
+    /// batch - and fills `input_gradients` if they are needed.
+    fn accumulate(
+        &mut self,
+        output_gradients: &Matrix,
+        input_gradients: bool,
+    ) -> Result<(), MathError> {
+        let samples = match self.last_batch {
+            Batch::Dense(ref batch) => batch.rows(),
+            Batch::Sparse(ref batch) => batch.rows(),
+            Batch::None => return Err(MathError),
+        };
+        if output_gradients.rows() != samples || output_gradients.columns() != self.outputs {
+            return Err(MathError);
+        }
+
+        for gradient in self
+            .weight_gradients
+            .iter_mut()
+            .chain(&mut self.bias_gradients)
+        {
+            *gradient = 0.0;
+        }
+        for gradients in output_gradients.data_container() {
+            simd::axpy(1.0, gradients, &mut self.bias_gradients);
+        }
+        match self.last_batch {
+            Batch::Dense(ref batch) => {
+                let samples = batch.data_container().iter().zip(output_gradients.data_container());
+                for (inputs, gradients) in samples {
+                    for (output, &gradient) in gradients.iter().enumerate() {
+                        let range = output * self.inputs..(output + 1) * self.inputs;
+                        simd::axpy(gradient, inputs, &mut self.weight_gradients[range]);
+                    }
+                }
+            }
+            Batch::Sparse(ref batch) => {
+                for (sample, input, value) in batch.iter() {
+                    let gradients = &output_gradients.data_container()[sample];
+                    for (output, gradient) in gradients.iter().enumerate() {
+                        self.weight_gradients[output * self.inputs + input] += gradient * value;
+                    }
+                }
+            }
+            Batch::None => {}
+        }
+
+        if input_gradients {
+            reset(&mut self.input_gradients, samples, self.inputs);
+            for (sample, gradients) in output_gradients.data_container().iter().enumerate() {
+                for (output, &gradient) in gradients.iter().enumerate() {
+                    let range = output * self.inputs..(output + 1) * self.inputs;
+                    simd::axpy(
+                        gradient,
+                        &self.weights[range],
+                        self.input_gradients.row_mut(sample),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Layer for Dense {
+    fn forward(&mut self, batch: &Matrix, _mode: Mode) -> Result<&Matrix, MathError> {
+        if batch.columns() != self.inputs || batch.rows() == 0 {
+            return Err(MathError);
+        }
+        match self.last_batch {
+            Batch::Dense(ref mut last) => copy_into(last, batch),
+            _ => self.last_batch = Batch::Dense(batch.clone()),
+        }
+        reset(&mut self.forward_outputs, batch.rows(), self.outputs);
+        weighted_sums(&self.weights, &self.bias, batch, &mut self.forward_outputs);
+        Ok(&self.forward_outputs)
+    }
+
+    fn forward_sparse(&mut self, batch: &SparseMatrix, _mode: Mode) -> Result<&Matrix, MathError> {
+        if batch.columns() != self.inputs || batch.rows() == 0 {
+            return Err(MathError);
+        }
+        self.last_batch = Batch::Sparse(batch.clone());
+        reset(&mut self.forward_outputs, batch.rows(), self.outputs);
+        sparse_weighted_sums(&self.weights, &self.bias, batch, &mut self.forward_outputs);
+        Ok(&self.forward_outputs)
+    }
+
+    fn predict(&self, batch: &Matrix) -> Result<Matrix, MathError> {
+        if batch.columns() != self.inputs || batch.rows() == 0 {
+            return Err(MathError);
+        }
+        let mut outputs = Matrix::zeros(batch.rows(), self.outputs);
+        weighted_sums(&self.weights, &self.bias, batch, &mut outputs);
+        Ok(outputs)
+    }
+
+    fn predict_sparse(&self, batch: &SparseMatrix) -> Result<Matrix, MathError> {
+        if batch.columns() != self.inputs || batch.rows() == 0 {
+            return Err(MathError);
+        }
+        let mut outputs = Matrix::zeros(batch.rows(), self.outputs);
+        sparse_weighted_sums(&self.weights, &self.bias, batch, &mut outputs);
+        Ok(outputs)
+    }
+
+    fn backward(&mut self, output_gradients: &Matrix) -> Result<&Matrix, MathError> {
+        self.accumulate(output_gradients, true)?;
+        Ok(&self.input_gradients)
+    }
+
+    fn backward_parameters(&mut self, output_gradients: &Matrix) -> Result<(), MathError> {
+        self.accumulate(output_gradients, false)
+    }
+
+    fn parameters(&mut self) -> Vec<Parameter<'_>> {
+        vec![
+            Parameter {
+                name: "weights",
+                values: &mut self.weights,
+                gradients: &self.weight_gradients,
+            },
+            Parameter {
+                name: "bias",
+                values: &mut self.bias,
+                gradients: &self.bias_gradients,
+            },
+        ]
+    }
+
+    fn visit_parameters(&mut self, visit: &mut dyn FnMut(Parameter)) {
+        visit(Parameter {
+            name: "weights",
+            values: &mut self.weights,
+            gradients: &self.weight_gradients,
+        });
+        visit(Parameter {
+            name: "bias",
+            values: &mut self.bias,
+            gradients: &self.bias_gradients,
+        });
+    }
+
+    fn output_shape(&self, input_features: usize) -> Result<usize, MathError> {
+        if input_features != self.inputs {
+            return Err(MathError);
+        }
+        Ok(self.outputs)
+    }
+
+    /// `dense <inputs> <outputs>`, then the weights row after row and the
+    /// biases.
+    fn write_to(&self, mut writer: &mut dyn Write) -> Result<(), ModelError> {
+        writeln!(writer, "dense {} {}", self.inputs, self.outputs)?;
+        write_values(&mut writer, "weights", &self.weights)?;
+        write_values(&mut writer, "bias", &self.bias)?;
+        Ok(())
+    }
+}
+
+/// `batch x weights^T + bias` into `outputs`, which has the right shape.
+/// `weights` has one row of `batch.columns()` values per output.
+fn weighted_sums(weights: &[f64], bias: &[f64], batch: &Matrix, outputs: &mut Matrix) {
+    let inputs = batch.columns();
+    for (sample, values) in batch.data_container().iter().enumerate() {
+        for (output, sum) in outputs.row_mut(sample).iter_mut().enumerate() {
+            let row = &weights[output * inputs..(output + 1) * inputs];
+            *sum = simd::dot(values, row) + bias[output];
+        }
+    }
+}
+
+/// Same as `weighted_sums` but only the non zero inputs are touched.
+fn sparse_weighted_sums(
+    weights: &[f64],
+    bias: &[f64],
+    batch: &SparseMatrix,
+    outputs: &mut Matrix,
+) {
+    let inputs = batch.columns();
+    for sample in 0..outputs.rows() {
+        outputs.row_mut(sample).copy_from_slice(bias);
+    }
+    for (sample, input, value) in batch.iter() {
+        for (output, sum) in outputs.row_mut(sample).iter_mut().enumerate() {
+            *sum += weights[output * inputs + input] * value;
+        }
+    }
+}
+
+#[cfg(test)]
+mod dense_tests {
+    use super::*;
+    use gradient_check::GradientChecker;
+    use layers::read_layer;
+
+    #[test]
+    fn test_forward() {
+        let weights = Matrix::from_2d_vec(&[vec![1.0, 2.0], vec![0.0, -1.0], vec![0.5, 0.5]]);
+        let mut layer = Dense::from_weights(&weights, vec![0.0, 1.0, -1.0]).unwrap();
+        assert_eq!(layer.output_shape(2).unwrap(), 3);
+        assert!(layer.output_shape(3).is_err());
+
+        let batch = Matrix::from_2d_vec(&[vec![1.0, 1.0], vec![2.0, 0.0]]);
+        let output = layer.forward(&batch, Mode::Train).unwrap();
+        assert_eq!(
+            *output,
+            Matrix::from_2d_vec(&[vec![3.0, 0.0, 0.0], vec![2.0, 1.0, 0.0]])
+        );
+        assert_eq!(layer.weights(), weights);
+        assert!(layer.forward(&Matrix::zeros(1, 3), Mode::Train).is_err());
+    }
+
+    #[test]
+    fn test_gradients() {
+        let mut layer = Dense::with_seed(4, 3, 1);
+        let batch = Matrix::from_2d_vec(&[vec![0.5, -1.0, 2.0, 0.1], vec![1.5, 0.0, -0.3, 0.7]]);
+        let check = GradientChecker::new()
+            .check_layer(&mut layer, &batch, Mode::Train)
+            .unwrap();
+        assert!(check.passed(1e-6), "{}", check);
+        assert_eq!(check.layers.len(), 3);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let layer = Dense::with_seed(3, 2, 4);
+        let mut text = Vec::new();
+        layer.write_to(&mut text).unwrap();
+        let mut loaded = read_layer(&mut TextReader::new(&text[..])).unwrap();
+
+        let batch = Matrix::from_2d_vec(&[vec![0.1, 0.2, 0.3]]);
+        assert_eq!(
+            *loaded.forward(&batch, Mode::Eval).unwrap(),
+            layer.predict(&batch).unwrap()
+        );
+
+        let text = b"dense 2 1\nweights 2 1 2\nbias 2 0 0\n";
+        let error = read_layer(&mut TextReader::new(&text[..])).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "invalid model in line 3: dense 2 1 needs 2 weights and 1 biases"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Inverted dropout: while training every value is dropped with the given
+//! rate and the others are scaled up, so inference can use them unchanged.
+
+use super::{copy_into, reset, Layer, Parameter};
+use matrix::error::MathError;
+use matrix::Matrix;
+use model::{ModelError, TextReader};
+use std::io::prelude::*;
+use util::SeededRng;
+use Mode;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dropout {
+    rate: f64,
+    rng: SeededRng,
+    /// Factors of the last `forward`, valid if `dropped` is set.
+    mask: Matrix,
+    dropped: bool,
+    outputs: Matrix,
+    input_gradients: Matrix,
+    last_shape: Option<(usize, usize)>,
+}
+
+impl Dropout {
+    /// The masks are the same for a seed. Panics unless `rate` is in [0, 1).
+    pub fn new(rate: f64, seed: u64) -> Dropout {
+        if !(0.0..1.0).contains(&rate) {
+            panic!("dropout rate has to be in [0, 1) but is {}", rate);
+        }
+        Dropout {
+            rate,
+            rng: SeededRng::new(seed),
+            mask: Matrix::zeros(0, 0),
+            dropped: false,
+            outputs: Matrix::zeros(0, 0),
+            input_gradients: Matrix::zeros(0, 0),
+            last_shape: None,
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Factors of the last `forward` - 0 for dropped and `1 / (1 - rate)`
+    /// for kept values. None if nothing was dropped.
+    pub fn mask(&self) -> Option<&Matrix> {
+        if self.dropped {
+            Some(&self.mask)
+        } else {
+            None
+        }
+    }
+
+    /// Reads a layer written by `write_to`, with the random generator where
+    /// it stopped.
+    pub fn read_from<R: BufRead>(reader: &mut TextReader<R>) -> Result<Dropout, ModelError> {
+        let line = reader.expect("dropout", 2)?;
+        let rate: f64 = line.parse(0)?;
+        if !(0.0..1.0).contains(&rate) {
+            return Err(reader.error(format!("dropout rate has to be in [0, 1) but is {}", rate)));
+        }
+        let mut layer = Dropout::new(rate, 0);
+        layer.rng = SeededRng::new(line.parse(1)?);
+        Ok(layer)
+    }
+}
+
+impl Layer for Dropout {
+    fn forward(&mut self, batch: &Matrix, mode: Mode) -> Result<&Matrix, MathError> {
+        if batch.rows() == 0 {
+            return Err(MathError);
+        }
+        self.last_shape = Some((batch.rows(), batch.columns()));
+        copy_into(&mut self.outputs, batch);
+        self.dropped = mode == Mode::Train && self.rate > 0.0;
+        if !self.dropped {
+            return Ok(&self.outputs);
+        }
+
+        reset(&mut self.mask, batch.rows(), batch.columns());
+        for row in 0..batch.rows() {
+            let mask = self.mask.row_mut(row);
+            draw_dropout_mask(&mut self.rng, self.rate, mask);
+            for (output, factor) in self.outputs.row_mut(row).iter_mut().zip(mask) {
+                *output *= *factor;
+            }
+        }
+        Ok(&self.outputs)
+    }
+
+    fn predict(&self, batch: &Matrix) -> Result<Matrix, MathError> {
+        if batch.rows() == 0 {
+            return Err(MathError);
+        }
+        Ok(batch.clone())
+    }
+
+    fn backward(&mut self, output_gradients: &Matrix) -> Result<&Matrix, MathError> {
+        if self.last_shape != Some((output_gradients.rows(), output_gradients.columns())) {
+            return Err(MathError);
+        }
+        copy_into(&mut self.input_gradients, output_gradients);
+        if self.dropped {
+            for (row, factors) in self.mask.data_container().iter().enumerate() {
+                let gradients = self.input_gradients.row_mut(row);
+                for (gradient, factor) in gradients.iter_mut().zip(factors) {
+                    *gradient *= factor;
+                }
+            }
+        }
+        Ok(&self.input_gradients)
+    }
+
+    fn parameters(&mut self) -> Vec<Parameter<'_>> {
+        Vec::new()
+    }
+
+    fn output_shape(&self, input_features: usize) -> Result<usize, MathError> {
+        Ok(input_features)
+    }
+
+    /// `dropout <rate> <state of the random generator>`
+    fn write_to(&self, writer: &mut dyn Write) -> Result<(), ModelError> {
+        writeln!(writer, "dropout {} {}", self.rate, self.rng.state())?;
+        Ok(())
+    }
+}
+
+/// Fills `mask` with 0 for dropped and `1 / (1 - rate)` for kept units.
+fn draw_dropout_mask(rng: &mut SeededRng, rate: f64, mask: &mut [f64]) {
+    let scale = 1.0 / (1.0 - rate);
+    for factor in mask {
+        *factor = if rng.next_f64() < rate { 0.0 } else { scale };
+    }
+}
+
+#[cfg(test)]
+mod dropout_tests {
+    use super::*;
+    use layers::read_layer;
+
+    #[test]
+    fn test_dropout() {
+        let batch = Matrix::from_2d_vec(&vec![vec![1.0; 50]; 40]);
+        let mut layer = Dropout::new(0.25, 3);
+
+        let output = layer.forward(&batch, Mode::Train).unwrap().flatten();
+        let dropped = output.iter().filter(|&&value| value == 0.0).count();
+        assert!(dropped > 400 && dropped < 600, "{}", dropped);
+        assert!(output
+            .iter()
+            .all(|&value| value == 0.0 || value == 1.0 / 0.75));
+
+        // gradients only flow through what was kept
+        let gradients = layer.backward(&batch).unwrap().flatten();
+        assert_eq!(gradients, output);
+        assert_eq!(layer.mask().unwrap().flatten(), output);
+
+        assert_eq!(*layer.forward(&batch, Mode::Eval).unwrap(), batch);
+        assert_eq!(*layer.backward(&batch).unwrap(), batch);
+        assert!(layer.mask().is_none());
+        assert_eq!(layer.predict(&batch).unwrap(), batch);
+        assert!(layer.backward(&Matrix::zeros(1, 50)).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let batch = Matrix::from_2d_vec(&vec![vec![1.0; 8]; 4]);
+        let mut layer = Dropout::new(0.5, 9);
+        layer.forward(&batch, Mode::Train).unwrap();
+
+        let mut text = Vec::new();
+        layer.write_to(&mut text).unwrap();
+        let mut loaded = read_layer(&mut TextReader::new(&text[..])).unwrap();
+        assert_eq!(
+            *loaded.forward(&batch, Mode::Train).unwrap(),
+            *layer.forward(&batch, Mode::Train).unwrap()
+        );
+
+        let mut reader = TextReader::new(&b"dropout 1 0\n"[..]);
+        assert_eq!(
+            read_layer(&mut reader).err().unwrap().to_string(),
+            "invalid model in line 1: dropout rate has to be in [0, 1) but is 1"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "dropout rate has to be in [0, 1) but is 1")]
+    fn test_invalid_rate() {
+        Dropout::new(1.0, 0);
+    }
+}
@@ -0,0 +1,198 @@
+//! Building blocks of networks that are trained on mini-batches. A batch is
+//! a matrix with one sample per row.
+//!
+//! Every layer implements `Layer`, and `Sequential` chains them. Stacks are
+//! trained with `Sequential::train_batch` and saved with `Sequential::save`,
+//! which only go through the trait, so a new kind of layer needs nothing but
+//! an implementation of it - plus a keyword in `read_layer` to be loadable.
+//!
+//! `NeuralNetwork` is such a stack as well: dense layers with its activation
+//! function after each and dropout where it is set. `Trainer`, `Model` and
+//! the checkpoints reach its parameters through the trait only.
+
+pub mod activation;
+pub mod dense;
+pub mod dropout;
+pub mod normalization;
+pub mod sequential;
+
+pub use self::activation::{Activation, ActivationFunction, Function};
+pub use self::dense::Dense;
+pub use self::dropout::Dropout;
+pub use self::normalization::{BatchNorm, LayerNorm, Normalization, NormalizationGradients};
+pub use self::sequential::Sequential;
+
+use matrix::error::MathError;
+use matrix::sparse::SparseMatrix;
+use matrix::Matrix;
+use model::{ModelError, TextReader};
+use std::any::Any;
+use std::io::prelude::*;
+use Mode;
+
+/// Learnable values of a layer next to their gradients of the last
+/// `backward`.
+pub struct Parameter<'a> {
+    pub name: &'static str,
+    pub values: &'a mut [f64],
+    pub gradients: &'a [f64],
+}
+
+/// Outputs and gradients are kept in buffers of the layers, which are
+/// reused as long as the batch size stays the same - so a training step does
+/// not need to allocate.
+pub trait Layer: LayerObject {
+    /// Outputs for a batch. Remembers what `backward` needs.
+    fn forward(&mut self, batch: &Matrix, mode: Mode) -> Result<&Matrix, MathError>;
+
+    /// Same as `forward` for a sparse batch. Layers that gain nothing from
+    /// the zeros get it as a dense matrix.
+    fn forward_sparse(&mut self, batch: &SparseMatrix, mode: Mode) -> Result<&Matrix, MathError> {
+        self.forward(&batch.to_matrix(), mode)
+    }
+
+    /// Outputs in `Mode::Eval` without changing the layer.
+    fn predict(&self, batch: &Matrix) -> Result<Matrix, MathError>;
+
+    /// Same as `predict` for a sparse batch.
+    fn predict_sparse(&self, batch: &SparseMatrix) -> Result<Matrix, MathError> {
+        self.predict(&batch.to_matrix())
+    }
+
+    /// Gradients by the inputs of the last `forward` for the gradients by
+    /// its outputs. The gradients of the parameters are kept for
+    /// `parameters` - sums over the samples of the batch.
+    fn backward(&mut self, output_gradients: &Matrix) -> Result<&Matrix, MathError>;
+
+    /// Same as `backward` but only for the gradients of the parameters - the
+    /// first layer of a network needs no gradients by its inputs.
+    fn backward_parameters(&mut self, output_gradients: &Matrix) -> Result<(), MathError> {
+        self.backward(output_gradients).map(|_| ())
+    }
+
+    /// Empty for layers without learnable values.
+    fn parameters(&mut self) -> Vec<Parameter<'_>>;
+
+    /// Passes the parameters one by one in the order of `parameters`,
+    /// without collecting them first.
+    fn visit_parameters(&mut self, visit: &mut dyn FnMut(Parameter)) {
+        for parameter in self.parameters() {
+            visit(parameter);
+        }
+    }
+
+    /// Features of every output sample for `input_features` features of
+    /// every input sample, or an error if the layer cannot take those.
+    fn output_shape(&self, input_features: usize) -> Result<usize, MathError>;
+
+    /// Writes the layer in the model text format - `read_layer` reads it.
+    fn write_to(&self, writer: &mut dyn Write) -> Result<(), ModelError>;
+}
+
+/// Copies of boxed layers and access to their concrete type. Every layer
+/// that is `Clone` gets it.
+pub trait LayerObject {
+    fn clone_box(&self) -> Box<dyn Layer>;
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<L: Layer + Clone + 'static> LayerObject for L {
+    fn clone_box(&self) -> Box<dyn Layer> {
+        Box::new(self.clone())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+impl Clone for Box<dyn Layer> {
+    fn clone(&self) -> Box<dyn Layer> {
+        self.clone_box()
+    }
+}
+
+/// Reads any layer that `Layer::write_to` wrote, chosen by its keyword.
+pub fn read_layer<R: BufRead>(reader: &mut TextReader<R>) -> Result<Box<dyn Layer>, ModelError> {
+    let keyword = reader.peek_keyword()?;
+    let layer: Box<dyn Layer> = match keyword.as_str() {
+        "dense" => Box::new(Dense::read_from(reader)?),
+        "activation" => Box::new(Activation::read_from(reader)?),
+        "dropout" => Box::new(Dropout::read_from(reader)?),
+        "batch_norm" => Box::new(BatchNorm::read_from(reader)?),
+        "layer_norm" => Box::new(LayerNorm::read_from(reader)?),
+        "sequential" => Box::new(Sequential::read_from(reader)?),
+        "function" => {
+            return Err(reader.error("the function of a 'function' layer is not stored".to_owned()))
+        }
+        _ => return Err(reader.error(format!("unknown layer '{}'", keyword))),
+    };
+    Ok(layer)
+}
+
+/// `parameter -= learning_rate * gradient` for every parameter.
+pub fn gradient_descent(parameters: Vec<Parameter>, learning_rate: f64) {
+    for parameter in parameters {
+        for (value, gradient) in parameter.values.iter_mut().zip(parameter.gradients) {
+            *value -= learning_rate * gradient;
+        }
+    }
+}
+
+/// Makes `buffer` a `rows` x `columns` matrix of zeros. Its memory is
+/// reused if it has that shape already.
+pub(crate) fn reset(buffer: &mut Matrix, rows: usize, columns: usize) {
+    if buffer.rows() != rows || buffer.columns() != columns {
+        *buffer = Matrix::zeros(rows, columns);
+        return;
+    }
+    for row in 0..rows {
+        for value in buffer.row_mut(row) {
+            *value = 0.0;
+        }
+    }
+}
+
+/// Copies `source` into `buffer`, reusing its memory.
+pub(crate) fn copy_into(buffer: &mut Matrix, source: &Matrix) {
+    reset(buffer, source.rows(), source.columns());
+    for (row, values) in source.data_container().iter().enumerate() {
+        buffer.row_mut(row).copy_from_slice(values);
+    }
+}
+
+/// `f(value)` of every cell into `buffer`.
+fn map_into<F: Fn(f64) -> f64>(buffer: &mut Matrix, matrix: &Matrix, f: F) {
+    reset(buffer, matrix.rows(), matrix.columns());
+    for (row, values) in matrix.data_container().iter().enumerate() {
+        for (cell, &value) in buffer.row_mut(row).iter_mut().zip(values) {
+            *cell = f(value);
+        }
+    }
+}
+
+/// `f(left, right)` of every pair of cells into `buffer` - both need the
+/// same shape.
+fn zip_into<F: Fn(f64, f64) -> f64>(
+    buffer: &mut Matrix,
+    left: &Matrix,
+    right: &Matrix,
+    f: F,
+) -> Result<(), MathError> {
+    if left.rows() != right.rows() || left.columns() != right.columns() {
+        return Err(MathError);
+    }
+    reset(buffer, left.rows(), left.columns());
+    let rows = left.data_container().iter().zip(right.data_container());
+    for (row, (left, right)) in rows.enumerate() {
+        for (cell, (&l, &r)) in buffer.row_mut(row).iter_mut().zip(left.iter().zip(right)) {
+            *cell = f(l, r);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,951 @@
+//! Normalisation of layer inputs: batch normalisation over the samples of a
+//! mini-batch and layer normalisation over the features of every sample.
+//!
+//! Both standardise the values to mean 0 and variance 1 and then learn a
+//! scale `gamma` and a shift `beta` per feature, so that the next layer sees
+//! inputs of a stable range whatever the learning rate did to the ones
+//! before.
+
+use super::{Layer, Parameter};
+use matrix::error::MathError;
+use matrix::Matrix;
+use model::{write_values, ModelError, TextReader};
+use std::io::prelude::*;
+use Mode;
+
+/// Added to the variance so that constant values do not divide by 0.
+pub const DEFAULT_EPSILON: f64 = 1e-5;
+/// Share of the old value when the running statistics are updated.
+pub const DEFAULT_MOMENTUM: f64 = 0.9;
+
+/// Normalisation of the hidden outputs of a `NeuralNetwork`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalization {
+    None,
+    /// `BatchNorm`
+    Batch,
+    /// `LayerNorm`
+    Layer,
+}
+
+impl Normalization {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Normalization::None => "none",
+            Normalization::Batch => "batch",
+            Normalization::Layer => "layer",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Normalization> {
+        match name {
+            "none" => Some(Normalization::None),
+            "batch" => Some(Normalization::Batch),
+            "layer" => Some(Normalization::Layer),
+            _ => None,
+        }
+    }
+}
+
+/// Gradients of the scale and shift of every feature - the sums over the
+/// samples of the batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NormalizationGradients {
+    pub gamma: Vec<f64>,
+    pub beta: Vec<f64>,
+}
+
+impl NormalizationGradients {
+    pub fn zeros(features: usize) -> NormalizationGradients {
+        NormalizationGradients {
+            gamma: vec![0.0; features],
+            beta: vec![0.0; features],
+        }
+    }
+}
+
+/// What `backward` needs of the last forward pass.
+#[derive(Debug, Clone, PartialEq)]
+struct Cache {
+    /// Normalised values lane by lane - features for batch normalisation,
+    /// samples for layer normalisation.
+    normalized: Vec<Vec<f64>>,
+    inv_std: Vec<f64>,
+    /// False if fixed running statistics were used, which do not depend on
+    /// the inputs.
+    batch_statistics: bool,
+}
+
+/// Normalises every feature over the samples of a batch. While training the
+/// batch statistics are used and the running mean and variance updated;
+/// in `Mode::Eval` the running statistics take their place. So they do for
+/// a training batch of one sample, whose variance says nothing - only scale
+/// and shift learn from it then.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchNorm {
+    gamma: Vec<f64>,
+    beta: Vec<f64>,
+    running_mean: Vec<f64>,
+    running_var: Vec<f64>,
+    momentum: f64,
+    epsilon: f64,
+    cache: Option<Cache>,
+    /// Of the last `Layer::backward`.
+    gradients: NormalizationGradients,
+    /// Of the last `Layer::forward` and `Layer::backward`.
+    outputs: Matrix,
+    input_gradients: Matrix,
+}
+
+impl BatchNorm {
+    /// Scale 1, shift 0, running mean 0 and running variance 1.
+    pub fn new(features: usize) -> BatchNorm {
+        BatchNorm {
+            gamma: vec![1.0; features],
+            beta: vec![0.0; features],
+            running_mean: vec![0.0; features],
+            running_var: vec![1.0; features],
+            momentum: DEFAULT_MOMENTUM,
+            epsilon: DEFAULT_EPSILON,
+            cache: None,
+            gradients: NormalizationGradients::zeros(features),
+            outputs: Matrix::zeros(0, 0),
+            input_gradients: Matrix::zeros(0, 0),
+        }
+    }
+
+    /// `running = momentum * running + (1 - momentum) * batch`
+    pub fn momentum(mut self, momentum: f64) -> BatchNorm {
+        self.momentum = check_momentum(momentum).unwrap_or_else(|message| panic!("{}", message));
+        self
+    }
+
+    pub fn epsilon(mut self, epsilon: f64) -> BatchNorm {
+        self.epsilon = check_epsilon(epsilon).unwrap_or_else(|message| panic!("{}", message));
+        self
+    }
+
+    pub fn features(&self) -> usize {
+        self.gamma.len()
+    }
+
+    pub fn gamma(&self) -> &[f64] {
+        &self.gamma
+    }
+
+    pub fn beta(&self) -> &[f64] {
+        &self.beta
+    }
+
+    pub fn running_mean(&self) -> &[f64] {
+        &self.running_mean
+    }
+
+    /// Unbiased estimate of the variance of every feature.
+    pub fn running_var(&self) -> &[f64] {
+        &self.running_var
+    }
+
+    /// A training batch of one sample leaves the running statistics as they
+    /// are.
+    pub fn forward(&mut self, batch: &Matrix, mode: Mode) -> Result<Matrix, MathError> {
+        if batch.columns() != self.features() || batch.rows() == 0 {
+            return Err(MathError);
+        }
+        let samples = batch.rows();
+        let batch_statistics = mode == Mode::Train && samples > 1;
+        let features = batch.transpose();
+        let mut cache = Cache {
+            normalized: Vec::with_capacity(self.features()),
+            inv_std: Vec::with_capacity(self.features()),
+            batch_statistics,
+        };
+
+        for (index, values) in features.data_container().iter().enumerate() {
+            let (mean, var) = if batch_statistics {
+                let (mean, var) = moments(values);
+                let unbiased = var * samples as f64 / (samples - 1) as f64;
+                let momentum = self.momentum;
+                self.running_mean[index] =
+                    momentum * self.running_mean[index] + (1.0 - momentum) * mean;
+                self.running_var[index] =
+                    momentum * self.running_var[index] + (1.0 - momentum) * unbiased;
+                (mean, var)
+            } else {
+                (self.running_mean[index], self.running_var[index])
+            };
+            let (normalized, inv_std) = normalize(values, mean, var, self.epsilon);
+            cache.normalized.push(normalized);
+            cache.inv_std.push(inv_std);
+        }
+
+        let output = scale_and_shift(&cache.normalized, &self.gamma, &self.beta).transpose();
+        self.cache = Some(cache);
+        Ok(output)
+    }
+
+    /// Same as `forward` in `Mode::Eval` but without keeping anything for
+    /// `backward`.
+    pub fn predict(&self, batch: &Matrix) -> Result<Matrix, MathError> {
+        if batch.columns() != self.features() || batch.rows() == 0 {
+            return Err(MathError);
+        }
+        let features = batch.transpose();
+        let normalized: Vec<Vec<f64>> = features
+            .data_container()
+            .iter()
+            .enumerate()
+            .map(|(index, values)| {
+                let (mean, var) = (self.running_mean[index], self.running_var[index]);
+                normalize(values, mean, var, self.epsilon).0
+            })
+            .collect();
+        Ok(scale_and_shift(&normalized, &self.gamma, &self.beta).transpose())
+    }
+
+    /// Gradients by the inputs and by scale and shift for the gradients by
+    /// the outputs of the last `forward`.
+    pub fn backward(
+        &self,
+        output_gradients: &Matrix,
+    ) -> Result<(Matrix, NormalizationGradients), MathError> {
+        let cache = match self.cache {
+            Some(ref cache) => cache,
+            None => return Err(MathError),
+        };
+        if output_gradients.columns() != self.features()
+            || output_gradients.rows() != cache.normalized[0].len()
+        {
+            return Err(MathError);
+        }
+
+        let mut input_gradients = Vec::with_capacity(self.features());
+        let mut gradients = NormalizationGradients {
+            gamma: Vec::with_capacity(self.features()),
+            beta: Vec::with_capacity(self.features()),
+        };
+        let feature_gradients = output_gradients.transpose();
+        for (index, output_gradients) in feature_gradients.data_container().iter().enumerate() {
+            let normalized = &cache.normalized[index];
+            gradients.gamma.push(dot(output_gradients, normalized));
+            gradients.beta.push(output_gradients.iter().sum());
+
+            let gamma = self.gamma[index];
+            let normalized_gradients: Vec<f64> = output_gradients
+                .iter()
+                .map(|gradient| gradient * gamma)
+                .collect();
+            input_gradients.push(if cache.batch_statistics {
+                normalize_backward(normalized, cache.inv_std[index], &normalized_gradients)
+            } else {
+                let inv_std = cache.inv_std[index];
+                normalized_gradients
+                    .iter()
+                    .map(|gradient| gradient * inv_std)
+                    .collect()
+            });
+        }
+        Ok((Matrix::from_2d_vec(&input_gradients).transpose(), gradients))
+    }
+
+    /// `parameter -= learning_rate * gradient` for scale and shift.
+    pub fn apply_gradients(
+        &mut self,
+        gradients: &NormalizationGradients,
+        learning_rate: f64,
+    ) -> Result<(), MathError> {
+        descend(&mut self.gamma, &mut self.beta, gradients, learning_rate)
+    }
+
+    /// Scale, shift and running statistics in the model text format.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), ModelError> {
+        writeln!(
+            writer,
+            "batch_norm {} {} {}",
+            self.features(),
+            self.momentum,
+            self.epsilon
+        )?;
+        write_values(writer, "gamma", &self.gamma)?;
+        write_values(writer, "beta", &self.beta)?;
+        write_values(writer, "running_mean", &self.running_mean)?;
+        write_values(writer, "running_var", &self.running_var)?;
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(reader: &mut TextReader<R>) -> Result<BatchNorm, ModelError> {
+        let line = reader.expect("batch_norm", 3)?;
+        let features = line.parse(0)?;
+        let momentum = check_momentum(line.parse(1)?).map_err(|message| reader.error(message))?;
+        let epsilon = check_epsilon(line.parse(2)?).map_err(|message| reader.error(message))?;
+        let layer = BatchNorm {
+            momentum,
+            epsilon,
+            gamma: read_values(reader, "gamma", features)?,
+            beta: read_values(reader, "beta", features)?,
+            running_mean: read_values(reader, "running_mean", features)?,
+            running_var: read_values(reader, "running_var", features)?,
+            cache: None,
+            gradients: NormalizationGradients::zeros(features),
+            outputs: Matrix::zeros(0, 0),
+            input_gradients: Matrix::zeros(0, 0),
+        };
+        Ok(layer)
+    }
+}
+
+impl Layer for BatchNorm {
+    fn forward(&mut self, batch: &Matrix, mode: Mode) -> Result<&Matrix, MathError> {
+        self.outputs = BatchNorm::forward(self, batch, mode)?;
+        Ok(&self.outputs)
+    }
+
+    fn predict(&self, batch: &Matrix) -> Result<Matrix, MathError> {
+        BatchNorm::predict(self, batch)
+    }
+
+    fn backward(&mut self, output_gradients: &Matrix) -> Result<&Matrix, MathError> {
+        let (input_gradients, gradients) = BatchNorm::backward(self, output_gradients)?;
+        self.input_gradients = input_gradients;
+        self.gradients = gradients;
+        Ok(&self.input_gradients)
+    }
+
+    fn parameters(&mut self) -> Vec<Parameter<'_>> {
+        scale_and_shift_parameters(&mut self.gamma, &mut self.beta, &self.gradients)
+    }
+
+    fn output_shape(&self, input_features: usize) -> Result<usize, MathError> {
+        if input_features != self.features() {
+            return Err(MathError);
+        }
+        Ok(input_features)
+    }
+
+    fn write_to(&self, mut writer: &mut dyn Write) -> Result<(), ModelError> {
+        BatchNorm::write_to(self, &mut writer)
+    }
+}
+
+/// Normalises every sample over its features - independent of the other
+/// samples, so it works the same for every batch size and in both modes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerNorm {
+    gamma: Vec<f64>,
+    beta: Vec<f64>,
+    epsilon: f64,
+    cache: Option<Cache>,
+    /// Of the last `Layer::backward`.
+    gradients: NormalizationGradients,
+    /// Of the last `Layer::forward` and `Layer::backward`.
+    outputs: Matrix,
+    input_gradients: Matrix,
+}
+
+impl LayerNorm {
+    /// Scale 1 and shift 0.
+    pub fn new(features: usize) -> LayerNorm {
+        LayerNorm {
+            gamma: vec![1.0; features],
+            beta: vec![0.0; features],
+            epsilon: DEFAULT_EPSILON,
+            cache: None,
+            gradients: NormalizationGradients::zeros(features),
+            outputs: Matrix::zeros(0, 0),
+            input_gradients: Matrix::zeros(0, 0),
+        }
+    }
+
+    pub fn epsilon(mut self, epsilon: f64) -> LayerNorm {
+        self.epsilon = check_epsilon(epsilon).unwrap_or_else(|message| panic!("{}", message));
+        self
+    }
+
+    pub fn features(&self) -> usize {
+        self.gamma.len()
+    }
+
+    pub fn gamma(&self) -> &[f64] {
+        &self.gamma
+    }
+
+    pub fn beta(&self) -> &[f64] {
+        &self.beta
+    }
+
+    pub fn forward(&mut self, batch: &Matrix) -> Result<Matrix, MathError> {
+        let (output, cache) = self.normalize_samples(batch)?;
+        self.cache = Some(cache);
+        Ok(output)
+    }
+
+    /// Same as `forward` but without keeping anything for `backward`.
+    pub fn predict(&self, batch: &Matrix) -> Result<Matrix, MathError> {
+        self.normalize_samples(batch).map(|(output, _)| output)
+    }
+
+    fn normalize_samples(&self, batch: &Matrix) -> Result<(Matrix, Cache), MathError> {
+        if batch.columns() != self.features() || batch.rows() == 0 {
+            return Err(MathError);
+        }
+        let mut cache = Cache {
+            normalized: Vec::with_capacity(batch.rows()),
+            inv_std: Vec::with_capacity(batch.rows()),
+            batch_statistics: true,
+        };
+        for values in batch.data_container() {
+            let (mean, var) = moments(values);
+            let (normalized, inv_std) = normalize(values, mean, var, self.epsilon);
+            cache.normalized.push(normalized);
+            cache.inv_std.push(inv_std);
+        }
+
+        let normalized = Matrix::from_2d_vec(&cache.normalized).transpose();
+        let output = scale_and_shift(normalized.data_container(), &self.gamma, &self.beta);
+        Ok((output.transpose(), cache))
+    }
+
+    /// Gradients by the inputs and by scale and shift for the gradients by
+    /// the outputs of the last `forward`.
+    pub fn backward(
+        &self,
+        output_gradients: &Matrix,
+    ) -> Result<(Matrix, NormalizationGradients), MathError> {
+        let cache = match self.cache {
+            Some(ref cache) => cache,
+            None => return Err(MathError),
+        };
+        if output_gradients.columns() != self.features()
+            || output_gradients.rows() != cache.normalized.len()
+        {
+            return Err(MathError);
+        }
+
+        let mut gradients = NormalizationGradients {
+            gamma: vec![0.0; self.features()],
+            beta: vec![0.0; self.features()],
+        };
+        let mut input_gradients = Vec::with_capacity(output_gradients.rows());
+        for (sample, output_gradients) in output_gradients.data_container().iter().enumerate() {
+            let normalized = &cache.normalized[sample];
+            for (feature, gradient) in output_gradients.iter().enumerate() {
+                gradients.gamma[feature] += gradient * normalized[feature];
+                gradients.beta[feature] += gradient;
+            }
+            let normalized_gradients: Vec<f64> = output_gradients
+                .iter()
+                .zip(&self.gamma)
+                .map(|(gradient, gamma)| gradient * gamma)
+                .collect();
+            input_gradients.push(normalize_backward(
+                normalized,
+                cache.inv_std[sample],
+                &normalized_gradients,
+            ));
+        }
+        Ok((Matrix::from_2d_vec(&input_gradients), gradients))
+    }
+
+    /// `parameter -= learning_rate * gradient` for scale and shift.
+    pub fn apply_gradients(
+        &mut self,
+        gradients: &NormalizationGradients,
+        learning_rate: f64,
+    ) -> Result<(), MathError> {
+        descend(&mut self.gamma, &mut self.beta, gradients, learning_rate)
+    }
+
+    /// Scale and shift in the model text format.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), ModelError> {
+        writeln!(writer, "layer_norm {} {}", self.features(), self.epsilon)?;
+        write_values(writer, "gamma", &self.gamma)?;
+        write_values(writer, "beta", &self.beta)?;
+        Ok(())
+    }
+
+    pub fn read_from<R: BufRead>(reader: &mut TextReader<R>) -> Result<LayerNorm, ModelError> {
+        let line = reader.expect("layer_norm", 2)?;
+        let features = line.parse(0)?;
+        let epsilon = check_epsilon(line.parse(1)?).map_err(|message| reader.error(message))?;
+        let layer = LayerNorm {
+            epsilon,
+            gamma: read_values(reader, "gamma", features)?,
+            beta: read_values(reader, "beta", features)?,
+            cache: None,
+            gradients: NormalizationGradients::zeros(features),
+            outputs: Matrix::zeros(0, 0),
+            input_gradients: Matrix::zeros(0, 0),
+        };
+        Ok(layer)
+    }
+}
+
+impl Layer for LayerNorm {
+    /// The same in both modes.
+    fn forward(&mut self, batch: &Matrix, _mode: Mode) -> Result<&Matrix, MathError> {
+        self.outputs = LayerNorm::forward(self, batch)?;
+        Ok(&self.outputs)
+    }
+
+    fn predict(&self, batch: &Matrix) -> Result<Matrix, MathError> {
+        LayerNorm::predict(self, batch)
+    }
+
+    fn backward(&mut self, output_gradients: &Matrix) -> Result<&Matrix, MathError> {
+        let (input_gradients, gradients) = LayerNorm::backward(self, output_gradients)?;
+        self.input_gradients = input_gradients;
+        self.gradients = gradients;
+        Ok(&self.input_gradients)
+    }
+
+    fn parameters(&mut self) -> Vec<Parameter<'_>> {
+        scale_and_shift_parameters(&mut self.gamma, &mut self.beta, &self.gradients)
+    }
+
+    fn output_shape(&self, input_features: usize) -> Result<usize, MathError> {
+        if input_features != self.features() {
+            return Err(MathError);
+        }
+        Ok(input_features)
+    }
+
+    fn write_to(&self, mut writer: &mut dyn Write) -> Result<(), ModelError> {
+        LayerNorm::write_to(self, &mut writer)
+    }
+}
+
+fn scale_and_shift_parameters<'a>(
+    gamma: &'a mut [f64],
+    beta: &'a mut [f64],
+    gradients: &'a NormalizationGradients,
+) -> Vec<Parameter<'a>> {
+    vec![
+        Parameter {
+            name: "gamma",
+            values: gamma,
+            gradients: &gradients.gamma,
+        },
+        Parameter {
+            name: "beta",
+            values: beta,
+            gradients: &gradients.beta,
+        },
+    ]
+}
+
+fn check_momentum(momentum: f64) -> Result<f64, String> {
+    if !(0.0..1.0).contains(&momentum) {
+        return Err(format!("momentum has to be in [0, 1) but is {}", momentum));
+    }
+    Ok(momentum)
+}
+
+fn check_epsilon(epsilon: f64) -> Result<f64, String> {
+    if epsilon.is_nan() || epsilon <= 0.0 {
+        return Err(format!("epsilon has to be positive but is {}", epsilon));
+    }
+    Ok(epsilon)
+}
+
+fn read_values<R: BufRead>(
+    reader: &mut TextReader<R>,
+    keyword: &str,
+    count: usize,
+) -> Result<Vec<f64>, ModelError> {
+    let values = reader.values(keyword)?;
+    if values.len() != count {
+        return Err(reader.error(format!("{} needs {} values", keyword, count)));
+    }
+    Ok(values)
+}
+
+/// Mean and biased variance.
+fn moments(values: &[f64]) -> (f64, f64) {
+    let count = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / count;
+    let var = values
+        .iter()
+        .map(|value| (value - mean) * (value - mean))
+        .sum::<f64>()
+        / count;
+    (mean, var)
+}
+
+/// `(values - mean) / sqrt(var + epsilon)` and `1 / sqrt(var + epsilon)`.
+fn normalize(values: &[f64], mean: f64, var: f64, epsilon: f64) -> (Vec<f64>, f64) {
+    let inv_std = 1.0 / (var + epsilon).sqrt();
+    (
+        values
+            .iter()
+            .map(|value| (value - mean) * inv_std)
+            .collect(),
+        inv_std,
+    )
+}
+
+/// Gradient by the values of one lane that was normalised with its own mean
+/// and variance, for the gradients by the normalised values.
+fn normalize_backward(normalized: &[f64], inv_std: f64, gradients: &[f64]) -> Vec<f64> {
+    let count = normalized.len() as f64;
+    let sum: f64 = gradients.iter().sum();
+    let projection = dot(gradients, normalized);
+    gradients
+        .iter()
+        .zip(normalized)
+        .map(|(gradient, value)| inv_std / count * (count * gradient - sum - value * projection))
+        .collect()
+}
+
+/// `gamma * value + beta` of every lane - one lane per feature.
+fn scale_and_shift(lanes: &[Vec<f64>], gamma: &[f64], beta: &[f64]) -> Matrix {
+    let data: Vec<Vec<f64>> = lanes
+        .iter()
+        .zip(gamma.iter().zip(beta))
+        .map(|(values, (gamma, beta))| values.iter().map(|value| gamma * value + beta).collect())
+        .collect();
+    Matrix::from_2d_vec(&data)
+}
+
+fn descend(
+    gamma: &mut [f64],
+    beta: &mut [f64],
+    gradients: &NormalizationGradients,
+    learning_rate: f64,
+) -> Result<(), MathError> {
+    if gradients.gamma.len() != gamma.len() || gradients.beta.len() != beta.len() {
+        return Err(MathError);
+    }
+    for (value, gradient) in gamma.iter_mut().zip(&gradients.gamma) {
+        *value -= learning_rate * gradient;
+    }
+    for (value, gradient) in beta.iter_mut().zip(&gradients.beta) {
+        *value -= learning_rate * gradient;
+    }
+    Ok(())
+}
+
+fn dot(left: &[f64], right: &[f64]) -> f64 {
+    left.iter()
+        .zip(right)
+        .map(|(left, right)| left * right)
+        .sum()
+}
+
+#[cfg(test)]
+mod normalization_tests {
+    use super::*;
+    use gradient_check::GradientChecker;
+    use layers::read_layer;
+    use util::SeededRng;
+
+    const EPSILON: f64 = 1e-6;
+
+    fn random_matrix(rng: &mut SeededRng, rows: usize, columns: usize) -> Matrix {
+        let data: Vec<Vec<f64>> = (0..rows)
+            .map(|_| (0..columns).map(|_| rng.range(-2.0, 3.0)).collect())
+            .collect();
+        Matrix::from_2d_vec(&data)
+    }
+
+    fn assert_close(analytic: &[f64], numerical: &[f64]) {
+        for (analytic, numerical) in analytic.iter().zip(numerical) {
+            let scale = (analytic.abs() + numerical.abs()).max(1e-8);
+            assert!(
+                (analytic - numerical).abs() / scale < 1e-6,
+                "analytic {} numerical {}",
+                analytic,
+                numerical
+            );
+        }
+    }
+
+    /// Inputs and parameters at which the loss is evaluated.
+    #[derive(Clone)]
+    struct Point {
+        batch: Matrix,
+        gamma: Vec<f64>,
+        beta: Vec<f64>,
+    }
+
+    /// Checks `backward` against central differences of the loss
+    /// `sum(weights * outputs)` by the inputs, scale and shift. `forward`
+    /// returns the outputs and what `backward` gives for `weights`.
+    fn check_gradients<F>(batch: &Matrix, weights: &Matrix, forward: F)
+    where
+        F: Fn(&Point) -> (Matrix, Matrix, NormalizationGradients),
+    {
+        let features = batch.columns();
+        let point = Point {
+            batch: batch.clone(),
+            gamma: (0..features).map(|index| 0.5 + index as f64).collect(),
+            beta: (0..features).map(|index| 0.1 * index as f64).collect(),
+        };
+        let loss = |point: &Point| dot(&forward(point).0.flatten(), &weights.flatten());
+        let numerical = |change: &dyn Fn(&mut Point, f64)| {
+            let (mut plus, mut minus) = (point.clone(), point.clone());
+            change(&mut plus, EPSILON);
+            change(&mut minus, -EPSILON);
+            (loss(&plus) - loss(&minus)) / (2.0 * EPSILON)
+        };
+        let (_, input_gradients, gradients) = forward(&point);
+
+        let mut inputs = Vec::new();
+        for row in 0..batch.rows() {
+            for column in 0..features {
+                inputs.push(numerical(&|point, delta| {
+                    point.batch.row_mut(row)[column] += delta
+                }));
+            }
+        }
+        assert_close(&input_gradients.flatten(), &inputs);
+        let gammas: Vec<f64> = (0..features)
+            .map(|index| numerical(&|point, delta| point.gamma[index] += delta))
+            .collect();
+        assert_close(&gradients.gamma, &gammas);
+        let betas: Vec<f64> = (0..features)
+            .map(|index| numerical(&|point, delta| point.beta[index] += delta))
+            .collect();
+        assert_close(&gradients.beta, &betas);
+    }
+
+    fn with_parameters(mut layer: BatchNorm, gamma: &[f64], beta: &[f64]) -> BatchNorm {
+        layer.gamma = gamma.to_vec();
+        layer.beta = beta.to_vec();
+        layer
+    }
+
+    #[test]
+    fn test_batch_norm_forward() {
+        let batch = Matrix::from_2d_vec(&[vec![1.0, 10.0], vec![3.0, 10.0], vec![5.0, 10.0]]);
+        let mut layer = with_parameters(BatchNorm::new(2), &[2.0, 1.0], &[0.5, -1.0]);
+
+        let output = layer.forward(&batch, Mode::Train).unwrap();
+        let expected = 2.0 * 2.0 / (8.0f64 / 3.0 + DEFAULT_EPSILON).sqrt();
+        assert!((output.data_container()[0][0] - (0.5 - expected)).abs() < 1e-12);
+        assert!((output.data_container()[2][0] - (0.5 + expected)).abs() < 1e-12);
+        // a constant feature becomes the shift
+        assert_eq!(output.data_container()[1][1], -1.0);
+
+        // running statistics with the unbiased variance
+        assert!((layer.running_mean()[0] - 0.3).abs() < 1e-12);
+        assert!((layer.running_var()[0] - (0.9 + 0.1 * 4.0)).abs() < 1e-12);
+        assert!((layer.running_mean()[1] - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_batch_norm_inference() {
+        let mut rng = SeededRng::new(2);
+        let mut layer = BatchNorm::new(3);
+        for _ in 0..60 {
+            let batch = random_matrix(&mut rng, 32, 3);
+            layer.forward(&batch, Mode::Train).unwrap();
+        }
+        // uniform in [-2, 3): mean 0.5, variance 25 / 12
+        for (mean, var) in layer.running_mean().iter().zip(layer.running_var()) {
+            assert!((mean - 0.5).abs() < 0.3, "{}", mean);
+            assert!((var - 25.0 / 12.0).abs() < 0.5, "{}", var);
+        }
+
+        // one sample at a time gives the same as the whole batch
+        let batch = random_matrix(&mut rng, 4, 3);
+        let whole = layer.forward(&batch, Mode::Eval).unwrap();
+        let single = Matrix::from_2d_vec(&[batch.data_container()[2].clone()]);
+        let running_mean = layer.running_mean().to_vec();
+        assert_eq!(
+            layer.forward(&single, Mode::Eval).unwrap().data_container()[0],
+            whole.data_container()[2]
+        );
+        assert_eq!(layer.running_mean(), &running_mean[..]);
+    }
+
+    #[test]
+    fn test_batch_norm_gradients() {
+        let mut rng = SeededRng::new(5);
+        let batch = random_matrix(&mut rng, 5, 3);
+        let weights = random_matrix(&mut rng, 5, 3);
+
+        for &mode in &[Mode::Train, Mode::Eval] {
+            let trained = {
+                let mut layer = BatchNorm::new(3);
+                layer.forward(&batch, Mode::Train).unwrap();
+                layer
+            };
+            check_gradients(&batch, &weights, |point| {
+                let mut layer = with_parameters(trained.clone(), &point.gamma, &point.beta);
+                let output = layer.forward(&point.batch, mode).unwrap();
+                let (input_gradients, gradients) = layer.backward(&weights).unwrap();
+                (output, input_gradients, gradients)
+            });
+        }
+    }
+
+    #[test]
+    fn test_layer_norm() {
+        let batch = Matrix::from_2d_vec(&[vec![1.0, 2.0, 3.0, 6.0]]);
+        let mut layer = LayerNorm::new(4);
+        let output = layer.forward(&batch).unwrap();
+
+        let values = output.flatten();
+        let (mean, var) = moments(&values);
+        assert!(mean.abs() < 1e-12);
+        assert!((var - 1.0).abs() < 1e-5);
+
+        let mut rng = SeededRng::new(6);
+        let batch = random_matrix(&mut rng, 3, 4);
+        let weights = random_matrix(&mut rng, 3, 4);
+        check_gradients(&batch, &weights, |point| {
+            let mut layer = LayerNorm::new(4);
+            layer.gamma = point.gamma.clone();
+            layer.beta = point.beta.clone();
+            let output = layer.forward(&point.batch).unwrap();
+            let (input_gradients, gradients) = layer.backward(&weights).unwrap();
+            (output, input_gradients, gradients)
+        });
+    }
+
+    #[test]
+    fn test_apply_gradients() {
+        let mut rng = SeededRng::new(7);
+        let batch = random_matrix(&mut rng, 4, 2);
+        let mut layer = BatchNorm::new(2);
+        layer.forward(&batch, Mode::Train).unwrap();
+
+        // the loss sum(outputs²) falls with every step
+        let mut last = f64::INFINITY;
+        for _ in 0..5 {
+            let output = layer.forward(&batch, Mode::Train).unwrap();
+            let loss: f64 = output.flatten().iter().map(|x| x * x).sum();
+            assert!(loss < last);
+            last = loss;
+            let output_gradients = Matrix::from_2d_vec(
+                &output
+                    .data_container()
+                    .iter()
+                    .map(|row| row.iter().map(|x| 2.0 * x).collect())
+                    .collect::<Vec<Vec<f64>>>(),
+            );
+            let (_, gradients) = layer.backward(&output_gradients).unwrap();
+            layer.apply_gradients(&gradients, 0.05).unwrap();
+        }
+
+        let wrong = NormalizationGradients {
+            gamma: vec![0.0],
+            beta: vec![0.0],
+        };
+        assert!(layer.apply_gradients(&wrong, 0.1).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut rng = SeededRng::new(8);
+        let mut batch_norm = BatchNorm::new(3).momentum(0.8).epsilon(1e-3);
+        let mut layer_norm = LayerNorm::new(3);
+        for _ in 0..3 {
+            let batch = random_matrix(&mut rng, 4, 3);
+            batch_norm.forward(&batch, Mode::Train).unwrap();
+            let (_, gradients) = batch_norm.backward(&batch).unwrap();
+            batch_norm.apply_gradients(&gradients, 0.1).unwrap();
+            layer_norm.forward(&batch).unwrap();
+            let (_, gradients) = layer_norm.backward(&batch).unwrap();
+            layer_norm.apply_gradients(&gradients, 0.1).unwrap();
+        }
+
+        let mut text = Vec::new();
+        batch_norm.write_to(&mut text).unwrap();
+        layer_norm.write_to(&mut text).unwrap();
+        let mut reader = TextReader::new(&text[..]);
+        let mut loaded_batch_norm = BatchNorm::read_from(&mut reader).unwrap();
+        let mut loaded_layer_norm = LayerNorm::read_from(&mut reader).unwrap();
+
+        let batch = random_matrix(&mut rng, 2, 3);
+        assert_eq!(
+            loaded_batch_norm.forward(&batch, Mode::Eval).unwrap(),
+            batch_norm.forward(&batch, Mode::Eval).unwrap()
+        );
+        assert_eq!(loaded_batch_norm, batch_norm);
+        assert_eq!(
+            loaded_layer_norm.forward(&batch).unwrap(),
+            layer_norm.forward(&batch).unwrap()
+        );
+
+        let mut reader = TextReader::new(&b"layer_norm 2 0.00001\ngamma 1 1\n"[..]);
+        assert_eq!(
+            LayerNorm::read_from(&mut reader).unwrap_err().to_string(),
+            "invalid model in line 2: gamma needs 2 values"
+        );
+
+        let mut reader = TextReader::new(&b"batch_norm 2 1 0.00001\n"[..]);
+        assert_eq!(
+            BatchNorm::read_from(&mut reader).unwrap_err().to_string(),
+            "invalid model in line 1: momentum has to be in [0, 1) but is 1"
+        );
+        let mut reader = TextReader::new(&b"layer_norm 2 -0\n"[..]);
+        assert_eq!(
+            LayerNorm::read_from(&mut reader).unwrap_err().to_string(),
+            "invalid model in line 1: epsilon has to be positive but is -0"
+        );
+    }
+
+    #[test]
+    fn test_layer() {
+        let mut rng = SeededRng::new(9);
+        let batch = random_matrix(&mut rng, 4, 3);
+        let checker = GradientChecker::new();
+
+        let mut batch_norm = BatchNorm::new(3);
+        let check = checker
+            .check_layer(&mut batch_norm, &batch, Mode::Train)
+            .unwrap();
+        assert!(check.passed(1e-6), "{}", check);
+        let mut layer_norm = LayerNorm::new(3);
+        let check = checker
+            .check_layer(&mut layer_norm, &batch, Mode::Eval)
+            .unwrap();
+        assert!(check.passed(1e-6), "{}", check);
+
+        assert_eq!(Layer::output_shape(&batch_norm, 3).unwrap(), 3);
+        assert!(Layer::output_shape(&layer_norm, 2).is_err());
+        let mut text = Vec::new();
+        Layer::write_to(&batch_norm, &mut text).unwrap();
+        let mut loaded = read_layer(&mut TextReader::new(&text[..])).unwrap();
+        assert_eq!(
+            *loaded.forward(&batch, Mode::Eval).unwrap(),
+            batch_norm.forward(&batch, Mode::Eval).unwrap()
+        );
+        assert_eq!(
+            loaded.predict(&batch).unwrap(),
+            batch_norm.forward(&batch, Mode::Eval).unwrap()
+        );
+        assert_eq!(
+            Layer::predict(&layer_norm, &batch).unwrap(),
+            layer_norm.forward(&batch).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_shapes() {
+        let mut layer = BatchNorm::new(2);
+        assert!(layer.backward(&Matrix::zeros(2, 2)).is_err());
+        assert!(layer.forward(&Matrix::zeros(3, 3), Mode::Train).is_err());
+        layer.forward(&Matrix::zeros(3, 2), Mode::Train).unwrap();
+        assert!(layer.backward(&Matrix::zeros(2, 2)).is_err());
+        assert!(LayerNorm::new(2).forward(&Matrix::zeros(1, 3)).is_err());
+    }
+
+    #[test]
+    fn test_batch_of_one() {
+        let mut layer = BatchNorm::new(2);
+        let sample = Matrix::from_2d_vec(&[vec![1.0, 2.0]]);
+        let outputs = layer.forward(&sample, Mode::Train).unwrap();
+        assert_eq!(layer.running_mean(), &[0.0, 0.0][..]);
+        assert_eq!(layer.running_var(), &[1.0, 1.0][..]);
+        assert_eq!(outputs, layer.forward(&sample, Mode::Eval).unwrap());
+
+        // the gradients are those of fixed statistics
+        let (input_gradients, gradients) =
+            layer.backward(&Matrix::from_2d_vec(&[vec![1.0, -1.0]])).unwrap();
+        let inv_std = 1.0 / (1.0 + DEFAULT_EPSILON).sqrt();
+        assert_eq!(input_gradients, Matrix::from_2d_vec(&[vec![inv_std, -inv_std]]));
+        assert_eq!(gradients.beta, vec![1.0, -1.0]);
+    }
+}
@@ -0,0 +1,435 @@
+//! A stack of layers where every layer takes the outputs of the one before.
+
+use super::{copy_into, gradient_descent, read_layer, zip_into, Layer, Parameter};
+use gradient_check::squared_error_loss;
+use matrix::error::MathError;
+use matrix::sparse::SparseMatrix;
+use matrix::Matrix;
+use model::{ModelError, TextReader, FORMAT_VERSION};
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use Mode;
+
+/// Chains layers - and is a layer itself, so stacks can be nested.
+#[derive(Clone)]
+pub struct Sequential {
+    input_features: usize,
+    layers: Vec<Box<dyn Layer>>,
+    /// What an empty stack passes on.
+    passed: Matrix,
+}
+
+impl Sequential {
+    /// An empty stack passes its inputs through unchanged.
+    pub fn new(input_features: usize) -> Sequential {
+        Sequential {
+            input_features,
+            layers: Vec::new(),
+            passed: Matrix::zeros(0, 0),
+        }
+    }
+
+    /// Appends a layer. Panics if it cannot take the outputs of the stack.
+    pub fn layer<L: Layer + 'static>(mut self, layer: L) -> Sequential {
+        if self.push(Box::new(layer)).is_err() {
+            panic!(
+                "layer {} cannot take {} features",
+                self.len() + 1,
+                self.output_features()
+            );
+        }
+        self
+    }
+
+    /// Appends a layer if it can take the outputs of the stack.
+    pub fn push(&mut self, layer: Box<dyn Layer>) -> Result<(), MathError> {
+        layer.output_shape(self.output_features())?;
+        self.layers.push(layer);
+        Ok(())
+    }
+
+    pub fn input_features(&self) -> usize {
+        self.input_features
+    }
+
+    pub fn output_features(&self) -> usize {
+        self.layers
+            .iter()
+            .fold(self.input_features, |features, layer| {
+                match layer.output_shape(features) {
+                    Ok(features) => features,
+                    Err(_) => panic!("layers of the same stack do not fit together"),
+                }
+            })
+    }
+
+    pub fn len(&self) -> usize {
+        self.layers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    pub fn layers(&self) -> &[Box<dyn Layer>] {
+        &self.layers
+    }
+
+    /// For changes that keep the stack fitting together - the callers check
+    /// the shapes.
+    pub(crate) fn layers_mut(&mut self) -> &mut Vec<Box<dyn Layer>> {
+        &mut self.layers
+    }
+
+    /// One step of gradient descent on the mean over the samples of the loss
+    /// `1/2 * sum((target - output)²)`, which `NeuralNetwork::train`
+    /// minimises. Gives the loss before the step.
+    pub fn train_batch(
+        &mut self,
+        batch: &Matrix,
+        targets: &Matrix,
+        learning_rate: f64,
+    ) -> Result<f64, MathError> {
+        let samples = batch.rows() as f64;
+        let mut output_gradients = Matrix::zeros(0, 0);
+        let loss = {
+            let outputs = self.forward(batch, Mode::Train)?;
+            zip_into(&mut output_gradients, outputs, targets, |output, target| {
+                (output - target) / samples
+            })?;
+            outputs
+                .data_container()
+                .iter()
+                .zip(targets.data_container())
+                .map(|(outputs, targets)| squared_error_loss(outputs, targets))
+                .sum::<f64>()
+        };
+        self.backward(&output_gradients)?;
+        gradient_descent(self.parameters(), learning_rate);
+        Ok(loss / samples)
+    }
+
+    /// Saves the stack together with the running statistics of its
+    /// normalisation layers, so that it predicts the same after `load`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ModelError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "# neural_network layers")?;
+        writeln!(writer, "version {}", FORMAT_VERSION)?;
+        self.write_to(&mut writer)?;
+        writeln!(writer, "end")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Sequential, ModelError> {
+        let mut reader = TextReader::new(BufReader::new(File::open(path)?));
+        let version: u32 = reader.expect("version", 1)?.parse(0)?;
+        if version != FORMAT_VERSION {
+            return Err(reader.error(format!("unsupported version {}", version)));
+        }
+        let sequential = Sequential::read_from(&mut reader)?;
+        reader.expect("end", 0)?;
+        Ok(sequential)
+    }
+
+    /// Reads a stack written by `write_to`.
+    pub fn read_from<R: BufRead>(reader: &mut TextReader<R>) -> Result<Sequential, ModelError> {
+        Sequential::read_with(reader, read_layer)
+    }
+
+    /// Same as `read_from` but every layer is read by `read_layer`, which
+    /// can handle keywords that the `read_layer` of this module cannot.
+    pub fn read_with<R, F>(
+        reader: &mut TextReader<R>,
+        mut read_layer: F,
+    ) -> Result<Sequential, ModelError>
+    where
+        R: BufRead,
+        F: FnMut(&mut TextReader<R>) -> Result<Box<dyn Layer>, ModelError>,
+    {
+        let line = reader.expect("sequential", 2)?;
+        let (input_features, count): (usize, usize) = (line.parse(0)?, line.parse(1)?);
+
+        let mut sequential = Sequential::new(input_features);
+        for index in 0..count {
+            let layer = read_layer(reader)?;
+            if sequential.push(layer).is_err() {
+                let features = sequential.output_features();
+                return Err(reader.error(format!(
+                    "layer {} cannot take {} features",
+                    index + 1,
+                    features
+                )));
+            }
+        }
+        Ok(sequential)
+    }
+}
+
+impl Layer for Sequential {
+    fn forward(&mut self, batch: &Matrix, mode: Mode) -> Result<&Matrix, MathError> {
+        if batch.columns() != self.input_features {
+            return Err(MathError);
+        }
+        match self.layers.split_first_mut() {
+            Some((first, rest)) => {
+                let mut outputs = first.forward(batch, mode)?;
+                for layer in rest {
+                    outputs = layer.forward(outputs, mode)?;
+                }
+                Ok(outputs)
+            }
+            None => {
+                copy_into(&mut self.passed, batch);
+                Ok(&self.passed)
+            }
+        }
+    }
+
+    /// The first layer gets the sparse batch - a dense layer only touches
+    /// its non zero inputs.
+    fn forward_sparse(&mut self, batch: &SparseMatrix, mode: Mode) -> Result<&Matrix, MathError> {
+        if batch.columns() != self.input_features {
+            return Err(MathError);
+        }
+        match self.layers.split_first_mut() {
+            Some((first, rest)) => {
+                let mut outputs = first.forward_sparse(batch, mode)?;
+                for layer in rest {
+                    outputs = layer.forward(outputs, mode)?;
+                }
+                Ok(outputs)
+            }
+            None => {
+                self.passed = batch.to_matrix();
+                Ok(&self.passed)
+            }
+        }
+    }
+
+    fn predict(&self, batch: &Matrix) -> Result<Matrix, MathError> {
+        if batch.columns() != self.input_features {
+            return Err(MathError);
+        }
+        let mut outputs = batch.clone();
+        for layer in &self.layers {
+            outputs = layer.predict(&outputs)?;
+        }
+        Ok(outputs)
+    }
+
+    fn predict_sparse(&self, batch: &SparseMatrix) -> Result<Matrix, MathError> {
+        if batch.columns() != self.input_features {
+            return Err(MathError);
+        }
+        let (first, rest) = match self.layers.split_first() {
+            Some(layers) => layers,
+            None => return Ok(batch.to_matrix()),
+        };
+        let mut outputs = first.predict_sparse(batch)?;
+        for layer in rest {
+            outputs = layer.predict(&outputs)?;
+        }
+        Ok(outputs)
+    }
+
+    fn backward(&mut self, output_gradients: &Matrix) -> Result<&Matrix, MathError> {
+        match self.layers.split_last_mut() {
+            Some((last, rest)) => {
+                let mut gradients = last.backward(output_gradients)?;
+                for layer in rest.iter_mut().rev() {
+                    gradients = layer.backward(gradients)?;
+                }
+                Ok(gradients)
+            }
+            None => {
+                copy_into(&mut self.passed, output_gradients);
+                Ok(&self.passed)
+            }
+        }
+    }
+
+    fn backward_parameters(&mut self, output_gradients: &Matrix) -> Result<(), MathError> {
+        let (first, rest) = match self.layers.split_first_mut() {
+            Some(layers) => layers,
+            None => return Ok(()),
+        };
+        let mut gradients = output_gradients;
+        for layer in rest.iter_mut().rev() {
+            gradients = layer.backward(gradients)?;
+        }
+        first.backward_parameters(gradients)
+    }
+
+    fn parameters(&mut self) -> Vec<Parameter<'_>> {
+        self.layers
+            .iter_mut()
+            .flat_map(|layer| layer.parameters())
+            .collect()
+    }
+
+    fn visit_parameters(&mut self, visit: &mut dyn FnMut(Parameter)) {
+        for layer in &mut self.layers {
+            layer.visit_parameters(visit);
+        }
+    }
+
+    fn output_shape(&self, input_features: usize) -> Result<usize, MathError> {
+        if input_features != self.input_features {
+            return Err(MathError);
+        }
+        Ok(self.output_features())
+    }
+
+    /// `sequential <input features> <layers>`, then the layers.
+    fn write_to(&self, writer: &mut dyn Write) -> Result<(), ModelError> {
+        writeln!(writer, "sequential {} {}", self.input_features, self.len())?;
+        for layer in &self.layers {
+            layer.write_to(writer)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod sequential_tests {
+    use super::*;
+    use gradient_check::GradientChecker;
+    use layers::{Activation, BatchNorm, Dense, Dropout, LayerNorm};
+    use util::TempFile;
+
+    fn stack() -> Sequential {
+        Sequential::new(3)
+            .layer(Dense::with_seed(3, 6, 1))
+            .layer(BatchNorm::new(6))
+            .layer(Activation::tanh())
+            .layer(Dropout::new(0.2, 2))
+            .layer(Dense::with_seed(6, 4, 3))
+            .layer(LayerNorm::new(4))
+            .layer(Activation::relu())
+            .layer(Dense::with_seed(4, 2, 4))
+            .layer(Activation::sigmoid())
+    }
+
+    /// XOR of the first two inputs, the third one is constant.
+    fn xor() -> (Matrix, Matrix) {
+        let batch = Matrix::from_2d_vec(&[
+            vec![0.0, 0.0, 1.0],
+            vec![0.0, 1.0, 1.0],
+            vec![1.0, 0.0, 1.0],
+            vec![1.0, 1.0, 1.0],
+        ]);
+        let targets = Matrix::from_2d_vec(&[
+            vec![0.1, 0.9],
+            vec![0.9, 0.1],
+            vec![0.9, 0.1],
+            vec![0.1, 0.9],
+        ]);
+        (batch, targets)
+    }
+
+    #[test]
+    fn test_shapes() {
+        let mut sequential = stack();
+        assert_eq!(sequential.len(), 9);
+        assert_eq!(sequential.output_features(), 2);
+        assert_eq!(sequential.output_shape(3).unwrap(), 2);
+        assert!(sequential.output_shape(4).is_err());
+        assert!(sequential.push(Box::new(Dense::new(3, 1))).is_err());
+        assert_eq!(sequential.parameters().len(), 10);
+        assert_eq!(Sequential::new(5).output_features(), 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "layer 2 cannot take 4 features")]
+    fn test_add_wrong_shape() {
+        Sequential::new(3)
+            .layer(Dense::new(3, 4))
+            .layer(Dense::new(3, 2));
+    }
+
+    #[test]
+    fn test_gradients() {
+        let batch = Matrix::from_2d_vec(&[
+            vec![0.3, -1.2, 2.0],
+            vec![1.1, 0.4, -0.7],
+            vec![-0.5, 0.9, 0.2],
+            vec![2.2, -0.1, 1.4],
+        ]);
+        // a bias right before a normalisation has no gradient at all
+        let mut sequential = Sequential::new(3)
+            .layer(Dense::with_seed(3, 5, 1))
+            .layer(Activation::tanh())
+            .layer(BatchNorm::new(5))
+            .layer(
+                Sequential::new(5)
+                    .layer(Dense::with_seed(5, 3, 2))
+                    .layer(Activation::relu()),
+            )
+            .layer(LayerNorm::new(3))
+            .layer(Activation::sigmoid());
+        let check = GradientChecker::new()
+            .check_layer(&mut sequential, &batch, Mode::Train)
+            .unwrap();
+        assert!(check.passed(1e-5), "{}", check);
+    }
+
+    #[test]
+    fn test_train_batch() {
+        let (batch, targets) = xor();
+        let mut sequential = stack();
+
+        let first = sequential.train_batch(&batch, &targets, 0.5).unwrap();
+        let mut last = first;
+        for _ in 0..500 {
+            last = sequential.train_batch(&batch, &targets, 0.5).unwrap();
+        }
+        assert!(last < first / 4.0, "{} -> {}", first, last);
+        assert!(sequential
+            .train_batch(&batch, &Matrix::zeros(4, 3), 0.5)
+            .is_err());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let (batch, targets) = xor();
+        let mut sequential = stack();
+        for _ in 0..5 {
+            sequential.train_batch(&batch, &targets, 0.5).unwrap();
+        }
+
+        let mut text = Vec::new();
+        sequential.write_to(&mut text).unwrap();
+        let mut loaded = read_layer(&mut TextReader::new(&text[..])).unwrap();
+        assert_eq!(loaded.output_shape(3).unwrap(), 2);
+        assert_eq!(
+            *loaded.forward(&batch, Mode::Eval).unwrap(),
+            sequential.predict(&batch).unwrap()
+        );
+        // the dropout masks continue where they stopped
+        assert_eq!(
+            *loaded.forward(&batch, Mode::Train).unwrap(),
+            *sequential.forward(&batch, Mode::Train).unwrap()
+        );
+
+        let path = TempFile::new("layers.txt");
+        sequential.save(&path).unwrap();
+        let loaded = Sequential::load(&path).unwrap();
+        assert_eq!(loaded.predict(&batch).unwrap(), sequential.predict(&batch).unwrap());
+
+        let text = b"sequential 3 2\ndense 3 1\nweights 3 0 0 0\nbias 1 0\n\
+            layer_norm 2 0.1\ngamma 2 1 1\nbeta 2 0 0\n";
+        let error = Sequential::read_from(&mut TextReader::new(&text[..]))
+            .err()
+            .unwrap();
+        assert!(
+            error
+                .to_string()
+                .ends_with("layer 2 cannot take 1 features"),
+            "{}",
+            error
+        );
+    }
+}
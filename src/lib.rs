@@ -1,23 +1,68 @@
+extern crate flate2;
 extern crate rand;
 
 pub mod util;
 pub mod matrix;
 pub mod mnist_data;
+pub mod dataset;
+pub mod preprocessing;
+pub mod model;
+pub mod training;
+pub mod evaluation;
+pub mod config;
+pub mod image;
+pub mod gradient_check;
+pub mod gradients;
+pub mod regularization;
+pub mod layers;
 
 
 use matrix::Matrix;
-use matrix::math;
+use matrix::simd;
+use matrix::sparse::SparseMatrix;
 use matrix::error::*;
+use gradients::Gradients;
+use layers::{BatchNorm, Dense, Dropout, Function, Layer, LayerNorm, Normalization, Sequential};
+use regularization::Regularization;
+use std::rc::Rc;
+use util::SeededRng;
+
+/// Whether training steps drop units and normalise with the statistics of
+/// their batch. `query` never does, so its result is the same in both modes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// `train`, `train_sparse` and `backward` use dropout and update the
+    /// running statistics of batch normalisation.
+    Train,
+    /// Nothing is dropped and the running statistics are used, e.g. for a
+    /// gradient check.
+    Eval,
+}
 
 pub struct NeuralNetwork<T>
 where
     T: Fn(f64) -> f64,
 {
     learning_rate: f64,
-    activation_function: T,
+    activation_function: Rc<T>,
+    activation_derivative: fn(f64) -> f64,
+
+    /// Dense layers input -> hidden and hidden -> output, each followed by
+    /// the activation function, and dropout in front of them where it is set.
+    /// Normalisation comes right after the hidden dense layer.
+    layers: Sequential,
+
+    wih_regularization: Regularization,
+    who_regularization: Regularization,
+    weight_decay: f64,
+
+    mode: Mode,
 
-    wih: Matrix, // weighting: input -> hidden
-    who: Matrix, // weighting: hidden -> output
+    // Reused by every training step so that it does not need to allocate
+    inputs: Matrix,
+    targets: Matrix,
+    final_outputs: Matrix,
+    output_gradients: Matrix,
 }
 
 impl<T> NeuralNetwork<T>
@@ -30,112 +75,557 @@ where
         output_nodes: usize,
         learning_rate: f64,
         activation_function: T,
-    ) -> NeuralNetwork<T> {
-        let wih = Matrix::create_weighting_matrix(input_nodes, hidden_nodes);
-        let who = Matrix::create_weighting_matrix(hidden_nodes, output_nodes);
+    ) -> NeuralNetwork<T>
+    where
+        T: 'static,
+    {
+        let wih = Matrix::create_weighting_matrix(hidden_nodes, input_nodes);
+        let who = Matrix::create_weighting_matrix(output_nodes, hidden_nodes);
+
+        match NeuralNetwork::from_weightings(wih, who, learning_rate, activation_function) {
+            Ok(network) => network,
+            Err(_) => panic!("weightings of the same network do not fit together"),
+        }
+    }
 
+    /// Same as `new` but the weightings are always the same for a seed.
+    pub fn with_seed(
+        input_nodes: usize,
+        hidden_nodes: usize,
+        output_nodes: usize,
+        learning_rate: f64,
+        activation_function: T,
+        seed: u64,
+    ) -> NeuralNetwork<T>
+    where
+        T: 'static,
+    {
+        let mut rng = util::SeededRng::new(seed);
+        let wih = Matrix::create_seeded_weighting_matrix(hidden_nodes, input_nodes, &mut rng);
+        let who = Matrix::create_seeded_weighting_matrix(output_nodes, hidden_nodes, &mut rng);
+
+        match NeuralNetwork::from_weightings(wih, who, learning_rate, activation_function) {
+            Ok(network) => network,
+            Err(_) => panic!("weightings of the same network do not fit together"),
+        }
+    }
+
+    /// Creates a network from existing weightings, e.g. loaded from a file.
+    /// The biases start at 0.
+    pub fn from_weightings(
+        wih: Matrix,
+        who: Matrix,
+        learning_rate: f64,
+        activation_function: T,
+    ) -> Result<NeuralNetwork<T>, MathError>
+    where
+        T: 'static,
+    {
+        if wih.rows() != who.columns() {
+            return Err(MathError);
+        }
+        let activation_function = Rc::new(activation_function);
+        let derivative: fn(f64) -> f64 = util::sigmoid_derivative;
+        let layers = Sequential::new(wih.columns())
+            .layer(Dense::from_weights(&wih, vec![0.0; wih.rows()])?)
+            .layer(Function::new(Rc::clone(&activation_function), derivative))
+            .layer(Dense::from_weights(&who, vec![0.0; who.rows()])?)
+            .layer(Function::new(Rc::clone(&activation_function), derivative));
+        Ok(NeuralNetwork::from_parts(layers, learning_rate, activation_function))
+    }
+
+    /// A network around layers that use `activation_function` with the
+    /// derivative `util::sigmoid_derivative`, e.g. read from a file.
+    pub(crate) fn from_parts(
+        layers: Sequential,
+        learning_rate: f64,
+        activation_function: Rc<T>,
+    ) -> NeuralNetwork<T> {
         NeuralNetwork {
             learning_rate,
             activation_function,
+            activation_derivative: util::sigmoid_derivative,
+
+            layers,
+
+            wih_regularization: Regularization::none(),
+            who_regularization: Regularization::none(),
+            weight_decay: 0.0,
 
-            wih, // weighting: input -> hidden
-            who, // weighting: hidden -> output
+            mode: Mode::Train,
+
+            inputs: Matrix::zeros(0, 0),
+            targets: Matrix::zeros(0, 0),
+            final_outputs: Matrix::zeros(0, 0),
+            output_gradients: Matrix::zeros(0, 0),
         }
     }
 
-    pub fn train(&mut self, inputs: &[f64], awaited_output: &[f64]) -> Result<(), MathError> {
-        let hidden_result = self.calculate_layer_output(inputs, self.wih.data_container());
-        let final_result = self.calculate_layer_output(&hidden_result, self.who.data_container());
-        let output_error = math::subtract_vectors(awaited_output, &final_result);
-
-        let who_adjustment = self.calculate_weighting_adjustment(
-            &output_error,
-            &final_result,
-            &hidden_result,
-        )?;
-        self.who = self.who.add(&Matrix::from_2d_vec(&who_adjustment))?;
-
-        let hidden_error = match self.who.transpose().multiply(&Matrix::from_1d_vec(
-            &output_error,
-            true,
-        )) {
-            Ok(r) => r,
-            Err(e) => panic!("error: {}", e),
-        };
+    pub fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
 
-        // change from two dimensional to one dimensional matrix - Need first row
-        let hidden_error = math::transpose_2d_vector(hidden_error.data_container());
-        let hidden_error = match hidden_error.first() {
-            Some(he) => he,
-            None => panic!("hidden error can never be without a first row!!!"),
-        };
+    /// E.g. for a learning rate schedule.
+    pub fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
 
-        let wih_adjustment = self.calculate_weighting_adjustment(
-            hidden_error,
-            &hidden_result,
-            inputs,
-        )?;
-        self.wih = self.wih.add(&Matrix::from_2d_vec(&wih_adjustment))?;
+    /// Derivative of the activation function, calculated from its output.
+    /// Training needs it, and it is the one of `util::sigmoid` unless set -
+    /// a network with another activation function has to be given its
+    /// derivative, e.g. `util::relu_derivative`.
+    pub fn set_activation_derivative(&mut self, derivative: fn(f64) -> f64)
+    where
+        T: 'static,
+    {
+        self.activation_derivative = derivative;
+        for layer in self.layers.layers_mut() {
+            if let Some(function) = layer.as_any_mut().downcast_mut::<Function<T>>() {
+                function.set_derivative(derivative);
+            }
+        }
+    }
 
-        Ok(())
+    pub fn activation_derivative(&self) -> fn(f64) -> f64 {
+        self.activation_derivative
     }
 
-    pub fn query(&self, inputs: &[f64]) -> Vec<f64> {
-        let hidden_outputs = self.calculate_layer_output(inputs, self.wih.data_container());
-        self.calculate_layer_output(&hidden_outputs, self.who.data_container())
+    /// Penalties of the weighting input -> hidden and hidden -> output.
+    /// They are part of the loss and so of the gradients. The biases are
+    /// not penalised.
+    pub fn set_regularization(&mut self, wih: Regularization, who: Regularization) {
+        self.wih_regularization = wih;
+        self.who_regularization = who;
     }
 
-    fn calculate_layer_output(&self, inputs: &[f64], weighting: &[Vec<f64>]) -> Vec<f64> {
-        let inputs = math::transpose_2d_vector(&[inputs.to_owned()]);
-        let weighted_input = match math::multiply_matrices(weighting, &inputs) {
-            Ok(r) => r,
-            Err(e) => panic!("{}", e),
-        };
+    /// Penalties of the weighting input -> hidden and hidden -> output.
+    pub fn regularization(&self) -> (Regularization, Regularization) {
+        (self.wih_regularization, self.who_regularization)
+    }
+
+    /// Sum of the penalties of both weightings.
+    pub fn regularization_loss(&self) -> f64 {
+        let mut regularization = self.wih_regularization;
+        let mut loss = 0.0;
+        for layer in self.dense_layers() {
+            loss += regularization.penalty(layer.weight_values());
+            regularization = self.who_regularization;
+        }
+        loss
+    }
+
+    /// Every step shrinks each weight by `learning_rate * weight_decay * weight`,
+    /// decoupled from the gradients and not part of the loss.
+    pub fn set_weight_decay(&mut self, weight_decay: f64) {
+        if weight_decay.is_nan() || weight_decay < 0.0 {
+            panic!("weight decay cannot be negative");
+        }
+        self.weight_decay = weight_decay;
+    }
+
+    pub fn weight_decay(&self) -> f64 {
+        self.weight_decay
+    }
+
+    /// Inverted dropout: while training, each input and each hidden output is
+    /// dropped with the rate of its layer and the kept ones are scaled by
+    /// `1 / (1 - rate)`, so inference needs no scaling. The masks come from
+    /// random generators seeded from `seed`.
+    ///
+    /// A `Dropout` layer goes in front of each dense layer whose inputs are
+    /// dropped; a rate of 0 leaves it out.
+    pub fn set_dropout(&mut self, input_rate: f64, hidden_rate: f64, seed: u64) {
+        for &rate in &[input_rate, hidden_rate] {
+            if !(0.0..1.0).contains(&rate) {
+                panic!("dropout rate has to be in [0, 1) but is {}", rate);
+            }
+        }
+        let mut seeds = SeededRng::new(seed);
+        let layers = self.layers.layers_mut();
+        layers.retain(|layer| !layer.as_any().is::<Dropout>());
+
+        let mut rate = input_rate;
+        let mut index = 0;
+        while index < layers.len() {
+            if layers[index].as_any().is::<Dense>() {
+                if rate > 0.0 {
+                    layers.insert(index, Box::new(Dropout::new(rate, seeds.next_u64())));
+                    index += 1;
+                }
+                rate = hidden_rate;
+            }
+            index += 1;
+        }
+    }
+
+    /// Normalises the outputs of the hidden dense layer before the activation
+    /// function, or removes the normalisation again. Scale and shift start
+    /// at 1 and 0.
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        let layers = self.layers.layers_mut();
+        layers.retain(|layer| {
+            let layer = layer.as_any();
+            !(layer.is::<BatchNorm>() || layer.is::<LayerNorm>())
+        });
+
+        let hidden = layers.iter().enumerate().find_map(|(index, layer)| {
+            let dense = layer.as_any().downcast_ref::<Dense>();
+            dense.map(|dense| (index, dense.outputs()))
+        });
+        if let Some((hidden, features)) = hidden {
+            let layer: Box<dyn Layer> = match normalization {
+                Normalization::None => return,
+                Normalization::Batch => Box::new(BatchNorm::new(features)),
+                Normalization::Layer => Box::new(LayerNorm::new(features)),
+            };
+            layers.insert(hidden + 1, layer);
+        }
+    }
 
-        let mut outputs: Vec<f64> = Vec::new();
-        for row in weighted_input {
-            // row.iter().sum() because there is only on value per row #shortcut
-            outputs.push((self.activation_function)(row.iter().sum()));
+    pub fn normalization(&self) -> Normalization {
+        for layer in self.layers.layers() {
+            if layer.as_any().is::<BatchNorm>() {
+                return Normalization::Batch;
+            } else if layer.as_any().is::<LayerNorm>() {
+                return Normalization::Layer;
+            }
         }
-        outputs
+        Normalization::None
+    }
+
+    /// Dropout rates of the inputs and of the hidden outputs.
+    pub fn dropout(&self) -> (f64, f64) {
+        let (mut input_rate, mut hidden_rate) = (0.0, 0.0);
+        let mut first = true;
+        for layer in self.layers.layers() {
+            if let Some(dropout) = layer.as_any().downcast_ref::<Dropout>() {
+                if first {
+                    input_rate = dropout.rate();
+                } else {
+                    hidden_rate = dropout.rate();
+                }
+            }
+            first = first && !layer.as_any().is::<Dense>();
+        }
+        (input_rate, hidden_rate)
+    }
+
+    pub fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn input_nodes(&self) -> usize {
+        self.layers.input_features()
+    }
+
+    pub fn output_nodes(&self) -> usize {
+        self.layers.output_features()
     }
 
-    fn calculate_weighting_adjustment(
-        &self,
-        error: &[f64],
-        output: &[f64],
-        previous_output: &[f64],
-    ) -> Result<Vec<Vec<f64>>, MathError> {
-        let inner_result: Vec<f64> = error
+    /// Weighting input -> hidden
+    pub fn wih(&self) -> Matrix {
+        match self.dense_layers().next() {
+            Some(layer) => layer.weights(),
+            None => panic!("a network needs a dense layer"),
+        }
+    }
+
+    /// Weighting hidden -> output
+    pub fn who(&self) -> Matrix {
+        match self.dense_layers().last() {
+            Some(layer) => layer.weights(),
+            None => panic!("a network needs a dense layer"),
+        }
+    }
+
+    /// Replaces the weightings, e.g. to restore an earlier state. The shapes
+    /// have to stay the same; the biases are kept.
+    pub fn set_weightings(&mut self, wih: Matrix, who: Matrix) -> Result<(), MathError> {
+        let (old_wih, old_who) = (self.wih(), self.who());
+        if wih.rows() != old_wih.rows()
+            || wih.columns() != old_wih.columns()
+            || who.rows() != old_who.rows()
+            || who.columns() != old_who.columns()
+        {
+            return Err(MathError);
+        }
+        let mut dense_layers = self
+            .layers
+            .layers_mut()
+            .iter_mut()
+            .filter_map(|layer| layer.as_any_mut().downcast_mut::<Dense>());
+        if let Some(layer) = dense_layers.next() {
+            *layer = Dense::from_weights(&wih, layer.bias().to_vec())?;
+        }
+        if let Some(layer) = dense_layers.last() {
+            *layer = Dense::from_weights(&who, layer.bias().to_vec())?;
+        }
+        Ok(())
+    }
+
+    /// All layers of the network. `Trainer`, `Model` and the checkpoints go
+    /// through them.
+    pub fn layers(&self) -> &Sequential {
+        &self.layers
+    }
+
+    /// Replaces the layers, e.g. to restore an earlier state. Inputs,
+    /// outputs and the size of every parameter have to stay the same.
+    pub fn set_layers(&mut self, mut layers: Sequential) -> Result<(), MathError> {
+        if layers.input_features() != self.input_nodes()
+            || layers.output_features() != self.output_nodes()
+            || parameter_sizes(&mut layers) != parameter_sizes(&mut self.layers)
+        {
+            return Err(MathError);
+        }
+        self.layers = layers;
+        Ok(())
+    }
+
+    fn dense_layers(&self) -> impl Iterator<Item = &Dense> {
+        self.layers
+            .layers()
             .iter()
-            .zip(output)
-            .map(|(x, y)| x * y * (1.0 - y))
-            .collect();
-        let inner_result = math::transpose_2d_vector(&[inner_result]);
-        let mut outer_result =
-            math::multiply_matrices(&inner_result, &[previous_output.to_owned()])?;
-
-        for row in &mut outer_result {
-            for cell in row.iter_mut() {
-                *cell *= self.learning_rate;
+            .filter_map(|layer| layer.as_any().downcast_ref::<Dense>())
+    }
+
+    /// Outputs of the network in the last `train`, `backward` or
+    /// `backward_batch` - one row per sample, with the dropout masks of that
+    /// step and calculated before the parameters were adjusted.
+    pub fn last_outputs(&self) -> &Matrix {
+        &self.final_outputs
+    }
+
+    /// Runs one training step. The parameters are updated in place and all
+    /// intermediate results live in buffers of the network and its layers -
+    /// no heap allocation happens here.
+    pub fn train(&mut self, inputs: &[f64], awaited_output: &[f64]) -> Result<(), MathError> {
+        if inputs.len() != self.input_nodes() || awaited_output.len() != self.output_nodes() {
+            return Err(MathError);
+        }
+        set_row(&mut self.inputs, inputs);
+        set_row(&mut self.targets, awaited_output);
+
+        let outputs = self.layers.forward(&self.inputs, self.mode)?;
+        let (final_outputs, gradients) = (&mut self.final_outputs, &mut self.output_gradients);
+        loss_gradients(outputs, &self.targets, final_outputs, gradients)?;
+        self.learn()
+    }
+
+    /// Same as `train` but for sparse inputs (one row or one column). Only the
+    /// non zero inputs are touched while calculating the first layer.
+    pub fn train_sparse(
+        &mut self,
+        inputs: &SparseMatrix,
+        awaited_output: &[f64],
+    ) -> Result<(), MathError> {
+        let inputs = sparse_input_row(inputs)?;
+        if inputs.columns() != self.input_nodes() || awaited_output.len() != self.output_nodes() {
+            return Err(MathError);
+        }
+        set_row(&mut self.targets, awaited_output);
+
+        let outputs = self.layers.forward_sparse(&inputs, self.mode)?;
+        let (final_outputs, gradients) = (&mut self.final_outputs, &mut self.output_gradients);
+        loss_gradients(outputs, &self.targets, final_outputs, gradients)?;
+        self.learn()
+    }
+
+    /// Moves every parameter against its gradient for `output_gradients`.
+    /// The penalties and the weight decay shrink the weights first - the
+    /// penalties touch all weights, not only those of non zero inputs.
+    fn learn(&mut self) -> Result<(), MathError> {
+        self.layers.backward_parameters(&self.output_gradients)?;
+
+        let (learning_rate, weight_decay) = (self.learning_rate, self.weight_decay);
+        let (mut regularization, who_regularization) =
+            (self.wih_regularization, self.who_regularization);
+        self.layers.visit_parameters(&mut |parameter| {
+            if parameter.name == "weights" {
+                let values = &mut *parameter.values;
+                regularization::shrink(values, learning_rate, regularization, weight_decay);
+                regularization = who_regularization;
+            }
+            simd::axpy(-learning_rate, parameter.gradients, parameter.values);
+        });
+        Ok(())
+    }
+
+    /// Calculates the gradients of one sample without changing the parameters;
+    /// only the random generators of the dropout masks advance and
+    /// `last_outputs` gives the outputs the gradients belong to. Unlike
+    /// `train` this allocates.
+    pub fn backward(
+        &mut self,
+        inputs: &[f64],
+        awaited_output: &[f64],
+    ) -> Result<Gradients, MathError> {
+        if inputs.len() != self.input_nodes() || awaited_output.len() != self.output_nodes() {
+            return Err(MathError);
+        }
+        let inputs = Matrix::from_1d_vec(inputs, false);
+        self.backward_batch(&inputs, &Matrix::from_1d_vec(awaited_output, false))
+    }
+
+    /// Same as `backward` for a batch with one sample per row, in one pass
+    /// through the layers. The gradients are the means over the samples.
+    pub fn backward_batch(
+        &mut self,
+        inputs: &Matrix,
+        awaited_outputs: &Matrix,
+    ) -> Result<Gradients, MathError> {
+        if inputs.columns() != self.input_nodes() || inputs.rows() != awaited_outputs.rows() {
+            return Err(MathError);
+        }
+        let outputs = self.layers.forward(inputs, self.mode)?;
+        let (final_outputs, gradients) = (&mut self.final_outputs, &mut self.output_gradients);
+        loss_gradients(outputs, awaited_outputs, final_outputs, gradients)?;
+        self.layers.backward_parameters(&self.output_gradients)?;
+
+        let (mut regularization, who_regularization) =
+            (self.wih_regularization, self.who_regularization);
+        let mut gradients = Vec::new();
+        self.layers.visit_parameters(&mut |parameter| {
+            let mut values = parameter.gradients.to_vec();
+            if parameter.name == "weights" {
+                regularization.add_gradient(parameter.values, &mut values);
+                regularization = who_regularization;
             }
+            gradients.push(values);
+        });
+        Ok(Gradients {
+            parameters: gradients,
+        })
+    }
+
+    /// Moves the parameters by `-learning_rate * gradients` and applies the
+    /// weight decay. `train` is the same as applying the gradients of
+    /// `backward` for the sample.
+    pub fn apply_gradients(&mut self, gradients: &Gradients) -> Result<(), MathError> {
+        let sizes: Vec<usize> = gradients.parameters.iter().map(Vec::len).collect();
+        if sizes != parameter_sizes(&mut self.layers) {
+            return Err(MathError);
+        }
+        let (learning_rate, weight_decay) = (self.learning_rate, self.weight_decay);
+        let mut gradients = gradients.parameters.iter();
+        self.layers.visit_parameters(&mut |parameter| {
+            if parameter.name == "weights" {
+                let none = Regularization::none();
+                regularization::shrink(parameter.values, learning_rate, none, weight_decay);
+            }
+            if let Some(gradients) = gradients.next() {
+                simd::axpy(-learning_rate, gradients, parameter.values);
+            }
+        });
+        Ok(())
+    }
+
+    /// Panics if the number of inputs does not fit the network.
+    pub fn query(&self, inputs: &[f64]) -> Vec<f64> {
+        let input_nodes = self.input_nodes();
+        assert_eq!(inputs.len(), input_nodes, "the network takes {} inputs", input_nodes);
+        match self.layers.predict(&Matrix::from_1d_vec(inputs, false)) {
+            Ok(outputs) => outputs.flatten(),
+            Err(_) => panic!("layers of the same network do not fit together"),
         }
+    }
 
-        Ok(outer_result)
+    /// Same as `query` but for sparse inputs (one row or one column).
+    pub fn query_sparse(&self, inputs: &SparseMatrix) -> Result<Vec<f64>, MathError> {
+        let inputs = sparse_input_row(inputs)?;
+        Ok(self.layers.predict_sparse(&inputs)?.flatten())
+    }
+}
+
+/// Size of every parameter in the order of `Layer::parameters`.
+fn parameter_sizes(layers: &mut Sequential) -> Vec<usize> {
+    layers
+        .parameters()
+        .iter()
+        .map(|parameter| parameter.values.len())
+        .collect()
+}
+
+/// Makes `buffer` a single row with `values`, reusing its memory.
+fn set_row(buffer: &mut Matrix, values: &[f64]) {
+    if buffer.rows() == 1 && buffer.columns() == values.len() {
+        buffer.row_mut(0).copy_from_slice(values);
+    } else {
+        *buffer = Matrix::from_1d_vec(values, false);
+    }
+}
+
+/// Keeps the outputs and fills `gradients` with the derivative of the loss
+/// `1/2 * sum((target - output)²)`, averaged over the samples, by them.
+fn loss_gradients(
+    outputs: &Matrix,
+    targets: &Matrix,
+    final_outputs: &mut Matrix,
+    gradients: &mut Matrix,
+) -> Result<(), MathError> {
+    if outputs.rows() != targets.rows() || outputs.columns() != targets.columns() {
+        return Err(MathError);
+    }
+    layers::copy_into(final_outputs, outputs);
+    layers::copy_into(gradients, outputs);
+    let samples = outputs.rows() as f64;
+    for (row, targets) in targets.data_container().iter().enumerate() {
+        for (gradient, target) in gradients.row_mut(row).iter_mut().zip(targets) {
+            *gradient = (*gradient - target) / samples;
+        }
+    }
+    Ok(())
+}
+
+/// Brings a sparse input vector into the shape of a row - one sample.
+fn sparse_input_row(inputs: &SparseMatrix) -> Result<SparseMatrix, MathError> {
+    if inputs.rows() == 1 {
+        Ok(inputs.clone())
+    } else if inputs.columns() == 1 {
+        Ok(inputs.transpose())
+    } else {
+        Err(MathError)
     }
 }
 
 #[cfg(test)]
 mod neural_network_tests {
     use super::*;
+    use matrix::sparse;
+
+    /// Values of all parameters in the order of the layers.
+    fn parameters<T: Fn(f64) -> f64>(network: &mut NeuralNetwork<T>) -> Vec<f64> {
+        network
+            .layers
+            .parameters()
+            .iter()
+            .flat_map(|parameter| parameter.values.to_vec())
+            .collect()
+    }
+
+    fn assert_same_parameters<T, U>(left: &mut NeuralNetwork<T>, right: &mut NeuralNetwork<U>)
+    where
+        T: Fn(f64) -> f64,
+        U: Fn(f64) -> f64,
+    {
+        let (left, right) = (parameters(left), parameters(right));
+        assert_eq!(left.len(), right.len());
+        for (left, right) in left.iter().zip(&right) {
+            assert!((left - right).abs() < 1e-12, "{} != {}", left, right);
+        }
+    }
 
     #[test]
     fn create_new_neural_network() {
         let nn = NeuralNetwork::new(3, 3, 3, 0.3, |x| x + 1.0);
 
-        assert_eq!(nn.input_nodes, 3);
-        assert_eq!(nn.hidden_nodes, 3);
-        assert_eq!(nn.output_nodes, 3);
+        assert_eq!(nn.input_nodes(), 3);
+        assert_eq!(nn.wih().rows(), 3); // hidden nodes
+        assert_eq!(nn.output_nodes(), 3);
         assert_eq!(nn.learning_rate, 0.3);
         assert_eq!((nn.activation_function)(1.0), 2.0);
     }
@@ -148,7 +638,7 @@ mod neural_network_tests {
         let inputs: Vec<f64> = vec![1.0, 1.0, 1.0];
         let outputs: Vec<f64> = vec![1.0, 1.0, 1.0];
 
-        nn.train(&inputs, &outputs);
+        nn.train(&inputs, &outputs).unwrap();
     }
 
     // Running query() should never panic
@@ -162,19 +652,331 @@ mod neural_network_tests {
     }
 
     #[test]
-    fn test_calculate_weighting_adjustment() {
-        let nn = NeuralNetwork::new(2, 2, 2, 0.5, |x| x + 1.0);
+    fn test_train_step() {
+        let wih = Matrix::from_2d_vec(&[vec![0.5, -0.3]]);
+        let who = Matrix::from_2d_vec(&[vec![0.8]]);
+        let mut nn = NeuralNetwork::from_weightings(wih, who, 0.5, util::sigmoid).unwrap();
+        let (inputs, target) = ([1.0, 2.0], 0.9);
+        nn.train(&inputs, &[target]).unwrap();
+
+        // one step on 1/2 * (target - output)² by hand
+        let hidden = util::sigmoid(0.5 - 0.6);
+        let output = util::sigmoid(0.8 * hidden);
+        let output_delta = (output - target) * output * (1.0 - output);
+        let hidden_delta = output_delta * 0.8 * hidden * (1.0 - hidden);
+        let expected = [
+            0.5 - 0.5 * hidden_delta,
+            -0.3 - 0.5 * hidden_delta * 2.0,
+            -0.5 * hidden_delta,
+            0.8 - 0.5 * output_delta * hidden,
+            -0.5 * output_delta,
+        ];
+        for (value, expected) in parameters(&mut nn).iter().zip(&expected) {
+            assert!((value - expected).abs() < 1e-15, "{} != {}", value, expected);
+        }
+        assert_eq!(nn.last_outputs().flatten(), vec![output]);
+    }
+
+    #[test]
+    fn test_from_weightings() {
+        let nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let copy = NeuralNetwork::from_weightings(nn.wih(), nn.who(), 0.3, util::sigmoid).unwrap();
+        assert_eq!(copy.query(&[0.1, 0.2, 0.3]), nn.query(&[0.1, 0.2, 0.3]));
+
+        let wrong = NeuralNetwork::from_weightings(nn.who(), nn.wih(), 0.3, util::sigmoid);
+        assert!(wrong.is_err());
+    }
+
+    #[test]
+    fn test_with_seed() {
+        let nn = NeuralNetwork::with_seed(3, 4, 2, 0.3, util::sigmoid, 5);
+        let same = NeuralNetwork::with_seed(3, 4, 2, 0.3, util::sigmoid, 5);
+        let other = NeuralNetwork::with_seed(3, 4, 2, 0.3, util::sigmoid, 6);
+
+        assert_eq!(nn.input_nodes(), 3);
+        assert_eq!(nn.output_nodes(), 2);
+        assert_eq!(nn.wih(), same.wih());
+        assert_eq!(nn.who(), same.who());
+        assert_ne!(nn.wih(), other.wih());
+    }
+
+    #[test]
+    fn test_set_weightings() {
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let other = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+
+        nn.set_weightings(other.wih(), other.who()).unwrap();
+        assert_eq!(nn.query(&[0.1, 0.2, 0.3]), other.query(&[0.1, 0.2, 0.3]));
+        assert!(nn.set_weightings(other.who(), other.wih()).is_err());
+    }
+
+    #[test]
+    fn test_normalization() {
+        let mut nn = NeuralNetwork::with_seed(3, 4, 2, 0.3, util::sigmoid, 1);
+        assert_eq!(nn.normalization(), Normalization::None);
+        let before = nn.query(&[0.1, 0.2, 0.3]);
+
+        nn.set_dropout(0.2, 0.0, 1);
+        nn.set_normalization(Normalization::Batch);
+        assert_eq!(nn.normalization(), Normalization::Batch);
+        assert_eq!(nn.layers().len(), 6);
+        assert!(nn.layers().layers()[2].as_any().is::<BatchNorm>());
+        nn.set_normalization(Normalization::Layer);
+        assert_eq!(nn.layers().len(), 6);
+        assert!(nn.layers().layers()[2].as_any().is::<LayerNorm>());
+
+        // a sample at a time as well as whole batches
+        nn.train(&[0.1, 0.2, 0.3], &[1.0, 0.0]).unwrap();
+        nn.set_normalization(Normalization::Batch);
+        nn.train(&[0.1, 0.2, 0.3], &[1.0, 0.0]).unwrap();
+        let batch = Matrix::from_2d_vec(&[vec![0.1, 0.2, 0.3], vec![0.6, 0.5, 0.4]]);
+        let targets = Matrix::from_2d_vec(&[vec![1.0, 0.0], vec![0.0, 1.0]]);
+        assert!(nn.backward_batch(&batch, &targets).unwrap().is_finite());
+
+        nn.set_normalization(Normalization::None);
+        nn.set_dropout(0.0, 0.0, 1);
+        assert_eq!(nn.layers().len(), 4);
+        assert_ne!(nn.query(&[0.1, 0.2, 0.3]), before);
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut nn = NeuralNetwork::with_seed(3, 4, 2, 0.3, util::sigmoid, 1);
+        assert_eq!(nn.layers().len(), 4);
+        assert_eq!(nn.layers().output_features(), 2);
+        assert_eq!(nn.dropout(), (0.0, 0.0));
+
+        nn.set_dropout(0.0, 0.5, 2);
+        assert_eq!(nn.layers().len(), 5);
+        assert!(nn.layers().layers()[2].as_any().is::<Dropout>());
+        assert_eq!(nn.dropout(), (0.0, 0.5));
+        nn.set_dropout(0.25, 0.0, 2);
+        assert_eq!(nn.layers().len(), 5);
+        assert!(nn.layers().layers()[0].as_any().is::<Dropout>());
+        assert_eq!(nn.dropout(), (0.25, 0.0));
+
+        // the layers of another network take the place of the own ones
+        let mut other = NeuralNetwork::with_seed(3, 4, 2, 0.3, util::sigmoid, 2);
+        nn.set_layers(other.layers().clone()).unwrap();
+        assert_eq!(nn.query(&[0.1, 0.2, 0.3]), other.query(&[0.1, 0.2, 0.3]));
+        assert_same_parameters(&mut nn, &mut other);
+        let wider = NeuralNetwork::with_seed(3, 5, 2, 0.3, util::sigmoid, 2);
+        assert!(nn.set_layers(wider.layers().clone()).is_err());
+    }
+
+    #[test]
+    fn test_train_checks_sizes() {
+        let mut nn = NeuralNetwork::new(3, 3, 2, 0.3, util::sigmoid);
+
+        assert!(nn.train(&[1.0, 1.0], &[1.0, 1.0]).is_err());
+        assert!(nn.train(&[1.0, 1.0, 1.0], &[1.0]).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "the network takes 3 inputs")]
+    fn test_query_checks_size() {
+        let nn = NeuralNetwork::new(3, 3, 2, 0.3, util::sigmoid);
+        nn.query(&[1.0, 1.0]);
+    }
 
-        let err = vec![0.2, 0.15];
-        let fin_result = vec![0.9, 0.7];
-        let hidden_result = vec![0.5, 0.8];
+    #[test]
+    fn test_backward() {
+        let mut trained = NeuralNetwork::with_seed(3, 4, 2, 0.3, util::sigmoid, 7);
+        let mut applied = NeuralNetwork::with_seed(3, 4, 2, 0.3, util::sigmoid, 7);
+        let (inputs, outputs) = ([0.9, 0.1, 0.4], [0.99, 0.01]);
+
+        let gradients = applied.backward(&inputs, &outputs).unwrap();
+        assert_eq!(parameters(&mut applied), parameters(&mut trained));
+        let sizes: Vec<usize> = gradients.parameters.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![12, 4, 8, 2]);
+
+        trained.train(&inputs, &outputs).unwrap();
+        applied.apply_gradients(&gradients).unwrap();
+        assert_same_parameters(&mut trained, &mut applied);
+
+        assert!(applied.backward(&[1.0], &outputs).is_err());
+        assert!(applied.backward(&inputs, &[1.0]).is_err());
+        let wrong = Gradients {
+            parameters: vec![vec![0.0; 12], vec![0.0; 4], vec![0.0; 8]],
+        };
+        assert!(applied.apply_gradients(&wrong).is_err());
+    }
 
-        let result = nn.calculate_weighting_adjustment(&err, &fin_result, &hidden_result)
+    #[test]
+    fn test_backward_batch() {
+        let mut nn = NeuralNetwork::with_seed(3, 4, 2, 0.3, util::sigmoid, 7);
+        let inputs = Matrix::from_2d_vec(&[vec![0.9, 0.1, 0.4], vec![0.2, 0.7, 0.5]]);
+        let targets = Matrix::from_2d_vec(&[vec![0.99, 0.01], vec![0.01, 0.99]]);
+
+        let mut expected = nn.backward(&inputs.data_container()[0], &[0.99, 0.01]).unwrap();
+        expected
+            .add(&nn.backward(&inputs.data_container()[1], &[0.01, 0.99]).unwrap())
             .unwrap();
+        expected.scale(0.5);
+        let gradients = nn.backward_batch(&inputs, &targets).unwrap();
+        for (expected, gradients) in expected.parameters.iter().zip(&gradients.parameters) {
+            for (expected, gradient) in expected.iter().zip(gradients) {
+                assert!((expected - gradient).abs() < 1e-15);
+            }
+        }
+        assert_eq!(nn.last_outputs().rows(), 2);
+        assert!(nn.backward_batch(&inputs, &Matrix::zeros(2, 3)).is_err());
+    }
+
+    #[test]
+    fn test_backward_outputs() {
+        let mut trained = NeuralNetwork::with_seed(3, 20, 2, 0.3, util::sigmoid, 7);
+        let mut applied = NeuralNetwork::with_seed(3, 20, 2, 0.3, util::sigmoid, 7);
+        for nn in &mut [&mut trained, &mut applied] {
+            nn.set_dropout(0.0, 0.5, 3);
+        }
+        let (inputs, outputs) = ([0.9, 0.1, 0.4], [0.99, 0.01]);
+
+        applied.backward(&inputs, &outputs).unwrap();
+        trained.train(&inputs, &outputs).unwrap();
+        // the outputs with the dropout mask of the step, not those of `query`
+        assert_eq!(applied.last_outputs(), trained.last_outputs());
+        assert_ne!(applied.last_outputs().flatten(), applied.query(&inputs));
+    }
 
-        assert_eq!(result[0][0], 0.0045);
-        assert_eq!(result[0][1], 0.0072);
-        assert_eq!(result[1][0], 0.007875);
-        assert_eq!(result[1][1], 0.0126);
+    #[test]
+    fn test_regularization() {
+        let mut trained = NeuralNetwork::with_seed(4, 3, 2, 0.3, util::sigmoid, 8);
+        let mut applied = NeuralNetwork::with_seed(4, 3, 2, 0.3, util::sigmoid, 8);
+        let mut sparse = NeuralNetwork::with_seed(4, 3, 2, 0.3, util::sigmoid, 8);
+        for nn in &mut [&mut trained, &mut applied, &mut sparse] {
+            nn.set_regularization(Regularization::l1(0.01), Regularization::new(0.02, 0.1));
+            nn.set_weight_decay(0.05);
+        }
+        let (inputs, outputs) = (vec![0.0, 0.8, 0.0, 0.3], vec![0.01, 0.99]);
+        let penalty = trained.regularization_loss();
+        assert!(penalty > 0.0);
+
+        trained.train(&inputs, &outputs).unwrap();
+        let gradients = applied.backward(&inputs, &outputs).unwrap();
+        applied.apply_gradients(&gradients).unwrap();
+        sparse
+            .train_sparse(&SparseMatrix::from_1d_vec(&inputs, true), &outputs)
+            .unwrap();
+        assert_same_parameters(&mut trained, &mut applied);
+        assert_same_parameters(&mut trained, &mut sparse);
+
+        // the weights of the zero inputs only shrink
+        assert!(trained.regularization_loss() < penalty);
+    }
+
+    /// Factors of the last step of every dropout layer.
+    fn dropout_masks<T: Fn(f64) -> f64>(network: &NeuralNetwork<T>) -> Vec<Vec<f64>> {
+        network
+            .layers()
+            .layers()
+            .iter()
+            .filter_map(|layer| layer.as_any().downcast_ref::<Dropout>())
+            .map(|layer| layer.mask().map_or(Vec::new(), Matrix::flatten))
+            .collect()
+    }
+
+    #[test]
+    fn test_dropout() {
+        let mut trained = NeuralNetwork::with_seed(4, 6, 2, 0.3, util::sigmoid, 9);
+        let mut applied = NeuralNetwork::with_seed(4, 6, 2, 0.3, util::sigmoid, 9);
+        let (inputs, outputs) = (vec![0.2, 0.8, 0.4, 0.3], vec![0.01, 0.99]);
+        let query = trained.query(&inputs);
+        for nn in &mut [&mut trained, &mut applied] {
+            nn.set_dropout(0.25, 0.5, 3);
+        }
+        assert_eq!(trained.dropout(), (0.25, 0.5));
+        assert_eq!(trained.query(&inputs), query);
+
+        for _ in 0..3 {
+            trained.train(&inputs, &outputs).unwrap();
+            let gradients = applied.backward(&inputs, &outputs).unwrap();
+            applied.apply_gradients(&gradients).unwrap();
+        }
+        assert_same_parameters(&mut trained, &mut applied);
+        let hidden_mask = &dropout_masks(&trained)[1];
+        assert!(hidden_mask.contains(&0.0));
+        assert!(hidden_mask.contains(&2.0));
+        assert_eq!(trained.query(&inputs), trained.query(&inputs));
+    }
+
+    #[test]
+    fn test_eval_mode() {
+        let mut plain = NeuralNetwork::with_seed(4, 6, 2, 0.3, util::sigmoid, 9);
+        let mut eval = NeuralNetwork::with_seed(4, 6, 2, 0.3, util::sigmoid, 9);
+        eval.set_dropout(0.5, 0.5, 3);
+        eval.set_mode(Mode::Eval);
+        assert_eq!(eval.mode(), Mode::Eval);
+
+        plain.train(&[0.2, 0.8, 0.4, 0.3], &[0.01, 0.99]).unwrap();
+        eval.train(&[0.2, 0.8, 0.4, 0.3], &[0.01, 0.99]).unwrap();
+        assert_eq!(parameters(&mut plain), parameters(&mut eval));
+    }
+
+    #[test]
+    fn test_train_sparse_dropout() {
+        let mut dense = NeuralNetwork::with_seed(4, 6, 2, 0.3, util::sigmoid, 4);
+        let mut sparse = NeuralNetwork::with_seed(4, 6, 2, 0.3, util::sigmoid, 4);
+        for nn in &mut [&mut dense, &mut sparse] {
+            nn.set_dropout(0.0, 0.5, 8);
+        }
+        let (inputs, outputs) = (vec![0.0, 0.8, 0.0, 0.3], vec![0.99, 0.01]);
+
+        dense.train(&inputs, &outputs).unwrap();
+        sparse
+            .train_sparse(&SparseMatrix::from_1d_vec(&inputs, true), &outputs)
+            .unwrap();
+        assert_same_parameters(&mut dense, &mut sparse);
+
+        // a dropped input does not change its weights
+        let wih = sparse.wih();
+        sparse.set_dropout(0.99, 0.0, 1);
+        sparse
+            .train_sparse(&SparseMatrix::from_1d_vec(&inputs, true), &outputs)
+            .unwrap();
+        assert_eq!(dropout_masks(&sparse)[0][1], 0.0);
+        for (before, after) in wih.data_container().iter().zip(sparse.wih().data_container()) {
+            assert_eq!(before[1], after[1]);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "dropout rate has to be in [0, 1) but is 1")]
+    fn test_dropout_rate() {
+        NeuralNetwork::new(2, 2, 2, 0.3, util::sigmoid).set_dropout(0.0, 1.0, 0);
+    }
+
+    #[test]
+    fn test_query_sparse() {
+        let nn = NeuralNetwork::new(4, 3, 2, 0.3, util::sigmoid);
+
+        let inputs = vec![0.0, 1.0, 0.0, 0.5];
+        let dense = nn.query(&inputs);
+
+        let row = nn.query_sparse(&SparseMatrix::from_1d_vec(&inputs, false)).unwrap();
+        let column = nn.query_sparse(&SparseMatrix::from_1d_vec(&inputs, true)).unwrap();
+        for ((d, r), c) in dense.iter().zip(&row).zip(&column) {
+            assert!((d - r).abs() < 1e-12);
+            assert!((d - c).abs() < 1e-12);
+        }
+
+        let not_a_vector =
+            SparseMatrix::from_matrix(&Matrix::zeros(2, 2), sparse::SparseFormat::Csr);
+        assert!(nn.query_sparse(&not_a_vector).is_err());
+    }
+
+    #[test]
+    fn test_train_sparse() {
+        let mut dense_nn = NeuralNetwork::new(4, 3, 2, 0.3, util::sigmoid);
+        let mut sparse_nn = NeuralNetwork::new(4, 3, 2, 0.3, util::sigmoid);
+        sparse_nn.set_weightings(dense_nn.wih(), dense_nn.who()).unwrap();
+
+        let inputs = vec![0.0, 1.0, 0.0, 0.5];
+        let outputs = vec![0.99, 0.01];
+        dense_nn.train(&inputs, &outputs).unwrap();
+        sparse_nn
+            .train_sparse(&SparseMatrix::from_1d_vec(&inputs, false), &outputs)
+            .unwrap();
+        assert_same_parameters(&mut dense_nn, &mut sparse_nn);
     }
 }
@@ -1,71 +1,370 @@
 extern crate neural_network;
 extern crate chrono;
 
+mod cli;
+
 use neural_network::*;
+use neural_network::dataset::{Dataset, Sample};
+use neural_network::dataset::csv::*;
+use neural_network::evaluation::classification::{top_classes, ClassificationReport};
+use neural_network::image::{GrayImage, MNIST_SIZE};
+use neural_network::layers::Normalization;
+use neural_network::matrix::Matrix;
+use neural_network::matrix::math;
+use neural_network::model::Model;
+use neural_network::config;
+use neural_network::config::{ExperimentConfig, ScalerConfig};
+use neural_network::regularization::Regularization;
+use neural_network::preprocessing::{InputScaler, MinMaxScaler, StandardScaler, TargetEncoder};
+use neural_network::training::callbacks::{CsvLogger, LearningRateScheduler, ProgressBar};
+use neural_network::training::{Callback, Trainer};
+use cli::{Command, EvaluateOptions, Experiment, InspectOptions, PredictOptions, TrainOptions};
+use std::env;
+use std::error::Error;
 use std::fs::File;
-use std::io::prelude::*;
+use std::io::{BufWriter, Write};
+use std::process;
 use chrono::prelude::*;
 
+type Activation = fn(f64) -> f64;
+
+/// Every error ends the program with a message instead of a panic.
+type CommandResult = Result<(), Box<dyn Error>>;
+
 fn main() {
-    let mut wrong = 0;
-    let mut correct = 0;
-    let mut nn = NeuralNetwork::new(784, 200, 10, 0.1, util::sigmoid);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match cli::parse(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
 
-    let mut awaited_output: Vec<f64>;
+    let result = match command {
+        Command::Train(options) => train(&options),
+        Command::Evaluate(options) => evaluate(&options),
+        Command::Predict(options) => predict(&options),
+        Command::Inspect(options) => inspect(&options),
+        Command::Help => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
 
-    println!("Start time: {}", Local::now());
+/// The activation functions a model may name, each with its derivative -
+/// which training needs.
+fn activation_function(name: &str) -> Option<(Activation, Activation)> {
+    match name {
+        "sigmoid" => Some((util::sigmoid, util::sigmoid_derivative)),
+        "relu" => Some((util::relu, util::relu_derivative)),
+        _ => None,
+    }
+}
 
-    for (index, line) in unpack("train.csv").lines().enumerate() {
-        let (number, values) = mnist_data::convert_mnist_line(line);
-        awaited_output = matrix::math::create_zeroed_vector(10);
-        match awaited_output.get_mut(number) {
-            Some(v) => *v = 0.99,
-            None => panic!("number {} could not occur!", number),
+fn train(options: &TrainOptions) -> CommandResult {
+    let config = match options.experiment {
+        Experiment::File(ref path) => ExperimentConfig::load(path)
+            .map_err(|e| format!("could not load {}: {}", path, e))?,
+        Experiment::Options(ref config) => {
+            config.validate()?;
+            (**config).clone()
         }
-        if index % 600 == 0 {
-            println!("{} %", index / 600);
+    };
+    if options.resume && config.training.checkpoint.is_none() {
+        return Err("resuming needs a checkpoint in the config".into());
+    }
+    let (inputs, hidden, outputs) = match config.network.layers[..] {
+        [inputs, hidden, outputs] => (inputs, hidden, outputs),
+        _ => unreachable!("validated config"),
+    };
+    let (activation, derivative) = match activation_function(&config.network.activation) {
+        Some(functions) => functions,
+        None => unreachable!("validated config"),
+    };
+    let normalization = match Normalization::from_name(&config.network.normalization) {
+        Some(normalization) => normalization,
+        None => unreachable!("validated config"),
+    };
+
+    let raw_samples = load(&config.data.train, inputs)?;
+    let scaler = match config.preprocessing.scaler {
+        ScalerConfig::None => None,
+        ScalerConfig::MinMax {
+            input_range: Some((input_min, input_max)),
+            output_min,
+            output_max,
+        } => Some(InputScaler::MinMax(MinMaxScaler::from_range(
+            inputs, input_min, input_max, output_min, output_max,
+        ))),
+        ScalerConfig::MinMax {
+            input_range: None,
+            output_min,
+            output_max,
+        } => Some(InputScaler::MinMax(MinMaxScaler::fit(&raw_samples, output_min, output_max))),
+        ScalerConfig::Standard => Some(InputScaler::Standard(StandardScaler::fit(&raw_samples))),
+    };
+    let encoder = TargetEncoder::new(
+        outputs,
+        config.preprocessing.target_on,
+        config.preprocessing.target_off,
+    );
+    let samples = scale(raw_samples, scaler.as_ref());
+    let training = &config.training;
+    let split = samples.split(config.data.validation_split, 0.0, training.seed)?;
+
+    let learning_rate = config.optimizer.learning_rate;
+    let mut network =
+        NeuralNetwork::with_seed(inputs, hidden, outputs, learning_rate, activation, training.seed);
+    network.set_activation_derivative(derivative);
+    let optimizer = &config.optimizer;
+    let regularization = Regularization::new(optimizer.l1, optimizer.l2);
+    network.set_regularization(regularization, regularization);
+    network.set_weight_decay(optimizer.weight_decay);
+    let dropout = (config.network.input_dropout, config.network.hidden_dropout);
+    network.set_dropout(dropout.0, dropout.1, training.seed);
+    network.set_normalization(normalization);
+    let mut trainer = Trainer::new(training.epochs, encoder.clone())
+        .batch_size(config.optimizer.batch_size)
+        .shuffle(training.seed)
+        .restore_best_weights(training.restore_best_weights);
+    if let Some(patience) = training.patience {
+        trainer = trainer.early_stopping(patience);
+    }
+    if let Some(ref checkpoint) = training.checkpoint {
+        trainer = trainer.checkpoint(checkpoint, training.checkpoint_every);
+    }
+
+    println!("Start time: {}", Local::now());
+    let mut progress = ProgressBar::new(40);
+    let mut scheduler = LearningRateScheduler::new(config.schedule.clone());
+    let mut logger = match training.log {
+        Some(ref path) => Some(
+            CsvLogger::create(path).map_err(|e| format!("could not create {}: {}", path, e))?,
+        ),
+        None => None,
+    };
+    let history = {
+        let mut callbacks: Vec<&mut dyn Callback<Activation>> = vec![&mut scheduler, &mut progress];
+        if let Some(ref mut logger) = logger {
+            callbacks.push(logger);
+        }
+        match (options.resume, &training.checkpoint) {
+            (true, Some(checkpoint)) => trainer.resume_from(
+                checkpoint,
+                &mut network,
+                &split.train,
+                &split.validation,
+                &mut callbacks,
+            )?,
+            _ => trainer.fit_with_callbacks(
+                &mut network,
+                &split.train,
+                &split.validation,
+                &mut callbacks,
+            )?,
+        }
+    };
+    if let Some(best) = history.best() {
+        println!("best epoch {}", best.epoch);
+    }
+
+    let mut model = Model::new(network);
+    model.activation = Some(config.network.activation.clone());
+    model.input_scaler = scaler;
+    model.target_encoder = Some(encoder);
+    model.save(&config.model)
+        .map_err(|e| format!("could not save {}: {}", config.model, e))?;
+    let config_path = config::config_path_for(&config.model);
+    config.save(&config_path)
+        .map_err(|e| format!("could not save {}: {}", config_path.display(), e))?;
+    println!("saved model to {} and its config to {}", config.model, config_path.display());
+
+    if let Some(ref test) = config.data.test {
+        let test_samples = load(test, inputs)?;
+        println!("{}", report(&model, &test_samples, 3));
+    }
+    println!("End time: {}", Local::now());
+    Ok(())
+}
+
+fn evaluate(options: &EvaluateOptions) -> CommandResult {
+    let model = load_model(&options.model)?;
+    let samples = load(&options.test, model.network.input_nodes())?;
+    let report = report(&model, &samples, options.top_k);
+    println!("{}", report);
+
+    if let Some(ref path) = options.csv {
+        export(path, |writer| report.write_csv(writer))?;
+    }
+    if let Some(ref path) = options.json {
+        export(path, |writer| report.write_json(writer))?;
+    }
+    Ok(())
+}
+
+fn predict(options: &PredictOptions) -> CommandResult {
+    let model = load_model(&options.model)?;
+    let inputs = model.network.input_nodes();
+
+    if let Some(ref input) = options.input {
+        for (row, (label, values)) in load(input, inputs)?.iter().enumerate() {
+            let outputs = model.query(values);
+            let name = format!("row {} (label {})", row + 1, label);
+            println!("{}: {}", name, format_top_classes(&outputs, options.top_k));
         }
-        if let Err(e) = nn.train(&values, &awaited_output) {
-            eprintln!("{}", e);
+    }
+
+    if !options.images.is_empty() && inputs != MNIST_SIZE * MNIST_SIZE {
+        let message = format!(
+            "the model needs {} inputs but images give {}",
+            inputs,
+            MNIST_SIZE * MNIST_SIZE
+        );
+        return Err(message.into());
+    }
+    for path in &options.images {
+        let image = GrayImage::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        // models with a scaler get the grey values, all others the values
+        // convert_mnist_line gives
+        let outputs = match model.input_scaler {
+            Some(_) => model.query(&image.mnist_pixels()),
+            None => model.network.query(&image.mnist_inputs()),
         };
+        println!("{}: {}", path, format_top_classes(&outputs, options.top_k));
     }
+    Ok(())
+}
 
-    // ----------------------------------------------------------
+fn format_top_classes(outputs: &[f64], k: usize) -> String {
+    top_classes(outputs, k)
+        .iter()
+        .map(|(class, score)| format!("{} ({:.3})", class, score))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
 
-    for line in unpack("test.csv").lines() {
-        let (number, values) = mnist_data::convert_mnist_line(line);
-        let mut answer: usize = 0;
-        let mut highest: f64 = 0.0;
+fn inspect(options: &InspectOptions) -> CommandResult {
+    // the weightings do not depend on the activation function - any name is fine
+    let model: Model<Activation> =
+        Model::load_with(&options.model, |_| Some(util::sigmoid as Activation))
+            .map_err(|e| format!("could not load {}: {}", options.model, e))?;
+    let network = &model.network;
 
-        for (index, value) in nn.query(&values).iter().enumerate() {
-            if *value > highest {
-                highest = *value;
-                answer = index;
-            }
+    println!("model: {}", options.model);
+    println!(
+        "layers: {} input, {} hidden, {} output nodes",
+        network.input_nodes(),
+        network.wih().rows(),
+        network.output_nodes()
+    );
+    println!(
+        "activation: {}",
+        model.activation.as_deref().unwrap_or("not stored")
+    );
+    println!("learning rate: {}", network.learning_rate());
+    println!("normalization: {}", network.normalization().name());
+    print_weighting("input -> hidden", &network.wih());
+    print_weighting("hidden -> output", &network.who());
+
+    match model.input_scaler {
+        Some(InputScaler::MinMax(ref scaler)) => {
+            let (output_min, output_max) = scaler.output_range();
+            println!(
+                "input scaler: min-max, inputs in [{}, {}] to [{}, {}]",
+                math::min(scaler.input_min()).unwrap_or(0.0),
+                math::max(scaler.input_max()).unwrap_or(0.0),
+                output_min,
+                output_max
+            );
         }
-        if number == answer {
-            correct += 1;
-        } else {
-            wrong += 1;
+        Some(InputScaler::Standard(ref scaler)) => {
+            println!("input scaler: standard, {} features", scaler.mean().len());
         }
+        None => println!("input scaler: none"),
     }
-    println!("Correct answers {}", correct);
+    match model.target_encoder {
+        Some(ref encoder) => println!(
+            "target encoder: {} classes, on {}, off {}",
+            encoder.classes(),
+            encoder.on(),
+            encoder.off()
+        ),
+        None => println!("target encoder: none"),
+    }
+    Ok(())
+}
+
+fn print_weighting(name: &str, weighting: &Matrix) {
+    let values = weighting.flatten();
     println!(
-        "Finish! Error rate {} %",
-        f64::from(wrong) / (f64::from(wrong) + f64::from(correct)) * 100.0
+        "weighting {}: {} x {}, min {:.4}, max {:.4}, mean {:.4}, std {:.4}",
+        name,
+        weighting.rows(),
+        weighting.columns(),
+        math::min(&values).unwrap_or(0.0),
+        math::max(&values).unwrap_or(0.0),
+        math::mean(&values).unwrap_or(0.0),
+        math::std(&values).unwrap_or(0.0)
     );
-    println!("End time: {}", Local::now());
 }
 
-fn unpack(file_name: &str) -> String {
-    let mut file = match File::open(file_name) {
-        Ok(f) => f,
-        Err(e) => panic!("{}", e),
-    };
+fn load_model(path: &str) -> Result<Model<Activation>, Box<dyn Error>> {
+    Model::load_with(path, |name| {
+        activation_function(name.unwrap_or("sigmoid")).map(|(function, _)| function)
+    })
+        .map_err(|e| format!("could not load {}: {}", path, e).into())
+}
 
-    let mut content = String::new();
-    if let Err(e) = file.read_to_string(&mut content) {
-        panic!("{}", e);
+/// Samples as they are written in the file - the scaling is up to the model.
+fn load(file_name: &str, values: usize) -> Result<Vec<Sample>, Box<dyn Error>> {
+    let mut records = CsvLoader::open(file_name)
+        .map_err(|e| format!("could not open {}: {}", file_name, e))?
+        .on_malformed(MalformedRowPolicy::Skip)
+        .expect_values(values)
+        .raw();
+    let samples = records.by_ref().collect::<Result<Vec<Sample>, CsvError>>()?;
+    for e in records.skipped() {
+        eprintln!("skipped {}", e);
     }
-    content
+    if samples.is_empty() {
+        return Err(format!("{} contains no samples", file_name).into());
+    }
+    Ok(samples)
+}
+
+fn scale(samples: Vec<Sample>, scaler: Option<&InputScaler>) -> Vec<Sample> {
+    match scaler {
+        Some(scaler) => samples
+            .into_iter()
+            .map(|(label, values)| (label, scaler.transform(&values)))
+            .collect(),
+        None => samples,
+    }
+}
+
+fn report(model: &Model<Activation>, samples: &[Sample], top_k: usize) -> ClassificationReport {
+    let labels: Vec<usize> = samples.iter().map(|(label, _)| *label).collect();
+    let outputs: Vec<Vec<f64>> = samples.iter().map(|(_, values)| model.query(values)).collect();
+    let top_k = if top_k > 1 { vec![top_k] } else { Vec::new() };
+    ClassificationReport::from_outputs(&outputs, &labels, &top_k)
+}
+
+fn export<F>(path: &str, write: F) -> CommandResult
+where
+    F: FnOnce(&mut BufWriter<File>) -> std::io::Result<()>,
+{
+    let result = File::create(path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        writer.flush()
+    });
+    result.map_err(|e| format!("could not write {}: {}", path, e))?;
+    println!("wrote {}", path);
+    Ok(())
 }
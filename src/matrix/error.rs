@@ -15,7 +15,7 @@ impl error::Error for MathError {
         "incompatible vectors: Sizes does not match"
     }
 
-    fn cause(&self) -> Option<&error::Error> {
+    fn cause(&self) -> Option<&dyn error::Error> {
         None
     }
 }
@@ -1,4 +1,5 @@
 use super::error::MathError;
+use super::simd;
 
 pub fn multiply_matrices(
     left: &[Vec<f64>],
@@ -16,8 +17,7 @@ pub fn multiply_matrices(
                 return Err(MathError);
             }
 
-            let cell = first_row.iter().zip(second_row).map(|(a, b)| a * b).sum();
-            result_row.push(cell);
+            result_row.push(simd::dot(first_row, &second_row));
         }
         product.push(result_row);
     }
@@ -26,7 +26,26 @@ pub fn multiply_matrices(
 }
 
 pub fn subtract_vectors(left: &[f64], right: &[f64]) -> Vec<f64> {
-    left.iter().zip(right).map(|(a, b)| a - b).collect()
+    let mut result = create_zeroed_vector(left.len().min(right.len()));
+    simd::sub(left, right, &mut result);
+    result
+}
+
+pub fn add_vectors(left: &[f64], right: &[f64]) -> Vec<f64> {
+    let mut result = create_zeroed_vector(left.len().min(right.len()));
+    simd::add(left, right, &mut result);
+    result
+}
+
+/// Multiplies both vectors cell by cell (Hadamard product).
+pub fn multiply_vectors(left: &[f64], right: &[f64]) -> Vec<f64> {
+    let mut result = create_zeroed_vector(left.len().min(right.len()));
+    simd::mul(left, right, &mut result);
+    result
+}
+
+pub fn dot_product(left: &[f64], right: &[f64]) -> f64 {
+    simd::dot(left, right)
 }
 
 pub fn sum_matrices(left: &[Vec<f64>], right: &[Vec<f64>]) -> Result<Vec<Vec<f64>>, MathError> {
@@ -51,13 +70,7 @@ pub fn sum_matrices(left: &[Vec<f64>], right: &[Vec<f64>]) -> Result<Vec<Vec<f64
             return Err(MathError);
         }
 
-        let row = row_first
-            .iter()
-            .zip(row_second)
-            .map(|(a, b)| a + b)
-            .collect();
-
-        result.push(row);
+        result.push(add_vectors(row_first, row_second));
         x += 1;
     }
 
@@ -74,9 +87,7 @@ where
     for line in target {
         for (index, cell) in line.iter().enumerate() {
             if is_first_run {
-                let mut column = Vec::new();
-                column.push(cell.clone());
-                trans_vec.push(column);
+                trans_vec.push(vec![cell.clone()]);
             } else {
                 let val = match trans_vec.get_mut(index) {
                     Some(v) => v,
@@ -100,15 +111,12 @@ pub fn from_vector_to_matrix(target_vec: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
         let row = create_zeroed_vector(target_vec.len());
         quadratic_vec.push(row);
 
-        let value = match value.get(0) {
+        let value = match value.first() {
             Some(v) => v,
             None => panic!("Vector is empty - invalid state!"),
         };
 
-        let row = match quadratic_vec.get_mut(index) {
-            Some(r) => r,
-            None => return None,
-        };
+        let row = quadratic_vec.get_mut(index)?;
 
         if let Some(v) = row.get_mut(index) {
             *v = *value;
@@ -131,6 +139,83 @@ pub fn create_zeroed_vector(columns: usize) -> Vec<f64> {
     zeroed_vec
 }
 
+/// Sums up all values of a vector. An empty vector sums up to 0.
+pub fn sum(values: &[f64]) -> f64 {
+    values.iter().sum()
+}
+
+/// Arithmetic mean of a vector - None when the vector is empty.
+pub fn mean(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(sum(values) / values.len() as f64)
+}
+
+/// Population variance (divided by n, not n - 1) of a vector.
+pub fn var(values: &[f64]) -> Option<f64> {
+    let average = mean(values)?;
+    let squared_diffs: Vec<f64> = values
+        .iter()
+        .map(|x| (x - average) * (x - average))
+        .collect();
+    mean(&squared_diffs)
+}
+
+/// Population standard deviation of a vector.
+pub fn std(values: &[f64]) -> Option<f64> {
+    var(values).map(f64::sqrt)
+}
+
+pub fn min(values: &[f64]) -> Option<f64> {
+    argmin(values).map(|index| values[index])
+}
+
+pub fn max(values: &[f64]) -> Option<f64> {
+    argmax(values).map(|index| values[index])
+}
+
+/// Index of the highest value. The first one wins if several values are equal.
+pub fn argmax(values: &[f64]) -> Option<usize> {
+    let mut result: Option<(usize, f64)> = None;
+
+    for (index, value) in values.iter().enumerate() {
+        match result {
+            Some((_, highest)) if *value <= highest => {}
+            _ => result = Some((index, *value)),
+        }
+    }
+    result.map(|(index, _)| index)
+}
+
+/// Index of the lowest value. The first one wins if several values are equal.
+pub fn argmin(values: &[f64]) -> Option<usize> {
+    let mut result: Option<(usize, f64)> = None;
+
+    for (index, value) in values.iter().enumerate() {
+        match result {
+            Some((_, lowest)) if *value >= lowest => {}
+            _ => result = Some((index, *value)),
+        }
+    }
+    result.map(|(index, _)| index)
+}
+
+/// Sum of absolute values.
+pub fn l1_norm(values: &[f64]) -> f64 {
+    values.iter().map(|x| x.abs()).sum()
+}
+
+/// Euclidean length - for the cells of a whole matrix this is the Frobenius norm.
+pub fn l2_norm(values: &[f64]) -> f64 {
+    values.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+/// Highest absolute value. An empty vector has the norm 0.
+pub fn linf_norm(values: &[f64]) -> f64 {
+    values.iter().fold(0.0, |highest, x| highest.max(x.abs()))
+}
+
 #[cfg(test)]
 mod math_tests {
     use super::*;
@@ -141,22 +226,22 @@ mod math_tests {
         let a_2: Vec<f64> = vec![3.0, 4.0];
         let a_3: Vec<f64> = vec![5.0, 6.0];
         let a = vec![a_1, a_2, a_3];
-        let new_a = transpose_matrix(&a);
+        let new_a = transpose_2d_vector(&a);
 
-        assert_eq!(a[0][0], 1.0);
-        assert_eq!(a[2][1], 6.0);
+        assert_eq!(new_a[0][0], 1.0);
+        assert_eq!(new_a[1][2], 6.0);
     }
 
     #[test]
     fn test_multiply_matrices() {
         let vec_a_1: Vec<f64> = vec![1.0, 2.0, 3.0];
         let vec_a_2: Vec<f64> = vec![4.0, 5.0, 6.0];
-        let mut vec_a: Vec<Vec<f64>> = vec![vec_a_1, vec_a_2];
+        let vec_a: Vec<Vec<f64>> = vec![vec_a_1, vec_a_2];
 
         let vec_b_1: Vec<f64> = vec![7.0, 8.0];
         let vec_b_2: Vec<f64> = vec![9.0, 10.0];
         let vec_b_3: Vec<f64> = vec![11.0, 12.0];
-        let mut vec_b: Vec<Vec<f64>> = vec![vec_b_1, vec_b_2, vec_b_3];
+        let vec_b: Vec<Vec<f64>> = vec![vec_b_1, vec_b_2, vec_b_3];
 
         let result: Vec<Vec<f64>> = multiply_matrices(&vec_a, &vec_b).unwrap();
 
@@ -171,9 +256,9 @@ mod math_tests {
     fn test_multiply_matrices_error() {
         let vec_a_1: Vec<f64> = vec![1.0, 2.0];
         let vec_a_2: Vec<f64> = vec![4.0, 5.0];
-        let mut vec_a: Vec<Vec<f64>> = vec![vec_a_1, vec_a_2];
+        let vec_a: Vec<Vec<f64>> = vec![vec_a_1, vec_a_2];
 
-        let mut vec_b: Vec<Vec<f64>> = vec![vec![7.0, 8.0]];
+        let vec_b: Vec<Vec<f64>> = vec![vec![7.0, 8.0]];
 
         if let Err(e) = multiply_matrices(&vec_a, &vec_b) {
             panic!("{}", e);
@@ -189,6 +274,15 @@ mod math_tests {
         assert_eq!(result[1], 1.5);
     }
 
+    #[test]
+    fn test_add_and_multiply_vectors() {
+        let vec_a = vec![2.0, 3.0, 4.0];
+        let vec_b = vec![1.0, 1.5, 0.5];
+        assert_eq!(add_vectors(&vec_a, &vec_b), vec![3.0, 4.5, 4.5]);
+        assert_eq!(multiply_vectors(&vec_a, &vec_b), vec![2.0, 4.5, 2.0]);
+        assert_eq!(dot_product(&vec_a, &vec_b), 8.5);
+    }
+
     #[test]
     fn test_from_vector_to_matrix() {
         let test_vec = vec![vec![1.0], vec![2.0]];
@@ -210,8 +304,6 @@ mod math_tests {
         assert_eq!(result[1][1], 12.0);
     }
 
-    use super::*;
-
     #[test]
     fn test_create_zeroed_vector() {
         let z_vec = create_zeroed_vector(3);
@@ -219,4 +311,37 @@ mod math_tests {
         assert_eq!(z_vec[1], 0.0);
         assert_eq!(z_vec[2], 0.0);
     }
+
+    #[test]
+    fn test_statistics() {
+        let values = vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(sum(&values), 40.0);
+        assert_eq!(mean(&values), Some(5.0));
+        assert_eq!(var(&values), Some(4.0));
+        assert_eq!(std(&values), Some(2.0));
+
+        assert_eq!(mean(&[]), None);
+        assert_eq!(var(&[]), None);
+    }
+
+    #[test]
+    fn test_min_max() {
+        let values = vec![3.0, -1.0, 7.0, 7.0, -1.0];
+        assert_eq!(min(&values), Some(-1.0));
+        assert_eq!(max(&values), Some(7.0));
+        assert_eq!(argmin(&values), Some(1));
+        assert_eq!(argmax(&values), Some(2));
+
+        assert_eq!(argmax(&[]), None);
+        assert_eq!(min(&[]), None);
+    }
+
+    #[test]
+    fn test_norms() {
+        let values = vec![3.0, -4.0];
+        assert_eq!(l1_norm(&values), 7.0);
+        assert_eq!(l2_norm(&values), 5.0);
+        assert_eq!(linf_norm(&values), 4.0);
+        assert_eq!(linf_norm(&[]), 0.0);
+    }
 }
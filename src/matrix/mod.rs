@@ -1,8 +1,21 @@
 pub mod math;
 pub mod error;
+pub mod sparse;
+pub mod simd;
 use super::util::*;
+use self::sparse::SparseMatrix;
+
+/// Selects whether an operation works on the rows or on the columns of a matrix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Axis {
+    /// Reductions give one value per row - stacking and splitting work on whole rows.
+    Row,
+    /// Reductions give one value per column - stacking and splitting work on whole columns.
+    Column,
+}
 
 /// This struct represents a basic matrix for mathematic operations.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Matrix {
     rows: usize,
     columns: usize,
@@ -10,7 +23,8 @@ pub struct Matrix {
 }
 
 impl Matrix {
-    pub fn zero(columns: usize, rows: usize) -> Matrix {
+    /// `rows` x `columns` zeros - every constructor takes the rows first.
+    pub fn zeros(rows: usize, columns: usize) -> Matrix {
         let mut data_container: Vec<Vec<f64>> = Vec::new();
 
         for _row_count in 0..rows {
@@ -24,7 +38,7 @@ impl Matrix {
         }
     }
 
-    pub fn create_weighting_matrix(columns: usize, rows: usize) -> Matrix {
+    pub fn create_weighting_matrix(rows: usize, columns: usize) -> Matrix {
         let mut weighting_vec: Vec<Vec<f64>> = Vec::new();
 
         for _number in 0..rows {
@@ -38,6 +52,21 @@ impl Matrix {
         }
     }
 
+    /// Same as `create_weighting_matrix` but reproducible.
+    pub fn create_seeded_weighting_matrix(
+        rows: usize,
+        columns: usize,
+        rng: &mut SeededRng,
+    ) -> Matrix {
+        Matrix {
+            rows,
+            columns,
+            data_container: (0..rows)
+                .map(|_| create_seeded_weighting_row(columns, rng))
+                .collect(),
+        }
+    }
+
     /// Creates a matrix from a 1D vector.
     ///
     /// # Example for source:
@@ -67,7 +96,7 @@ impl Matrix {
 
     /// Creates a matrix from a
     pub fn from_2d_vec(source: &[Vec<f64>]) -> Matrix {
-        let row_size = match source.get(0) {
+        let row_size = match source.first() {
             Some(val) => val.len(),
             None => panic!("Cannot create a matrix from 2d vec without at least one row."),
         };
@@ -88,6 +117,15 @@ impl Matrix {
         }
     }
 
+    /// Creates a matrix from already checked parts.
+    fn from_parts(rows: usize, columns: usize, data_container: Vec<Vec<f64>>) -> Matrix {
+        Matrix {
+            rows,
+            columns,
+            data_container,
+        }
+    }
+
     /// Multiply a matrix with another one.
     pub fn multiply(&self, right: &Matrix) -> Result<Matrix, error::MathError> {
         match math::multiply_matrices(&self.data_container, &right.data_container) {
@@ -111,6 +149,26 @@ impl Matrix {
         }
     }
 
+    /// Multiply a matrix with a sparse one: dense x sparse.
+    pub fn multiply_sparse(&self, right: &SparseMatrix) -> Result<Matrix, error::MathError> {
+        if self.columns != right.rows() {
+            return Err(error::MathError);
+        }
+
+        let mut product = vec![vec![0.0; right.columns()]; self.rows];
+        for (row, column, value) in right.iter() {
+            for (product_row, left_row) in product.iter_mut().zip(&self.data_container) {
+                product_row[column] += left_row[row] * value;
+            }
+        }
+
+        Ok(Matrix {
+            rows: self.rows,
+            columns: right.columns(),
+            data_container: product,
+        })
+    }
+
     /// Adds one matrix to another.
     pub fn add(&self, right: &Matrix) -> Result<Matrix, error::MathError> {
         let result = math::sum_matrices(&self.data_container, &right.data_container)?;
@@ -131,9 +189,288 @@ impl Matrix {
         }
     }
 
+    /// Puts another matrix on the right side. Both need the same amount of rows.
+    pub fn hstack(&self, right: &Matrix) -> Result<Matrix, error::MathError> {
+        if self.rows != right.rows {
+            return Err(error::MathError);
+        }
+
+        let data_container = self.data_container
+            .iter()
+            .zip(&right.data_container)
+            .map(|(left_row, right_row)| {
+                let mut row = left_row.clone();
+                row.extend_from_slice(right_row);
+                row
+            })
+            .collect();
+
+        Ok(Matrix {
+            rows: self.rows,
+            columns: self.columns + right.columns,
+            data_container,
+        })
+    }
+
+    /// Puts another matrix below. Both need the same amount of columns.
+    pub fn vstack(&self, below: &Matrix) -> Result<Matrix, error::MathError> {
+        if self.columns != below.columns {
+            return Err(error::MathError);
+        }
+
+        let mut data_container = self.data_container.clone();
+        data_container.extend_from_slice(&below.data_container);
+
+        Ok(Matrix {
+            rows: self.rows + below.rows,
+            columns: self.columns,
+            data_container,
+        })
+    }
+
+    /// Joins several matrices. `Axis::Row` appends the rows (like `vstack`),
+    /// `Axis::Column` appends the columns (like `hstack`).
+    pub fn concat(matrices: &[Matrix], axis: Axis) -> Result<Matrix, error::MathError> {
+        let (first, rest) = match matrices.split_first() {
+            Some(parts) => parts,
+            None => return Err(error::MathError),
+        };
+
+        let mut result = first.clone();
+        for matrix in rest {
+            result = match axis {
+                Axis::Row => result.vstack(matrix)?,
+                Axis::Column => result.hstack(matrix)?,
+            };
+        }
+        Ok(result)
+    }
+
+    /// Splits the matrix in front of row or column `index`. The first matrix
+    /// contains everything before `index`, the second one the rest.
+    pub fn split_at(&self, axis: Axis, index: usize) -> Result<(Matrix, Matrix), error::MathError> {
+        match axis {
+            Axis::Row => {
+                if index > self.rows {
+                    return Err(error::MathError);
+                }
+                let (upper, lower) = self.data_container.split_at(index);
+
+                Ok((
+                    Matrix {
+                        rows: index,
+                        columns: self.columns,
+                        data_container: upper.to_vec(),
+                    },
+                    Matrix {
+                        rows: self.rows - index,
+                        columns: self.columns,
+                        data_container: lower.to_vec(),
+                    },
+                ))
+            }
+            Axis::Column => {
+                if index > self.columns {
+                    return Err(error::MathError);
+                }
+                let mut left = Vec::new();
+                let mut right = Vec::new();
+                for row in &self.data_container {
+                    let (left_part, right_part) = row.split_at(index);
+                    left.push(left_part.to_vec());
+                    right.push(right_part.to_vec());
+                }
+
+                Ok((
+                    Matrix {
+                        rows: self.rows,
+                        columns: index,
+                        data_container: left,
+                    },
+                    Matrix {
+                        rows: self.rows,
+                        columns: self.columns - index,
+                        data_container: right,
+                    },
+                ))
+            }
+        }
+    }
+
+    /// Changes the shape while keeping the cells in row-major order. A
+    /// matrix without cells keeps its number of rows.
+    pub fn reshape(&self, rows: usize, columns: usize) -> Result<Matrix, error::MathError> {
+        if rows.checked_mul(columns) != Some(self.rows * self.columns) {
+            return Err(error::MathError);
+        }
+        if columns == 0 && rows != self.rows {
+            return Err(error::MathError);
+        }
+
+        let cells = self.flatten();
+        let data_container = (0..rows)
+            .map(|row| cells[row * columns..(row + 1) * columns].to_vec())
+            .collect();
+
+        Ok(Matrix {
+            rows,
+            columns,
+            data_container,
+        })
+    }
+
+    /// All cells in row-major order.
+    pub fn flatten(&self) -> Vec<f64> {
+        self.data_container.concat()
+    }
+
+    /// Repeats every row or column `count` times in place.
+    ///
+    /// # Example for `repeat(2, Axis::Column)`:
+    ///
+    /// | 1   2 |   =>  | 1   1   2   2 |
+    ///
+    pub fn repeat(&self, count: usize, axis: Axis) -> Matrix {
+        match axis {
+            Axis::Row => {
+                let mut data_container = Vec::new();
+                for row in &self.data_container {
+                    for _ in 0..count {
+                        data_container.push(row.clone());
+                    }
+                }
+                Matrix {
+                    rows: self.rows * count,
+                    columns: self.columns,
+                    data_container,
+                }
+            }
+            Axis::Column => {
+                let data_container = self.data_container
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .flat_map(|cell| vec![*cell; count])
+                            .collect()
+                    })
+                    .collect();
+                Matrix {
+                    rows: self.rows,
+                    columns: self.columns * count,
+                    data_container,
+                }
+            }
+        }
+    }
+
+    /// Repeats the whole matrix `row_count` times downwards and `column_count`
+    /// times to the right.
+    ///
+    /// # Example for `tile(1, 2)`:
+    ///
+    /// | 1   2 |   =>  | 1   2   1   2 |
+    ///
+    pub fn tile(&self, row_count: usize, column_count: usize) -> Matrix {
+        let mut data_container = Vec::new();
+        for _ in 0..row_count {
+            for row in &self.data_container {
+                data_container.push(row.repeat(column_count));
+            }
+        }
+
+        Matrix {
+            rows: self.rows * row_count,
+            columns: self.columns * column_count,
+            data_container,
+        }
+    }
+
     pub fn data_container(&self) -> &Vec<Vec<f64>> {
         &self.data_container
     }
+
+    /// Mutable access to the cells of one row - the shape cannot change this way.
+    pub fn row_mut(&mut self, index: usize) -> &mut [f64] {
+        &mut self.data_container[index]
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn sum(&self, axis: Axis) -> Vec<f64> {
+        self.lanes(axis).iter().map(|lane| math::sum(lane)).collect()
+    }
+
+    /// Mean per row or column - None when the lanes are empty.
+    pub fn mean(&self, axis: Axis) -> Option<Vec<f64>> {
+        self.lanes(axis).iter().map(|lane| math::mean(lane)).collect()
+    }
+
+    /// Population variance per row or column.
+    pub fn var(&self, axis: Axis) -> Option<Vec<f64>> {
+        self.lanes(axis).iter().map(|lane| math::var(lane)).collect()
+    }
+
+    /// Population standard deviation per row or column.
+    pub fn std(&self, axis: Axis) -> Option<Vec<f64>> {
+        self.lanes(axis).iter().map(|lane| math::std(lane)).collect()
+    }
+
+    pub fn min(&self, axis: Axis) -> Option<Vec<f64>> {
+        self.lanes(axis).iter().map(|lane| math::min(lane)).collect()
+    }
+
+    pub fn max(&self, axis: Axis) -> Option<Vec<f64>> {
+        self.lanes(axis).iter().map(|lane| math::max(lane)).collect()
+    }
+
+    pub fn argmin(&self, axis: Axis) -> Option<Vec<usize>> {
+        self.lanes(axis).iter().map(|lane| math::argmin(lane)).collect()
+    }
+
+    pub fn argmax(&self, axis: Axis) -> Option<Vec<usize>> {
+        self.lanes(axis).iter().map(|lane| math::argmax(lane)).collect()
+    }
+
+    /// Square root of the sum of all squared cells.
+    pub fn frobenius_norm(&self) -> f64 {
+        let squared_sum: f64 = self.data_container
+            .iter()
+            .map(|row| row.iter().map(|x| x * x).sum::<f64>())
+            .sum();
+        squared_sum.sqrt()
+    }
+
+    /// Highest absolute column sum.
+    pub fn l1_norm(&self) -> f64 {
+        let column_sums: Vec<f64> = self.lanes(Axis::Column)
+            .iter()
+            .map(|column| math::l1_norm(column))
+            .collect();
+        math::linf_norm(&column_sums)
+    }
+
+    /// Highest absolute row sum.
+    pub fn linf_norm(&self) -> f64 {
+        let row_sums: Vec<f64> = self.data_container
+            .iter()
+            .map(|row| math::l1_norm(row))
+            .collect();
+        math::linf_norm(&row_sums)
+    }
+
+    /// Rows or columns of the matrix, depending on the axis.
+    fn lanes(&self, axis: Axis) -> Vec<Vec<f64>> {
+        match axis {
+            Axis::Row => self.data_container.clone(),
+            Axis::Column => math::transpose_2d_vector(&self.data_container),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -142,23 +479,42 @@ mod matrix_tests {
 
     #[test]
     fn test_zero() {
-        let m = Matrix::zero(2, 2);
+        let m = Matrix::zeros(2, 2);
         assert_eq!(m.rows, 2);
         assert_eq!(m.columns, 2);
         assert_eq!(m.data_container[0][0], 0.0);
         assert_eq!(m.data_container[1][1], 0.0);
     }
 
+    #[test]
+    fn test_zeros_shape() {
+        let m = Matrix::zeros(2, 3);
+        assert_eq!(m.rows(), 2);
+        assert_eq!(m.columns(), 3);
+        assert_eq!(m.data_container().len(), 2);
+    }
+
     #[test]
     fn test_create_weighting_vec() {
-        let vec = Matrix::create_weighting_matrix(3, 4);
-        assert_eq!(vec.len(), 4);
-        assert_eq!(vec[0].len(), 3);
+        let vec = Matrix::create_weighting_matrix(4, 3);
+        assert_eq!(vec.rows, 4);
+        assert_eq!(vec.columns, 3);
+        assert_eq!(vec.data_container.len(), 4);
+        assert_eq!(vec.data_container[0].len(), 3);
+    }
+
+    #[test]
+    fn test_create_seeded_weighting_matrix() {
+        let matrix = Matrix::create_seeded_weighting_matrix(4, 3, &mut SeededRng::new(1));
+        assert_eq!(matrix.rows, 4);
+        assert_eq!(matrix.data_container[3].len(), 3);
+        assert_eq!(matrix, Matrix::create_seeded_weighting_matrix(4, 3, &mut SeededRng::new(1)));
+        assert!(matrix.data_container[0].iter().all(|v| v.abs() < 0.1));
     }
 
     #[test]
     fn test_multiply() {
-        let mut m1 = Matrix::zero(2, 3);
+        let mut m1 = Matrix::zeros(2, 3);
         m1.data_container[0][0] = 2.0;
         m1.data_container[0][1] = 3.0;
         m1.data_container[0][2] = 1.0;
@@ -166,7 +522,7 @@ mod matrix_tests {
         m1.data_container[1][1] = 1.0;
         m1.data_container[1][2] = 1.0;
 
-        let mut m2 = Matrix::zero(3, 2);
+        let mut m2 = Matrix::zeros(3, 2);
         m2.data_container[0][0] = 3.0;
         m2.data_container[0][1] = 3.0;
         m2.data_container[1][0] = 2.0;
@@ -186,7 +542,7 @@ mod matrix_tests {
 
     #[test]
     fn test_multiply_err() {
-        let mut m1 = Matrix::zero(2, 3);
+        let mut m1 = Matrix::zeros(2, 3);
         m1.data_container[0][0] = 2.0;
         m1.data_container[0][1] = 3.0;
         m1.data_container[0][2] = 1.0;
@@ -194,7 +550,7 @@ mod matrix_tests {
         m1.data_container[1][1] = 1.0;
         m1.data_container[1][2] = 1.0;
 
-        let mut m2 = Matrix::zero(2, 2);
+        let mut m2 = Matrix::zeros(2, 2);
         m2.data_container[0][0] = 3.0;
         m2.data_container[0][1] = 3.0;
         m2.data_container[1][0] = 2.0;
@@ -207,13 +563,13 @@ mod matrix_tests {
 
     #[test]
     fn test_add() {
-        let mut m1 = Matrix::zero(2, 2);
+        let mut m1 = Matrix::zeros(2, 2);
         m1.data_container[0][0] = 2.0;
         m1.data_container[0][1] = 3.0;
         m1.data_container[1][0] = 2.0;
         m1.data_container[1][1] = 1.0;
 
-        let mut m2 = Matrix::zero(2, 2);
+        let mut m2 = Matrix::zeros(2, 2);
         m2.data_container[0][0] = 3.0;
         m2.data_container[0][1] = 3.0;
         m2.data_container[1][0] = 2.0;
@@ -232,13 +588,13 @@ mod matrix_tests {
 
     #[test]
     fn test_add_err() {
-        let mut m1 = Matrix::zero(2, 2);
+        let mut m1 = Matrix::zeros(2, 2);
         m1.data_container[0][0] = 2.0;
         m1.data_container[0][1] = 3.0;
         m1.data_container[1][0] = 2.0;
         m1.data_container[1][1] = 1.0;
 
-        let mut m2 = Matrix::zero(2, 1);
+        let mut m2 = Matrix::zeros(2, 1);
         m2.data_container[0][0] = 3.0;
         m2.data_container[1][0] = 3.0;
 
@@ -246,4 +602,134 @@ mod matrix_tests {
 
         assert!(m3.is_err());
     }
+
+    #[test]
+    fn test_reductions() {
+        let m = Matrix::from_2d_vec(&[vec![1.0, 2.0, 3.0], vec![4.0, 8.0, 0.0]]);
+
+        assert_eq!(m.sum(Axis::Row), vec![6.0, 12.0]);
+        assert_eq!(m.sum(Axis::Column), vec![5.0, 10.0, 3.0]);
+        assert_eq!(m.mean(Axis::Row), Some(vec![2.0, 4.0]));
+        assert_eq!(m.mean(Axis::Column), Some(vec![2.5, 5.0, 1.5]));
+        assert_eq!(m.var(Axis::Column), Some(vec![2.25, 9.0, 2.25]));
+        assert_eq!(m.std(Axis::Column), Some(vec![1.5, 3.0, 1.5]));
+        assert_eq!(m.min(Axis::Row), Some(vec![1.0, 0.0]));
+        assert_eq!(m.max(Axis::Column), Some(vec![4.0, 8.0, 3.0]));
+        assert_eq!(m.argmin(Axis::Row), Some(vec![0, 2]));
+        assert_eq!(m.argmax(Axis::Row), Some(vec![2, 1]));
+        assert_eq!(m.argmax(Axis::Column), Some(vec![1, 1, 0]));
+    }
+
+    #[test]
+    fn test_reductions_without_columns() {
+        let m = Matrix::zeros(2, 0);
+
+        assert_eq!(m.sum(Axis::Row), vec![0.0, 0.0]);
+        assert_eq!(m.mean(Axis::Row), None);
+        assert_eq!(m.argmax(Axis::Row), None);
+    }
+
+    #[test]
+    fn test_norms() {
+        let m = Matrix::from_2d_vec(&[vec![1.0, -2.0], vec![-3.0, 4.0]]);
+
+        assert_eq!(m.frobenius_norm(), 30.0_f64.sqrt());
+        assert_eq!(m.l1_norm(), 6.0);
+        assert_eq!(m.linf_norm(), 7.0);
+    }
+
+    #[test]
+    fn test_hstack_vstack() {
+        let m1 = Matrix::from_2d_vec(&[vec![1.0, 2.0], vec![3.0, 4.0]]);
+        let m2 = Matrix::from_2d_vec(&[vec![5.0], vec![6.0]]);
+
+        let h = m1.hstack(&m2).unwrap();
+        assert_eq!(h, Matrix::from_2d_vec(&[vec![1.0, 2.0, 5.0], vec![3.0, 4.0, 6.0]]));
+        assert!(m1.vstack(&m2).is_err());
+
+        let v = m1.vstack(&m2.transpose()).unwrap();
+        assert_eq!(v.rows, 3);
+        assert_eq!(v.columns, 2);
+        assert_eq!(v.data_container[2], vec![5.0, 6.0]);
+        assert!(m1.hstack(&m2.transpose()).is_err());
+    }
+
+    #[test]
+    fn test_concat() {
+        let m = Matrix::from_1d_vec(&[1.0, 2.0], false);
+
+        let rows = Matrix::concat(&[m.clone(), m.clone(), m.clone()], Axis::Row).unwrap();
+        assert_eq!(rows.rows, 3);
+        assert_eq!(rows.columns, 2);
+
+        let columns = Matrix::concat(&[m.clone(), m.clone()], Axis::Column).unwrap();
+        assert_eq!(columns.data_container, vec![vec![1.0, 2.0, 1.0, 2.0]]);
+
+        assert!(Matrix::concat(&[], Axis::Row).is_err());
+    }
+
+    #[test]
+    fn test_split_at() {
+        let m = Matrix::from_2d_vec(&[vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+
+        let (upper, lower) = m.split_at(Axis::Row, 1).unwrap();
+        assert_eq!(upper.data_container, vec![vec![1.0, 2.0, 3.0]]);
+        assert_eq!(lower.data_container, vec![vec![4.0, 5.0, 6.0]]);
+
+        let (left, right) = m.split_at(Axis::Column, 2).unwrap();
+        assert_eq!(left.columns, 2);
+        assert_eq!(right.columns, 1);
+        assert_eq!(right.data_container, vec![vec![3.0], vec![6.0]]);
+        assert_eq!(left.hstack(&right).unwrap(), m);
+
+        assert!(m.split_at(Axis::Row, 3).is_err());
+        assert!(m.split_at(Axis::Column, 4).is_err());
+    }
+
+    #[test]
+    fn test_reshape_flatten() {
+        let m = Matrix::from_2d_vec(&[vec![1.0, 2.0, 3.0], vec![4.0, 5.0, 6.0]]);
+
+        assert_eq!(m.flatten(), vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+
+        let reshaped = m.reshape(3, 2).unwrap();
+        assert_eq!(reshaped.rows, 3);
+        assert_eq!(reshaped.columns, 2);
+        assert_eq!(reshaped.data_container[1], vec![3.0, 4.0]);
+
+        assert!(m.reshape(4, 2).is_err());
+        assert!(m.reshape(usize::MAX, 2).is_err());
+        assert!(Matrix::zeros(2, 0).reshape(usize::MAX, 0).is_err());
+        assert_eq!(Matrix::zeros(2, 0).reshape(2, 0).unwrap(), Matrix::zeros(2, 0));
+    }
+
+    #[test]
+    fn test_repeat_tile() {
+        let m = Matrix::from_2d_vec(&[vec![1.0, 2.0], vec![3.0, 4.0]]);
+
+        let repeated = m.repeat(2, Axis::Column);
+        assert_eq!(repeated.columns, 4);
+        assert_eq!(repeated.data_container[0], vec![1.0, 1.0, 2.0, 2.0]);
+
+        let repeated = m.repeat(2, Axis::Row);
+        assert_eq!(repeated.rows, 4);
+        assert_eq!(repeated.data_container[1], vec![1.0, 2.0]);
+        assert_eq!(repeated.data_container[2], vec![3.0, 4.0]);
+
+        let tiled = m.tile(2, 2);
+        assert_eq!(tiled.rows, 4);
+        assert_eq!(tiled.columns, 4);
+        assert_eq!(tiled.data_container[0], vec![1.0, 2.0, 1.0, 2.0]);
+        assert_eq!(tiled.data_container[2], vec![1.0, 2.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_multiply_sparse() {
+        let left = Matrix::from_2d_vec(&[vec![1.0, 2.0], vec![3.0, 4.0]]);
+        let right = Matrix::from_2d_vec(&[vec![0.0, 5.0, 0.0], vec![2.0, 0.0, 0.0]]);
+        let sparse = SparseMatrix::from_matrix(&right, sparse::SparseFormat::Csc);
+
+        assert_eq!(left.multiply_sparse(&sparse).unwrap(), left.multiply(&right).unwrap());
+        assert!(right.multiply_sparse(&sparse).is_err());
+    }
 }
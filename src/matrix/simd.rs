@@ -0,0 +1,356 @@
+//! Vectorised kernels for the hot loops of the network.
+//!
+//! Every kernel checks at runtime which instructions the CPU supports and
+//! falls back to the portable scalar version in the `scalar` module. All
+//! kernels work on the common length of their slices - like `zip` does.
+
+use util;
+
+/// Sum of the products of both slices.
+pub fn dot(left: &[f64], right: &[f64]) -> f64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            return unsafe { avx::dot(left, right) };
+        }
+    }
+    scalar::dot(left, right)
+}
+
+/// `y += alpha * x`
+pub fn axpy(alpha: f64, x: &[f64], y: &mut [f64]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            return unsafe { avx::axpy(alpha, x, y) };
+        }
+    }
+    scalar::axpy(alpha, x, y)
+}
+
+/// `out = left + right`
+pub fn add(left: &[f64], right: &[f64], out: &mut [f64]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            return unsafe { avx::add(left, right, out) };
+        }
+    }
+    scalar::add(left, right, out)
+}
+
+/// `out = left - right`
+pub fn sub(left: &[f64], right: &[f64], out: &mut [f64]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            return unsafe { avx::sub(left, right, out) };
+        }
+    }
+    scalar::sub(left, right, out)
+}
+
+/// `out = left * right` cell by cell.
+pub fn mul(left: &[f64], right: &[f64], out: &mut [f64]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            return unsafe { avx::mul(left, right, out) };
+        }
+    }
+    scalar::mul(left, right, out)
+}
+
+/// Logistic function for every value.
+pub fn sigmoid(values: &[f64], out: &mut [f64]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            return unsafe { avx::sigmoid(values, out) };
+        }
+    }
+    scalar::sigmoid(values, out)
+}
+
+/// Rectified linear unit for every value.
+pub fn relu(values: &[f64], out: &mut [f64]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            return unsafe { avx::relu(values, out) };
+        }
+    }
+    scalar::relu(values, out)
+}
+
+/// Portable reference implementations.
+pub mod scalar {
+    use super::util;
+
+    pub fn dot(left: &[f64], right: &[f64]) -> f64 {
+        left.iter().zip(right).map(|(a, b)| a * b).sum()
+    }
+
+    pub fn axpy(alpha: f64, x: &[f64], y: &mut [f64]) {
+        for (y, x) in y.iter_mut().zip(x) {
+            *y += alpha * x;
+        }
+    }
+
+    pub fn add(left: &[f64], right: &[f64], out: &mut [f64]) {
+        for ((o, a), b) in out.iter_mut().zip(left).zip(right) {
+            *o = a + b;
+        }
+    }
+
+    pub fn sub(left: &[f64], right: &[f64], out: &mut [f64]) {
+        for ((o, a), b) in out.iter_mut().zip(left).zip(right) {
+            *o = a - b;
+        }
+    }
+
+    pub fn mul(left: &[f64], right: &[f64], out: &mut [f64]) {
+        for ((o, a), b) in out.iter_mut().zip(left).zip(right) {
+            *o = a * b;
+        }
+    }
+
+    pub fn sigmoid(values: &[f64], out: &mut [f64]) {
+        for (o, x) in out.iter_mut().zip(values) {
+            *o = util::sigmoid(*x);
+        }
+    }
+
+    pub fn relu(values: &[f64], out: &mut [f64]) {
+        for (o, x) in out.iter_mut().zip(values) {
+            *o = util::relu(*x);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod avx {
+    use std::arch::x86_64::*;
+    use super::scalar;
+
+    const LANES: usize = 4;
+
+    #[target_feature(enable = "avx")]
+    pub unsafe fn dot(left: &[f64], right: &[f64]) -> f64 {
+        let len = left.len().min(right.len());
+        let split = len - len % LANES;
+        let mut acc = _mm256_setzero_pd();
+
+        let mut i = 0;
+        while i < split {
+            let a = _mm256_loadu_pd(left.as_ptr().add(i));
+            let b = _mm256_loadu_pd(right.as_ptr().add(i));
+            acc = _mm256_add_pd(acc, _mm256_mul_pd(a, b));
+            i += LANES;
+        }
+
+        let mut lanes = [0.0; LANES];
+        _mm256_storeu_pd(lanes.as_mut_ptr(), acc);
+        lanes.iter().sum::<f64>() + scalar::dot(&left[split..len], &right[split..len])
+    }
+
+    #[target_feature(enable = "avx")]
+    pub unsafe fn axpy(alpha: f64, x: &[f64], y: &mut [f64]) {
+        let len = x.len().min(y.len());
+        let split = len - len % LANES;
+        let a = _mm256_set1_pd(alpha);
+
+        let mut i = 0;
+        while i < split {
+            let xs = _mm256_loadu_pd(x.as_ptr().add(i));
+            let ys = _mm256_loadu_pd(y.as_ptr().add(i));
+            _mm256_storeu_pd(y.as_mut_ptr().add(i), _mm256_add_pd(ys, _mm256_mul_pd(a, xs)));
+            i += LANES;
+        }
+        scalar::axpy(alpha, &x[split..len], &mut y[split..len]);
+    }
+
+    macro_rules! binary_kernel {
+        ($name:ident, $op:ident) => {
+            #[target_feature(enable = "avx")]
+            pub unsafe fn $name(left: &[f64], right: &[f64], out: &mut [f64]) {
+                let len = left.len().min(right.len()).min(out.len());
+                let split = len - len % LANES;
+
+                let mut i = 0;
+                while i < split {
+                    let a = _mm256_loadu_pd(left.as_ptr().add(i));
+                    let b = _mm256_loadu_pd(right.as_ptr().add(i));
+                    _mm256_storeu_pd(out.as_mut_ptr().add(i), $op(a, b));
+                    i += LANES;
+                }
+                scalar::$name(&left[split..len], &right[split..len], &mut out[split..len]);
+            }
+        };
+    }
+
+    binary_kernel!(add, _mm256_add_pd);
+    binary_kernel!(sub, _mm256_sub_pd);
+    binary_kernel!(mul, _mm256_mul_pd);
+
+    #[target_feature(enable = "avx")]
+    pub unsafe fn relu(values: &[f64], out: &mut [f64]) {
+        let len = values.len().min(out.len());
+        let split = len - len % LANES;
+        let zero = _mm256_setzero_pd();
+
+        let mut i = 0;
+        while i < split {
+            let x = _mm256_loadu_pd(values.as_ptr().add(i));
+            _mm256_storeu_pd(out.as_mut_ptr().add(i), _mm256_max_pd(x, zero));
+            i += LANES;
+        }
+        scalar::relu(&values[split..len], &mut out[split..len]);
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn sigmoid(values: &[f64], out: &mut [f64]) {
+        let len = values.len().min(out.len());
+        let split = len - len % LANES;
+        let one = _mm256_set1_pd(1.0);
+
+        let mut i = 0;
+        while i < split {
+            let x = _mm256_loadu_pd(values.as_ptr().add(i));
+            let e = exp(_mm256_sub_pd(_mm256_setzero_pd(), x));
+            _mm256_storeu_pd(out.as_mut_ptr().add(i), _mm256_div_pd(one, _mm256_add_pd(one, e)));
+            i += LANES;
+        }
+        scalar::sigmoid(&values[split..len], &mut out[split..len]);
+    }
+
+    /// e^x with the range reduction x = n * ln(2) + r, |r| <= ln(2) / 2, and a
+    /// Taylor polynomial for e^r. 2^n is built directly in the exponent bits.
+    /// NaN stays NaN, the clamp below would turn it into a number.
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn exp(x: __m256d) -> __m256d {
+        const LN2_HI: f64 = 6.931_471_803_691_238e-1;
+        const LN2_LO: f64 = 1.908_214_929_270_587_7e-10;
+        // 13th order is exact to f64 precision for |r| <= ln(2) / 2
+        const ORDER: usize = 13;
+
+        let nan = _mm256_cmp_pd(x, x, _CMP_UNORD_Q);
+        let x = _mm256_min_pd(_mm256_max_pd(x, _mm256_set1_pd(-708.0)), _mm256_set1_pd(709.0));
+        let n = _mm256_round_pd(
+            _mm256_mul_pd(x, _mm256_set1_pd(::std::f64::consts::LOG2_E)),
+            _MM_FROUND_TO_NEAREST_INT | _MM_FROUND_NO_EXC,
+        );
+        let r = _mm256_fnmadd_pd(n, _mm256_set1_pd(LN2_HI), x);
+        let r = _mm256_fnmadd_pd(n, _mm256_set1_pd(LN2_LO), r);
+
+        // Horner scheme for sum(r^k / k!)
+        let mut coefficients = [1.0; ORDER + 1];
+        for k in 1..ORDER + 1 {
+            coefficients[k] = coefficients[k - 1] / k as f64;
+        }
+        let mut p = _mm256_set1_pd(coefficients[ORDER]);
+        for k in (0..ORDER).rev() {
+            p = _mm256_fmadd_pd(p, r, _mm256_set1_pd(coefficients[k]));
+        }
+
+        // (n + 1023) lands in the low mantissa bits of (n + 1023 + 2^52)
+        let two_52 = _mm256_set1_pd(4_503_599_627_370_496.0);
+        let biased = _mm256_add_pd(_mm256_add_pd(n, _mm256_set1_pd(1023.0)), two_52);
+        let exponent = _mm256_sub_epi64(_mm256_castpd_si256(biased), _mm256_castpd_si256(two_52));
+        let scale = _mm256_castsi256_pd(_mm256_slli_epi64(exponent, 52));
+
+        _mm256_blendv_pd(_mm256_mul_pd(p, scale), _mm256_set1_pd(f64::NAN), nan)
+    }
+}
+
+#[cfg(test)]
+mod simd_tests {
+    use super::*;
+
+    type BinaryKernel = fn(&[f64], &[f64], &mut [f64]);
+
+    // Odd length so that the scalar tail is covered too
+    fn values() -> Vec<f64> {
+        (0..37).map(|x| (x as f64 - 18.0) * 0.73).collect()
+    }
+
+    fn others() -> Vec<f64> {
+        (0..37).map(|x| (x as f64 * 1.37).sin()).collect()
+    }
+
+    fn assert_close(left: &[f64], right: &[f64]) {
+        assert_eq!(left.len(), right.len());
+        for (a, b) in left.iter().zip(right) {
+            assert!((a - b).abs() <= 1e-12 * a.abs().max(1.0), "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_dot() {
+        let expected = scalar::dot(&values(), &others());
+        assert!((dot(&values(), &others()) - expected).abs() < 1e-10);
+        assert_eq!(dot(&[1.0, 2.0, 3.0], &[4.0, 5.0]), 14.0);
+    }
+
+    #[test]
+    fn test_axpy() {
+        let mut expected = others();
+        scalar::axpy(0.5, &values(), &mut expected);
+        let mut result = others();
+        axpy(0.5, &values(), &mut result);
+        assert_close(&result, &expected);
+    }
+
+    #[test]
+    fn test_binary_kernels() {
+        let kernels: Vec<(BinaryKernel, BinaryKernel)> =
+            vec![(add, scalar::add), (sub, scalar::sub), (mul, scalar::mul)];
+
+        for (kernel, reference) in kernels {
+            let mut expected = vec![0.0; 37];
+            reference(&values(), &others(), &mut expected);
+            let mut result = vec![0.0; 37];
+            kernel(&values(), &others(), &mut result);
+            assert_close(&result, &expected);
+        }
+    }
+
+    #[test]
+    fn test_activations() {
+        let mut expected = vec![0.0; 37];
+        let mut result = vec![0.0; 37];
+
+        scalar::sigmoid(&values(), &mut expected);
+        sigmoid(&values(), &mut result);
+        assert_close(&result, &expected);
+
+        scalar::relu(&values(), &mut expected);
+        relu(&values(), &mut result);
+        assert_close(&result, &expected);
+    }
+
+    #[test]
+    fn test_sigmoid_extremes() {
+        let extremes = vec![-800.0, -40.0, 0.0, 40.0, 800.0];
+        let mut result = vec![0.0; 5];
+        sigmoid(&extremes, &mut result);
+
+        assert!(result[0] < 1e-300);
+        assert_eq!(result[2], 0.5);
+        assert_eq!(result[4], 1.0);
+        assert!(result.iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn test_sigmoid_nan() {
+        // The first four go through the vector kernel, the last through the tail
+        let values = vec![0.0, f64::NAN, 1.0, -1.0, f64::NAN];
+        let mut result = vec![0.0; 5];
+        sigmoid(&values, &mut result);
+
+        assert_eq!(result[0], 0.5);
+        assert!(result[1].is_nan());
+        assert!(result[4].is_nan());
+        assert!(result[2].is_finite() && result[3].is_finite());
+    }
+}
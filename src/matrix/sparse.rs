@@ -0,0 +1,274 @@
+use super::Matrix;
+use super::error::MathError;
+
+/// Storage order of a sparse matrix.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SparseFormat {
+    /// Compressed sparse row - fast access to single rows.
+    Csr,
+    /// Compressed sparse column - fast access to single columns.
+    Csc,
+}
+
+/// A matrix that only stores its non zero cells.
+///
+/// Depending on the format the cells are compressed by rows (CSR) or by
+/// columns (CSC). The "major" lanes are the rows for CSR and the columns for
+/// CSC. The cells of major lane `i` are found in `indices[indptr[i]..indptr[i + 1]]`
+/// (minor positions) and `values[indptr[i]..indptr[i + 1]]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseMatrix {
+    rows: usize,
+    columns: usize,
+    format: SparseFormat,
+    indptr: Vec<usize>,
+    indices: Vec<usize>,
+    values: Vec<f64>,
+}
+
+impl SparseMatrix {
+    /// Creates a sparse matrix from (row, column, value) triplets. Zero values
+    /// are dropped and duplicated positions are summed up.
+    pub fn from_triplets(
+        rows: usize,
+        columns: usize,
+        triplets: &[(usize, usize, f64)],
+        format: SparseFormat,
+    ) -> Result<SparseMatrix, MathError> {
+        let major_size = match format {
+            SparseFormat::Csr => rows,
+            SparseFormat::Csc => columns,
+        };
+        let mut lanes: Vec<Vec<(usize, f64)>> = vec![Vec::new(); major_size];
+
+        for &(row, column, value) in triplets {
+            if row >= rows || column >= columns {
+                return Err(MathError);
+            }
+            let (major, minor) = match format {
+                SparseFormat::Csr => (row, column),
+                SparseFormat::Csc => (column, row),
+            };
+            lanes[major].push((minor, value));
+        }
+
+        let mut indptr = vec![0];
+        let mut indices = Vec::new();
+        let mut values = Vec::new();
+
+        for lane in &mut lanes {
+            lane.sort_by_key(|&(minor, _)| minor);
+
+            let mut merged: Vec<(usize, f64)> = Vec::new();
+            for &(minor, value) in lane.iter() {
+                match merged.last_mut() {
+                    Some(last) if last.0 == minor => last.1 += value,
+                    _ => merged.push((minor, value)),
+                }
+            }
+            for (minor, value) in merged {
+                if value != 0.0 {
+                    indices.push(minor);
+                    values.push(value);
+                }
+            }
+            indptr.push(indices.len());
+        }
+
+        Ok(SparseMatrix {
+            rows,
+            columns,
+            format,
+            indptr,
+            indices,
+            values,
+        })
+    }
+
+    /// Creates a sparse matrix from a dense one.
+    pub fn from_matrix(source: &Matrix, format: SparseFormat) -> SparseMatrix {
+        let mut triplets = Vec::new();
+        for (row, cells) in source.data_container().iter().enumerate() {
+            for (column, value) in cells.iter().enumerate() {
+                if *value != 0.0 {
+                    triplets.push((row, column, *value));
+                }
+            }
+        }
+
+        match SparseMatrix::from_triplets(source.rows(), source.columns(), &triplets, format) {
+            Ok(m) => m,
+            Err(e) => panic!("cells of a dense matrix are always in range: {}", e),
+        }
+    }
+
+    /// Creates a sparse vector - see `Matrix::from_1d_vec` for the meaning
+    /// of `is_vertical`.
+    pub fn from_1d_vec(source: &[f64], is_vertical: bool) -> SparseMatrix {
+        let matrix = Matrix::from_1d_vec(source, is_vertical);
+        let format = if is_vertical {
+            SparseFormat::Csc
+        } else {
+            SparseFormat::Csr
+        };
+        SparseMatrix::from_matrix(&matrix, format)
+    }
+
+    /// Converts back into a dense matrix.
+    pub fn to_matrix(&self) -> Matrix {
+        let mut data_container = vec![vec![0.0; self.columns]; self.rows];
+        for (row, column, value) in self.iter() {
+            data_container[row][column] = value;
+        }
+
+        Matrix::from_parts(self.rows, self.columns, data_container)
+    }
+
+    /// Changes the storage order.
+    pub fn to_format(&self, format: SparseFormat) -> SparseMatrix {
+        if self.format == format {
+            return self.clone();
+        }
+        let triplets: Vec<(usize, usize, f64)> = self.iter().collect();
+
+        match SparseMatrix::from_triplets(self.rows, self.columns, &triplets, format) {
+            Ok(m) => m,
+            Err(e) => panic!("cells of a sparse matrix are always in range: {}", e),
+        }
+    }
+
+    /// Transposes the matrix without copying cells around: the transpose of a
+    /// CSR matrix is the same data read as CSC and vice versa.
+    pub fn transpose(&self) -> SparseMatrix {
+        SparseMatrix {
+            rows: self.columns,
+            columns: self.rows,
+            format: match self.format {
+                SparseFormat::Csr => SparseFormat::Csc,
+                SparseFormat::Csc => SparseFormat::Csr,
+            },
+            indptr: self.indptr.clone(),
+            indices: self.indices.clone(),
+            values: self.values.clone(),
+        }
+    }
+
+    /// Multiplies this sparse matrix with a dense one: sparse x dense.
+    pub fn multiply_dense(&self, right: &Matrix) -> Result<Matrix, MathError> {
+        if self.columns != right.rows() {
+            return Err(MathError);
+        }
+
+        let right_rows = right.data_container();
+        let mut product = vec![vec![0.0; right.columns()]; self.rows];
+        for (row, column, value) in self.iter() {
+            for (cell, right_cell) in product[row].iter_mut().zip(&right_rows[column]) {
+                *cell += value * right_cell;
+            }
+        }
+
+        Ok(Matrix::from_parts(self.rows, right.columns(), product))
+    }
+
+    /// All non zero cells as (row, column, value) in storage order.
+    pub fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (usize, usize, f64)> + 'a> {
+        let format = self.format;
+        Box::new((0..self.indptr.len() - 1).flat_map(move |major| {
+            let range = self.indptr[major]..self.indptr[major + 1];
+            self.indices[range.clone()]
+                .iter()
+                .zip(&self.values[range])
+                .map(move |(&minor, &value)| match format {
+                    SparseFormat::Csr => (major, minor, value),
+                    SparseFormat::Csc => (minor, major, value),
+                })
+        }))
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn format(&self) -> SparseFormat {
+        self.format
+    }
+
+    /// Amount of stored (non zero) cells.
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+}
+
+#[cfg(test)]
+mod sparse_tests {
+    use super::*;
+
+    fn dense() -> Matrix {
+        Matrix::from_2d_vec(&[vec![0.0, 2.0, 0.0], vec![1.0, 0.0, 3.0]])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for format in &[SparseFormat::Csr, SparseFormat::Csc] {
+            let sparse = SparseMatrix::from_matrix(&dense(), *format);
+            assert_eq!(sparse.nnz(), 3);
+            assert_eq!(sparse.to_matrix(), dense());
+        }
+    }
+
+    #[test]
+    fn test_from_triplets() {
+        let triplets = vec![(1, 2, 1.0), (0, 1, 2.0), (1, 0, 1.0), (1, 2, 2.0), (0, 0, 0.0)];
+        let sparse = SparseMatrix::from_triplets(2, 3, &triplets, SparseFormat::Csr).unwrap();
+
+        assert_eq!(sparse.nnz(), 3);
+        assert_eq!(sparse.to_matrix(), dense());
+        assert!(SparseMatrix::from_triplets(2, 3, &[(2, 0, 1.0)], SparseFormat::Csr).is_err());
+    }
+
+    #[test]
+    fn test_transpose() {
+        let sparse = SparseMatrix::from_matrix(&dense(), SparseFormat::Csr);
+        let transposed = sparse.transpose();
+
+        assert_eq!(transposed.format(), SparseFormat::Csc);
+        assert_eq!(transposed.rows(), 3);
+        assert_eq!(transposed.columns(), 2);
+        assert_eq!(transposed.to_matrix(), dense().transpose());
+    }
+
+    #[test]
+    fn test_to_format() {
+        let csr = SparseMatrix::from_matrix(&dense(), SparseFormat::Csr);
+        let csc = csr.to_format(SparseFormat::Csc);
+
+        assert_eq!(csc.format(), SparseFormat::Csc);
+        assert_eq!(csc, SparseMatrix::from_matrix(&dense(), SparseFormat::Csc));
+    }
+
+    #[test]
+    fn test_multiply_dense() {
+        let right = Matrix::from_2d_vec(&[vec![1.0, 2.0], vec![3.0, 4.0], vec![5.0, 6.0]]);
+        let expected = dense().multiply(&right).unwrap();
+
+        for format in &[SparseFormat::Csr, SparseFormat::Csc] {
+            let sparse = SparseMatrix::from_matrix(&dense(), *format);
+            assert_eq!(sparse.multiply_dense(&right).unwrap(), expected);
+        }
+        let sparse = SparseMatrix::from_matrix(&dense(), SparseFormat::Csr);
+        assert!(sparse.multiply_dense(&dense()).is_err());
+    }
+
+    #[test]
+    fn test_from_1d_vec() {
+        let sparse = SparseMatrix::from_1d_vec(&[0.0, 0.0, 1.0, 0.0], true);
+        assert_eq!(sparse.rows(), 4);
+        assert_eq!(sparse.columns(), 1);
+        assert_eq!(sparse.nnz(), 1);
+        assert_eq!(sparse.iter().collect::<Vec<_>>(), vec![(2, 0, 1.0)]);
+    }
+}
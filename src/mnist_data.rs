@@ -1,41 +1,405 @@
-/// Converts one data set.
-pub fn convert_mnist_line(line: &str) -> (usize, Vec<f64>) {
-    // Is a iterator
-    let elements = line.split(',');
-    let values: Vec<f64> = elements
-        .map(|x: &str| match x.trim().parse() {
-            Ok(v) => v,
-            Err(e) => panic!("{:?}", e),
-        })
-        .collect();
-
-    let specific_number = match values.first() {
-        Some(v) => *v as usize,
-        None => panic!("First value in mnist_line is not a number"),
-    };
-
-    let values: Vec<f64> = values
-        .iter()
-        .skip(1)
-        .map(|x| x / 255.0 * 0.99 + 0.01)
-        .collect();
-
-    (specific_number, values)
+use dataset::{Dataset, Sample};
+use dataset::csv::{parse_mnist_record, CsvError, CsvLoader};
+use flate2::read::GzDecoder;
+use image::MNIST_SIZE;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+/// Magic number of an IDX file with unsigned bytes in 3 dimensions (images).
+pub const IMAGES_MAGIC_NUMBER: u32 = 0x0000_0803;
+/// Magic number of an IDX file with unsigned bytes in 1 dimension (labels).
+pub const LABELS_MAGIC_NUMBER: u32 = 0x0000_0801;
+
+/// Everything that can be wrong with an IDX file.
+#[derive(Debug)]
+pub enum IdxError {
+    Io(io::Error),
+    WrongMagicNumber { expected: u32, found: u32 },
+    /// The file ends before all announced items were read.
+    UnexpectedEnd,
+    /// Image and label file announce a different amount of items.
+    CountMismatch { images: usize, labels: usize },
+    /// The images are not 28 x 28 pixels like MNIST.
+    WrongImageSize { rows: usize, columns: usize },
+}
+
+impl fmt::Display for IdxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IdxError::Io(ref e) => write!(f, "could not read idx file: {}", e),
+            IdxError::WrongMagicNumber { expected, found } => write!(
+                f,
+                "invalid idx file: magic number {:#010x} expected but found {:#010x}",
+                expected,
+                found
+            ),
+            IdxError::UnexpectedEnd => write!(f, "invalid idx file: data ends too early"),
+            IdxError::CountMismatch { images, labels } => write!(
+                f,
+                "idx files do not match: {} images but {} labels",
+                images,
+                labels
+            ),
+            IdxError::WrongImageSize { rows, columns } => write!(
+                f,
+                "invalid idx file: images of {} x {} pixels but MNIST has {} x {}",
+                rows,
+                columns,
+                MNIST_SIZE,
+                MNIST_SIZE
+            ),
+        }
+    }
+}
+
+impl error::Error for IdxError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            IdxError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for IdxError {
+    fn from(e: io::Error) -> IdxError {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            IdxError::UnexpectedEnd
+        } else {
+            IdxError::Io(e)
+        }
+    }
+}
+
+/// MNIST samples in memory, so that they can be shuffled, split and batched.
+pub struct MnistDataset {
+    samples: Vec<Sample>,
+}
+
+impl MnistDataset {
+    /// Loads a pair of IDX files like `load_idx`.
+    pub fn from_idx<P: AsRef<Path>>(
+        images_path: P,
+        labels_path: P,
+    ) -> Result<MnistDataset, IdxError> {
+        Ok(MnistDataset::from(load_idx(images_path, labels_path)?))
+    }
+
+    /// Reads all records of a loader. Malformed rows are handled by the
+    /// policy of the loader.
+    pub fn from_csv<R: BufRead>(loader: CsvLoader<R>) -> Result<MnistDataset, CsvError> {
+        Ok(MnistDataset::from(loader.collect::<Result<Vec<_>, _>>()?))
+    }
+
+    pub fn samples(&self) -> &[Sample] {
+        &self.samples
+    }
+
+    pub fn into_samples(self) -> Vec<Sample> {
+        self.samples
+    }
+}
+
+impl From<Vec<Sample>> for MnistDataset {
+    fn from(samples: Vec<Sample>) -> MnistDataset {
+        MnistDataset { samples }
+    }
+}
+
+impl Dataset for MnistDataset {
+    fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    fn get(&self, index: usize) -> Option<(usize, &[f64])> {
+        Dataset::get(&self.samples[..], index)
+    }
+}
+
+/// Converts one data set - `dataset::csv::CsvLoader` does the same for whole
+/// files. `line_number` counts from 1 and is what errors report.
+pub fn convert_mnist_line(line: &str, line_number: usize) -> Result<Sample, CsvError> {
+    parse_mnist_record(line, line_number)
+}
+
+/// Scales a grey value from 0..255 to 0.01..1.0 - zero would stop the learning.
+pub fn normalise_pixel(value: f64) -> f64 {
+    value / 255.0 * 0.99 + 0.01
+}
+
+/// Loads the official MNIST files, e.g. `train-images-idx3-ubyte` and
+/// `train-labels-idx1-ubyte`. Gzipped files are detected and unpacked
+/// on the fly. The samples are normalised like in `convert_mnist_line`.
+pub fn load_idx<P: AsRef<Path>>(
+    images_path: P,
+    labels_path: P,
+) -> Result<Vec<Sample>, IdxError> {
+    convert_idx(open_idx(images_path)?, open_idx(labels_path)?)
+}
+
+/// Combines an IDX image and an IDX label source into samples.
+pub fn convert_idx<I: Read, L: Read>(
+    images: I,
+    labels: L,
+) -> Result<Vec<Sample>, IdxError> {
+    let images = read_idx_images(images)?;
+    let labels = read_idx_labels(labels)?;
+
+    if images.len() != labels.len() {
+        return Err(IdxError::CountMismatch {
+            images: images.len(),
+            labels: labels.len(),
+        });
+    }
+    Ok(labels.into_iter().zip(images).collect())
+}
+
+/// Reads all images of an IDX3 file - every image as one normalised row.
+/// The counts in the header are not trusted: memory only grows with the data
+/// that is actually there.
+pub fn read_idx_images<R: Read>(mut reader: R) -> Result<Vec<Vec<f64>>, IdxError> {
+    check_magic_number(&mut reader, IMAGES_MAGIC_NUMBER)?;
+    let count = read_u32(&mut reader)?;
+    let rows = read_u32(&mut reader)? as usize;
+    let columns = read_u32(&mut reader)? as usize;
+    if rows != MNIST_SIZE || columns != MNIST_SIZE {
+        return Err(IdxError::WrongImageSize { rows, columns });
+    }
+
+    let mut pixels = [0u8; MNIST_SIZE * MNIST_SIZE];
+    let mut images = Vec::new();
+    for _ in 0..count {
+        reader.read_exact(&mut pixels)?;
+        images.push(
+            pixels
+                .iter()
+                .map(|pixel| normalise_pixel(f64::from(*pixel)))
+                .collect(),
+        );
+    }
+    Ok(images)
+}
+
+/// Reads all labels of an IDX1 file.
+pub fn read_idx_labels<R: Read>(mut reader: R) -> Result<Vec<usize>, IdxError> {
+    check_magic_number(&mut reader, LABELS_MAGIC_NUMBER)?;
+    let count = u64::from(read_u32(&mut reader)?);
+
+    let mut labels = Vec::new();
+    reader.take(count).read_to_end(&mut labels)?;
+    if (labels.len() as u64) < count {
+        return Err(IdxError::UnexpectedEnd);
+    }
+    Ok(labels.into_iter().map(usize::from).collect())
+}
+
+/// Opens a (possibly gzipped) IDX file.
+fn open_idx<P: AsRef<Path>>(path: P) -> Result<Box<dyn Read>, IdxError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let is_gzip = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+
+    if is_gzip {
+        Ok(Box::new(GzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+fn check_magic_number<R: Read>(reader: &mut R, expected: u32) -> Result<(), IdxError> {
+    let found = read_u32(reader)?;
+    if found != expected {
+        return Err(IdxError::WrongMagicNumber { expected, found });
+    }
+    Ok(())
+}
+
+/// IDX stores all numbers in big endian.
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, IdxError> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_be_bytes(bytes))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use util::TempFile;
 
     #[test]
     fn test_convert_mnist_line() {
         let test_line = "1,255,16,100";
-        let (number, values) = convert_mnist_line(test_line);
+        let (number, values) = convert_mnist_line(test_line, 1).unwrap();
 
         assert_eq!(number, 1);
         assert_eq!(values[0], 1.0);
         assert_eq!(values[1], 0.07211764705882352);
         assert_eq!(values[2], 0.3982352941176471);
+
+        assert_eq!(
+            convert_mnist_line("1,255,x", 4).unwrap_err().to_string(),
+            "line 4, column 3: 'x' is not a number"
+        );
+    }
+
+    /// Builds IDX image and label files from CSV lines.
+    fn idx_fixture(csv: &str) -> (Vec<u8>, Vec<u8>) {
+        let lines: Vec<&str> = csv.lines().collect();
+        let mut images = Vec::new();
+        let mut labels = Vec::new();
+        images.extend_from_slice(&IMAGES_MAGIC_NUMBER.to_be_bytes());
+        images.extend_from_slice(&(lines.len() as u32).to_be_bytes());
+        images.extend_from_slice(&28u32.to_be_bytes());
+        images.extend_from_slice(&28u32.to_be_bytes());
+        labels.extend_from_slice(&LABELS_MAGIC_NUMBER.to_be_bytes());
+        labels.extend_from_slice(&(lines.len() as u32).to_be_bytes());
+
+        for line in lines {
+            let mut values = line.split(',').map(|x| x.trim().parse::<u8>().unwrap());
+            labels.push(values.next().unwrap());
+            images.extend(values);
+        }
+        (images, labels)
+    }
+
+    #[test]
+    fn test_convert_idx() {
+        let csv = include_str!("../mnist/mnist_test_10.csv");
+        let (images, labels) = idx_fixture(csv);
+
+        let samples = convert_idx(&images[..], &labels[..]).unwrap();
+        let expected: Vec<Sample> = csv
+            .lines()
+            .enumerate()
+            .map(|(index, line)| convert_mnist_line(line, index + 1).unwrap())
+            .collect();
+
+        assert_eq!(samples.len(), 10);
+        assert_eq!(samples, expected);
+    }
+
+    #[test]
+    fn test_load_idx_gzip() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        let csv = include_str!("../mnist/mnist_train_100.csv");
+        let (images, labels) = idx_fixture(csv);
+
+        let images_path = TempFile::new("train-images-idx3-ubyte.gz");
+        let labels_path = TempFile::new("train-labels-idx1-ubyte");
+        let images_file = File::create(&images_path).unwrap();
+        let mut encoder = GzEncoder::new(images_file, Compression::default());
+        encoder.write_all(&images).unwrap();
+        encoder.finish().unwrap();
+        File::create(&labels_path).unwrap().write_all(&labels).unwrap();
+
+        let samples = load_idx(&images_path, &labels_path).unwrap();
+        assert_eq!(samples.len(), 100);
+        assert_eq!(samples[7], convert_mnist_line(csv.lines().nth(7).unwrap(), 8).unwrap());
+
+        let dataset = MnistDataset::from_idx(&images_path, &labels_path).unwrap();
+        assert_eq!(dataset.samples(), &samples[..]);
+    }
+
+    #[test]
+    fn test_dataset() {
+        let loader = CsvLoader::new(&include_bytes!("../mnist/mnist_test_10.csv")[..]);
+        let dataset = MnistDataset::from_csv(loader).unwrap();
+        assert_eq!(Dataset::len(&dataset), 10);
+        assert_eq!(dataset.get(0).unwrap().0, 7);
+        assert_eq!(dataset.get(0).unwrap().1.len(), 784);
+
+        let split = dataset.split(0.2, 0.2, 1).unwrap();
+        assert_eq!(split.train.len(), 6);
+        let batches = split.train.batches(4).unwrap();
+        assert_eq!(batches.map(|batch| batch.unwrap().inputs.rows()).sum::<usize>(), 6);
+
+        let broken = CsvLoader::new(&b"1,2,3\n1,x\n"[..]);
+        assert!(MnistDataset::from_csv(broken).is_err());
     }
 
+    #[test]
+    fn test_wrong_magic_number() {
+        let (images, labels) = idx_fixture(include_str!("../mnist/mnist_test_10.csv"));
+
+        match convert_idx(&labels[..], &images[..]) {
+            Err(IdxError::WrongMagicNumber { expected, found }) => {
+                assert_eq!(expected, IMAGES_MAGIC_NUMBER);
+                assert_eq!(found, LABELS_MAGIC_NUMBER);
+            }
+            _ => panic!("magic number has to be checked"),
+        }
+    }
+
+    #[test]
+    fn test_broken_idx() {
+        let (images, labels) = idx_fixture(include_str!("../mnist/mnist_test_10.csv"));
+
+        match read_idx_images(&images[..images.len() - 1]) {
+            Err(IdxError::UnexpectedEnd) => {}
+            _ => panic!("truncated images have to be detected"),
+        }
+
+        let (fewer_images, _) = idx_fixture("7,0,0\n");
+        match convert_idx(&fewer_images[..], &labels[..]) {
+            Err(IdxError::UnexpectedEnd) => {}
+            _ => panic!("image size has to be checked"),
+        }
+
+        // last byte of the header is the low byte of the label count
+        let mut fewer_labels = labels.clone();
+        fewer_labels[7] = 9;
+        fewer_labels.pop();
+        match convert_idx(&images[..], &fewer_labels[..]) {
+            Err(IdxError::CountMismatch { images, labels }) => {
+                assert_eq!(images, 10);
+                assert_eq!(labels, 9);
+            }
+            _ => panic!("counts have to be checked"),
+        }
+
+        let mut missing_labels = labels.clone();
+        missing_labels.truncate(12);
+        match read_idx_labels(&missing_labels[..]) {
+            Err(IdxError::UnexpectedEnd) => {}
+            _ => panic!("truncated labels have to be detected"),
+        }
+    }
+
+    #[test]
+    fn test_untrusted_header() {
+        let (mut images, mut labels) = idx_fixture("7,0,0\n");
+        // billions of items announced but only a few bytes there
+        images[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+        labels[4..8].copy_from_slice(&u32::MAX.to_be_bytes());
+        match read_idx_images(&images[..]) {
+            Err(IdxError::UnexpectedEnd) => {}
+            _ => panic!("missing images have to be detected"),
+        }
+        match read_idx_labels(&labels[..]) {
+            Err(IdxError::UnexpectedEnd) => {}
+            _ => panic!("missing labels have to be detected"),
+        }
+
+        for &(rows, columns) in &[(0u32, 28u32), (28, 0), (u32::MAX, u32::MAX), (20, 20)] {
+            let (mut images, _) = idx_fixture("7,0,0\n");
+            images[8..12].copy_from_slice(&rows.to_be_bytes());
+            images[12..16].copy_from_slice(&columns.to_be_bytes());
+            match read_idx_images(&images[..]) {
+                Err(IdxError::WrongImageSize { .. }) => {}
+                _ => panic!("image size {} x {} has to be rejected", rows, columns),
+            }
+        }
+        let (mut images, _) = idx_fixture("7,0,0\n");
+        images[8..12].copy_from_slice(&20u32.to_be_bytes());
+        assert_eq!(
+            read_idx_images(&images[..]).unwrap_err().to_string(),
+            "invalid idx file: images of 20 x 28 pixels but MNIST has 28 x 28"
+        );
+    }
 }
@@ -0,0 +1,701 @@
+//! Saving and loading of trained networks.
+//!
+//! Models are stored as plain text. Every line starts with a keyword, numbers
+//! are written with `{}` which gives back exactly the same `f64` when parsed.
+//! Lines starting with `#` are comments.
+
+use layers::{self, Dense, Function, Layer, Sequential};
+use matrix::Matrix;
+use matrix::error::MathError;
+use preprocessing::*;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::rc::Rc;
+use std::str::FromStr;
+use util;
+use NeuralNetwork;
+
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum ModelError {
+    Io(io::Error),
+    /// The content does not follow the format.
+    Format { line: usize, message: String },
+    /// The stored weightings do not fit together.
+    Math(MathError),
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ModelError::Io(ref e) => write!(f, "could not access model: {}", e),
+            ModelError::Format { line, ref message } => {
+                write!(f, "invalid model in line {}: {}", line, message)
+            }
+            ModelError::Math(ref e) => write!(f, "invalid model: {}", e),
+        }
+    }
+}
+
+impl error::Error for ModelError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            ModelError::Io(ref e) => Some(e),
+            ModelError::Math(ref e) => Some(e),
+            ModelError::Format { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for ModelError {
+    fn from(e: io::Error) -> ModelError {
+        ModelError::Io(e)
+    }
+}
+
+impl From<MathError> for ModelError {
+    fn from(e: MathError) -> ModelError {
+        ModelError::Math(e)
+    }
+}
+
+/// A trained network together with the preprocessing its inputs and
+/// targets went through - inference has to use exactly the same.
+pub struct Model<T>
+where
+    T: Fn(f64) -> f64,
+{
+    pub network: NeuralNetwork<T>,
+    /// Name of the activation function. The function itself can not be
+    /// stored - whoever loads the model has to map the name back.
+    pub activation: Option<String>,
+    pub input_scaler: Option<InputScaler>,
+    pub target_encoder: Option<TargetEncoder>,
+}
+
+impl<T> Model<T>
+where
+    T: Fn(f64) -> f64,
+{
+    pub fn new(network: NeuralNetwork<T>) -> Model<T> {
+        Model {
+            network,
+            activation: None,
+            input_scaler: None,
+            target_encoder: None,
+        }
+    }
+
+    /// Raw inputs as the network needs them.
+    pub fn prepare_inputs(&self, raw_inputs: &[f64]) -> Vec<f64> {
+        match self.input_scaler {
+            Some(ref scaler) => scaler.transform(raw_inputs),
+            None => raw_inputs.to_vec(),
+        }
+    }
+
+    /// Scales the raw inputs and queries the network.
+    pub fn query(&self, raw_inputs: &[f64]) -> Vec<f64> {
+        self.network.query(&self.prepare_inputs(raw_inputs))
+    }
+
+    /// The most likely class for the raw inputs.
+    pub fn predict_class(&self, raw_inputs: &[f64]) -> Option<usize> {
+        let outputs = self.query(raw_inputs);
+        match self.target_encoder {
+            Some(ref encoder) => encoder.decode(&outputs),
+            None => ::matrix::math::argmax(&outputs),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ModelError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), ModelError> {
+        write_model(
+            writer,
+            &self.network,
+            self.activation.as_deref(),
+            self.input_scaler.as_ref(),
+            self.target_encoder.as_ref(),
+        )
+    }
+
+    /// Loads a saved model. The activation function is not part of the
+    /// file and has to be given again.
+    pub fn load<P: AsRef<Path>>(path: P, activation_function: T) -> Result<Model<T>, ModelError>
+    where
+        T: 'static,
+    {
+        Model::load_with(path, |_| Some(activation_function))
+    }
+
+    /// Loads a saved model and picks the activation function by the stored
+    /// name. None means the name is unknown.
+    pub fn load_with<P, F>(path: P, activation_function: F) -> Result<Model<T>, ModelError>
+    where
+        P: AsRef<Path>,
+        F: FnOnce(Option<&str>) -> Option<T>,
+        T: 'static,
+    {
+        let mut reader = TextReader::new(BufReader::new(File::open(path)?));
+        Model::read_with(&mut reader, activation_function)
+    }
+
+    pub fn read_from<R: BufRead>(
+        reader: &mut TextReader<R>,
+        activation_function: T,
+    ) -> Result<Model<T>, ModelError>
+    where
+        T: 'static,
+    {
+        Model::read_with(reader, |_| Some(activation_function))
+    }
+
+    pub fn read_with<R, F>(
+        reader: &mut TextReader<R>,
+        activation_function: F,
+    ) -> Result<Model<T>, ModelError>
+    where
+        R: BufRead,
+        F: FnOnce(Option<&str>) -> Option<T>,
+        T: 'static,
+    {
+        let version: u32 = reader.expect("version", 1)?.parse(0)?;
+        if version != FORMAT_VERSION {
+            return Err(reader.error(format!("unsupported version {}", version)));
+        }
+        let activation = if reader.peek_keyword()? == "activation" {
+            Some(reader.expect("activation", 1)?.get(0).to_owned())
+        } else {
+            None
+        };
+        let activation_function = match activation_function(activation.as_deref()) {
+            Some(function) => function,
+            None => {
+                return Err(reader.error(format!(
+                    "unknown activation function '{}'",
+                    activation.unwrap_or_default()
+                )))
+            }
+        };
+        let network = read_network(reader, activation_function)?;
+
+        let mut model = Model::new(network);
+        model.activation = activation;
+        loop {
+            let keyword = reader.peek_keyword()?;
+            match keyword.as_str() {
+                "input_scaler" => {
+                    let line = reader.expect("input_scaler", 1)?;
+                    model.input_scaler = Some(match line.get(0) {
+                        "min_max" => {
+                            let (output_min, output_max) = (line.parse(1)?, line.parse(2)?);
+                            let input_min = reader.values("input_min")?;
+                            let input_max = reader.values("input_max")?;
+                            check_scaler_features(reader, &model.network, input_min.len())?;
+                            check_scaler_features(reader, &model.network, input_max.len())?;
+                            InputScaler::MinMax(MinMaxScaler::from_parts(
+                                input_min,
+                                input_max,
+                                output_min,
+                                output_max,
+                            ))
+                        }
+                        "standard" => {
+                            let mean = reader.values("mean")?;
+                            let std = reader.values("std")?;
+                            check_scaler_features(reader, &model.network, mean.len())?;
+                            check_scaler_features(reader, &model.network, std.len())?;
+                            InputScaler::Standard(StandardScaler::from_parts(mean, std))
+                        }
+                        other => return Err(reader.error(format!("unknown scaler '{}'", other))),
+                    });
+                }
+                "target_encoder" => {
+                    let line = reader.expect("target_encoder", 3)?;
+                    model.target_encoder =
+                        Some(TargetEncoder::new(line.parse(0)?, line.parse(1)?, line.parse(2)?));
+                }
+                "end" => {
+                    reader.expect("end", 0)?;
+                    return Ok(model);
+                }
+                other => return Err(reader.error(format!("unknown section '{}'", other))),
+            }
+        }
+    }
+}
+
+/// The scaler prepares the inputs of the network, so it needs one value per input.
+fn check_scaler_features<R: BufRead, T>(
+    reader: &TextReader<R>,
+    network: &NeuralNetwork<T>,
+    features: usize,
+) -> Result<(), ModelError>
+where
+    T: Fn(f64) -> f64,
+{
+    let inputs = network.input_nodes();
+    if features != inputs {
+        return Err(reader.error(format!(
+            "the scaler has {} features but the network {} inputs",
+            features, inputs
+        )));
+    }
+    Ok(())
+}
+
+/// Writes a model without owning the network - e.g. while it is trained.
+pub fn write_model<W: Write, T>(
+    writer: &mut W,
+    network: &NeuralNetwork<T>,
+    activation: Option<&str>,
+    input_scaler: Option<&InputScaler>,
+    target_encoder: Option<&TargetEncoder>,
+) -> Result<(), ModelError>
+where
+    T: Fn(f64) -> f64,
+{
+    writeln!(writer, "# neural_network model")?;
+    writeln!(writer, "version {}", FORMAT_VERSION)?;
+    if let Some(name) = activation {
+        writeln!(writer, "activation {}", name)?;
+    }
+    write_network(writer, network)?;
+
+    match input_scaler {
+        Some(InputScaler::MinMax(scaler)) => {
+            let (output_min, output_max) = scaler.output_range();
+            writeln!(writer, "input_scaler min_max {} {}", output_min, output_max)?;
+            write_values(writer, "input_min", scaler.input_min())?;
+            write_values(writer, "input_max", scaler.input_max())?;
+        }
+        Some(InputScaler::Standard(scaler)) => {
+            writeln!(writer, "input_scaler standard")?;
+            write_values(writer, "mean", scaler.mean())?;
+            write_values(writer, "std", scaler.std())?;
+        }
+        None => {}
+    }
+    if let Some(encoder) = target_encoder {
+        writeln!(
+            writer,
+            "target_encoder {} {} {}",
+            encoder.classes(),
+            encoder.on(),
+            encoder.off()
+        )?;
+    }
+    writeln!(writer, "end")?;
+    Ok(())
+}
+
+/// Writes the learning rate and the layers of a network.
+pub fn write_network<W: Write, T>(
+    writer: &mut W,
+    network: &NeuralNetwork<T>,
+) -> Result<(), ModelError>
+where
+    T: Fn(f64) -> f64,
+{
+    writeln!(writer, "learning_rate {}", network.learning_rate())?;
+    network.layers().write_to(writer)?;
+    Ok(())
+}
+
+/// Reads a network written by `write_network`. Models that still store the
+/// two weightings `wih` and `who` instead of layers are read as well.
+pub fn read_network<R: BufRead, T>(
+    reader: &mut TextReader<R>,
+    activation_function: T,
+) -> Result<NeuralNetwork<T>, ModelError>
+where
+    T: Fn(f64) -> f64 + 'static,
+{
+    let learning_rate = reader.expect("learning_rate", 1)?.parse(0)?;
+    if reader.peek_keyword()? == "matrix" {
+        let wih = reader.matrix("wih")?;
+        let who = reader.matrix("who")?;
+        return Ok(NeuralNetwork::from_weightings(wih, who, learning_rate, activation_function)?);
+    }
+    let activation_function = Rc::new(activation_function);
+    let layers = read_layers(reader, &activation_function, util::sigmoid_derivative)?;
+    Ok(NeuralNetwork::from_parts(layers, learning_rate, activation_function))
+}
+
+/// Reads the layers of a network. The function of a `function` layer is not
+/// stored, every one of them gets `activation_function` and `derivative`.
+pub(crate) fn read_layers<R: BufRead, T>(
+    reader: &mut TextReader<R>,
+    activation_function: &Rc<T>,
+    derivative: fn(f64) -> f64,
+) -> Result<Sequential, ModelError>
+where
+    T: Fn(f64) -> f64 + 'static,
+{
+    let layers = Sequential::read_with(reader, |reader| {
+        if reader.peek_keyword()? == "function" {
+            let function = Function::read_from(reader, Rc::clone(activation_function), derivative)?;
+            Ok(Box::new(function) as Box<dyn Layer>)
+        } else {
+            layers::read_layer(reader)
+        }
+    })?;
+    if !layers.layers().iter().any(|layer| layer.as_any().is::<Dense>()) {
+        return Err(reader.error("a network needs a dense layer".to_owned()));
+    }
+    Ok(layers)
+}
+
+/// `<name> <rows> <columns>` followed by one line per row.
+pub fn write_matrix<W: Write>(writer: &mut W, name: &str, matrix: &Matrix) -> io::Result<()> {
+    writeln!(writer, "matrix {} {} {}", name, matrix.rows(), matrix.columns())?;
+    for row in matrix.data_container() {
+        write_values(writer, "row", row)?;
+    }
+    Ok(())
+}
+
+/// `<keyword> <count> <value> <value> ...`
+pub fn write_values<W: Write>(writer: &mut W, keyword: &str, values: &[f64]) -> io::Result<()> {
+    write!(writer, "{} {}", keyword, values.len())?;
+    for value in values {
+        write!(writer, " {}", value)?;
+    }
+    writeln!(writer)
+}
+
+/// The tokens of one line, without the keyword.
+pub struct TextLine {
+    number: usize,
+    tokens: Vec<String>,
+}
+
+impl TextLine {
+    pub fn get(&self, index: usize) -> &str {
+        &self.tokens[index]
+    }
+
+    pub fn len(&self) -> usize {
+        self.tokens.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    pub fn parse<V: FromStr>(&self, index: usize) -> Result<V, ModelError> {
+        self.tokens[index].parse().map_err(|_| ModelError::Format {
+            line: self.number,
+            message: format!("'{}' is not a valid value", self.tokens[index]),
+        })
+    }
+}
+
+/// Reads the keyword based text format line by line.
+pub struct TextReader<R: BufRead> {
+    lines: io::Lines<R>,
+    line_number: usize,
+    peeked: Option<(String, TextLine)>,
+}
+
+impl<R: BufRead> TextReader<R> {
+    pub fn new(reader: R) -> TextReader<R> {
+        TextReader {
+            lines: reader.lines(),
+            line_number: 0,
+            peeked: None,
+        }
+    }
+
+    /// The keyword of the next line without consuming it.
+    pub fn peek_keyword(&mut self) -> Result<String, ModelError> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.read_line()?);
+        }
+        match self.peeked {
+            Some((ref keyword, _)) => Ok(keyword.clone()),
+            None => Err(self.error("unexpected end".to_owned())),
+        }
+    }
+
+    /// Reads the next line which has to start with `keyword` and have at least
+    /// `min_tokens` further tokens.
+    pub fn expect(&mut self, keyword: &str, min_tokens: usize) -> Result<TextLine, ModelError> {
+        let (found, line) = match self.peeked.take() {
+            Some(peeked) => peeked,
+            None => self.read_line()?,
+        };
+        if found != keyword {
+            return Err(ModelError::Format {
+                line: line.number,
+                message: format!("'{}' expected but found '{}'", keyword, found),
+            });
+        }
+        if line.len() < min_tokens {
+            return Err(ModelError::Format {
+                line: line.number,
+                message: format!("'{}' needs {} values", keyword, min_tokens),
+            });
+        }
+        Ok(line)
+    }
+
+    /// Reads a line written by `write_values`.
+    pub fn values(&mut self, keyword: &str) -> Result<Vec<f64>, ModelError> {
+        let line = self.expect(keyword, 1)?;
+        let count: usize = line.parse(0)?;
+        if line.len() != count + 1 {
+            return Err(ModelError::Format {
+                line: line.number,
+                message: format!("{} values expected but found {}", count, line.len() - 1),
+            });
+        }
+        (1..line.len()).map(|index| line.parse(index)).collect()
+    }
+
+    /// Reads a matrix written by `write_matrix`.
+    pub fn matrix(&mut self, name: &str) -> Result<Matrix, ModelError> {
+        let line = self.expect("matrix", 3)?;
+        if line.get(0) != name {
+            return Err(ModelError::Format {
+                line: line.number,
+                message: format!("matrix '{}' expected but found '{}'", name, line.get(0)),
+            });
+        }
+        let (rows, columns): (usize, usize) = (line.parse(1)?, line.parse(2)?);
+
+        // the row count is not trusted - memory only grows with actual rows
+        let mut data_container = Vec::new();
+        for _ in 0..rows {
+            let row = self.values("row")?;
+            if row.len() != columns {
+                return Err(self.error(format!("row needs {} values", columns)));
+            }
+            data_container.push(row);
+        }
+        if rows == 0 {
+            return Ok(Matrix::zeros(0, columns));
+        }
+        Ok(Matrix::from_2d_vec(&data_container))
+    }
+
+    /// A format error for the line read last.
+    pub fn error(&self, message: String) -> ModelError {
+        ModelError::Format {
+            line: self.line_number,
+            message,
+        }
+    }
+
+    fn read_line(&mut self) -> Result<(String, TextLine), ModelError> {
+        loop {
+            let line = match self.lines.next() {
+                Some(line) => line?,
+                None => return Err(self.error("unexpected end".to_owned())),
+            };
+            self.line_number += 1;
+
+            let mut tokens = line.split_whitespace().map(str::to_owned);
+            let keyword = match tokens.next() {
+                Some(ref k) if k.starts_with('#') => continue,
+                Some(k) => k,
+                None => continue,
+            };
+            return Ok((
+                keyword,
+                TextLine {
+                    number: self.line_number,
+                    tokens: tokens.collect(),
+                },
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod model_tests {
+    use super::*;
+    use layers::{BatchNorm, Normalization};
+    use util;
+    use util::TempFile;
+
+    fn round_trip(model: &Model<fn(f64) -> f64>) -> Model<fn(f64) -> f64> {
+        let mut content = Vec::new();
+        model.write_to(&mut content).unwrap();
+        let mut reader = TextReader::new(&content[..]);
+        Model::read_from(&mut reader, util::sigmoid as fn(f64) -> f64).unwrap()
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let network = NeuralNetwork::new(3, 4, 2, 0.1, util::sigmoid as fn(f64) -> f64);
+        let mut model = Model::new(network);
+        model.input_scaler = Some(InputScaler::MinMax(MinMaxScaler::from_range(
+            3,
+            0.0,
+            255.0,
+            0.01,
+            1.0,
+        )));
+        model.target_encoder = Some(TargetEncoder::label_smoothing(2, 0.1));
+
+        let loaded = round_trip(&model);
+        assert_eq!(loaded.network.wih(), model.network.wih());
+        assert_eq!(loaded.network.who(), model.network.who());
+        assert_eq!(loaded.network.learning_rate(), 0.1);
+        assert_eq!(loaded.input_scaler, model.input_scaler);
+        assert_eq!(loaded.target_encoder, model.target_encoder);
+        assert_eq!(loaded.query(&[1.0, 100.0, 200.0]), model.query(&[1.0, 100.0, 200.0]));
+
+        let path = TempFile::new("model.txt");
+        model.save(&path).unwrap();
+        let loaded = Model::load(&path, util::sigmoid).unwrap();
+        assert_eq!(loaded.network.wih(), model.network.wih());
+    }
+
+    #[test]
+    fn test_layers_round_trip() {
+        let sigmoid = util::sigmoid as fn(f64) -> f64;
+        let mut network = NeuralNetwork::with_seed(3, 4, 2, 0.1, sigmoid, 3);
+        network.set_dropout(0.1, 0.3, 7);
+        network.train(&[0.2, 0.4, 0.6], &[1.0, 0.0]).unwrap();
+        let model = Model::new(network);
+
+        let mut loaded = round_trip(&model);
+        assert_eq!(loaded.network.dropout(), (0.1, 0.3));
+        assert_eq!(loaded.query(&[0.5, 0.1, 0.9]), model.query(&[0.5, 0.1, 0.9]));
+        // the next dropout masks are the same as well
+        let mut network = model.network;
+        network.train(&[0.2, 0.4, 0.6], &[1.0, 0.0]).unwrap();
+        loaded.network.train(&[0.2, 0.4, 0.6], &[1.0, 0.0]).unwrap();
+        assert_eq!(loaded.network.layers().len(), network.layers().len());
+        assert_eq!(loaded.network.last_outputs(), network.last_outputs());
+    }
+
+    #[test]
+    fn test_running_statistics() {
+        let sigmoid = util::sigmoid as fn(f64) -> f64;
+        let mut network = NeuralNetwork::with_seed(2, 3, 2, 0.1, sigmoid, 4);
+        network.set_normalization(Normalization::Batch);
+        let batch = Matrix::from_2d_vec(&[vec![0.1, 0.9], vec![0.7, 0.2], vec![0.4, 0.4]]);
+        let targets = Matrix::from_2d_vec(&[vec![1.0, 0.0], vec![0.0, 1.0], vec![0.0, 1.0]]);
+        let gradients = network.backward_batch(&batch, &targets).unwrap();
+        network.apply_gradients(&gradients).unwrap();
+        let model = Model::new(network);
+
+        let loaded = round_trip(&model);
+        let batch_norm = |model: &Model<fn(f64) -> f64>| {
+            let layers = model.network.layers().layers();
+            let layer = layers[1].as_any().downcast_ref::<BatchNorm>().unwrap();
+            (layer.running_mean().to_vec(), layer.running_var().to_vec())
+        };
+        assert_ne!(batch_norm(&model).0, vec![0.0; 3]);
+        assert_eq!(batch_norm(&loaded), batch_norm(&model));
+        assert_eq!(loaded.query(&[0.3, 0.6]), model.query(&[0.3, 0.6]));
+    }
+
+    #[test]
+    fn test_weightings_format() {
+        // models written before the layers were stored
+        let content = "version 1\nlearning_rate 0.2\nmatrix wih 1 2\nrow 2 0.5 -0.5\n\
+                       matrix who 1 1\nrow 1 2\nend\n";
+        let loaded = Model::read_from(&mut TextReader::new(content.as_bytes()), util::sigmoid);
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.network.learning_rate(), 0.2);
+        assert_eq!(loaded.network.wih(), Matrix::from_2d_vec(&[vec![0.5, -0.5]]));
+        assert_eq!(loaded.network.who(), Matrix::from_2d_vec(&[vec![2.0]]));
+
+        let content = "version 1\nlearning_rate 0.2\nsequential 2 1\nfunction\nend\n";
+        match Model::read_from(&mut TextReader::new(content.as_bytes()), util::sigmoid) {
+            Err(ModelError::Format { message, .. }) => {
+                assert_eq!(message, "a network needs a dense layer");
+            }
+            _ => panic!("a network without dense layers has to be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_activation_name() {
+        let network = NeuralNetwork::new(2, 2, 2, 0.3, util::relu as fn(f64) -> f64);
+        let mut model = Model::new(network);
+        model.activation = Some("relu".to_owned());
+        let mut content = Vec::new();
+        model.write_to(&mut content).unwrap();
+
+        let by_name = |name: Option<&str>| match name {
+            Some("relu") => Some(util::relu as fn(f64) -> f64),
+            _ => None,
+        };
+        let loaded = Model::read_with(&mut TextReader::new(&content[..]), by_name).unwrap();
+        assert_eq!(loaded.activation, Some("relu".to_owned()));
+        assert_eq!(loaded.query(&[0.5, 0.5]), model.query(&[0.5, 0.5]));
+
+        let unknown = |_: Option<&str>| None::<fn(f64) -> f64>;
+        match Model::read_with(&mut TextReader::new(&content[..]), unknown) {
+            Err(ModelError::Format { line, message }) => {
+                assert_eq!(line, 3);
+                assert_eq!(message, "unknown activation function 'relu'");
+            }
+            _ => panic!("unknown activation functions have to be detected"),
+        }
+    }
+
+    #[test]
+    fn test_standard_scaler_round_trip() {
+        let network = NeuralNetwork::new(2, 2, 2, 0.3, util::sigmoid as fn(f64) -> f64);
+        let mut model = Model::new(network);
+        let scaler = StandardScaler::from_parts(vec![1.0, 0.1], vec![0.5, 1e-7]);
+        model.input_scaler = Some(InputScaler::Standard(scaler));
+
+        let loaded = round_trip(&model);
+        assert_eq!(loaded.input_scaler, model.input_scaler);
+        assert_eq!(loaded.target_encoder, None);
+    }
+
+    #[test]
+    fn test_invalid_model() {
+        let content = "version 1\nlearning_rate 0.1\nmatrix wih 1 2\nrow 2 1 x\n";
+        let mut reader = TextReader::new(content.as_bytes());
+
+        match Model::read_from(&mut reader, util::sigmoid) {
+            Err(ModelError::Format { line, message }) => {
+                assert_eq!(line, 4);
+                assert_eq!(message, "'x' is not a valid value");
+            }
+            _ => panic!("invalid values have to be detected"),
+        }
+
+        let content = "version 1\nlearning_rate 0.1\nmatrix wih 1 2\nrow 2 1 1\nmatrix who 2 2\n";
+        let mut reader = TextReader::new(content.as_bytes());
+        assert!(Model::read_from(&mut reader, util::sigmoid).is_err());
+        // a huge row count must not be allocated up front
+        let content = "version 1\nlearning_rate 0.1\nmatrix wih 18446744073709551615 2\n";
+        let mut reader = TextReader::new(content.as_bytes());
+        assert!(Model::read_from(&mut reader, util::sigmoid).is_err());
+
+        let network = NeuralNetwork::new(3, 4, 2, 0.1, util::sigmoid as fn(f64) -> f64);
+        let mut model = Model::new(network);
+        let scaler = StandardScaler::from_parts(vec![0.0; 2], vec![1.0; 2]);
+        model.input_scaler = Some(InputScaler::Standard(scaler));
+        let mut content = Vec::new();
+        model.write_to(&mut content).unwrap();
+        match Model::read_from(&mut TextReader::new(&content[..]), util::sigmoid) {
+            Err(ModelError::Format { message, .. }) => {
+                assert_eq!(message, "the scaler has 2 features but the network 3 inputs");
+            }
+            _ => panic!("a scaler that does not fit the network has to be detected"),
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use dataset::Dataset;
+use matrix::math;
+
+/// Maps every input value linearly from the range seen in the data (or a
+/// known range) into `output_min..output_max`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinMaxScaler {
+    input_min: Vec<f64>,
+    input_max: Vec<f64>,
+    output_min: f64,
+    output_max: f64,
+}
+
+impl MinMaxScaler {
+    /// Learns the range of every feature from the training data. Panics if
+    /// the samples have different numbers of values.
+    pub fn fit<D: Dataset>(dataset: &D, output_min: f64, output_max: f64) -> MinMaxScaler {
+        let mut input_min: Vec<f64> = Vec::new();
+        let mut input_max: Vec<f64> = Vec::new();
+
+        for (index, (_, values)) in dataset.iter().enumerate() {
+            if index == 0 {
+                input_min = values.to_vec();
+                input_max = values.to_vec();
+            }
+            check_features(input_min.len(), values.len());
+            for (index, value) in values.iter().enumerate() {
+                input_min[index] = input_min[index].min(*value);
+                input_max[index] = input_max[index].max(*value);
+            }
+        }
+
+        MinMaxScaler {
+            input_min,
+            input_max,
+            output_min,
+            output_max,
+        }
+    }
+
+    /// Uses the same known input range for all features.
+    pub fn from_range(
+        features: usize,
+        input_min: f64,
+        input_max: f64,
+        output_min: f64,
+        output_max: f64,
+    ) -> MinMaxScaler {
+        MinMaxScaler {
+            input_min: vec![input_min; features],
+            input_max: vec![input_max; features],
+            output_min,
+            output_max,
+        }
+    }
+
+    /// Panics if the ranges have different lengths.
+    pub fn from_parts(
+        input_min: Vec<f64>,
+        input_max: Vec<f64>,
+        output_min: f64,
+        output_max: f64,
+    ) -> MinMaxScaler {
+        check_features(input_min.len(), input_max.len());
+        MinMaxScaler {
+            input_min,
+            input_max,
+            output_min,
+            output_max,
+        }
+    }
+
+    /// The classic MNIST scaling: grey values 0..255 to 0.01..1.0.
+    pub fn mnist() -> MinMaxScaler {
+        MinMaxScaler::from_range(784, 0.0, 255.0, 0.01, 1.0)
+    }
+
+    /// Features without any range are mapped to `output_min`. Panics if the
+    /// number of values does not fit the scaler.
+    pub fn transform(&self, values: &[f64]) -> Vec<f64> {
+        check_features(self.features(), values.len());
+        values
+            .iter()
+            .zip(self.input_min.iter().zip(&self.input_max))
+            .map(|(x, (min, max))| {
+                if max > min {
+                    (x - min) / (max - min) * (self.output_max - self.output_min) + self.output_min
+                } else {
+                    self.output_min
+                }
+            })
+            .collect()
+    }
+
+    pub fn features(&self) -> usize {
+        self.input_min.len()
+    }
+
+    pub fn input_min(&self) -> &[f64] {
+        &self.input_min
+    }
+
+    pub fn input_max(&self) -> &[f64] {
+        &self.input_max
+    }
+
+    pub fn output_range(&self) -> (f64, f64) {
+        (self.output_min, self.output_max)
+    }
+}
+
+/// Z-score standardisation: every feature gets the mean 0 and the standard
+/// deviation 1 on the training data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StandardScaler {
+    mean: Vec<f64>,
+    std: Vec<f64>,
+}
+
+impl StandardScaler {
+    /// Panics if the samples have different numbers of values.
+    pub fn fit<D: Dataset>(dataset: &D) -> StandardScaler {
+        let rows: Vec<Vec<f64>> = dataset.iter().map(|(_, values)| values.to_vec()).collect();
+        if let Some(first) = rows.first() {
+            for row in &rows {
+                check_features(first.len(), row.len());
+            }
+        }
+        let columns: Vec<Vec<f64>> = math::transpose_2d_vector(&rows);
+
+        StandardScaler {
+            mean: columns
+                .iter()
+                .map(|column| math::mean(column).unwrap_or(0.0))
+                .collect(),
+            std: columns
+                .iter()
+                .map(|column| math::std(column).unwrap_or(1.0))
+                .collect(),
+        }
+    }
+
+    /// Panics if `mean` and `std` have different lengths.
+    pub fn from_parts(mean: Vec<f64>, std: Vec<f64>) -> StandardScaler {
+        check_features(mean.len(), std.len());
+        StandardScaler { mean, std }
+    }
+
+    /// Features without any variance are only shifted. Panics if the number
+    /// of values does not fit the scaler.
+    pub fn transform(&self, values: &[f64]) -> Vec<f64> {
+        check_features(self.features(), values.len());
+        values
+            .iter()
+            .zip(self.mean.iter().zip(&self.std))
+            .map(|(x, (mean, std))| if *std > 0.0 { (x - mean) / std } else { x - mean })
+            .collect()
+    }
+
+    pub fn features(&self) -> usize {
+        self.mean.len()
+    }
+
+    pub fn mean(&self) -> &[f64] {
+        &self.mean
+    }
+
+    pub fn std(&self) -> &[f64] {
+        &self.std
+    }
+}
+
+/// One of the input preprocessors - so that a model can store whichever it uses.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputScaler {
+    MinMax(MinMaxScaler),
+    Standard(StandardScaler),
+}
+
+impl InputScaler {
+    pub fn transform(&self, values: &[f64]) -> Vec<f64> {
+        match *self {
+            InputScaler::MinMax(ref scaler) => scaler.transform(values),
+            InputScaler::Standard(ref scaler) => scaler.transform(values),
+        }
+    }
+
+    pub fn features(&self) -> usize {
+        match *self {
+            InputScaler::MinMax(ref scaler) => scaler.features(),
+            InputScaler::Standard(ref scaler) => scaler.features(),
+        }
+    }
+}
+
+fn check_features(expected: usize, found: usize) {
+    assert_eq!(found, expected, "the scaler takes {} features", expected);
+}
+
+/// Turns a class label into the awaited output of the network: the value
+/// `on` at the position of the label and `off` everywhere else.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetEncoder {
+    classes: usize,
+    on: f64,
+    off: f64,
+}
+
+impl TargetEncoder {
+    pub fn new(classes: usize, on: f64, off: f64) -> TargetEncoder {
+        TargetEncoder { classes, on, off }
+    }
+
+    /// Plain one-hot encoding with 1 and 0.
+    pub fn one_hot(classes: usize) -> TargetEncoder {
+        TargetEncoder::new(classes, 1.0, 0.0)
+    }
+
+    /// Label smoothing: `epsilon` of the certainty is spread over all classes.
+    pub fn label_smoothing(classes: usize, epsilon: f64) -> TargetEncoder {
+        let off = epsilon / classes as f64;
+        TargetEncoder::new(classes, 1.0 - epsilon + off, off)
+    }
+
+    /// None if the label is not one of the classes.
+    pub fn encode(&self, label: usize) -> Option<Vec<f64>> {
+        if label >= self.classes {
+            return None;
+        }
+        let mut target = vec![self.off; self.classes];
+        target[label] = self.on;
+        Some(target)
+    }
+
+    /// The class with the highest output.
+    pub fn decode(&self, outputs: &[f64]) -> Option<usize> {
+        math::argmax(outputs)
+    }
+
+    pub fn classes(&self) -> usize {
+        self.classes
+    }
+
+    pub fn on(&self) -> f64 {
+        self.on
+    }
+
+    pub fn off(&self) -> f64 {
+        self.off
+    }
+}
+
+#[cfg(test)]
+mod preprocessing_tests {
+    use super::*;
+    use dataset::Sample;
+    use mnist_data;
+
+    fn samples() -> Vec<Sample> {
+        vec![(0, vec![1.0, 10.0, 5.0]), (1, vec![3.0, 20.0, 5.0]), (0, vec![2.0, 30.0, 5.0])]
+    }
+
+    #[test]
+    fn test_min_max_fit() {
+        let scaler = MinMaxScaler::fit(&samples(), -1.0, 1.0);
+
+        assert_eq!(scaler.input_min(), &[1.0, 10.0, 5.0]);
+        assert_eq!(scaler.input_max(), &[3.0, 30.0, 5.0]);
+        assert_eq!(scaler.transform(&[2.0, 10.0, 5.0]), vec![0.0, -1.0, -1.0]);
+        assert_eq!(scaler.transform(&[3.0, 40.0, 7.0]), vec![1.0, 2.0, -1.0]);
+    }
+
+    #[test]
+    fn test_min_max_mnist() {
+        let scaler = MinMaxScaler::mnist();
+        let (_, expected) = mnist_data::convert_mnist_line("1,255,16,100", 1).unwrap();
+
+        let mut values = vec![0.0; 784];
+        values[..3].copy_from_slice(&[255.0, 16.0, 100.0]);
+        let result = scaler.transform(&values);
+        for (r, e) in result.iter().zip(&expected) {
+            assert!((r - e).abs() < 1e-15);
+        }
+    }
+
+    #[test]
+    fn test_standard_scaler() {
+        let scaler = StandardScaler::fit(&samples());
+
+        assert_eq!(scaler.mean(), &[2.0, 20.0, 5.0]);
+        assert_eq!(scaler.std()[2], 0.0);
+        let result = scaler.transform(&[3.0, 20.0, 6.0]);
+        assert!((result[0] - 1.224744871391589).abs() < 1e-12);
+        assert_eq!(result[1], 0.0);
+        assert_eq!(result[2], 1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "the scaler takes 3 features")]
+    fn test_transform_checks_size() {
+        MinMaxScaler::fit(&samples(), 0.0, 1.0).transform(&[1.0, 2.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "the scaler takes 3 features")]
+    fn test_fit_checks_size() {
+        let mut samples = samples();
+        samples[2].1.pop();
+        StandardScaler::fit(&samples);
+    }
+
+    #[test]
+    fn test_target_encoder() {
+        let encoder = TargetEncoder::new(3, 0.99, 0.01);
+        assert_eq!(encoder.encode(1), Some(vec![0.01, 0.99, 0.01]));
+        assert_eq!(encoder.encode(3), None);
+        assert_eq!(encoder.decode(&[0.1, 0.2, 0.7]), Some(2));
+
+        assert_eq!(TargetEncoder::one_hot(2).encode(0), Some(vec![1.0, 0.0]));
+
+        let smoothed = TargetEncoder::label_smoothing(4, 0.2).encode(3).unwrap();
+        assert_eq!(smoothed[0], 0.05);
+        assert!((smoothed[3] - 0.85).abs() < 1e-12);
+        assert!((smoothed.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+    }
+}
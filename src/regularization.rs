@@ -0,0 +1,128 @@
+//! Penalties on large weights against overfitting.
+
+/// Penalty `l1 * sum(|w|) + l2 / 2 * sum(w²)` of one weighting. It is added
+/// to the loss, so its gradient `l1 * sign(w) + l2 * w` becomes part of
+/// every training step.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Regularization {
+    pub l1: f64,
+    pub l2: f64,
+}
+
+impl Regularization {
+    pub fn new(l1: f64, l2: f64) -> Regularization {
+        if l1.is_nan() || l2.is_nan() || l1 < 0.0 || l2 < 0.0 {
+            panic!("regularisation strengths cannot be negative");
+        }
+        Regularization { l1, l2 }
+    }
+
+    /// No penalty at all.
+    pub fn none() -> Regularization {
+        Regularization::default()
+    }
+
+    pub fn l1(l1: f64) -> Regularization {
+        Regularization::new(l1, 0.0)
+    }
+
+    pub fn l2(l2: f64) -> Regularization {
+        Regularization::new(0.0, l2)
+    }
+
+    pub fn is_none(&self) -> bool {
+        self.l1 == 0.0 && self.l2 == 0.0
+    }
+
+    pub fn penalty(&self, weights: &[f64]) -> f64 {
+        if self.is_none() {
+            return 0.0;
+        }
+        weights
+            .iter()
+            .map(|weight| self.l1 * weight.abs() + self.l2 / 2.0 * weight * weight)
+            .sum()
+    }
+
+    /// Derivative of the penalty by one weight - 0 for the L1 part at 0.
+    pub fn gradient(&self, weight: f64) -> f64 {
+        let sign = if weight > 0.0 {
+            1.0
+        } else if weight < 0.0 {
+            -1.0
+        } else {
+            0.0
+        };
+        self.l1 * sign + self.l2 * weight
+    }
+
+    /// Adds the gradient of the penalty to the gradients of `weights`.
+    pub fn add_gradient(&self, weights: &[f64], gradients: &mut [f64]) {
+        if self.is_none() {
+            return;
+        }
+        for (gradient, &weight) in gradients.iter_mut().zip(weights) {
+            *gradient += self.gradient(weight);
+        }
+    }
+}
+
+/// One step against the gradient of the penalty plus the decoupled weight
+/// decay `weight -= learning_rate * weight_decay * weight`.
+pub(crate) fn shrink(
+    weights: &mut [f64],
+    learning_rate: f64,
+    regularization: Regularization,
+    weight_decay: f64,
+) {
+    if regularization.is_none() && weight_decay == 0.0 {
+        return;
+    }
+    for weight in weights {
+        *weight -= learning_rate * (regularization.gradient(*weight) + weight_decay * *weight);
+    }
+}
+
+#[cfg(test)]
+mod regularization_tests {
+    use super::*;
+    use matrix::Matrix;
+
+    #[test]
+    fn test_penalty() {
+        let weighting = Matrix::from_2d_vec(&[vec![1.0, -2.0], vec![0.0, 0.5]]).flatten();
+
+        assert_eq!(Regularization::none().penalty(&weighting), 0.0);
+        assert_eq!(Regularization::l1(0.5).penalty(&weighting), 1.75);
+        assert_eq!(Regularization::l2(1.0).penalty(&weighting), 2.625);
+        assert_eq!(Regularization::new(1.0, 2.0).penalty(&weighting), 3.5 + 5.25);
+    }
+
+    #[test]
+    fn test_gradient() {
+        let regularization = Regularization::new(0.5, 2.0);
+        assert_eq!(regularization.gradient(1.0), 2.5);
+        assert_eq!(regularization.gradient(-1.0), -2.5);
+        assert_eq!(regularization.gradient(0.0), 0.0);
+
+        let mut gradients = vec![0.1, 0.1];
+        regularization.add_gradient(&[1.0, -0.5], &mut gradients);
+        assert_eq!(gradients, vec![2.6, -1.4]);
+    }
+
+    #[test]
+    fn test_shrink() {
+        let mut weights = vec![1.0, -2.0];
+        shrink(&mut weights, 0.5, Regularization::none(), 0.5);
+        assert_eq!(weights, vec![0.75, -1.5]);
+
+        shrink(&mut weights, 0.5, Regularization::l1(0.5), 0.0);
+        assert_eq!(weights, vec![0.5, -1.25]);
+    }
+
+    #[test]
+    #[should_panic(expected = "regularisation strengths cannot be negative")]
+    fn test_negative() {
+        Regularization::l2(-0.1);
+    }
+}
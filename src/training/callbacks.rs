@@ -0,0 +1,507 @@
+//! Callbacks for the most common needs while training.
+
+use model;
+use preprocessing::{InputScaler, TargetEncoder};
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use super::*;
+
+/// Shows the progress of every epoch in one line, and the metrics and
+/// duration once the epoch is done.
+pub struct ProgressBar<W: Write> {
+    writer: W,
+    width: usize,
+    shown: Option<usize>,
+    started: Instant,
+}
+
+impl ProgressBar<io::Stdout> {
+    pub fn new(width: usize) -> ProgressBar<io::Stdout> {
+        ProgressBar::to_writer(io::stdout(), width)
+    }
+}
+
+impl<W: Write> ProgressBar<W> {
+    pub fn to_writer(writer: W, width: usize) -> ProgressBar<W> {
+        ProgressBar {
+            writer,
+            width,
+            shown: None,
+            started: Instant::now(),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn draw(&mut self, epoch: usize, epochs: usize, filled: usize) -> io::Result<()> {
+        write!(
+            self.writer,
+            "\repoch {}/{} [{}{}]",
+            epoch + 1,
+            epochs,
+            "=".repeat(filled),
+            " ".repeat(self.width - filled)
+        )
+    }
+}
+
+impl<T, W> Callback<T> for ProgressBar<W>
+where
+    T: Fn(f64) -> f64,
+    W: Write,
+{
+    fn on_epoch_start(&mut self, _state: &mut TrainingState<T>) -> Result<(), TrainingError> {
+        self.shown = None;
+        self.started = Instant::now();
+        Ok(())
+    }
+
+    fn on_batch_end(&mut self, state: &mut TrainingState<T>) -> Result<(), TrainingError> {
+        let filled = self.width * state.batch / state.batches.max(1);
+        // only redraw when the bar changes
+        if self.shown != Some(filled) {
+            self.shown = Some(filled);
+            self.draw(state.epoch, state.epochs, filled)?;
+            write!(
+                self.writer,
+                " loss {:.5} accuracy {:.4}",
+                state.running.loss,
+                state.running.accuracy
+            )?;
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn on_epoch_end(
+        &mut self,
+        state: &mut TrainingState<T>,
+        metrics: &EpochMetrics,
+    ) -> Result<(), TrainingError> {
+        let width = self.width;
+        self.draw(state.epoch, state.epochs, width)?;
+        write!(
+            self.writer,
+            " loss {:.5} accuracy {:.4}",
+            metrics.train.loss,
+            metrics.train.accuracy
+        )?;
+        if let Some(validation) = metrics.validation {
+            write!(
+                self.writer,
+                " validation loss {:.5} accuracy {:.4}",
+                validation.loss,
+                validation.accuracy
+            )?;
+        }
+        let elapsed = self.started.elapsed();
+        writeln!(
+            self.writer,
+            " ({}.{:01} s)",
+            elapsed.as_secs(),
+            elapsed.subsec_millis() / 100
+        )?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Writes the metrics of every epoch as one CSV line.
+pub struct CsvLogger<W: Write> {
+    writer: W,
+    header_written: bool,
+}
+
+impl CsvLogger<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<CsvLogger<BufWriter<File>>> {
+        Ok(CsvLogger::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> CsvLogger<W> {
+    pub fn new(writer: W) -> CsvLogger<W> {
+        CsvLogger {
+            writer,
+            header_written: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<T, W> Callback<T> for CsvLogger<W>
+where
+    T: Fn(f64) -> f64,
+    W: Write,
+{
+    fn on_epoch_end(
+        &mut self,
+        state: &mut TrainingState<T>,
+        metrics: &EpochMetrics,
+    ) -> Result<(), TrainingError> {
+        if !self.header_written {
+            writeln!(
+                self.writer,
+                "epoch,loss,accuracy,validation_loss,validation_accuracy,learning_rate"
+            )?;
+            self.header_written = true;
+        }
+
+        write!(
+            self.writer,
+            "{},{},{},",
+            metrics.epoch,
+            metrics.train.loss,
+            metrics.train.accuracy
+        )?;
+        match metrics.validation {
+            Some(validation) => {
+                write!(self.writer, "{},{},", validation.loss, validation.accuracy)?
+            }
+            None => write!(self.writer, ",,")?,
+        }
+        writeln!(self.writer, "{}", state.network.learning_rate())?;
+        // flush every epoch so that the log survives a crash
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Saves the network as a model file after epochs.
+///
+/// `{epoch}` in the path is replaced by the number of the epoch. Without
+/// it the same file is overwritten every time.
+pub struct ModelCheckpoint {
+    path: String,
+    every: usize,
+    best_only: bool,
+    activation: Option<String>,
+    input_scaler: Option<InputScaler>,
+    target_encoder: Option<TargetEncoder>,
+}
+
+impl ModelCheckpoint {
+    pub fn new(path: &str) -> ModelCheckpoint {
+        ModelCheckpoint {
+            path: path.to_owned(),
+            every: 1,
+            best_only: false,
+            activation: None,
+            input_scaler: None,
+            target_encoder: None,
+        }
+    }
+
+    /// Only saves every `every`-th epoch.
+    pub fn every(mut self, every: usize) -> ModelCheckpoint {
+        if every == 0 {
+            panic!("checkpoint interval has to be at least 1");
+        }
+        self.every = every;
+        self
+    }
+
+    /// Only saves epochs that are better than all before.
+    pub fn best_only(mut self, best_only: bool) -> ModelCheckpoint {
+        self.best_only = best_only;
+        self
+    }
+
+    /// Name of the activation function, stored with the network.
+    pub fn with_activation(mut self, name: &str) -> ModelCheckpoint {
+        self.activation = Some(name.to_owned());
+        self
+    }
+
+    /// Stored with the network so that the file is a complete model.
+    pub fn with_preprocessing(
+        mut self,
+        input_scaler: Option<InputScaler>,
+        target_encoder: Option<TargetEncoder>,
+    ) -> ModelCheckpoint {
+        self.input_scaler = input_scaler;
+        self.target_encoder = target_encoder;
+        self
+    }
+
+    pub fn path_for(&self, epoch: usize) -> PathBuf {
+        PathBuf::from(self.path.replace("{epoch}", &epoch.to_string()))
+    }
+}
+
+impl<T> Callback<T> for ModelCheckpoint
+where
+    T: Fn(f64) -> f64,
+{
+    fn on_epoch_end(
+        &mut self,
+        state: &mut TrainingState<T>,
+        metrics: &EpochMetrics,
+    ) -> Result<(), TrainingError> {
+        if !(metrics.epoch + 1).is_multiple_of(self.every) {
+            return Ok(());
+        }
+        if self.best_only && state.history.best_epoch != Some(metrics.epoch) {
+            return Ok(());
+        }
+
+        let mut writer = BufWriter::new(File::create(self.path_for(metrics.epoch))?);
+        model::write_model(
+            &mut writer,
+            state.network,
+            self.activation.as_deref(),
+            self.input_scaler.as_ref(),
+            self.target_encoder.as_ref(),
+        )?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// How the learning rate develops over the epochs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LearningRateSchedule {
+    Constant,
+    /// Multiplied with `factor` every `every` epochs.
+    Step { every: usize, factor: f64 },
+    /// Multiplied with `gamma` every epoch.
+    Exponential { gamma: f64 },
+    /// Follows half a cosine down to `minimum` within `epochs` epochs.
+    Cosine { epochs: usize, minimum: f64 },
+}
+
+impl LearningRateSchedule {
+    /// The learning rate of the epoch (starting with 0).
+    pub fn learning_rate(&self, initial: f64, epoch: usize) -> f64 {
+        match *self {
+            LearningRateSchedule::Constant => initial,
+            LearningRateSchedule::Step { every, factor } => {
+                initial * factor.powi((epoch / every.max(1)) as i32)
+            }
+            LearningRateSchedule::Exponential { gamma } => initial * gamma.powi(epoch as i32),
+            LearningRateSchedule::Cosine { epochs, minimum } => {
+                let progress = epoch.min(epochs) as f64 / epochs.max(1) as f64;
+                minimum + (initial - minimum) * (1.0 + (PI * progress).cos()) / 2.0
+            }
+        }
+    }
+}
+
+/// Sets the learning rate of the network at the start of every epoch.
+pub struct LearningRateScheduler {
+    schedule: LearningRateSchedule,
+    initial: Option<f64>,
+}
+
+impl LearningRateScheduler {
+    /// The learning rate of the network at the start of the training is the
+    /// initial one.
+    pub fn new(schedule: LearningRateSchedule) -> LearningRateScheduler {
+        LearningRateScheduler {
+            schedule,
+            initial: None,
+        }
+    }
+
+    pub fn with_initial(schedule: LearningRateSchedule, initial: f64) -> LearningRateScheduler {
+        LearningRateScheduler {
+            schedule,
+            initial: Some(initial),
+        }
+    }
+
+    pub fn schedule(&self) -> &LearningRateSchedule {
+        &self.schedule
+    }
+
+    pub fn initial(&self) -> Option<f64> {
+        self.initial
+    }
+}
+
+impl<T> Callback<T> for LearningRateScheduler
+where
+    T: Fn(f64) -> f64,
+{
+    fn on_epoch_start(&mut self, state: &mut TrainingState<T>) -> Result<(), TrainingError> {
+        let initial = self.initial.unwrap_or(state.initial_learning_rate);
+        state
+            .network
+            .set_learning_rate(self.schedule.learning_rate(initial, state.epoch));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod callbacks_tests {
+    use super::*;
+    use dataset::Sample;
+    use model::Model;
+    use std::env;
+    use std::fs;
+    use util;
+
+    fn samples(count: usize) -> Vec<Sample> {
+        (0..count)
+            .map(|x| (x % 2, vec![(x % 2) as f64, 0.5, 1.0]))
+            .collect()
+    }
+
+    /// Stops the training after a number of batches.
+    struct StopAfter {
+        batches: usize,
+        seen: usize,
+        train_ended: bool,
+    }
+
+    impl<T: Fn(f64) -> f64> Callback<T> for StopAfter {
+        fn on_batch_end(&mut self, state: &mut TrainingState<T>) -> Result<(), TrainingError> {
+            self.seen += 1;
+            if self.seen == self.batches {
+                state.stop();
+            }
+            Ok(())
+        }
+
+        fn on_train_end(
+            &mut self,
+            _network: &mut NeuralNetwork<T>,
+            _history: &History,
+        ) -> Result<(), TrainingError> {
+            self.train_ended = true;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_callback_stops_training() {
+        let data = samples(10);
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let trainer = Trainer::new(5, TargetEncoder::one_hot(2)).batch_size(4);
+        let mut stop = StopAfter {
+            batches: 5,
+            seen: 0,
+            train_ended: false,
+        };
+
+        let history = trainer
+            .fit_with_callbacks(&mut nn, &data, &data, &mut [&mut stop])
+            .unwrap();
+
+        // 3 batches per epoch, stopped in the middle of the second epoch
+        assert_eq!(history.epochs.len(), 2);
+        assert!(history.stopped_early);
+        assert!(history.epochs[1].validation.is_some());
+        assert!(stop.train_ended);
+    }
+
+    #[test]
+    fn test_progress_bar() {
+        let data = samples(4);
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let trainer = Trainer::new(2, TargetEncoder::one_hot(2)).batch_size(2);
+        let mut progress = ProgressBar::to_writer(Vec::new(), 4);
+
+        trainer
+            .fit_with_callbacks(&mut nn, &data, &Vec::<Sample>::new(), &mut [&mut progress])
+            .unwrap();
+
+        let output = String::from_utf8(progress.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("\repoch 1/2 [==  ] loss "));
+        assert!(lines[0].contains("\repoch 1/2 [====] loss "));
+        assert!(lines[1].starts_with("\repoch 2/2 [==  ]"));
+        assert!(!lines[1].contains("validation"));
+    }
+
+    #[test]
+    fn test_csv_logger_and_schedule() {
+        let data = samples(4);
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.4, util::sigmoid);
+        let trainer = Trainer::new(3, TargetEncoder::one_hot(2));
+        let mut scheduler = LearningRateScheduler::new(LearningRateSchedule::Step {
+            every: 2,
+            factor: 0.5,
+        });
+        let mut logger = CsvLogger::new(Vec::new());
+
+        let history = trainer
+            .fit_with_callbacks(&mut nn, &data, &data, &mut [&mut scheduler, &mut logger])
+            .unwrap();
+
+        let output = String::from_utf8(logger.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[0],
+            "epoch,loss,accuracy,validation_loss,validation_accuracy,learning_rate"
+        );
+        let train = history.epochs[2].train;
+        let validation = history.epochs[2].validation.unwrap();
+        assert_eq!(
+            lines[3],
+            format!(
+                "2,{},{},{},{},0.2",
+                train.loss, train.accuracy, validation.loss, validation.accuracy
+            )
+        );
+        assert!(lines[1].ends_with(",0.4"));
+        assert!(lines[2].ends_with(",0.4"));
+        assert_eq!(nn.learning_rate(), 0.2);
+    }
+
+    #[test]
+    fn test_learning_rate_schedule() {
+        let step = LearningRateSchedule::Step {
+            every: 3,
+            factor: 0.1,
+        };
+        assert_eq!(step.learning_rate(1.0, 2), 1.0);
+        assert_eq!(step.learning_rate(1.0, 3), 0.1);
+        assert_eq!(LearningRateSchedule::Exponential { gamma: 0.5 }.learning_rate(1.0, 3), 0.125);
+
+        let cosine = LearningRateSchedule::Cosine {
+            epochs: 4,
+            minimum: 0.1,
+        };
+        assert_eq!(cosine.learning_rate(0.5, 0), 0.5);
+        assert!((cosine.learning_rate(0.5, 2) - 0.3).abs() < 1e-12);
+        assert!((cosine.learning_rate(0.5, 4) - 0.1).abs() < 1e-12);
+        assert!((cosine.learning_rate(0.5, 9) - 0.1).abs() < 1e-12);
+        assert_eq!(LearningRateSchedule::Constant.learning_rate(0.3, 7), 0.3);
+    }
+
+    #[test]
+    fn test_model_checkpoint() {
+        let data = samples(6);
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let trainer = Trainer::new(4, TargetEncoder::one_hot(2));
+        let directory = env::temp_dir().join(format!("nn_checkpoint_{}", ::std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let pattern = directory.join("model_{epoch}.txt");
+        let mut checkpoint = ModelCheckpoint::new(pattern.to_str().unwrap())
+            .every(2)
+            .with_preprocessing(None, Some(TargetEncoder::one_hot(2)));
+
+        trainer
+            .fit_with_callbacks(&mut nn, &data, &data, &mut [&mut checkpoint])
+            .unwrap();
+
+        assert!(!checkpoint.path_for(0).exists());
+        assert!(checkpoint.path_for(1).exists());
+        let model = Model::load(checkpoint.path_for(3), util::sigmoid).unwrap();
+        assert_eq!(model.network.wih(), nn.wih());
+        assert_eq!(model.target_encoder, Some(TargetEncoder::one_hot(2)));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
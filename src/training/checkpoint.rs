@@ -0,0 +1,163 @@
+//! The checkpoint file - in the same text format as the model files.
+//!
+//! The network has no optimizer state apart from its learning rate, so
+//! layers, learning rate, cursor, the state of the shuffle random generator
+//! and the history of the finished epochs are everything needed to continue.
+//! The layers bring their dropout rates and random generators and the running
+//! statistics of their normalisation along. Penalties stay those of the
+//! network.
+
+use model::{read_layers, write_network, ModelError, TextReader};
+use std::io::prelude::*;
+use std::rc::Rc;
+use super::*;
+
+const FORMAT_VERSION: u32 = 2;
+
+pub fn write<W: Write, T>(
+    writer: &mut W,
+    network: &NeuralNetwork<T>,
+    history: &History,
+    progress: &Progress,
+) -> Result<(), ModelError>
+where
+    T: Fn(f64) -> f64,
+{
+    writeln!(writer, "# neural_network checkpoint")?;
+    writeln!(writer, "version {}", FORMAT_VERSION)?;
+    writeln!(
+        writer,
+        "cursor {} {} {}",
+        progress.epoch,
+        progress.sample,
+        progress.batch
+    )?;
+    match progress.rng_state {
+        Some(state) => writeln!(writer, "rng {}", state)?,
+        None => writeln!(writer, "rng none")?,
+    }
+    writeln!(writer, "initial_learning_rate {}", progress.initial_learning_rate)?;
+    let summary = &progress.summary;
+    writeln!(
+        writer,
+        "running {} {} {}",
+        summary.squared_error,
+        summary.correct,
+        summary.count
+    )?;
+    write_network(writer, network)?;
+
+    match history.best_epoch {
+        Some(epoch) => writeln!(writer, "best {} {}", progress.best_loss, epoch)?,
+        None => writeln!(writer, "best {} none", progress.best_loss)?,
+    }
+    if let Some(ref layers) = progress.best_layers {
+        layers.write_to(writer)?;
+    }
+
+    writeln!(writer, "skipped {}", progress.skipped_steps)?;
+
+    for metrics in &history.epochs {
+        write!(
+            writer,
+            "epoch {} {} {}",
+            metrics.epoch,
+            metrics.train.loss,
+            metrics.train.accuracy
+        )?;
+        if let Some(validation) = metrics.validation {
+            write!(writer, " {} {}", validation.loss, validation.accuracy)?;
+        }
+        writeln!(writer)?;
+    }
+    writeln!(writer, "end")?;
+    Ok(())
+}
+
+/// Puts the stored layers and learning rate into the network.
+pub fn read<R: BufRead, T>(
+    reader: &mut TextReader<R>,
+    network: &mut NeuralNetwork<T>,
+) -> Result<(History, Progress), ModelError>
+where
+    T: Fn(f64) -> f64 + 'static,
+{
+    let version: u32 = reader.expect("version", 1)?.parse(0)?;
+    if version != FORMAT_VERSION {
+        return Err(reader.error(format!("unsupported checkpoint version {}", version)));
+    }
+
+    let cursor = reader.expect("cursor", 3)?;
+    let rng = reader.expect("rng", 1)?;
+    let rng_state = match rng.get(0) {
+        "none" => None,
+        _ => Some(rng.parse(0)?),
+    };
+    let initial_learning_rate = reader.expect("initial_learning_rate", 1)?.parse(0)?;
+    let running = reader.expect("running", 3)?;
+    let summary = MetricsSummary {
+        squared_error: running.parse(0)?,
+        correct: running.parse(1)?,
+        count: running.parse(2)?,
+    };
+
+    // the activation function stays the one of the given network
+    let learning_rate = reader.expect("learning_rate", 1)?.parse(0)?;
+    let (function, derivative) =
+        (Rc::clone(&network.activation_function), network.activation_derivative);
+    network.set_layers(read_layers(reader, &function, derivative)?)?;
+    network.set_learning_rate(learning_rate);
+
+    let mut history = History::default();
+    let best = reader.expect("best", 2)?;
+    let best_loss = best.parse(0)?;
+    history.best_epoch = match best.get(1) {
+        "none" => None,
+        _ => Some(best.parse(1)?),
+    };
+    let best_layers = if reader.peek_keyword()? == "sequential" {
+        Some(read_layers(reader, &function, derivative)?)
+    } else {
+        None
+    };
+
+    let skipped_steps = reader.expect("skipped", 1)?.parse(0)?;
+    history.skipped_steps = skipped_steps;
+
+    loop {
+        if reader.peek_keyword()? == "end" {
+            reader.expect("end", 0)?;
+            break;
+        }
+        let line = reader.expect("epoch", 3)?;
+        let validation = if line.len() >= 5 {
+            Some(Metrics {
+                loss: line.parse(3)?,
+                accuracy: line.parse(4)?,
+            })
+        } else {
+            None
+        };
+        history.epochs.push(EpochMetrics {
+            epoch: line.parse(0)?,
+            train: Metrics {
+                loss: line.parse(1)?,
+                accuracy: line.parse(2)?,
+            },
+            validation,
+        });
+    }
+
+    let progress = Progress {
+        epoch: cursor.parse(0)?,
+        sample: cursor.parse(1)?,
+        batch: cursor.parse(2)?,
+        rng_state,
+        initial_learning_rate,
+        summary,
+        best_loss,
+        best_layers,
+        skipped_steps,
+    };
+    Ok((history, progress))
+}
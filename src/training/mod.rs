@@ -0,0 +1,1184 @@
+//! Training of a network over several epochs.
+
+pub mod callbacks;
+mod checkpoint;
+
+use dataset::Dataset;
+use gradients::Gradients;
+use layers::{Layer, Sequential};
+use matrix::error::MathError;
+use matrix::math;
+use model::ModelError;
+use preprocessing::TargetEncoder;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use matrix::Matrix;
+use model::TextReader;
+use util::SeededRng;
+use NeuralNetwork;
+
+#[derive(Debug)]
+pub enum TrainingError {
+    /// The samples do not fit to the network.
+    Math(MathError),
+    /// A sample has a label the target encoder does not know.
+    UnknownLabel { index: usize, label: usize },
+    /// A callback could not write its output.
+    Io(io::Error),
+    /// A model or checkpoint could not be saved or loaded.
+    Model(ModelError),
+    /// A training step gave a NaN or infinite value and the guard aborts.
+    NonFinite {
+        epoch: usize,
+        /// Position of the sample in the epoch, starts with 0. For the
+        /// gradients and the weights it is the last sample of the batch.
+        sample: usize,
+        value: NonFinite,
+    },
+}
+
+impl fmt::Display for TrainingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrainingError::Math(ref e) => write!(f, "could not train: {}", e),
+            TrainingError::UnknownLabel { index, label } => {
+                write!(f, "sample {} has the unknown label {}", index, label)
+            }
+            TrainingError::Io(ref e) => write!(f, "could not write training output: {}", e),
+            TrainingError::Model(ref e) => write!(f, "{}", e),
+            TrainingError::NonFinite {
+                epoch,
+                sample,
+                value,
+            } => write!(
+                f,
+                "training diverged: {} became NaN or infinite in epoch {} at sample {}",
+                value, epoch, sample
+            ),
+        }
+    }
+}
+
+impl error::Error for TrainingError {
+    fn cause(&self) -> Option<&dyn error::Error> {
+        match *self {
+            TrainingError::Math(ref e) => Some(e),
+            TrainingError::UnknownLabel { .. } => None,
+            TrainingError::Io(ref e) => Some(e),
+            TrainingError::Model(ref e) => Some(e),
+            TrainingError::NonFinite { .. } => None,
+        }
+    }
+}
+
+impl From<MathError> for TrainingError {
+    fn from(e: MathError) -> TrainingError {
+        TrainingError::Math(e)
+    }
+}
+
+impl From<io::Error> for TrainingError {
+    fn from(e: io::Error) -> TrainingError {
+        TrainingError::Io(e)
+    }
+}
+
+impl From<ModelError> for TrainingError {
+    fn from(e: ModelError) -> TrainingError {
+        TrainingError::Model(e)
+    }
+}
+
+/// The value of a training step that was NaN or infinite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NonFinite {
+    Loss,
+    Gradients,
+    /// The weightings after the step.
+    Weightings,
+}
+
+impl fmt::Display for NonFinite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NonFinite::Loss => write!(f, "the loss"),
+            NonFinite::Gradients => write!(f, "a gradient"),
+            NonFinite::Weightings => write!(f, "a weight"),
+        }
+    }
+}
+
+/// What the guard does with a step that gives a NaN or infinite value.
+/// The step itself is never applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NonFiniteAction {
+    /// Leaves the step out and goes on with the next batch.
+    Skip,
+    /// Puts back the weightings of the start of the epoch and stops the
+    /// training after that epoch.
+    Rollback,
+    /// Ends the training with `TrainingError::NonFinite`.
+    Abort,
+}
+
+/// Mean squared error plus the regularisation penalty of the network, and
+/// share of correctly classified samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metrics {
+    pub loss: f64,
+    pub accuracy: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochMetrics {
+    /// Starts with 0.
+    pub epoch: usize,
+    pub train: Metrics,
+    /// None without validation samples.
+    pub validation: Option<Metrics>,
+}
+
+impl EpochMetrics {
+    /// The loss early stopping looks at: validation loss if there is one.
+    pub fn monitored_loss(&self) -> f64 {
+        match self.validation {
+            Some(ref metrics) => metrics.loss,
+            None => self.train.loss,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct History {
+    pub epochs: Vec<EpochMetrics>,
+    /// Epoch with the lowest monitored loss.
+    pub best_epoch: Option<usize>,
+    pub stopped_early: bool,
+    /// Steps the non finite guard left out.
+    pub skipped_steps: usize,
+}
+
+impl History {
+    pub fn best(&self) -> Option<&EpochMetrics> {
+        self.epochs.get(self.best_epoch?)
+    }
+}
+
+/// Hooks into the training loop. All of them do nothing by default.
+///
+/// A callback can stop the training with `TrainingState::stop`. If it does
+/// so after a batch, the running epoch ends there and is still evaluated.
+pub trait Callback<T>
+where
+    T: Fn(f64) -> f64,
+{
+    fn on_epoch_start(&mut self, _state: &mut TrainingState<T>) -> Result<(), TrainingError> {
+        Ok(())
+    }
+
+    fn on_batch_end(&mut self, _state: &mut TrainingState<T>) -> Result<(), TrainingError> {
+        Ok(())
+    }
+
+    /// `metrics` is the last entry of `state.history`.
+    fn on_epoch_end(
+        &mut self,
+        _state: &mut TrainingState<T>,
+        _metrics: &EpochMetrics,
+    ) -> Result<(), TrainingError> {
+        Ok(())
+    }
+
+    /// Called once after the last epoch - and after the best weightings
+    /// have been restored.
+    fn on_train_end(
+        &mut self,
+        _network: &mut NeuralNetwork<T>,
+        _history: &History,
+    ) -> Result<(), TrainingError> {
+        Ok(())
+    }
+}
+
+/// What a callback gets to see of the running training.
+pub struct TrainingState<'a, T>
+where
+    T: Fn(f64) -> f64 + 'a,
+{
+    pub network: &'a mut NeuralNetwork<T>,
+    /// Starts with 0.
+    pub epoch: usize,
+    pub epochs: usize,
+    /// Number of finished batches in this epoch.
+    pub batch: usize,
+    pub batches: usize,
+    /// Loss and accuracy of this epoch so far.
+    pub running: Metrics,
+    /// Learning rate of the network when the training started.
+    pub initial_learning_rate: f64,
+    /// All finished epochs.
+    pub history: &'a History,
+    stop: bool,
+}
+
+impl<'a, T> TrainingState<'a, T>
+where
+    T: Fn(f64) -> f64,
+{
+    /// Ends the training after the current epoch or batch.
+    pub fn stop(&mut self) {
+        self.stop = true;
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop
+    }
+}
+
+/// Runs a network for several epochs over a dataset.
+///
+/// After every epoch the network is evaluated on the validation samples.
+/// With early stopping the training ends as soon as the validation loss has
+/// not improved for `patience` epochs.
+///
+/// Gradients can be clipped by their global norm and by value, and a guard
+/// can check loss, gradients and weightings of every step for NaN and
+/// infinite values. Both make a step allocate.
+///
+/// With checkpoints the training can be continued by `resume_from` after it
+/// was interrupted - with exactly the same result as without interruption.
+/// Callbacks are not part of a checkpoint and start fresh.
+pub struct Trainer {
+    epochs: usize,
+    batch_size: usize,
+    target_encoder: TargetEncoder,
+    shuffle_seed: Option<u64>,
+    patience: Option<usize>,
+    restore_best_weights: bool,
+    checkpoint: Option<(PathBuf, usize)>,
+    clip_norm: Option<f64>,
+    clip_value: Option<f64>,
+    non_finite: Option<NonFiniteAction>,
+}
+
+impl Trainer {
+    pub fn new(epochs: usize, target_encoder: TargetEncoder) -> Trainer {
+        Trainer {
+            epochs,
+            batch_size: 1,
+            target_encoder,
+            shuffle_seed: None,
+            patience: None,
+            restore_best_weights: false,
+            checkpoint: None,
+            clip_norm: None,
+            clip_value: None,
+            non_finite: None,
+        }
+    }
+
+    /// Samples per mini-batch: their gradients are averaged and applied in
+    /// one step, after which the callbacks are notified. Batches of more
+    /// than one sample make a step allocate.
+    pub fn batch_size(mut self, batch_size: usize) -> Trainer {
+        if batch_size == 0 {
+            panic!("batch size has to be at least 1");
+        }
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Visits the training samples in a new random order every epoch.
+    pub fn shuffle(mut self, seed: u64) -> Trainer {
+        self.shuffle_seed = Some(seed);
+        self
+    }
+
+    /// Stops after `patience` epochs without improvement.
+    pub fn early_stopping(mut self, patience: usize) -> Trainer {
+        self.patience = Some(patience);
+        self
+    }
+
+    /// Puts the weightings of the best epoch back into the network at the end.
+    pub fn restore_best_weights(mut self, restore: bool) -> Trainer {
+        self.restore_best_weights = restore;
+        self
+    }
+
+    /// Writes a checkpoint after every `every` batches and at the end of
+    /// every epoch. The file is replaced each time.
+    pub fn checkpoint<P: AsRef<Path>>(mut self, path: P, every: usize) -> Trainer {
+        if every == 0 {
+            panic!("checkpoint interval has to be at least 1");
+        }
+        self.checkpoint = Some((path.as_ref().to_path_buf(), every));
+        self
+    }
+
+    /// Scales the gradients of every step down to a global norm of at most
+    /// `max_norm`.
+    pub fn clip_gradient_norm(mut self, max_norm: f64) -> Trainer {
+        if max_norm.is_nan() || max_norm <= 0.0 {
+            panic!("maximum gradient norm has to be positive");
+        }
+        self.clip_norm = Some(max_norm);
+        self
+    }
+
+    /// Limits every gradient to `[-limit, limit]` - before the norm is clipped.
+    pub fn clip_gradient_value(mut self, limit: f64) -> Trainer {
+        if limit.is_nan() || limit <= 0.0 {
+            panic!("gradient limit has to be positive");
+        }
+        self.clip_value = Some(limit);
+        self
+    }
+
+    /// Checks loss, gradients and the resulting weightings of every step.
+    pub fn non_finite_guard(mut self, action: NonFiniteAction) -> Trainer {
+        self.non_finite = Some(action);
+        self
+    }
+
+    pub fn target_encoder(&self) -> &TargetEncoder {
+        &self.target_encoder
+    }
+
+    /// Trains the network. `validation` may be empty - the training loss is
+    /// monitored then.
+    pub fn fit<T, D, V>(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        train: &D,
+        validation: &V,
+    ) -> Result<History, TrainingError>
+    where
+        T: Fn(f64) -> f64,
+        D: Dataset,
+        V: Dataset,
+    {
+        self.fit_with_callbacks(network, train, validation, &mut [])
+    }
+
+    /// Same as `fit` but notifies the callbacks in the given order.
+    pub fn fit_with_callbacks<T, D, V>(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        train: &D,
+        validation: &V,
+        callbacks: &mut [&mut dyn Callback<T>],
+    ) -> Result<History, TrainingError>
+    where
+        T: Fn(f64) -> f64,
+        D: Dataset,
+        V: Dataset,
+    {
+        let progress = Progress {
+            epoch: 0,
+            sample: 0,
+            batch: 0,
+            rng_state: self.shuffle_seed,
+            initial_learning_rate: network.learning_rate(),
+            summary: MetricsSummary::default(),
+            best_loss: f64::INFINITY,
+            best_layers: None,
+            skipped_steps: 0,
+        };
+        self.run(network, train, validation, callbacks, History::default(), progress)
+    }
+
+    /// Continues an interrupted training from a checkpoint. The layers and
+    /// the learning rate of the network are replaced by the stored ones;
+    /// train and validation samples have to be the same as before.
+    pub fn resume_from<P, T, D, V>(
+        &self,
+        path: P,
+        network: &mut NeuralNetwork<T>,
+        train: &D,
+        validation: &V,
+        callbacks: &mut [&mut dyn Callback<T>],
+    ) -> Result<History, TrainingError>
+    where
+        P: AsRef<Path>,
+        T: Fn(f64) -> f64 + 'static,
+        D: Dataset,
+        V: Dataset,
+    {
+        let mut reader = TextReader::new(BufReader::new(File::open(path)?));
+        let (history, progress) = checkpoint::read(&mut reader, network)?;
+        self.run(network, train, validation, callbacks, history, progress)
+    }
+
+    fn run<T, D, V>(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        train: &D,
+        validation: &V,
+        callbacks: &mut [&mut dyn Callback<T>],
+        mut history: History,
+        mut progress: Progress,
+    ) -> Result<History, TrainingError>
+    where
+        T: Fn(f64) -> f64,
+        D: Dataset,
+        V: Dataset,
+    {
+        let batches = train.len().div_ceil(self.batch_size);
+
+        while progress.epoch < self.epochs {
+            let epoch = progress.epoch;
+            let running = progress.summary.metrics(network.regularization_loss());
+            let mut state = TrainingState {
+                network: &mut *network,
+                epoch,
+                epochs: self.epochs,
+                batch: progress.batch,
+                batches,
+                running,
+                initial_learning_rate: progress.initial_learning_rate,
+                history: &history,
+                stop: false,
+            };
+            for callback in callbacks.iter_mut() {
+                callback.on_epoch_start(&mut state)?;
+            }
+            if state.stop {
+                break;
+            }
+
+            let rollback = match self.non_finite {
+                Some(NonFiniteAction::Rollback) => Some(state.network.layers().clone()),
+                _ => None,
+            };
+            let mut rng = progress.rng_state.map(SeededRng::new);
+            let train_metrics = match rng {
+                Some(ref mut rng) => {
+                    let shuffled = train.shuffle(rng.next_u64());
+                    let indices = Some(shuffled.indices());
+                    self.train_epoch(
+                        &shuffled,
+                        indices,
+                        &mut state,
+                        callbacks,
+                        &mut progress,
+                        rollback,
+                    )?
+                }
+                None => {
+                    self.train_epoch(train, None, &mut state, callbacks, &mut progress, rollback)?
+                }
+            };
+            let mut stop = state.stop;
+            history.skipped_steps = progress.skipped_steps;
+
+            let validation_metrics = if validation.is_empty() {
+                None
+            } else {
+                Some(self.evaluate(network, validation)?)
+            };
+            let metrics = EpochMetrics {
+                epoch,
+                train: train_metrics,
+                validation: validation_metrics,
+            };
+
+            let loss = metrics.monitored_loss();
+            history.epochs.push(metrics);
+            if loss < progress.best_loss {
+                progress.best_loss = loss;
+                history.best_epoch = Some(epoch);
+                if self.restore_best_weights {
+                    progress.best_layers = Some(network.layers().clone());
+                }
+            }
+
+            let mut state = TrainingState {
+                network: &mut *network,
+                epoch,
+                epochs: self.epochs,
+                batch: progress.batch,
+                batches,
+                running: train_metrics,
+                initial_learning_rate: progress.initial_learning_rate,
+                history: &history,
+                stop,
+            };
+            for callback in callbacks.iter_mut() {
+                callback.on_epoch_end(&mut state, &history.epochs[epoch])?;
+            }
+            stop = state.stop;
+
+            if let (Some(patience), Some(best_epoch)) = (self.patience, history.best_epoch) {
+                stop = stop || epoch - best_epoch >= patience;
+            }
+            if stop {
+                history.stopped_early = epoch + 1 < self.epochs;
+                break;
+            }
+
+            progress.epoch += 1;
+            progress.sample = 0;
+            progress.batch = 0;
+            progress.summary = MetricsSummary::default();
+            progress.rng_state = rng.map(|rng| rng.state());
+            self.save_checkpoint(network, &history, &progress)?;
+        }
+
+        if let Some(layers) = progress.best_layers {
+            network.set_layers(layers)?;
+        }
+        for callback in callbacks.iter_mut() {
+            callback.on_train_end(network, &history)?;
+        }
+        Ok(history)
+    }
+
+    /// Loss and accuracy of the network on the samples - without training.
+    pub fn evaluate<T, D>(
+        &self,
+        network: &NeuralNetwork<T>,
+        dataset: &D,
+    ) -> Result<Metrics, TrainingError>
+    where
+        T: Fn(f64) -> f64,
+        D: Dataset,
+    {
+        let mut summary = MetricsSummary::default();
+        for (index, (label, inputs)) in dataset.iter().enumerate() {
+            let target = self.encode(index, label)?;
+            if inputs.len() != network.input_nodes() {
+                return Err(TrainingError::Math(MathError));
+            }
+            summary.add(label, &target, &network.query(inputs));
+        }
+        Ok(summary.metrics(network.regularization_loss()))
+    }
+
+    /// One pass over all samples - or the rest of them after a resume.
+    /// The metrics are based on the outputs before the step of each batch;
+    /// steps the guard leaves out do not count. `indices` are the positions of
+    /// the samples in the training data if `dataset` shows them shuffled.
+    fn train_epoch<T, D>(
+        &self,
+        dataset: &D,
+        indices: Option<&[usize]>,
+        state: &mut TrainingState<T>,
+        callbacks: &mut [&mut dyn Callback<T>],
+        progress: &mut Progress,
+        rollback: Option<Sequential>,
+    ) -> Result<Metrics, TrainingError>
+    where
+        T: Fn(f64) -> f64,
+        D: Dataset,
+    {
+        while progress.sample < dataset.len() {
+            let batch = progress.sample..dataset.len().min(progress.sample + self.batch_size);
+            progress.sample = batch.end;
+            let non_finite = self.step(state.network, dataset, indices, batch, progress)?;
+
+            match (non_finite, self.non_finite) {
+                (Some((sample, value)), Some(NonFiniteAction::Abort)) => {
+                    return Err(TrainingError::NonFinite {
+                        epoch: state.epoch,
+                        sample,
+                        value,
+                    });
+                }
+                (Some(_), Some(NonFiniteAction::Rollback)) => {
+                    if let Some(ref layers) = rollback {
+                        state.network.set_layers(layers.clone())?;
+                    }
+                    state.stop();
+                    break;
+                }
+                (Some(_), _) => progress.skipped_steps += 1,
+                (None, _) => {}
+            }
+
+            progress.batch += 1;
+            state.batch = progress.batch;
+            state.running = progress.summary.metrics(state.network.regularization_loss());
+            for callback in callbacks.iter_mut() {
+                callback.on_batch_end(state)?;
+            }
+            if state.stop {
+                break;
+            }
+            // the checkpoint at the end of the epoch is written later
+            if let Some((_, every)) = self.checkpoint {
+                if progress.batch.is_multiple_of(every) && progress.sample < dataset.len() {
+                    self.save_checkpoint(state.network, state.history, progress)?;
+                }
+            }
+        }
+        Ok(progress.summary.metrics(state.network.regularization_loss()))
+    }
+
+    /// Trains the network with the samples of `batch` in one step on the mean
+    /// of their gradients. Returns the position of the sample and the value
+    /// that was not finite if the guard left out the step.
+    fn step<T, D>(
+        &self,
+        network: &mut NeuralNetwork<T>,
+        dataset: &D,
+        indices: Option<&[usize]>,
+        batch: Range<usize>,
+        progress: &mut Progress,
+    ) -> Result<Option<(usize, NonFinite)>, TrainingError>
+    where
+        T: Fn(f64) -> f64,
+        D: Dataset,
+    {
+        let size = batch.len();
+        let samples = dataset.iter().enumerate().skip(batch.start).take(size);
+        let is_plain = self.clip_norm.is_none() && self.clip_value.is_none();
+        if size == 1 && is_plain && self.non_finite.is_none() {
+            for (index, (label, inputs)) in samples {
+                let target = self.encode(indices.map_or(index, |indices| indices[index]), label)?;
+                network.train(inputs, &target)?;
+                progress.summary.add(label, &target, &network.last_outputs().data_container()[0]);
+            }
+            return Ok(None);
+        }
+
+        let mut labels = Vec::with_capacity(size);
+        let mut inputs = Vec::with_capacity(size);
+        let mut targets = Vec::with_capacity(size);
+        for (index, (label, sample)) in samples {
+            let target = self.encode(indices.map_or(index, |indices| indices[index]), label)?;
+            if sample.len() != network.input_nodes() {
+                return Err(TrainingError::Math(MathError));
+            }
+            labels.push(label);
+            inputs.push(sample.to_vec());
+            targets.push(target);
+        }
+        if labels.is_empty() {
+            return Ok(None);
+        }
+
+        // all samples go through the layers together, so that batch
+        // normalisation sees the whole batch
+        let mut gradients =
+            network.backward_batch(&Matrix::from_2d_vec(&inputs), &Matrix::from_2d_vec(&targets))?;
+        if self.non_finite.is_some() {
+            // the loss is finite as long as all outputs are
+            let outputs = network.last_outputs().data_container();
+            let row = outputs.iter().position(|row| !row.iter().all(|output| output.is_finite()));
+            if let Some(row) = row {
+                return Ok(Some((batch.start + row, NonFinite::Loss)));
+            }
+            if !gradients.is_finite() {
+                return Ok(Some((batch.end - 1, NonFinite::Gradients)));
+            }
+        }
+
+        if let Some(limit) = self.clip_value {
+            gradients.clip_by_value(limit);
+        }
+        if let Some(max_norm) = self.clip_norm {
+            gradients.clip_by_norm(max_norm);
+        }
+        if self.non_finite.is_some() && !stays_finite(network, &gradients) {
+            return Ok(Some((batch.end - 1, NonFinite::Weightings)));
+        }
+
+        network.apply_gradients(&gradients)?;
+        // the outputs of the same forward pass as the gradients - with its
+        // dropout masks
+        let outputs = network.last_outputs().data_container();
+        for ((label, target), output) in labels.into_iter().zip(&targets).zip(outputs) {
+            progress.summary.add(label, target, output);
+        }
+        Ok(None)
+    }
+
+    /// Writes into a temporary file first so that a crash while writing
+    /// does not destroy the last checkpoint.
+    fn save_checkpoint<T>(
+        &self,
+        network: &NeuralNetwork<T>,
+        history: &History,
+        progress: &Progress,
+    ) -> Result<(), TrainingError>
+    where
+        T: Fn(f64) -> f64,
+    {
+        let path = match self.checkpoint {
+            Some((ref path, _)) => path,
+            None => return Ok(()),
+        };
+        let temporary = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        checkpoint::write(&mut writer, network, history, progress)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(&temporary, path)?;
+        Ok(())
+    }
+
+    fn encode(&self, index: usize, label: usize) -> Result<Vec<f64>, TrainingError> {
+        self.target_encoder
+            .encode(label)
+            .ok_or(TrainingError::UnknownLabel { index, label })
+    }
+}
+
+/// Where the training is and everything needed to continue it from there.
+struct Progress {
+    epoch: usize,
+    /// Samples of the epoch already trained.
+    sample: usize,
+    batch: usize,
+    /// State of the shuffle random generator at the start of the epoch.
+    rng_state: Option<u64>,
+    initial_learning_rate: f64,
+    summary: MetricsSummary,
+    best_loss: f64,
+    best_layers: Option<Sequential>,
+    skipped_steps: usize,
+}
+
+/// True if every parameter is finite after the step with the gradients.
+fn stays_finite<T>(network: &mut NeuralNetwork<T>, gradients: &Gradients) -> bool
+where
+    T: Fn(f64) -> f64,
+{
+    let learning_rate = network.learning_rate();
+    network
+        .layers
+        .parameters()
+        .iter()
+        .zip(&gradients.parameters)
+        .all(|(parameter, gradients)| {
+            parameter
+                .values
+                .iter()
+                .zip(gradients)
+                .all(|(value, gradient)| (value - learning_rate * gradient).is_finite())
+        })
+}
+
+#[derive(Default)]
+struct MetricsSummary {
+    squared_error: f64,
+    correct: usize,
+    count: usize,
+}
+
+impl MetricsSummary {
+    fn add(&mut self, label: usize, target: &[f64], outputs: &[f64]) {
+        let squared_error: f64 = target
+            .iter()
+            .zip(outputs)
+            .map(|(t, o)| (t - o) * (t - o))
+            .sum();
+        self.squared_error += squared_error / target.len() as f64;
+        if math::argmax(outputs) == Some(label) {
+            self.correct += 1;
+        }
+        self.count += 1;
+    }
+
+    fn metrics(&self, penalty: f64) -> Metrics {
+        if self.count == 0 {
+            return Metrics {
+                loss: 0.0,
+                accuracy: 0.0,
+            };
+        }
+        Metrics {
+            loss: self.squared_error / self.count as f64 + penalty,
+            accuracy: self.correct as f64 / self.count as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod training_tests {
+    use super::*;
+    use dataset::Sample;
+    use layers::{BatchNorm, Normalization};
+    use regularization::Regularization;
+    use training::callbacks::*;
+    use util;
+    use util::TempFile;
+
+    /// The values of every parameter of the network.
+    fn parameters<T: Fn(f64) -> f64>(nn: &mut NeuralNetwork<T>) -> Vec<Vec<f64>> {
+        nn.layers.parameters().iter().map(|parameter| parameter.values.to_vec()).collect()
+    }
+
+    /// Label 1 if the first input is larger than the second.
+    fn samples(count: usize) -> Vec<Sample> {
+        let mut rng = SeededRng::new(5);
+        (0..count)
+            .map(|_| {
+                let (a, b) = (rng.next_f64(), rng.next_f64());
+                ((a > b) as usize, vec![a, b, 1.0])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fit_learns() {
+        let data = samples(200);
+        let split = data.split(0.25, 0.0, 1).unwrap();
+        let mut nn = NeuralNetwork::new(3, 6, 2, 0.3, util::sigmoid);
+        let trainer = Trainer::new(30, TargetEncoder::new(2, 0.99, 0.01)).shuffle(2);
+
+        let before = trainer.evaluate(&nn, &split.validation).unwrap();
+        let history = trainer.fit(&mut nn, &split.train, &split.validation).unwrap();
+
+        assert_eq!(history.epochs.len(), 30);
+        assert!(!history.stopped_early);
+        let last = history.epochs.last().unwrap();
+        assert!(last.validation.unwrap().loss < before.loss);
+        assert!(last.validation.unwrap().accuracy > 0.9);
+        assert_eq!(trainer.evaluate(&nn, &split.validation).unwrap(), last.validation.unwrap());
+    }
+
+    #[test]
+    fn test_shuffle_is_reproducible() {
+        let data = samples(50);
+        let trainer = Trainer::new(3, TargetEncoder::one_hot(2)).shuffle(9);
+        let nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let (wih, who) = (nn.wih().clone(), nn.who().clone());
+
+        let mut first = NeuralNetwork::from_weightings(wih.clone(), who.clone(), 0.3, util::sigmoid)
+            .unwrap();
+        let mut second = NeuralNetwork::from_weightings(wih, who, 0.3, util::sigmoid).unwrap();
+        let empty: Vec<Sample> = vec![];
+        let first_history = trainer.fit(&mut first, &data, &empty).unwrap();
+        let second_history = trainer.fit(&mut second, &data, &empty).unwrap();
+
+        assert_eq!(first_history, second_history);
+        assert_eq!(first.wih(), second.wih());
+        assert!(first_history.epochs[0].validation.is_none());
+    }
+
+    #[test]
+    fn test_early_stopping_restores_best_weights() {
+        // the validation labels are the opposite of what the network learns,
+        // so the validation loss grows from the first epoch on
+        let train = samples(100);
+        let validation: Vec<Sample> = train
+            .iter()
+            .map(|(label, values)| (1 - label, values.to_vec()))
+            .collect();
+        let mut nn = NeuralNetwork::new(3, 6, 2, 0.3, util::sigmoid);
+        let trainer = Trainer::new(50, TargetEncoder::one_hot(2))
+            .early_stopping(2)
+            .restore_best_weights(true);
+
+        let history = trainer.fit(&mut nn, &train, &validation).unwrap();
+
+        assert!(history.stopped_early);
+        let best = history.best().unwrap();
+        assert_eq!(history.epochs.len(), best.epoch + 3);
+        assert_eq!(trainer.evaluate(&nn, &validation).unwrap(), best.validation.unwrap());
+    }
+
+    /// Fails like a crash after a number of batches.
+    struct CrashAfter {
+        batches: usize,
+    }
+
+    impl<T: Fn(f64) -> f64> Callback<T> for CrashAfter {
+        fn on_batch_end(&mut self, _state: &mut TrainingState<T>) -> Result<(), TrainingError> {
+            self.batches -= 1;
+            if self.batches == 0 {
+                return Err(TrainingError::Io(io::Error::other("crash")));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_resume_is_identical() {
+        let data = samples(60);
+        let split = data.split(0.2, 0.0, 4).unwrap();
+        let path = TempFile::new("resume.txt");
+        let trainer = Trainer::new(6, TargetEncoder::one_hot(2))
+            .batch_size(5)
+            .shuffle(11)
+            .early_stopping(3)
+            .restore_best_weights(true)
+            .checkpoint(&path, 3);
+        let schedule = LearningRateSchedule::Exponential { gamma: 0.8 };
+        let nn = NeuralNetwork::new(3, 5, 2, 0.5, util::sigmoid);
+        let (wih, who) = (nn.wih().clone(), nn.who().clone());
+
+        let mut uninterrupted =
+            NeuralNetwork::from_weightings(wih.clone(), who.clone(), 0.5, util::sigmoid).unwrap();
+        uninterrupted.set_dropout(0.1, 0.2, 6);
+        uninterrupted.set_normalization(Normalization::Batch);
+        let mut scheduler = LearningRateScheduler::new(schedule.clone());
+        let expected = trainer
+            .fit_with_callbacks(
+                &mut uninterrupted,
+                &split.train,
+                &split.validation,
+                &mut [&mut scheduler],
+            )
+            .unwrap();
+
+        // 10 batches per epoch - crash in the middle of the third epoch
+        let mut interrupted = NeuralNetwork::from_weightings(wih, who, 0.5, util::sigmoid).unwrap();
+        interrupted.set_dropout(0.1, 0.2, 6);
+        interrupted.set_normalization(Normalization::Batch);
+        let mut scheduler = LearningRateScheduler::new(schedule.clone());
+        let mut crash = CrashAfter { batches: 25 };
+        let result = trainer.fit_with_callbacks(
+            &mut interrupted,
+            &split.train,
+            &split.validation,
+            &mut [&mut scheduler, &mut crash],
+        );
+        assert!(result.is_err());
+
+        let mut resumed = NeuralNetwork::new(3, 5, 2, 0.1, util::sigmoid);
+        resumed.set_dropout(0.1, 0.2, 0);
+        resumed.set_normalization(Normalization::Batch);
+        let mut scheduler = LearningRateScheduler::new(schedule);
+        let history = trainer
+            .resume_from(&path, &mut resumed, &split.train, &split.validation, &mut [
+                &mut scheduler,
+            ])
+            .unwrap();
+
+        assert_eq!(history, expected);
+        assert_eq!(parameters(&mut resumed), parameters(&mut uninterrupted));
+        // the running statistics of batch normalisation come from the checkpoint
+        fn running_mean<T: Fn(f64) -> f64>(nn: &NeuralNetwork<T>) -> Vec<f64> {
+            let layer = nn.layers().layers()[2].as_any().downcast_ref::<BatchNorm>();
+            layer.unwrap().running_mean().to_vec()
+        }
+        assert_eq!(running_mean(&resumed), running_mean(&uninterrupted));
+        assert_eq!(resumed.learning_rate(), uninterrupted.learning_rate());
+    }
+
+    #[test]
+    fn test_resume_checks_shape() {
+        let data = samples(10);
+        let path = TempFile::new("resume_shape.txt");
+        let trainer = Trainer::new(1, TargetEncoder::one_hot(2)).checkpoint(&path, 1);
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        trainer.fit(&mut nn, &data, &data).unwrap();
+
+        let mut other = NeuralNetwork::new(3, 5, 2, 0.3, util::sigmoid);
+        match trainer.resume_from(&path, &mut other, &data, &data, &mut []) {
+            Err(TrainingError::Model(ModelError::Math(_))) => (),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+
+    /// The third sample makes every step with it NaN.
+    fn samples_with_nan() -> Vec<Sample> {
+        let mut data = samples(6);
+        data[2].1[0] = f64::NAN;
+        data
+    }
+
+    #[test]
+    fn test_non_finite_guard_skips() {
+        let data = samples_with_nan();
+        let empty: Vec<Sample> = vec![];
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let trainer = Trainer::new(2, TargetEncoder::one_hot(2))
+            .non_finite_guard(NonFiniteAction::Skip);
+
+        let history = trainer.fit(&mut nn, &data, &empty).unwrap();
+        assert_eq!(history.skipped_steps, 2);
+        assert!(history.epochs.iter().all(|epoch| epoch.train.loss.is_finite()));
+        assert!(nn.wih().flatten().iter().all(|weight| weight.is_finite()));
+
+        // without the guard the weightings are destroyed
+        let mut unguarded = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        Trainer::new(1, TargetEncoder::one_hot(2)).fit(&mut unguarded, &data, &empty).unwrap();
+        assert!(unguarded.wih().flatten().iter().any(|weight| weight.is_nan()));
+    }
+
+    #[test]
+    fn test_non_finite_guard_aborts() {
+        let data = samples_with_nan();
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let trainer = Trainer::new(2, TargetEncoder::one_hot(2))
+            .non_finite_guard(NonFiniteAction::Abort);
+
+        match trainer.fit(&mut nn, &data, &data[..2].to_vec()) {
+            Err(TrainingError::NonFinite {
+                epoch: 0,
+                sample: 2,
+                value: NonFinite::Loss,
+            }) => (),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+        assert!(nn.who().flatten().iter().all(|weight| weight.is_finite()));
+
+        // finite loss and gradients but the step makes the weights overflow
+        let who = nn.who().clone();
+        let mut nn =
+            NeuralNetwork::from_weightings(Matrix::zeros(4, 3), who, 1e20, util::sigmoid).unwrap();
+        let huge: Vec<Sample> = vec![(0, vec![1e300, 0.0, 1.0])];
+        let error = trainer.fit(&mut nn, &huge, &huge).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "training diverged: a weight became NaN or infinite in epoch 0 at sample 0"
+        );
+    }
+
+    #[test]
+    fn test_non_finite_guard_rolls_back() {
+        let mut data = samples(8);
+        data[6].1[1] = f64::INFINITY;
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let trainer = Trainer::new(3, TargetEncoder::one_hot(2))
+            .non_finite_guard(NonFiniteAction::Rollback);
+
+        let mut expected =
+            NeuralNetwork::from_weightings(nn.wih(), nn.who(), 0.3, util::sigmoid).unwrap();
+        let history = trainer.fit(&mut nn, &data, &data[..6].to_vec()).unwrap();
+
+        assert_eq!(history.epochs.len(), 1);
+        assert!(history.stopped_early);
+        assert_eq!(parameters(&mut nn), parameters(&mut expected));
+        assert_eq!(
+            trainer.evaluate(&nn, &data[..6].to_vec()).unwrap(),
+            trainer.evaluate(&expected, &data[..6].to_vec()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_clip_gradients() {
+        let data: Vec<Sample> = vec![(0, vec![0.9, 0.1, 1.0])];
+        let mut nn = NeuralNetwork::with_seed(3, 4, 2, 0.5, util::sigmoid, 2);
+        let gradients = nn.backward(&data[0].1, &[1.0, 0.0]).unwrap();
+        let max_norm = gradients.norm() / 4.0;
+
+        let mut clipped =
+            NeuralNetwork::from_weightings(nn.wih(), nn.who(), 0.5, util::sigmoid).unwrap();
+        Trainer::new(1, TargetEncoder::one_hot(2))
+            .clip_gradient_value(1.0)
+            .clip_gradient_norm(max_norm)
+            .fit(&mut clipped, &data, &data)
+            .unwrap();
+
+        // the biases are parameters as well
+        let step: Vec<f64> = parameters(&mut nn)
+            .concat()
+            .iter()
+            .zip(parameters(&mut clipped).concat())
+            .map(|(before, after)| before - after)
+            .collect();
+        let norm = step.iter().map(|x| x * x).sum::<f64>().sqrt();
+        assert!((norm - 0.5 * max_norm).abs() < 1e-12, "{}", norm);
+    }
+
+    #[test]
+    fn test_mini_batches() {
+        let data = samples(6);
+        let mut expected = NeuralNetwork::with_seed(3, 4, 2, 0.5, util::sigmoid, 5);
+        let mut nn = NeuralNetwork::with_seed(3, 4, 2, 0.5, util::sigmoid, 5);
+        let encoder = TargetEncoder::one_hot(2);
+
+        for batch in data.chunks(4) {
+            let mut sum: Option<Gradients> = None;
+            for &(label, ref inputs) in batch {
+                let target = encoder.encode(label).unwrap();
+                let gradients = expected.backward(inputs, &target).unwrap();
+                match sum {
+                    Some(ref mut sum) => sum.add(&gradients).unwrap(),
+                    None => sum = Some(gradients),
+                }
+            }
+            let mut sum = sum.unwrap();
+            sum.scale(1.0 / batch.len() as f64);
+            expected.apply_gradients(&sum).unwrap();
+        }
+
+        let history = Trainer::new(1, encoder)
+            .batch_size(4)
+            .fit(&mut nn, &data, &Vec::<Sample>::new())
+            .unwrap();
+        assert_eq!(history.epochs.len(), 1);
+        // the batch goes through the layers at once, so the sums are in
+        // another order
+        let expected = parameters(&mut expected).concat();
+        for (value, expected) in parameters(&mut nn).concat().iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-12, "{} != {}", value, expected);
+        }
+    }
+
+    #[test]
+    fn test_batch_normalization() {
+        let data = samples(200);
+        let split = data.split(0.25, 0.0, 1).unwrap();
+        let mut nn = NeuralNetwork::with_seed(3, 6, 2, 0.5, util::sigmoid, 3);
+        nn.set_normalization(Normalization::Batch);
+        let trainer = Trainer::new(20, TargetEncoder::new(2, 0.99, 0.01))
+            .batch_size(8)
+            .shuffle(2);
+
+        let before = trainer.evaluate(&nn, &split.validation).unwrap();
+        let history = trainer.fit(&mut nn, &split.train, &split.validation).unwrap();
+
+        let last = history.epochs.last().unwrap().validation.unwrap();
+        assert!(last.loss < before.loss);
+        assert!(last.accuracy > 0.9, "{:?}", last);
+        let layer = nn.layers().layers()[1].as_any().downcast_ref::<BatchNorm>().unwrap();
+        assert!(layer.running_var().iter().all(|&var| var != 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "maximum gradient norm has to be positive")]
+    fn test_clip_norm_positive() {
+        Trainer::new(1, TargetEncoder::one_hot(2)).clip_gradient_norm(0.0);
+    }
+
+    #[test]
+    fn test_loss_includes_penalty() {
+        let data = samples(20);
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let trainer = Trainer::new(1, TargetEncoder::one_hot(2));
+        let unregularized = trainer.evaluate(&nn, &data).unwrap();
+
+        nn.set_regularization(Regularization::l2(0.01), Regularization::l1(0.01));
+        let penalty = nn.regularization_loss();
+        let regularized = trainer.evaluate(&nn, &data).unwrap();
+        assert!((regularized.loss - unregularized.loss - penalty).abs() < 1e-12);
+        assert_eq!(regularized.accuracy, unregularized.accuracy);
+
+        let history = trainer.fit(&mut nn, &data, &data).unwrap();
+        assert_eq!(history.epochs[0].validation, Some(trainer.evaluate(&nn, &data).unwrap()));
+    }
+
+    #[test]
+    fn test_unknown_label() {
+        let data: Vec<Sample> = vec![(0, vec![0.5, 0.5, 1.0]), (2, vec![0.1, 0.2, 1.0])];
+        let mut nn = NeuralNetwork::new(3, 4, 2, 0.3, util::sigmoid);
+        let trainer = Trainer::new(1, TargetEncoder::one_hot(2));
+
+        match trainer.fit(&mut nn, &data, &data) {
+            Err(TrainingError::UnknownLabel { index: 1, label: 2 }) => (),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+        // the index is the one in the data, not the one in the shuffled epoch
+        let mut data = samples(20);
+        data[13].0 = 5;
+        let trainer = Trainer::new(1, TargetEncoder::one_hot(2)).shuffle(3);
+        assert_ne!(data.shuffle(SeededRng::new(3).next_u64()).indices()[13], 13);
+        match trainer.fit(&mut nn, &data, &Vec::<Sample>::new()) {
+            Err(TrainingError::UnknownLabel { index: 13, label: 5 }) => (),
+            other => panic!("unexpected result {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -13,8 +13,115 @@ pub fn create_weighting_row(x: usize) -> Vec<f64> {
     row
 }
 
+/// Same as `create_weighting_row` but reproducible.
+pub fn create_seeded_weighting_row(x: usize, rng: &mut SeededRng) -> Vec<f64> {
+    let mut row: Vec<f64> = Vec::new();
+
+    for _number in 0..x {
+        let min = rng.range(-0.1, -0.01);
+        let max = rng.range(0.01, 0.1);
+        row.push(rng.range(min, max));
+    }
+    row
+}
+
 pub fn sigmoid(x: f64) -> f64 {
-    (1.0 / (1.0 + E.powf(-x)))
+    1.0 / (1.0 + E.powf(-x))
+}
+
+pub fn relu(x: f64) -> f64 {
+    x.max(0.0)
+}
+
+/// Derivative of `sigmoid` at the point where it gives `output`.
+pub fn sigmoid_derivative(output: f64) -> f64 {
+    output * (1.0 - output)
+}
+
+/// Derivative of `relu` at the point where it gives `output`.
+pub fn relu_derivative(output: f64) -> f64 {
+    if output > 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Small reproducible random number generator (SplitMix64).
+///
+/// Unlike `rand::thread_rng` the whole state is one number, so it can be
+/// stored and restored to continue with exactly the same sequence.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> SeededRng {
+        SeededRng { state: seed }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform value in [min, max).
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + self.next_f64() * (max - min)
+    }
+
+    /// Uniform value in [0, upper_bound).
+    pub fn below(&mut self, upper_bound: usize) -> usize {
+        ((u128::from(self.next_u64()) * upper_bound as u128) >> 64) as usize
+    }
+
+    /// Fisher-Yates shuffle.
+    pub fn shuffle<T>(&mut self, values: &mut [T]) {
+        for index in (1..values.len()).rev() {
+            let other = self.below(index + 1);
+            values.swap(index, other);
+        }
+    }
+}
+
+/// A file in the temp directory for one test: the name is unique per test
+/// process and the file is removed on drop - also when an assertion fails.
+#[cfg(test)]
+pub struct TempFile(::std::path::PathBuf);
+
+#[cfg(test)]
+impl TempFile {
+    pub fn new(name: &str) -> TempFile {
+        let name = format!("nn_{}_{}", ::std::process::id(), name);
+        TempFile(::std::env::temp_dir().join(name))
+    }
+}
+
+#[cfg(test)]
+impl AsRef<::std::path::Path> for TempFile {
+    fn as_ref(&self) -> &::std::path::Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = ::std::fs::remove_file(&self.0);
+    }
 }
 
 #[cfg(test)]
@@ -26,7 +133,7 @@ mod util_tests {
         let row = create_weighting_row(1000);
         assert_eq!(row.len(), 1000);
 
-        let check_values: Vec<&f64> = row.iter().filter(|x| **x < -0.5 && **x > 0.5).collect();
+        let check_values: Vec<&f64> = row.iter().filter(|x| **x < -0.5 || **x > 0.5).collect();
 
         assert_eq!(check_values.len(), 0);
     }
@@ -37,4 +144,33 @@ mod util_tests {
         let x = sigmoid(2.0);
         assert_eq!(y, x);
     }
+
+    #[test]
+    fn test_seeded_rng() {
+        let mut rng = SeededRng::new(42);
+        let mut same_rng = SeededRng::new(42);
+        let values: Vec<u64> = (0..5).map(|_| rng.next_u64()).collect();
+        assert_eq!(values, (0..5).map(|_| same_rng.next_u64()).collect::<Vec<_>>());
+
+        let mut restored = SeededRng::new(rng.state());
+        assert_eq!(rng.next_u64(), restored.next_u64());
+
+        for _ in 0..1000 {
+            let x = rng.next_f64();
+            assert!((0.0..1.0).contains(&x));
+            assert!(rng.below(7) < 7);
+        }
+
+        let mut shuffled: Vec<usize> = (0..10).collect();
+        rng.shuffle(&mut shuffled);
+        assert_ne!(shuffled, (0..10).collect::<Vec<_>>());
+        shuffled.sort();
+        assert_eq!(shuffled, (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_relu() {
+        assert_eq!(relu(-2.0), 0.0);
+        assert_eq!(relu(2.0), 2.0);
+    }
 }
@@ -0,0 +1,64 @@
+extern crate neural_network;
+
+use neural_network::*;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Counts every allocation while `COUNTING` is switched on.
+struct CountingAllocator;
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.load(Ordering::SeqCst) {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if COUNTING.load(Ordering::SeqCst) {
+            ALLOCATIONS.fetch_add(1, Ordering::SeqCst);
+        }
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+#[test]
+fn train_does_not_allocate() {
+    let mut nn = NeuralNetwork::new(784, 100, 10, 0.1, util::sigmoid);
+    assert_eq!(count_allocations(&mut nn), 0);
+
+    nn.set_dropout(0.2, 0.5, 1);
+    assert_eq!(count_allocations(&mut nn), 0);
+}
+
+/// Allocations of ten training steps. One test only because the counter is
+/// shared by all threads.
+fn count_allocations<T: Fn(f64) -> f64>(nn: &mut NeuralNetwork<T>) -> usize {
+    ALLOCATIONS.store(0, Ordering::SeqCst);
+    let inputs: Vec<f64> = (0..784).map(|x| f64::from(x % 255) / 255.0).collect();
+    let mut awaited_output = matrix::math::create_zeroed_vector(10);
+    awaited_output[3] = 0.99;
+
+    // warm up - e.g. the CPU feature detection
+    nn.train(&inputs, &awaited_output).unwrap();
+
+    COUNTING.store(true, Ordering::SeqCst);
+    for _ in 0..10 {
+        nn.train(&inputs, &awaited_output).unwrap();
+    }
+    COUNTING.store(false, Ordering::SeqCst);
+
+    ALLOCATIONS.load(Ordering::SeqCst)
+}
+
@@ -0,0 +1,53 @@
+extern crate neural_network;
+
+use neural_network::dataset::RegressionSample;
+use neural_network::evaluation::regression::RegressionMetrics;
+use neural_network::matrix::Matrix;
+use neural_network::util::SeededRng;
+use neural_network::*;
+use std::f64::consts::PI;
+
+/// x in [0, 2 pi] scaled to [0, 1] plus a bias input; sin(x) scaled into
+/// the range a sigmoid output can reach.
+fn sine_samples(count: usize) -> Vec<RegressionSample> {
+    (0..count)
+        .map(|index| {
+            let x = index as f64 / (count - 1) as f64;
+            let y = (2.0 * PI * x).sin();
+            (vec![x, 1.0], vec![0.5 + 0.4 * y])
+        })
+        .collect()
+}
+
+/// Seeded instead of random weightings so that the result is always the same.
+fn weighting(rng: &mut SeededRng, columns: usize, rows: usize) -> Matrix {
+    let data: Vec<Vec<f64>> = (0..rows)
+        .map(|_| (0..columns).map(|_| rng.next_f64() * 2.0 - 1.0).collect())
+        .collect();
+    Matrix::from_2d_vec(&data)
+}
+
+#[test]
+fn fits_sine_curve() {
+    let train = sine_samples(64);
+    let test = sine_samples(101);
+    let mut rng = SeededRng::new(4);
+    let (wih, who) = (weighting(&mut rng, 2, 12), weighting(&mut rng, 12, 1));
+    let mut nn = NeuralNetwork::from_weightings(wih, who, 1.0, util::sigmoid).unwrap();
+    let mut order: Vec<usize> = (0..train.len()).collect();
+
+    let before = RegressionMetrics::evaluate(&nn, &test);
+    for _ in 0..3000 {
+        rng.shuffle(&mut order);
+        for &index in &order {
+            let (ref inputs, ref target) = train[index];
+            nn.train(inputs, target).unwrap();
+        }
+    }
+    let after = RegressionMetrics::evaluate(&nn, &test);
+
+    assert!(after.mse < before.mse);
+    assert!(after.rmse < 0.03, "rmse {}", after.rmse);
+    assert!(after.max_error < 0.1, "max error {}", after.max_error);
+    assert!(after.r2 > 0.99, "r2 {}", after.r2);
+}